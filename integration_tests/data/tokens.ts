@@ -1,6 +1,6 @@
 import * as jwt from "jsonwebtoken";
 
-const cert = "cHVibGljS2V5";
+const cert = "wodbook-integration-test-secret-key";
 export const userId = "07254ac4-f1ac-43d8-9f7e-2ae12c0a7c74";
 export const adminId = "d2e45489-b50c-4a4d-b015-84830b246fae";
 
@@ -0,0 +1,129 @@
+import { MongoClient, Db } from "mongodb";
+import { StatusCodes } from "http-status-codes";
+import { createUsers, getMongoClient } from "./common";
+import { LoginData, LoginPayload } from "./types/user";
+import { FeedData, ManyFeedsData } from "./types/feed";
+
+const baseUrl = `${process.env.API_URL || "http://127.0.0.1:43210"}/v1`;
+
+describe("/v1/feeds", () => {
+  let mongoClient: MongoClient;
+  let db: Db;
+
+  let userToken: string;
+  let adminToken: string;
+
+  const login = async ({ email, password }: LoginPayload) => {
+    const res = await fetch(`${baseUrl}/users/login`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json",
+      },
+      body: JSON.stringify({
+        email,
+        password,
+      }),
+    });
+
+    const body: LoginData = await res.json();
+    expect(res.status).toBe(StatusCodes.OK);
+    expect(body).toHaveProperty("token");
+    const { token } = body;
+
+    return token;
+  };
+
+  const subscribe = async (token: string, title: string, url: string) =>
+    fetch(`${baseUrl}/feeds`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json",
+        Authorization: `Bearer ${token}`,
+      },
+      body: JSON.stringify({ title, url }),
+    });
+
+  beforeAll(async () => {
+    mongoClient = await getMongoClient();
+    db = mongoClient.db();
+
+    await createUsers(db);
+
+    userToken = await login({
+      email: "user@wodbook.com",
+      password: "user",
+    });
+    adminToken = await login({
+      email: "admin@wodbook.com",
+      password: "admin",
+    });
+  });
+
+  beforeEach(async () => {
+    await db.collection("feeds").deleteMany({});
+  });
+
+  afterAll(async () => {
+    await mongoClient.close();
+  });
+
+  it("should subscribe to, list and unsubscribe from a feed", async () => {
+    const res1 = await subscribe(
+      userToken,
+      "CrossFit 101",
+      "http://crossfit101.com/feed/"
+    );
+    const body1: FeedData = await res1.json();
+    expect(res1.status).toBe(StatusCodes.CREATED);
+    expect(body1.title).toBe("CrossFit 101");
+
+    const res2 = await fetch(`${baseUrl}/feeds`, {
+      method: "GET",
+      headers: {
+        Authorization: `Bearer ${userToken}`,
+      },
+    });
+    const body2: ManyFeedsData = await res2.json();
+    expect(res2.status).toBe(StatusCodes.OK);
+    expect(body2.data).toHaveLength(1);
+
+    // Feeds are not shared between users
+    const res3 = await fetch(`${baseUrl}/feeds`, {
+      method: "GET",
+      headers: {
+        Authorization: `Bearer ${adminToken}`,
+      },
+    });
+    const body3: ManyFeedsData = await res3.json();
+    expect(body3.data).toHaveLength(0);
+
+    const res4 = await fetch(`${baseUrl}/feeds/${body1.feed_id}`, {
+      method: "DELETE",
+      headers: {
+        Authorization: `Bearer ${userToken}`,
+      },
+    });
+    expect(res4.status).toBe(StatusCodes.NO_CONTENT);
+
+    const res5 = await fetch(`${baseUrl}/feeds/${body1.feed_id}`, {
+      method: "DELETE",
+      headers: {
+        Authorization: `Bearer ${userToken}`,
+      },
+    });
+    expect(res5.status).toBe(StatusCodes.NOT_FOUND);
+  });
+
+  it("should not subscribe to the same feed twice", async () => {
+    const res1 = await subscribe(userToken, "Box", "https://box.com/feed");
+    expect(res1.status).toBe(StatusCodes.CREATED);
+
+    const res2 = await subscribe(userToken, "Box again", "https://box.com/feed");
+    expect(res2.status).toBe(StatusCodes.CONFLICT);
+  });
+
+  it("should only subscribe to web feeds", async () => {
+    const res = await subscribe(userToken, "Box", "ftp://box.com/feed");
+    expect(res.status).toBe(StatusCodes.BAD_REQUEST);
+  });
+});
@@ -4,6 +4,7 @@ import { createUsers, getMongoClient } from "./common";
 import { LoginData, LoginPayload } from "./types/user";
 import {
   ManyMovementsData,
+  MeasurementChangePreviewData,
   MovementData,
   MovementScoreData,
 } from "./types/movement";
@@ -358,6 +359,176 @@ describe("/v1/movements", () => {
       expect(res4.status).toBe(StatusCodes.OK);
       expect(body4).toHaveProperty("name", "Sholder Press");
     });
+
+    it("should be able to keep the name when changing other fields", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ name: "Pistols", measurement: "reps" }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ name: "Pistols", is_public: true }),
+      });
+      const body2: MovementData = await res2.json();
+
+      expect(res2.status).toBe(StatusCodes.OK);
+      expect(body2).toHaveProperty("name", "Pistols");
+      expect(body2).toHaveProperty("is_public", true);
+    });
+
+    it("should convert the scores when changing from time to distance", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ name: "Assault Bike", measurement: "time" }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 60, distance: 1000, distance_unit: "m" }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(
+        `${baseUrl}/movements/${movementId}?preview=true`,
+        {
+          method: "PATCH",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+          body: JSON.stringify({ measurement: "distance" }),
+        }
+      );
+      const body3: MeasurementChangePreviewData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.OK);
+      expect(body3).toEqual({ converted_scores: 1, invalid_scores: [] });
+
+      const res4 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ measurement: "distance" }),
+      });
+      const body4: MovementData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4).toHaveProperty("measurement", "distance");
+      expect(body4.scores[0]).toHaveProperty("score", 1000);
+      expect(body4.scores[0]).toHaveProperty("duration", 60);
+    });
+
+    it("should get 409 Conflict if scores can not be converted", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ name: "Box Jump", measurement: "height" }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 76 }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(
+        `${baseUrl}/movements/${movementId}?preview=true`,
+        {
+          method: "PATCH",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+          body: JSON.stringify({ measurement: "weight" }),
+        }
+      );
+      const body3: MeasurementChangePreviewData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.OK);
+      expect(body3.converted_scores).toBe(0);
+      expect(body3.invalid_scores).toHaveLength(1);
+
+      const res4 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ measurement: "weight" }),
+      });
+
+      expect(res4.status).toBe(StatusCodes.CONFLICT);
+    });
+
+    it("should get 403 Forbidden when changing a movement of another user", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${adminToken}`,
+        },
+        body: JSON.stringify({
+          name: "Ring Dips",
+          measurement: "reps",
+          is_public: true,
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ is_public: false }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.FORBIDDEN);
+    });
   });
 
   describe("deleting movements", () => {
@@ -595,6 +766,181 @@ describe("/v1/movements", () => {
       expect(body4.updated_at).not.toEqual(movementScore.updated_at);
     });
 
+    it("should use the top set as the score when given a list of sets", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          name: "Back Squat",
+          measurement: "weight",
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          set_details: [
+            { reps: 5, load: 100 },
+            { reps: 5, load: 105 },
+            { reps: 5, load: 110 },
+            { reps: 5, load: 115, rpe: 8 },
+            { reps: 3, load: 120, completed: false },
+          ],
+        }),
+      });
+      const body2: MovementScoreData = await res2.json();
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+      expect(body2).toHaveProperty("score", 115);
+      expect(body2).toHaveProperty("sets", 5);
+      expect(body2).toHaveProperty("reps", 5);
+      expect(body2.set_details).toHaveLength(5);
+      expect(body2.set_details[3]).toHaveProperty("rpe", 8);
+      expect(body2.set_details[4]).toHaveProperty("completed", false);
+    });
+
+    it("should convert scores into the unit system of the user", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          name: "Deadlift",
+          measurement: "weight",
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 225, unit: "lb" }),
+      });
+      const body2: MovementScoreData = await res2.json();
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+      expect(body2).toHaveProperty("score", 225);
+      expect(body2).toHaveProperty("unit", "lb");
+
+      const res3 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 100 }),
+      });
+      const body3: MovementScoreData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.CREATED);
+      expect(body3).toHaveProperty("unit", "kg");
+
+      const res4 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body4: MovementData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4.scores[0]).toHaveProperty("score", 102.06);
+      expect(body4.scores[0]).toHaveProperty("unit", "kg");
+      expect(body4.scores[1]).toHaveProperty("score", 100);
+    });
+
+    it("should return the pace of time scores for a set distance", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          name: "2000m Rowing",
+          measurement: "time",
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const movementId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 420, distance: 2000, distance_unit: "m" }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(`${baseUrl}/movements/${movementId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body3: MovementData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.OK);
+      expect(body3.scores[0]).toHaveProperty("distance", 2000);
+      expect(body3.scores[0].pace).toEqual({
+        split_500m: 105,
+        per_distance: 210,
+        distance_unit: "km",
+      });
+    });
+
+    it("should get 400 Bad Request when neither a score nor sets are given", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          name: "Back Squat",
+          measurement: "weight",
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+
+      const res2 = await fetch(`${baseUrl}/movements/${body1.movement_id}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ notes: "forgot the score" }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.BAD_REQUEST);
+    });
+
     it("should delete an existing score", async () => {
       const movement = {
         name: "Deadlift",
@@ -804,4 +1150,107 @@ describe("/v1/movements", () => {
       expect(body2).toHaveProperty("updated_at");
     });
   });
+
+  describe("forking movements", () => {
+    it("should copy a public movement and move the scores over", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${adminToken}`,
+        },
+        body: JSON.stringify({
+          name: "Kettlebell Windmill",
+          measurement: "weight",
+          is_public: true,
+        }),
+      });
+      const body1: MovementData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const originalId = body1.movement_id;
+
+      const res2 = await fetch(`${baseUrl}/movements/${originalId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 24 }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(
+        `${baseUrl}/movements/${originalId}/fork?move_scores=true`,
+        {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+        }
+      );
+      const body3: MovementData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.CREATED);
+      expect(body3).toHaveProperty("name", "Kettlebell Windmill (2)");
+      expect(body3).toHaveProperty("forked_from", originalId);
+      expect(body3).toHaveProperty("measurement", body1.measurement);
+      expect(body3).toHaveProperty("is_public", false);
+      const forkId = body3.movement_id;
+
+      const res4 = await fetch(`${baseUrl}/movements/${forkId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body4: MovementData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4.scores).toHaveLength(1);
+
+      const res5 = await fetch(`${baseUrl}/movements/${originalId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body5: MovementData = await res5.json();
+
+      expect(res5.status).toBe(StatusCodes.OK);
+      expect(body5.scores).toHaveLength(0);
+    });
+
+    it("should get 409 Conflict when forking to a name that already exists", async () => {
+      const res1 = await fetch(`${baseUrl}/movements`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body1 = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.OK);
+      const original: MovementData = body1.data.find(
+        (item: MovementData) => item.name === "Kettlebell Windmill"
+      );
+
+      const res2 = await fetch(
+        `${baseUrl}/movements/${original.movement_id}/fork?name=${encodeURIComponent(
+          "Kettlebell Windmill (2)"
+        )}`,
+        {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+        }
+      );
+
+      expect(res2.status).toBe(StatusCodes.CONFLICT);
+    });
+  });
 });
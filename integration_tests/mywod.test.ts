@@ -3,10 +3,10 @@ import { readFile } from "node:fs/promises";
 import { StatusCodes } from "http-status-codes";
 import { createUsers, getMongoClient } from "./common";
 import users from "./data/users";
-import { LoginPayload, LoginData, UserData } from "./types/user";
-import { ManyWorkoutsData } from "./types/workout";
+import { LoginPayload, LoginData, UserData, UserScores } from "./types/user";
+import { ManyWorkoutsData, WorkoutScoreData } from "./types/workout";
 import { ManyMovementsData } from "./types/movement";
-import { MyWodData } from "./types/mywod";
+import { ImportData, MyWodData, MyWodPreviewData } from "./types/mywod";
 
 const mywodFilePath = `${process.cwd()}/data.mywod`;
 const packageJsonFilePath = `${process.cwd()}/package.json`;
@@ -40,6 +40,36 @@ describe(`${baseUrl}/users/mywod`, () => {
     return token;
   };
 
+  // Uploads a backup and waits for its import job to finish
+  const importBackup = async (formData: FormData, path = "mywod") => {
+    const res = await fetch(`${baseUrl}/users/${path}`, {
+      method: "POST",
+      headers: {
+        Authorization: `Bearer ${userToken}`,
+      },
+      body: formData,
+    });
+    let body: ImportData = await res.json();
+
+    expect(res.status).toBe(StatusCodes.ACCEPTED);
+    expect(body).toHaveProperty("import_id");
+    expect(body).toHaveProperty("status", "pending");
+
+    while (body.status === "pending" || body.status === "running") {
+      await new Promise((resolve) => setTimeout(resolve, 200));
+      const res2 = await fetch(`${baseUrl}/imports/${body.import_id}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      expect(res2.status).toBe(StatusCodes.OK);
+      body = await res2.json();
+    }
+
+    return body;
+  };
+
   beforeAll(async () => {
     mongoClient = await getMongoClient();
     db = mongoClient.db();
@@ -61,6 +91,7 @@ describe(`${baseUrl}/users/mywod`, () => {
     await db.collection("workoutscores").deleteMany({});
     await db.collection("movements").deleteMany({});
     await db.collection("movementscores").deleteMany({});
+    await db.collection("feeds").deleteMany({});
   });
 
   afterAll(async () => {
@@ -68,7 +99,70 @@ describe(`${baseUrl}/users/mywod`, () => {
   });
 
   describe("/mywod", () => {
-    jest.setTimeout(10000);
+    jest.setTimeout(30000);
+
+    it("should only preview the migration on a dry run", async () => {
+      const res1 = await fetch(`${baseUrl}/users/me`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const userBefore: UserData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.OK);
+
+      const formData = new FormData();
+      formData.append("file", new Blob([await readFile(mywodFilePath)]));
+
+      const res2 = await fetch(`${baseUrl}/users/mywod?dry_run=true`, {
+        method: "POST",
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: formData,
+      });
+      const body2: MyWodPreviewData = await res2.json();
+
+      expect(res2.status).toBe(StatusCodes.OK);
+      expect(body2.athlete.first_name).not.toEqual(userBefore.first_name);
+      expect(body2.athlete.has_avatar).toBe(true);
+      expect(body2.athlete.gender).toBe("male");
+      // The only feed in the backup was deleted by the athlete
+      expect(body2.feeds.added).toBe(0);
+      expect(body2.workouts.added).toBeGreaterThan(50);
+      expect(body2.workout_scores.added).toBeGreaterThan(100);
+      expect(body2.movements.added).toBeGreaterThan(15);
+      expect(body2.movement_scores.added).toBeGreaterThan(50);
+      expect(body2.new_movements).toContain("1000m Rowing");
+      expect(body2.new_workouts.length).toBe(body2.workouts.added);
+
+      // Nothing should have been saved
+      const res3 = await fetch(`${baseUrl}/users/me`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const userAfter: UserData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.OK);
+      expect(userAfter).toEqual(userBefore);
+
+      const res4 = await fetch(`${baseUrl}/workouts`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body4: ManyWorkoutsData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4.data).toHaveLength(0);
+    });
 
     it("should migrate data from a mywod backup to the user", async () => {
       // GET DATA ABOUT USER OWNED STUFF FOR REFERENCE
@@ -129,20 +223,19 @@ describe(`${baseUrl}/users/mywod`, () => {
       const formData = new FormData();
       formData.append("file", new Blob([await readFile(mywodFilePath)]));
 
-      const res6 = await fetch(`${baseUrl}/users/mywod`, {
-        method: "POST",
-        headers: {
-          Authorization: `Bearer ${userToken}`,
-        },
-        body: formData,
-      });
-      const body6: MyWodData = await res6.json();
+      const import6 = await importBackup(formData);
 
-      expect(res6.status).toEqual(StatusCodes.OK);
+      expect(import6.status).toBe("completed");
+      expect(import6.progress.processed).toBe(import6.progress.total);
+      const body6 = import6.result as MyWodData;
       expect(body6.added_workouts).toBeGreaterThan(50);
       expect(body6.added_workout_scores).toBeGreaterThan(100);
       expect(body6.added_movements).toBeGreaterThan(15);
       expect(body6.added_movement_scores).toBeGreaterThan(50);
+      for (const row of import6.report) {
+        expect(["skipped", "altered", "failed"]).toContain(row.outcome);
+        expect(row.original).toBeDefined();
+      }
 
       // Verify that things got updated
       const res7 = await fetch(`${baseUrl}/users/me`, {
@@ -163,6 +256,7 @@ describe(`${baseUrl}/users/mywod`, () => {
       expect(userAfter.height).not.toEqual(user.height);
       expect(userAfter.weight).not.toEqual(user.weight);
       expect(userAfter.avatar_url).not.toEqual(user.avatar_url);
+      expect(userAfter.gender).toBe("male");
 
       const res8 = await fetch(`${baseUrl}/workouts`, {
         method: "GET",
@@ -210,13 +304,44 @@ describe(`${baseUrl}/users/mywod`, () => {
       expect(longRow?.measurement).toEqual("time");
 
       // TODO: Check movement scores
+
+      // Importing the same backup again should not add anything
+      const import11 = await importBackup(formData);
+
+      expect(import11.status).toBe("completed");
+      const body11 = import11.result as MyWodData;
+      expect(body11.added_workouts).toBe(0);
+      expect(body11.added_workout_scores).toBe(0);
+      expect(body11.added_movements).toBe(0);
+      expect(body11.added_movement_scores).toBe(0);
+      expect(body11.skipped_workouts).toBeGreaterThan(50);
+      expect(body11.skipped_workout_scores).toBe(
+        body6.added_workout_scores + body6.skipped_workout_scores
+      );
+      expect(body11.skipped_movements).toBe(
+        body6.added_movements + body6.skipped_movements
+      );
+      expect(body11.skipped_movement_scores).toBe(
+        body6.added_movement_scores + body6.skipped_movement_scores
+      );
+
+      const res12 = await fetch(`${baseUrl}/workouts`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body12: ManyWorkoutsData = await res12.json();
+
+      expect(res12.status).toBe(StatusCodes.OK);
+      expect(body12.data.length).toEqual(workoutsAfter.length);
     });
 
-    it("should get a 500 error if file is not valid", async () => {
+    it("should get 415 Unsupported Media Type if file is not a backup", async () => {
       const formData = new FormData();
       formData.append("file", new Blob([await readFile(packageJsonFilePath)]));
 
-      // Submit the mywod file for migration
       const res1 = await fetch(`${baseUrl}/users/mywod`, {
         method: "POST",
         headers: {
@@ -224,8 +349,329 @@ describe(`${baseUrl}/users/mywod`, () => {
         },
         body: formData,
       });
+      const body1 = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.UNSUPPORTED_MEDIA_TYPE);
+      expect(body1).toHaveProperty("message");
+    });
+
+    it("should get 422 Unprocessable Entity if backup is incomplete", async () => {
+      // Only the start of the backup, which has the header but no tables
+      const backup = await readFile(mywodFilePath);
+      const formData = new FormData();
+      formData.append("file", new Blob([backup.subarray(0, 100)]));
+
+      const res1 = await fetch(`${baseUrl}/users/mywod`, {
+        method: "POST",
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: formData,
+      });
+      const body1 = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.UNPROCESSABLE_ENTITY);
+      expect(body1).toHaveProperty("message");
+    });
+
+    it("should get 400 Bad Request if file is in another field", async () => {
+      const formData = new FormData();
+      formData.append("backup", new Blob([await readFile(mywodFilePath)]));
+
+      const res1 = await fetch(`${baseUrl}/users/mywod`, {
+        method: "POST",
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: formData,
+      });
+
+      expect(res1.status).toBe(StatusCodes.BAD_REQUEST);
+    });
+
+    it("should get 404 Not Found for an import that does not exist", async () => {
+      const res1 = await fetch(`${baseUrl}/imports/does-not-exist`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+
+      expect(res1.status).toBe(StatusCodes.NOT_FOUND);
+    });
+  });
+
+  describe("/me/mywod", () => {
+    jest.setTimeout(30000);
+
+    const getScores = async () => {
+      const res = await fetch(`${baseUrl}/users/me/scores`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      expect(res.status).toBe(StatusCodes.OK);
+      const body: UserScores = await res.json();
+      return body;
+    };
+
+    it("should export data that imports to the same data", async () => {
+      const formData1 = new FormData();
+      formData1.append("file", new Blob([await readFile(mywodFilePath)]));
+      const import1 = await importBackup(formData1);
+      expect(import1.status).toBe("completed");
+      const scoresBefore = await getScores();
+
+      const res2 = await fetch(`${baseUrl}/users/me/mywod`, {
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      expect(res2.status).toBe(StatusCodes.OK);
+      expect(res2.headers.get("content-type")).toBe("application/x-sqlite3");
+      const backup = await res2.blob();
+
+      // Start over and import the export
+      await db.collection("workouts").deleteMany({});
+      await db.collection("workoutscores").deleteMany({});
+      await db.collection("movements").deleteMany({});
+      await db.collection("movementscores").deleteMany({});
+
+      const formData3 = new FormData();
+      formData3.append("file", backup);
+      const import3 = await importBackup(formData3);
+      expect(import3.status).toBe("completed");
+      const scoresAfter = await getScores();
+
+      const summary = ({ score, notes, created_at }: WorkoutScoreData) =>
+        `${created_at} ${score} ${notes}`;
+      expect(scoresAfter.workout_scores.map(summary).sort()).toEqual(
+        scoresBefore.workout_scores.map(summary).sort()
+      );
+      expect(scoresAfter.movement_scores.length).toEqual(
+        scoresBefore.movement_scores.length
+      );
+
+      // Importing the export on top of the same data changes nothing
+      const formData4 = new FormData();
+      formData4.append("file", backup);
+      const import4 = await importBackup(formData4);
+      const body4 = import4.result as MyWodData;
+      expect(body4.added_workouts).toBe(0);
+      expect(body4.added_workout_scores).toBe(0);
+      expect(body4.added_movements).toBe(0);
+      expect(body4.added_movement_scores).toBe(0);
+    });
+  });
+
+  describe("/import/{source}", () => {
+    jest.setTimeout(30000);
+
+    const strongExport = [
+      "Date,Workout Name,Duration,Exercise Name,Set Order,Weight,Reps,Distance,Seconds,Notes,Workout Notes,RPE",
+      "2022-01-10 18:00:00,Evening,1h,Bench Press (Barbell),1,60,10,0,0,,,",
+      "2022-01-10 18:00:00,Evening,1h,Bench Press (Barbell),2,80,5,0,0,paused,,8",
+      "2022-01-10 18:00:00,Evening,1h,Pull Up,1,0,12,0,0,,,",
+    ].join("\n");
+
+    it("should not import from an unknown app", async () => {
+      const formData = new FormData();
+      formData.append("file", new Blob([strongExport]));
+
+      const res = await fetch(`${baseUrl}/users/import/fitbod`, {
+        method: "POST",
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: formData,
+      });
+
+      expect(res.status).toBe(StatusCodes.BAD_REQUEST);
+    });
+
+    it("should import a Strong export once", async () => {
+      const formData = new FormData();
+      formData.append("file", new Blob([strongExport]));
+      const import1 = await importBackup(formData, "import/strong");
+
+      expect(import1.source).toBe("strong");
+      expect(import1.status).toBe("completed");
+      expect(import1.progress.processed).toBe(import1.progress.total);
+      const body1 = import1.result as MyWodData;
+      expect(body1.user_updated).toBe(false);
+      expect(body1.added_movements).toBe(2);
+      expect(body1.added_movement_scores).toBe(2);
+
+      const res = await fetch(`${baseUrl}/movements`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const movements: ManyMovementsData = await res.json();
+      const bench = movements.data.find(
+        ({ name }) => name === "Bench Press (Barbell)"
+      );
+      expect(bench).toHaveProperty("measurement", "weight");
+
+      const formData2 = new FormData();
+      formData2.append("file", new Blob([strongExport]));
+      const import2 = await importBackup(formData2, "import/strong");
+      const body2 = import2.result as MyWodData;
+      expect(body2.added_movements).toBe(0);
+      expect(body2.added_movement_scores).toBe(0);
+      expect(body2.skipped_movement_scores).toBe(2);
+    });
 
-      expect(res1.status).toBe(StatusCodes.INTERNAL_SERVER_ERROR);
+    it("should report results that can not be parsed", async () => {
+      const sugarwodExport = [
+        "date,title,description,best_result_raw,best_result_display,score_type,barbell_lift,set_details,notes,rx_or_scaled,pr",
+        "03/14/2022,Fran,21-15-9,245,4:05,Time,,,,RX,PR",
+        "03/16/2022,Grace,,,DNF,Time,,,,RX,",
+      ].join("\n");
+      const formData = new FormData();
+      formData.append("file", new Blob([sugarwodExport]));
+      const import1 = await importBackup(formData, "import/sugarwod");
+
+      expect(import1.status).toBe("completed");
+      const body1 = import1.result as MyWodData;
+      expect(body1.added_workouts).toBe(2);
+      expect(body1.added_workout_scores).toBe(2);
+      expect(import1.report).toHaveLength(1);
+      expect(import1.report[0]).toHaveProperty("table", "sugarwod");
+      expect(import1.report[0]).toHaveProperty("outcome", "altered");
+    });
+  });
+
+  describe("/imports/{id}", () => {
+    jest.setTimeout(30000);
+
+    const getUser = async () => {
+      const res = await fetch(`${baseUrl}/users/me`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      expect(res.status).toBe(StatusCodes.OK);
+      const user: UserData = await res.json();
+      return user;
+    };
+
+    const rollback = (importId: string) =>
+      fetch(`${baseUrl}/imports/${importId}`, {
+        method: "DELETE",
+        headers: {
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+
+    it("should remove what an import added and restore the profile", async () => {
+      const res1 = await fetch(`${baseUrl}/users/me`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ first_name: "Before", box_name: "Before box" }),
+      });
+      expect(res1.status).toBe(StatusCodes.OK);
+      const userBefore = await getUser();
+
+      const formData = new FormData();
+      formData.append("file", new Blob([await readFile(mywodFilePath)]));
+      const import1 = await importBackup(formData);
+      expect(import1.status).toBe("completed");
+      const body1 = import1.result as MyWodData;
+      expect((await getUser()).first_name).not.toBe("Before");
+
+      const res2 = await rollback(import1.import_id);
+      expect(res2.status).toBe(StatusCodes.OK);
+      const rolledBack: ImportData = await res2.json();
+
+      expect(rolledBack.status).toBe("rolled_back");
+      expect(rolledBack.rollback?.removed_workouts).toBe(body1.added_workouts);
+      expect(rolledBack.rollback?.removed_workout_scores).toBe(
+        body1.added_workout_scores
+      );
+      expect(rolledBack.rollback?.removed_movements).toBe(
+        body1.added_movements
+      );
+      expect(rolledBack.rollback?.removed_movement_scores).toBe(
+        body1.added_movement_scores
+      );
+      expect(rolledBack.rollback?.removed_feeds).toBe(body1.added_feeds);
+      expect(rolledBack.rollback?.profile_restored).toBe(true);
+
+      const userAfter = await getUser();
+      expect(userAfter.first_name).toBe(userBefore.first_name);
+      expect(userAfter.box_name).toBe(userBefore.box_name);
+      expect(userAfter.avatar_url).toBe(userBefore.avatar_url);
+
+      expect(await db.collection("workoutscores").countDocuments()).toBe(0);
+      expect(await db.collection("movementscores").countDocuments()).toBe(0);
+      expect(await db.collection("feeds").countDocuments()).toBe(0);
+
+      // An import can only be rolled back once
+      const res3 = await rollback(import1.import_id);
+      expect(res3.status).toBe(StatusCodes.CONFLICT);
+    });
+
+    it("should keep a profile the user changed after the import", async () => {
+      const formData = new FormData();
+      formData.append("file", new Blob([await readFile(mywodFilePath)]));
+      const import1 = await importBackup(formData);
+      expect(import1.status).toBe("completed");
+
+      const res1 = await fetch(`${baseUrl}/users/me`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ first_name: "After" }),
+      });
+      expect(res1.status).toBe(StatusCodes.OK);
+
+      const res2 = await rollback(import1.import_id);
+      const rolledBack: ImportData = await res2.json();
+      expect(rolledBack.rollback?.profile_restored).toBe(false);
+      expect(rolledBack.rollback?.profile_conflict).toBe(true);
+      expect((await getUser()).first_name).toBe("After");
+    });
+
+    it("should keep workouts the user logged other scores for", async () => {
+      const formData = new FormData();
+      formData.append(
+        "file",
+        new Blob([
+          [
+            "date,title,description,best_result_raw,best_result_display,score_type,barbell_lift,set_details,notes,rx_or_scaled,pr",
+            "03/14/2022,Fran,21-15-9,245,4:05,Time,,,,RX,PR",
+          ].join("\n"),
+        ])
+      );
+      const import1 = await importBackup(formData, "import/sugarwod");
+      expect(import1.status).toBe("completed");
+
+      const workout = await db.collection("workouts").findOne({ name: "Fran" });
+      const res1 = await fetch(`${baseUrl}/workouts/${workout?.workout_id}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 230 }),
+      });
+      expect(res1.status).toBe(StatusCodes.CREATED);
+
+      const res2 = await rollback(import1.import_id);
+      const rolledBack: ImportData = await res2.json();
+      expect(rolledBack.rollback?.removed_workouts).toBe(0);
+      expect(rolledBack.rollback?.kept_workouts).toBe(1);
+      expect(rolledBack.rollback?.removed_workout_scores).toBe(1);
+      expect(rolledBack.rollback?.profile_restored).toBe(false);
     });
   });
 });
@@ -0,0 +1,11 @@
+export type FeedData = {
+  feed_id: string;
+  title: string;
+  url: string;
+  created_at: string;
+  updated_at: string;
+};
+
+export type ManyFeedsData = {
+  data: FeedData[];
+};
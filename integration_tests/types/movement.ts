@@ -1,6 +1,7 @@
 export type MovementData = {
   movement_id: string;
   user_id: string;
+  forked_from?: string;
   name: string;
   measurement: string;
   is_public: boolean;
@@ -17,9 +18,40 @@ export type MovementScoreData = {
   movement_id: string;
   movement_score_id: string;
   score: number;
+  unit?: string;
   reps: number;
   sets: number;
+  set_details: MovementSetData[];
+  distance?: number;
+  distance_unit?: string;
+  duration?: number;
+  pace?: PaceData;
   notes: string;
   created_at: string;
   updated_at: string;
 };
+
+export type MovementSetData = {
+  reps: number;
+  load: number;
+  rpe?: number;
+  completed: boolean;
+};
+
+export type PaceData = {
+  split_500m: number;
+  per_distance: number;
+  distance_unit: string;
+};
+
+export type InvalidScoreData = {
+  score_id: string;
+  user_id: string;
+  score: number;
+  reason: string;
+};
+
+export type MeasurementChangePreviewData = {
+  converted_scores: number;
+  invalid_scores: InvalidScoreData[];
+};
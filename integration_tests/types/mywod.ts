@@ -1,7 +1,80 @@
 export type MyWodData = {
   user_updated: boolean;
   added_workouts: number;
+  updated_workouts: number;
+  skipped_workouts: number;
   added_workout_scores: number;
+  updated_workout_scores: number;
+  skipped_workout_scores: number;
   added_movements: number;
+  skipped_movements: number;
   added_movement_scores: number;
+  updated_movement_scores: number;
+  skipped_movement_scores: number;
+  added_feeds: number;
+  skipped_feeds: number;
+};
+
+export type ImportCountData = {
+  added: number;
+  updated: number;
+  skipped: number;
+};
+
+export type RowReportData = {
+  table: string;
+  source_id: string;
+  outcome: "skipped" | "altered" | "failed";
+  reason: string;
+  original: Record<string, string>;
+};
+
+export type MyWodPreviewData = {
+  athlete: {
+    first_name: string;
+    last_name: string;
+    date_of_birth: string;
+    height: number;
+    weight: number;
+    box_name: string;
+    unit_system: string;
+    gender?: "female" | "male";
+    has_avatar: boolean;
+  };
+  workouts: ImportCountData;
+  workout_scores: ImportCountData;
+  movements: ImportCountData;
+  movement_scores: ImportCountData;
+  feeds: ImportCountData;
+  new_workouts: string[];
+  new_movements: string[];
+  report: RowReportData[];
+};
+
+export type ImportData = {
+  import_id: string;
+  source: string;
+  status: "pending" | "running" | "completed" | "failed" | "rolled_back";
+  progress: {
+    processed: number;
+    total: number;
+  };
+  result?: MyWodData;
+  report: RowReportData[];
+  error?: string;
+  rollback?: RollbackData;
+  created_at: string;
+  updated_at: string;
+};
+
+export type RollbackData = {
+  removed_workouts: number;
+  kept_workouts: number;
+  removed_workout_scores: number;
+  removed_movements: number;
+  kept_movements: number;
+  removed_movement_scores: number;
+  removed_feeds: number;
+  profile_restored: boolean;
+  profile_conflict: boolean;
 };
@@ -22,6 +22,14 @@ export type UserData = {
   weight: number;
   box_name: string;
   avatar_url: string;
+  avatar_privacy: "public" | "users" | "private";
+  unit_system: string;
+  gender?: "female" | "male";
+};
+
+export type SignedAvatarData = {
+  avatar_url: string;
+  expires_at: string;
 };
 
 export type UserScores = {
@@ -1,6 +1,7 @@
 export type WorkoutData = {
   workout_id: string;
   user_id: string;
+  forked_from?: string;
   name: string;
   description: string;
   measurement: string;
@@ -18,8 +19,10 @@ export type WorkoutScoreData = {
   workout_id: string;
   workout_score_id: string;
   score: number;
+  unit?: string;
   rx: boolean;
   notes: string;
+  personal_record: boolean;
   created_at: string;
   updated_at: string;
 };
@@ -1,14 +1,20 @@
 import { MongoClient } from "mongodb";
 import { StatusCodes } from "http-status-codes";
 import users from "./data/users";
-import { LoginData, UserData, UserScores } from "./types/user";
+import {
+  LoginData,
+  SignedAvatarData,
+  UserData,
+  UserScores,
+} from "./types/user";
 import { MovementData } from "./types/movement";
 import { WorkoutData } from "./types/workout";
 
 const MONGO_URI =
   process.env.MONGO_URI || "mongodb://localhost:27017/wodbook-test";
 
-const baseUrl = `${process.env.API_URL || "http://127.0.0.1:43210"}/v1`;
+const serverUrl = process.env.API_URL || "http://127.0.0.1:43210";
+const baseUrl = `${serverUrl}/v1`;
 
 describe("/users", () => {
   let mongoClient: MongoClient;
@@ -342,6 +348,135 @@ describe("/users", () => {
     });
   });
 
+  describe("/me/avatar", () => {
+    // A 1x1 pixel PNG image
+    const pngAvatar = Buffer.from(
+      "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==",
+      "base64"
+    );
+
+    const uploadAvatar = async (token: string, avatar: Blob) => {
+      const formData = new FormData();
+      formData.append("file", avatar, "avatar.png");
+      return fetch(`${baseUrl}/users/me/avatar`, {
+        method: "PUT",
+        headers: {
+          Authorization: `Bearer ${token}`,
+        },
+        body: formData,
+      });
+    };
+
+    it("should set and remove the avatar", async () => {
+      const login_res = await fetch(`${baseUrl}/users/login`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify({
+          email: "user@wodbook.com",
+          password: "user",
+        }),
+      });
+      const login_body: LoginData = await login_res.json();
+      expect(login_res.status).toBe(StatusCodes.OK);
+      const { token } = login_body;
+
+      const res1 = await uploadAvatar(token, new Blob([pngAvatar]));
+      const body1: UserData = await res1.json();
+      expect(res1.status).toBe(StatusCodes.OK);
+      expect(body1.avatar_url).toMatch(/^\/avatars\/[0-9a-f]{32}\.png$/);
+      expect(body1.avatar_url).not.toContain(body1.user_id);
+
+      // The same image keeps the same path
+      const res2 = await uploadAvatar(token, new Blob([pngAvatar]));
+      const body2: UserData = await res2.json();
+      expect(res2.status).toBe(StatusCodes.OK);
+      expect(body2.avatar_url).toEqual(body1.avatar_url);
+
+      const res3 = await uploadAvatar(token, new Blob(["not an image"]));
+      expect(res3.status).toBe(StatusCodes.UNSUPPORTED_MEDIA_TYPE);
+
+      // Public avatars are served to anyone
+      const thumbnailUrl = body1.avatar_url.replace(".png", "-64.png");
+      const avatar_res1 = await fetch(`${serverUrl}${thumbnailUrl}`);
+      expect(avatar_res1.status).toBe(StatusCodes.OK);
+      expect(avatar_res1.headers.get("content-type")).toBe("image/png");
+      expect(avatar_res1.headers.get("cache-control")).toContain("immutable");
+
+      const avatar_res2 = await fetch(`${serverUrl}/avatars/`);
+      expect(avatar_res2.status).toBe(StatusCodes.NOT_FOUND);
+
+      const private_res = await fetch(`${baseUrl}/users/me`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+        body: JSON.stringify({ avatar_privacy: "private" }),
+      });
+      const private_body: UserData = await private_res.json();
+      expect(private_res.status).toBe(StatusCodes.OK);
+      expect(private_body.avatar_privacy).toBe("private");
+
+      // Private avatars need the user or a signed link
+      const avatar_res3 = await fetch(`${serverUrl}${body1.avatar_url}`);
+      expect(avatar_res3.status).toBe(StatusCodes.UNAUTHORIZED);
+
+      const avatar_res4 = await fetch(`${serverUrl}${body1.avatar_url}`, {
+        headers: {
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      expect(avatar_res4.status).toBe(StatusCodes.OK);
+
+      const signed_res = await fetch(`${baseUrl}/users/me/avatar`, {
+        method: "GET",
+        headers: {
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      const signed_body: SignedAvatarData = await signed_res.json();
+      expect(signed_res.status).toBe(StatusCodes.OK);
+
+      const avatar_res5 = await fetch(`${serverUrl}${signed_body.avatar_url}`);
+      expect(avatar_res5.status).toBe(StatusCodes.OK);
+      expect(avatar_res5.headers.get("cache-control")).toContain("private");
+
+      const avatar_res6 = await fetch(
+        `${serverUrl}${signed_body.avatar_url.replace("signature=", "signature=x")}`
+      );
+      expect(avatar_res6.status).toBe(StatusCodes.FORBIDDEN);
+
+      const res4 = await fetch(`${baseUrl}/users/me/avatar`, {
+        method: "DELETE",
+        headers: {
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      expect(res4.status).toBe(StatusCodes.NO_CONTENT);
+
+      const res5 = await fetch(`${baseUrl}/users/me`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      const body5: UserData = await res5.json();
+      expect(res5.status).toBe(StatusCodes.OK);
+      expect(body5.avatar_url).toEqual("");
+
+      const res6 = await fetch(`${baseUrl}/users/me/avatar`, {
+        method: "DELETE",
+        headers: {
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      expect(res6.status).toBe(StatusCodes.NOT_FOUND);
+    });
+  });
+
   describe("/me/scores", () => {
     it("should return all scores for the user", async () => {
       const movement = {
@@ -479,5 +614,100 @@ describe("/users", () => {
       expect(score_body2.movement_scores.length).toEqual(1);
       expect(score_body2.workout_scores.length).toEqual(2);
     });
+
+    it("should log several scores at once", async () => {
+      const login_res = await fetch(`${baseUrl}/users/login`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify({
+          email: "user@wodbook.com",
+          password: "user",
+        }),
+      });
+
+      const login_body: LoginData = await login_res.json();
+      expect(login_res.status).toBe(StatusCodes.OK);
+      const { token } = login_body;
+
+      const movement_res = await fetch(`${baseUrl}/movements`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+        body: JSON.stringify({ name: "Deadlift", measurement: "weight" }),
+      });
+      const movement_body: MovementData = await movement_res.json();
+      expect(movement_res.status).toBe(StatusCodes.CREATED);
+
+      const workout_res = await fetch(`${baseUrl}/workouts`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+        body: JSON.stringify({
+          name: "Fran",
+          measurement: "time",
+          description: "21-15-9 Thruster / Pull-up",
+        }),
+      });
+      const workout_body: WorkoutData = await workout_res.json();
+      expect(workout_res.status).toBe(StatusCodes.CREATED);
+
+      const bulk_res = await fetch(`${baseUrl}/users/me/scores`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+        body: JSON.stringify({
+          workout_scores: [
+            { workout_id: workout_body.workout_id, score: 260, rx: true },
+            { workout_id: workout_body.workout_id, score: 245, rx: true },
+          ],
+          movement_scores: [
+            { movement_id: movement_body.movement_id, score: 200 },
+            { movement_id: movement_body.movement_id, score: 205 },
+            { movement_id: movement_body.movement_id, score: 210 },
+          ],
+        }),
+      });
+
+      const bulk_body: UserScores = await bulk_res.json();
+      expect(bulk_res.status).toBe(StatusCodes.CREATED);
+      expect(bulk_body.workout_scores.length).toEqual(2);
+      expect(bulk_body.movement_scores.length).toEqual(3);
+
+      // Nothing is logged when a workout does not exist
+      const missing_res = await fetch(`${baseUrl}/users/me/scores`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+        body: JSON.stringify({
+          workout_scores: [{ workout_id: "000000000000000000000000", score: 1 }],
+          movement_scores: [
+            { movement_id: movement_body.movement_id, score: 220 },
+          ],
+        }),
+      });
+      expect(missing_res.status).toBe(StatusCodes.NOT_FOUND);
+
+      const score_res = await fetch(`${baseUrl}/users/me/scores`, {
+        method: "GET",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
+        },
+      });
+      const score_body: UserScores = await score_res.json();
+      expect(score_res.status).toBe(StatusCodes.OK);
+      expect(score_body.workout_scores.length).toEqual(2);
+      expect(score_body.movement_scores.length).toEqual(3);
+    });
   });
 });
@@ -379,6 +379,63 @@ describe("/v1/workouts", () => {
       expect(res4.status).toBe(StatusCodes.OK);
       expect(body4).toHaveProperty("name", "Fran");
     });
+
+    it("should be able to change the measurement when the scores fit", async () => {
+      const res1 = await fetch(`${baseUrl}/workouts`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({
+          name: "Cindy",
+          description: "20 min AMRAP of 5 pull ups, 10 push ups, 15 squats",
+          measurement: "repetitions",
+        }),
+      });
+      const body1: WorkoutData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const workoutId = body1.workout_id;
+
+      const res2 = await fetch(`${baseUrl}/workouts/${workoutId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 20, rx: true }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(`${baseUrl}/workouts/${workoutId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ measurement: "time" }),
+      });
+
+      expect(res3.status).toBe(StatusCodes.CONFLICT);
+
+      const res4 = await fetch(`${baseUrl}/workouts/${workoutId}`, {
+        method: "PATCH",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ measurement: "rounds", is_public: true }),
+      });
+      const body4: WorkoutData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4).toHaveProperty("name", "Cindy");
+      expect(body4).toHaveProperty("measurement", "rounds");
+      expect(body4).toHaveProperty("is_public", true);
+      expect(body4.scores[0]).toHaveProperty("score", 20);
+    });
   });
 
   describe("deleting workouts", () => {
@@ -988,4 +1045,108 @@ describe("/v1/workouts", () => {
       expect(body2).toHaveProperty("updated_at");
     });
   });
+
+  describe("forking workouts", () => {
+    it("should copy a public workout and move the scores over", async () => {
+      const res1 = await fetch(`${baseUrl}/workouts`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${adminToken}`,
+        },
+        body: JSON.stringify({
+          name: "Hero Chipper",
+          description: "For time: 100 burpees, 100 wall balls",
+          measurement: "time",
+          is_public: true,
+        }),
+      });
+      const body1: WorkoutData = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.CREATED);
+      const originalId = body1.workout_id;
+
+      const res2 = await fetch(`${baseUrl}/workouts/${originalId}`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+        body: JSON.stringify({ score: 180, rx: true }),
+      });
+
+      expect(res2.status).toBe(StatusCodes.CREATED);
+
+      const res3 = await fetch(
+        `${baseUrl}/workouts/${originalId}/fork?move_scores=true`,
+        {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+        }
+      );
+      const body3: WorkoutData = await res3.json();
+
+      expect(res3.status).toBe(StatusCodes.CREATED);
+      expect(body3).toHaveProperty("name", "Hero Chipper (2)");
+      expect(body3).toHaveProperty("forked_from", originalId);
+      expect(body3).toHaveProperty("measurement", body1.measurement);
+      expect(body3).toHaveProperty("is_public", false);
+      const forkId = body3.workout_id;
+
+      const res4 = await fetch(`${baseUrl}/workouts/${forkId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body4: WorkoutData = await res4.json();
+
+      expect(res4.status).toBe(StatusCodes.OK);
+      expect(body4.scores).toHaveLength(1);
+
+      const res5 = await fetch(`${baseUrl}/workouts/${originalId}`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body5: WorkoutData = await res5.json();
+
+      expect(res5.status).toBe(StatusCodes.OK);
+      expect(body5.scores).toHaveLength(0);
+    });
+
+    it("should get 409 Conflict when forking to a name that already exists", async () => {
+      const res1 = await fetch(`${baseUrl}/workouts`, {
+        headers: {
+          "Content-Type": "application/json",
+          Authorization: `Bearer ${userToken}`,
+        },
+      });
+      const body1 = await res1.json();
+
+      expect(res1.status).toBe(StatusCodes.OK);
+      const original: WorkoutData = body1.data.find(
+        (item: WorkoutData) => item.name === "Hero Chipper"
+      );
+
+      const res2 = await fetch(
+        `${baseUrl}/workouts/${original.workout_id}/fork?name=${encodeURIComponent(
+          "Hero Chipper (2)"
+        )}`,
+        {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${userToken}`,
+          },
+        }
+      );
+
+      expect(res2.status).toBe(StatusCodes.CONFLICT);
+    });
+  });
 });
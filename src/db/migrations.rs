@@ -0,0 +1,652 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::movement::MovementMeasurement;
+use crate::models::unit::parse_distance;
+use bson::{DateTime, Document};
+use futures::stream::StreamExt;
+use mongodb::error::ErrorKind;
+use mongodb::options::UpdateOptions;
+use mongodb::{Collection, Database};
+use std::time::Duration;
+
+static COLLECTION_NAME: &str = "_migrations";
+static LOCK_ID: &str = "lock";
+/// A lock this old was left behind by an instance that stopped while migrating
+const LOCK_TIMEOUT_SECONDS: i64 = 10 * 60;
+
+/// What a migration changes.
+enum Step {
+    /// Database commands, e.g. `createIndexes`, run one after the other
+    Commands(fn() -> Vec<Document>),
+    /// See `add_distances_to_time_scores`
+    TimeMovementDistances,
+    /// See `add_units_to_scores`
+    ScoreUnits,
+    /// See `classify_time_movements`
+    MonostructuralMeasurements,
+    /// See `index_names_by_user`
+    UniqueNames,
+}
+
+/// A change to the collections that is applied once. Applied migrations are
+/// recorded in the `_migrations` collection by their version.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    step: Step,
+}
+
+/// All migrations, oldest first. Released migrations are never changed,
+/// changes are made by adding a migration with the next version.
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "create_indexes",
+            step: Step::Commands(v1_indexes),
+        },
+        Migration {
+            version: 2,
+            name: "add_distances_to_time_scores",
+            step: Step::TimeMovementDistances,
+        },
+        Migration {
+            version: 3,
+            name: "index_scores_by_user",
+            step: Step::Commands(score_indexes),
+        },
+        Migration {
+            version: 4,
+            name: "add_units_to_scores",
+            step: Step::ScoreUnits,
+        },
+        Migration {
+            version: 5,
+            name: "classify_time_movements",
+            step: Step::MonostructuralMeasurements,
+        },
+        Migration {
+            version: 6,
+            name: "index_names_by_user",
+            step: Step::UniqueNames,
+        },
+        Migration {
+            version: 7,
+            name: "index_imports_feeds_and_avatars",
+            step: Step::Commands(import_feed_avatar_indexes),
+        },
+    ]
+}
+
+impl Migration {
+    /// Returns how many documents were changed.
+    async fn apply(&self, db: &Database) -> WebResult<u64> {
+        match self.step {
+            Step::Commands(commands) => {
+                for command in commands() {
+                    db.run_command(command, None).await?;
+                }
+                Ok(0)
+            }
+            Step::TimeMovementDistances => add_distances_to_time_scores(db).await,
+            Step::ScoreUnits => add_units_to_scores(db).await,
+            Step::MonostructuralMeasurements => classify_time_movements(db).await,
+            Step::UniqueNames => index_names_by_user(db).await,
+        }
+    }
+}
+
+/// The migrations that have not been applied to the database yet.
+pub async fn pending_migrations(db: &Database) -> WebResult<Vec<Migration>> {
+    let applied = applied_versions(&db.collection(COLLECTION_NAME)).await?;
+
+    Ok(migrations()
+        .into_iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
+/// Applies the pending migrations in order and returns their names. Only one
+/// instance migrates at a time, the others wait until it is done. A failed
+/// migration stops the ones after it, which run again the next time.
+pub async fn run_migrations(db: &Database) -> WebResult<Vec<&'static str>> {
+    let collection: Collection<Document> = db.collection(COLLECTION_NAME);
+    let owner = uuid::Uuid::new_v4().to_string();
+
+    acquire_lock(&collection, &owner).await?;
+    let renewal = actix_web::rt::spawn(renew_lock(collection.clone(), owner.clone()));
+    let result = apply_pending(db, &collection).await;
+    renewal.abort();
+
+    // The migrations are done either way, a lock that is left behind runs out
+    if let Err(e) = release_lock(&collection, &owner).await {
+        error!("Could not release the migration lock: {}", e);
+    }
+
+    result
+}
+
+async fn apply_pending(
+    db: &Database,
+    collection: &Collection<Document>,
+) -> WebResult<Vec<&'static str>> {
+    let mut applied = Vec::new();
+
+    // Checked again while holding the lock, another instance may have
+    // applied them in the meantime
+    for migration in pending_migrations(db).await? {
+        info!("Running migration {} {}", migration.version, migration.name);
+        let changed = migration.apply(db).await.map_err(|e| {
+            AppError::Internal(format!(
+                "Migration {} {} failed: {}",
+                migration.version, migration.name, e
+            ))
+        })?;
+
+        collection
+            .insert_one(
+                doc! {
+                    "_id": migration.version,
+                    "version": migration.version,
+                    "name": migration.name,
+                    "changed": changed as i64,
+                    "applied_at": DateTime::now(),
+                },
+                None,
+            )
+            .await?;
+        applied.push(migration.name);
+    }
+
+    Ok(applied)
+}
+
+async fn applied_versions(collection: &Collection<Document>) -> WebResult<Vec<i32>> {
+    let mut cursor = collection
+        .find(doc! { "version": { "$exists": true } }, None)
+        .await?;
+    let mut versions = Vec::new();
+
+    while let Some(result) = cursor.next().await {
+        if let Ok(version) = result?.get_i32("version") {
+            versions.push(version);
+        }
+    }
+
+    Ok(versions)
+}
+
+/// The lock is a document that is taken by setting its owner. When another
+/// instance holds it, the upsert runs into the unique `_id` and is retried.
+async fn acquire_lock(collection: &Collection<Document>, owner: &str) -> WebResult<()> {
+    let mut waiting = false;
+
+    loop {
+        let now = DateTime::now();
+        let filter = doc! {
+            "_id": LOCK_ID,
+            "$or": [
+                { "locked_by": null },
+                { "expires_at": { "$lt": now } },
+            ],
+        };
+        let update = doc! {
+            "$set": { "locked_by": owner, "locked_at": now, "expires_at": lock_expiry() }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        match collection.update_one(filter, update, options).await {
+            Ok(_) => return Ok(()),
+            Err(e) => match AppError::from(e) {
+                AppError::Conflict(_) => {
+                    if !waiting {
+                        info!("Waiting for another instance to finish migrating");
+                        waiting = true;
+                    }
+                    actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+                }
+                e => return Err(e),
+            },
+        }
+    }
+}
+
+/// Keeps the lock from running out while migrations of large collections
+/// take longer than the timeout, until the task is aborted.
+async fn renew_lock(collection: Collection<Document>, owner: String) {
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(LOCK_TIMEOUT_SECONDS as u64 / 3)).await;
+
+        let renewed = collection
+            .update_one(
+                doc! { "_id": LOCK_ID, "locked_by": &owner },
+                doc! { "$set": { "expires_at": lock_expiry() } },
+                None,
+            )
+            .await;
+        match renewed {
+            Ok(res) if res.matched_count == 0 => {
+                warn!("The migration lock was taken over by another instance")
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not renew the migration lock: {}", e),
+        }
+    }
+}
+
+fn lock_expiry() -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() + LOCK_TIMEOUT_SECONDS * 1000)
+}
+
+async fn release_lock(collection: &Collection<Document>, owner: &str) -> WebResult<()> {
+    collection
+        .update_one(
+            doc! { "_id": LOCK_ID, "locked_by": owner },
+            doc! { "$set": { "locked_by": null } },
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// The indexes of the first release. Later changes to indexes are made by
+/// their own migrations, so this stays as it was released.
+fn v1_indexes() -> Vec<Document> {
+    let users_index = doc! {
+        "createIndexes": "users",
+        "indexes": [
+            {
+                "key": { "email": 1 },
+                "name": "users-index",
+                "unique": true
+            },
+        ]
+    };
+    let workouts_index = doc! {
+        "createIndexes": "workouts",
+        "indexes": [
+            {
+                "key": { "user_id": 1, "name": 1, "measurement": 1 },
+                "name": "workouts-index",
+                "unique": true
+            },
+        ]
+    };
+    let movements_index = doc! {
+        "createIndexes": "movements",
+        "indexes": [
+            {
+                "key": { "user_id": 1, "name": 1, "measurement": 1 },
+                "name": "movements-index",
+                "unique": true
+            },
+        ]
+    };
+
+    vec![users_index, workouts_index, movements_index]
+}
+
+/// Indexes for the imports and feeds collections and for looking up users by
+/// their avatar. Creating an index that already exists with the same options
+/// does nothing, so databases that have them already are left as they are.
+fn import_feed_avatar_indexes() -> Vec<Document> {
+    let users_index = doc! {
+        "createIndexes": "users",
+        "indexes": [
+            {
+                "key": { "avatar_url": 1 },
+                "name": "users-avatar-index"
+            },
+        ]
+    };
+
+    let imports_index = doc! {
+        "createIndexes": "imports",
+        "indexes": [
+            {
+                "key": { "import_id": 1 },
+                "name": "imports-index",
+                "unique": true
+            },
+            {
+                "key": { "status": 1 },
+                "name": "imports-status-index"
+            },
+        ]
+    };
+
+    let feeds_index = doc! {
+        "createIndexes": "feeds",
+        "indexes": [
+            {
+                "key": { "user_id": 1, "url": 1 },
+                "name": "feeds-index",
+                "unique": true
+            },
+        ]
+    };
+
+    vec![users_index, imports_index, feeds_index]
+}
+
+/// Scores are mostly looked up by the user and the workout or movement.
+fn score_indexes() -> Vec<Document> {
+    ["workoutscores", "movementscores"]
+        .iter()
+        .zip(["workout_id", "movement_id"].iter())
+        .map(|(collection, id)| {
+            doc! {
+                "createIndexes": *collection,
+                "indexes": [
+                    {
+                        "key": { "user_id": 1, *id: 1 },
+                        "name": format!("{}-user-index", collection),
+                    },
+                ]
+            }
+        })
+        .collect()
+}
+
+/// Movements measured in `time` used to only keep the time of their scores,
+/// with the distance being part of the movement name, e.g. "1000m Rowing".
+/// Copies that distance onto the scores that do not have one yet.
+async fn add_distances_to_time_scores(db: &Database) -> WebResult<u64> {
+    let movements = db.collection::<Document>("movements");
+    let scores = db.collection::<Document>("movementscores");
+
+    let mut cursor = movements.find(doc! { "measurement": "time" }, None).await?;
+    let mut migrated = 0;
+
+    while let Some(movement) = cursor.next().await {
+        let movement = movement?;
+        let (movement_id, name) = match (movement.get_str("movement_id"), movement.get_str("name"))
+        {
+            (Ok(movement_id), Ok(name)) => (movement_id, name),
+            _ => continue,
+        };
+
+        if let Some((distance, unit)) = parse_distance(name) {
+            let query = doc! {
+                "movement_id": movement_id,
+                "distance": { "$exists": false },
+            };
+            let update = doc! {
+                "$set": {
+                    "distance": distance,
+                    "distance_unit": unit.to_string(),
+                }
+            };
+            let res = scores.update_many(query, update, None).await?;
+            migrated += res.modified_count;
+        }
+    }
+
+    Ok(migrated)
+}
+
+/// Scores used to be logged without a unit, which were always in the metric
+/// unit of their measurement. Sets that unit on the scores without one.
+async fn add_units_to_scores(db: &Database) -> WebResult<u64> {
+    let defaults = [
+        ("workouts", "workoutscores", "workout_id", "load", "kg"),
+        ("workouts", "workoutscores", "workout_id", "distance", "m"),
+        ("movements", "movementscores", "movement_id", "weight", "kg"),
+        ("movements", "movementscores", "movement_id", "height", "cm"),
+        (
+            "movements",
+            "movementscores",
+            "movement_id",
+            "distance",
+            "m",
+        ),
+    ];
+    let mut migrated = 0;
+
+    for (parents, scores, id, measurement, unit) in defaults.iter() {
+        let ids = db
+            .collection::<Document>(parents)
+            .distinct(*id, doc! { "measurement": *measurement }, None)
+            .await?;
+        if ids.is_empty() {
+            continue;
+        }
+
+        let res = db
+            .collection::<Document>(scores)
+            .update_many(
+                doc! { *id: { "$in": ids }, "unit": null },
+                doc! { "$set": { "unit": *unit } },
+                None,
+            )
+            .await?;
+        migrated += res.modified_count;
+    }
+
+    Ok(migrated)
+}
+
+/// Workouts and movements of a user are looked up by their name, whatever
+/// their measurement.
+fn name_indexes() -> Vec<Document> {
+    ["workouts", "movements"]
+        .iter()
+        .map(|collection| {
+            doc! {
+                "createIndexes": *collection,
+                "indexes": [
+                    {
+                        "key": { "user_id": 1, "name": 1 },
+                        "name": format!("{}-name-index", collection),
+                        "unique": true,
+                    },
+                ]
+            }
+        })
+        .collect()
+}
+
+/// Whether a `dropIndexes` failed because the index does not exist.
+fn is_index_not_found(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Command(command) if command.code == 27)
+}
+
+/// The names of workouts and movements used to be unique per user and
+/// measurement, now that the measurement can be changed they are unique per
+/// user. Renames the ones that share their name with an older one of the
+/// same user, e.g. to "Fran (2)", and replaces the indexes.
+async fn index_names_by_user(db: &Database) -> WebResult<u64> {
+    let mut renamed = 0;
+
+    for (collection, id, index) in [
+        ("workouts", "workout_id", "workouts-index"),
+        ("movements", "movement_id", "movements-index"),
+    ]
+    .iter()
+    {
+        let records = db.collection::<Document>(collection);
+        let pipeline = vec![
+            doc! { "$sort": { "created_at": 1 } },
+            doc! {
+                "$group": {
+                    "_id": { "user_id": "$user_id", "name": "$name" },
+                    "ids": { "$push": format!("${}", id) },
+                }
+            },
+            doc! { "$match": { "ids.1": { "$exists": true } } },
+        ];
+        let mut duplicates = records.aggregate(pipeline, None).await?;
+
+        while let Some(duplicate) = duplicates.next().await {
+            let duplicate = duplicate?;
+            let (user_id, name, ids) =
+                match (duplicate.get_document("_id"), duplicate.get_array("ids")) {
+                    (Ok(group), Ok(ids)) => match (group.get_str("user_id"), group.get_str("name"))
+                    {
+                        (Ok(user_id), Ok(name)) => (user_id, name, ids),
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+
+            // The oldest one keeps its name
+            for record_id in ids.iter().skip(1) {
+                let mut n = 2;
+                let candidate = loop {
+                    let candidate = format!("{} ({})", name, n);
+                    let query = doc! { "user_id": user_id, "name": &candidate };
+                    if records.count_documents(query, None).await? == 0 {
+                        break candidate;
+                    }
+                    n += 1;
+                };
+
+                records
+                    .update_one(
+                        doc! { *id: record_id },
+                        doc! { "$set": { "name": candidate } },
+                        None,
+                    )
+                    .await?;
+                renamed += 1;
+            }
+        }
+
+        // Already dropped when an earlier run stopped before the new indexes
+        let drop = doc! { "dropIndexes": *collection, "index": *index };
+        match db.run_command(drop, None).await {
+            Err(e) if !is_index_not_found(&e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    for command in name_indexes() {
+        db.run_command(command, None).await?;
+    }
+
+    Ok(renamed)
+}
+
+/// Monostructural movements used to all be measured in `time`, also the ones
+/// for a set time like "20 min Run", with what was covered kept as the
+/// distance of their scores. Moves those movements to `distance` or
+/// `calories`, which makes the distance the score and the time the duration.
+/// Movements of a set distance, e.g. "1000m Rowing", stay `time`, as do
+/// movements with scores without a distance, which can not be converted.
+async fn classify_time_movements(db: &Database) -> WebResult<u64> {
+    let movements = db.collection::<Document>("movements");
+    let scores = db.collection::<Document>("movementscores");
+
+    let mut cursor = movements.find(doc! { "measurement": "time" }, None).await?;
+    let mut migrated = 0;
+
+    while let Some(movement) = cursor.next().await {
+        let movement = movement?;
+        let (movement_id, name) = match (movement.get_str("movement_id"), movement.get_str("name"))
+        {
+            (Ok(movement_id), Ok(name)) => (movement_id, name),
+            _ => continue,
+        };
+        let measurement = MovementMeasurement::for_monostructural(name);
+        if measurement == MovementMeasurement::Time {
+            continue;
+        }
+
+        // Scores converted by an earlier run that stopped have a duration
+        let unconvertible = scores
+            .count_documents(
+                doc! { "movement_id": movement_id, "distance": null, "duration": null },
+                None,
+            )
+            .await?;
+        if unconvertible > 0 {
+            warn!(
+                "Keeping movement {} ({}) measured in time, {} of its scores have no distance",
+                movement_id, name, unconvertible
+            );
+            continue;
+        }
+
+        let unit = match measurement {
+            MovementMeasurement::Distance => bson::Bson::String("$distance_unit".to_owned()),
+            _ => bson::Bson::Null,
+        };
+        let convert = vec![
+            doc! { "$set": { "score": "$distance", "unit": unit, "duration": "$score" } },
+            doc! { "$unset": ["distance", "distance_unit"] },
+        ];
+        let res = scores
+            .update_many(
+                doc! { "movement_id": movement_id, "distance": { "$ne": null }, "duration": null },
+                convert,
+                None,
+            )
+            .await?;
+        movements
+            .update_one(
+                doc! { "movement_id": movement_id },
+                doc! { "$set": { "measurement": measurement.to_string() } },
+                None,
+            )
+            .await?;
+        migrated += res.modified_count;
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        let migrations = migrations();
+        for (previous, migration) in migrations.iter().zip(migrations.iter().skip(1)) {
+            assert!(previous.version < migration.version);
+            assert_ne!(previous.name, migration.name);
+        }
+    }
+
+    #[test]
+    fn test_v1_indexes() {
+        let indexes = v1_indexes();
+        let collections: Vec<&str> = indexes
+            .iter()
+            .map(|command| command.get_str("createIndexes").unwrap())
+            .collect();
+        assert_eq!(collections, vec!["users", "workouts", "movements"]);
+        assert_eq!(indexes[0].get_array("indexes").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_name_indexes() {
+        let indexes = name_indexes();
+        assert_eq!(indexes.len(), 2);
+        let index = indexes[0].get_array("indexes").unwrap()[0]
+            .as_document()
+            .unwrap();
+        assert_eq!(index.get_str("name").unwrap(), "workouts-name-index");
+        assert!(!index
+            .get_document("key")
+            .unwrap()
+            .contains_key("measurement"));
+    }
+
+    #[test]
+    fn test_score_indexes() {
+        let indexes = score_indexes();
+        assert_eq!(indexes.len(), 2);
+        assert_eq!(
+            indexes[1].get_str("createIndexes").unwrap(),
+            "movementscores"
+        );
+        let index = indexes[1].get_array("indexes").unwrap()[0]
+            .as_document()
+            .unwrap();
+        assert!(index
+            .get_document("key")
+            .unwrap()
+            .contains_key("movement_id"));
+    }
+}
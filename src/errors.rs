@@ -9,10 +9,18 @@ pub enum AppError {
     #[display(fmt = "{}", _0)]
     Unauthorized(String),
     #[display(fmt = "{}", _0)]
+    Forbidden(String),
+    #[display(fmt = "{}", _0)]
     NotFound(String),
     #[display(fmt = "{}", _0)]
     Conflict(String),
     #[display(fmt = "{}", _0)]
+    PayloadTooLarge(String),
+    #[display(fmt = "{}", _0)]
+    UnsupportedMediaType(String),
+    #[display(fmt = "{}", _0)]
+    UnprocessableEntity(String),
+    #[display(fmt = "{}", _0)]
     Internal(String),
 }
 
@@ -26,8 +34,12 @@ impl ResponseError for AppError {
         match *self {
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
             AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -63,3 +75,22 @@ impl From<mongodb::error::Error> for AppError {
         parse_mongodb_error(err)
     }
 }
+
+/// Unique constraints are violated by records that already exist, the same as
+/// duplicate keys in MongoDB.
+pub fn parse_sqlite_error(err: rusqlite::Error) -> AppError {
+    match &err {
+        rusqlite::Error::SqliteFailure(failure, _)
+            if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            AppError::Conflict(format!("Entity already exists: {}", err))
+        }
+        _ => AppError::Internal(err.to_string()),
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        parse_sqlite_error(err)
+    }
+}
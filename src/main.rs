@@ -3,15 +3,18 @@ extern crate log;
 #[macro_use]
 extern crate bson;
 
+use crate::db::migrations;
 use crate::db::mongo::Connection;
-use crate::utils::mywod::AVATAR_FILE_LOCATION;
-use crate::utils::{AppState, Config};
+use crate::repositories::{transfer, Database, MemoryDatabase, SqliteDatabase};
+use crate::storage::{Storages, AVATAR_FILE_LOCATION, UPLOAD_FILE_LOCATION};
+use crate::utils::{AppState, Config, DatabaseBackend};
 
 use actix_web::middleware::{Compress, Logger};
-use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{App, HttpServer};
+use chrono::Duration;
 use dotenv::dotenv;
-use std::{fs, io};
+use std::sync::Arc;
+use std::{env, fs, io};
 
 mod db;
 mod errors;
@@ -20,38 +23,143 @@ mod models;
 mod repositories;
 mod routes;
 mod services;
+mod storage;
 mod utils;
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
-    fs::create_dir_all("./tmp")?;
+    fs::create_dir_all(UPLOAD_FILE_LOCATION)?;
     fs::create_dir_all(AVATAR_FILE_LOCATION)?;
 
     dotenv().ok();
 
     env_logger::init();
 
-    let config = Config::from_env().unwrap();
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("migrate") => return migrate(&config).await,
+        Some("migrate-data") => return migrate_data(&config, &args[1..]).await,
+        _ => {}
+    }
+
     let server_addr = format!("{}:{}", config.host, config.port);
-    let mongo_connection = Connection::new().await.unwrap();
-    mongo_connection.create_indexes().await;
-    let client = mongo_connection.client;
+    let database = connect(&config, config.database.backend).await?;
+    let storages = Storages::new(&config.storage).map_err(|e| io::Error::other(e.to_string()))?;
+    let state = AppState::new(database, storages, config.clone());
+    match services::imports::resume_imports(state.repositories.clone(), &state.storages).await {
+        Ok(resumed) => info!("Resumed {} unfinished imports", resumed),
+        Err(e) => error!("Could not resume unfinished imports: {}", e),
+    }
+
+    // Files left behind while the server was down are removed straight away
+    let max_age = Duration::seconds(config.cleanup.max_age as i64);
+    services::janitor::run_cleanup(state.repositories.clone(), &state.storages, max_age).await;
+    services::janitor::spawn_janitor(
+        state.repositories.clone(),
+        state.storages.clone(),
+        Duration::seconds(config.cleanup.interval as i64),
+        max_age,
+    );
 
     let app = move || {
         App::new()
-            .app_data(Data::new(AppState {
-                mongo_client: client.clone(),
-            }))
+            .configure(|cfg| routes::init_app_data(cfg, &state))
             .wrap(Compress::default())
             .wrap(Logger::default())
-            // Setup endpoints (strictest matcher first)
-            .service(actix_files::Files::new("/avatars", AVATAR_FILE_LOCATION).show_files_listing())
-            .service(web::scope("/v1/users").configure(routes::users::init_routes))
-            .service(web::scope("/v1/movements").configure(routes::movements::init_routes))
-            .service(web::scope("/v1/workouts").configure(routes::workouts::init_routes))
-            .service(web::scope("").configure(routes::index::init_routes))
+            .configure(routes::init_routes)
     };
 
     info!("Starting server on {}", server_addr);
     HttpServer::new(app).bind(server_addr)?.run().await
 }
+
+async fn connect(config: &Config, backend: DatabaseBackend) -> io::Result<Database> {
+    let database = match backend {
+        DatabaseBackend::Mongo => {
+            let mongo_connection = Connection::new(&config.mongo).await.unwrap();
+            let db = mongo_connection.client.database(&config.mongo.db_name);
+            if config.mongo.migrate_on_startup {
+                run_migrations(&db).await?;
+            } else {
+                let pending = migrations::pending_migrations(&db)
+                    .await
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+                if !pending.is_empty() {
+                    warn!(
+                        "{} migrations have not been applied, run `wodbook-api migrate`",
+                        pending.len()
+                    );
+                }
+            }
+            Database::Mongo(db)
+        }
+        DatabaseBackend::Memory => {
+            warn!("Data is kept in memory and lost once the server stops");
+            Database::Memory(MemoryDatabase::new())
+        }
+        DatabaseBackend::Sqlite => {
+            info!("Using the SQLite database at {}", config.sqlite.path);
+            let database = SqliteDatabase::open(&config.sqlite.path)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            Database::Sqlite(database)
+        }
+    };
+
+    Ok(database)
+}
+
+async fn run_migrations(db: &mongodb::Database) -> io::Result<()> {
+    match migrations::run_migrations(db).await {
+        Ok(applied) if applied.is_empty() => info!("The database is up to date"),
+        Ok(applied) => info!("Applied migrations {}", applied.join(", ")),
+        Err(e) => return Err(io::Error::other(e.to_string())),
+    }
+
+    Ok(())
+}
+
+/// `wodbook-api migrate` applies the pending MongoDB migrations and exits.
+async fn migrate(config: &Config) -> io::Result<()> {
+    let mongo_connection = Connection::new(&config.mongo).await.unwrap();
+    run_migrations(&mongo_connection.client.database(&config.mongo.db_name)).await
+}
+
+/// `wodbook-api migrate-data <from> <to>` copies everything from one database
+/// to another, e.g. `migrate-data mongo sqlite`. The target has to be empty.
+async fn migrate_data(config: &Config, args: &[String]) -> io::Result<()> {
+    let backends: Vec<DatabaseBackend> = args
+        .iter()
+        .filter_map(|arg| match arg.as_str() {
+            "mongo" => Some(DatabaseBackend::Mongo),
+            "sqlite" => Some(DatabaseBackend::Sqlite),
+            _ => None,
+        })
+        .collect();
+    let (source, target) = match backends.as_slice() {
+        [source, target] if args.len() == 2 && source != target => (*source, *target),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "usage: wodbook-api migrate-data <mongo|sqlite> <mongo|sqlite>",
+            ))
+        }
+    };
+
+    let source = connect(config, source).await?;
+    let target = connect(config, target).await?;
+    match transfer::transfer(&source, &target).await {
+        Ok(copied) => {
+            info!("Copied {}", copied);
+            Ok(())
+        }
+        Err(e) => Err(io::Error::other(e.to_string())),
+    }
+}
@@ -1,9 +1,10 @@
 use crate::errors::AppError;
 use crate::models::user::Claims;
-use crate::utils::Config;
+use crate::utils::AppState;
 
+use actix_web::error::ErrorInternalServerError;
 use actix_web::error::ErrorUnauthorized;
-use actix_web::{dev, Error, FromRequest, HttpRequest};
+use actix_web::{dev, web, Error, FromRequest, HttpRequest};
 use futures::future::{err, ok, Ready};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 
@@ -22,11 +23,18 @@ impl FromRequest for Claims {
         }
 
         let token = auth.unwrap().to_str().unwrap().replace("Bearer ", "");
-        let config = Config::from_env().unwrap();
+        let state = match req.app_data::<web::Data<AppState>>() {
+            Some(state) => state,
+            None => {
+                return err(ErrorInternalServerError(AppError::Internal(
+                    "The app state is missing".to_string(),
+                )))
+            }
+        };
 
         match decode::<Claims>(
             token.trim(),
-            &DecodingKey::from_secret(config.auth.secret.as_bytes()),
+            &DecodingKey::from_secret(state.config.auth.secret.as_bytes()),
             &Validation::new(Algorithm::HS256),
         ) {
             Ok(token_data) => ok(token_data.claims),
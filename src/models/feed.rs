@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// A WOD feed of a box the user is subscribed to, e.g. the RSS feed of the
+/// box website.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedModel {
+    pub feed_id: String,
+    pub user_id: String,
+    pub title: String,
+    pub url: String,
+    /// Where an imported record came from, e.g. `mywod:<client id>:<record id>`
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// The import that added the record, so the import can be rolled back
+    #[serde(default)]
+    pub import_id: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManyFeedsResponse {
+    pub data: Vec<FeedModel>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateFeed {
+    pub title: String,
+    pub url: String,
+    /// Set by importers to recognize records they added before
+    #[serde(skip)]
+    pub source_id: Option<String>,
+    /// Set by importers to roll back what an import added
+    #[serde(skip)]
+    pub import_id: Option<String>,
+}
+
+/// Only web feeds can be subscribed to.
+pub fn is_valid_feed_url(url: &str) -> bool {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+
+    match rest {
+        Some(rest) => !rest.is_empty() && !rest.contains(char::is_whitespace),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_feed_url() {
+        assert!(is_valid_feed_url("http://crossfit101.com/feed/"));
+        assert!(is_valid_feed_url("https://crossfit101.com/feed/"));
+        assert!(!is_valid_feed_url("https://"));
+        assert!(!is_valid_feed_url("ftp://crossfit101.com/feed/"));
+        assert!(!is_valid_feed_url("crossfit101.com/feed/"));
+        assert!(!is_valid_feed_url("http://crossfit 101.com/feed/"));
+    }
+}
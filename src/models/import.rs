@@ -0,0 +1,163 @@
+use crate::models::mywod::{MyWodResponse, RowReport};
+use crate::models::unit::UnitSystem;
+use crate::models::user::{Gender, User};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    /// Uploaded and waiting for a worker
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    /// Everything the import added has been removed again
+    RolledBack,
+}
+
+// TODO: Find a nicer way of serializing into strings without the quotes
+impl fmt::Display for ImportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let string_val = serde_json::to_string(self).unwrap_or_else(|_| "".to_owned());
+        write!(f, "{}", string_val.trim_matches('"'))
+    }
+}
+
+/// How far along an import is, counted in rows of the backup.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ImportProgress {
+    pub processed: u32,
+    pub total: u32,
+}
+
+/// The profile fields of the user before an import overwrote them, so they
+/// can be restored when the import is rolled back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileSnapshot {
+    pub first_name: String,
+    pub last_name: String,
+    pub date_of_birth: String,
+    pub height: i32,
+    pub weight: i32,
+    pub box_name: String,
+    pub avatar_url: String,
+    pub unit_system: UnitSystem,
+    pub gender: Option<Gender>,
+}
+
+impl From<&User> for ProfileSnapshot {
+    fn from(user: &User) -> Self {
+        ProfileSnapshot {
+            first_name: user.first_name.to_owned(),
+            last_name: user.last_name.to_owned(),
+            date_of_birth: user.date_of_birth.to_owned(),
+            height: user.height,
+            weight: user.weight,
+            box_name: user.box_name.to_owned(),
+            avatar_url: user.avatar_url.to_owned(),
+            unit_system: user.unit_system,
+            gender: user.gender,
+        }
+    }
+}
+
+/// What rolling back an import removed. Workouts and movements the import
+/// added are kept when the user has logged other scores for them since.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct RollbackResult {
+    pub removed_workouts: u64,
+    pub kept_workouts: u64,
+    pub removed_workout_scores: u64,
+    pub removed_movements: u64,
+    pub kept_movements: u64,
+    pub removed_movement_scores: u64,
+    pub removed_feeds: u64,
+    pub profile_restored: bool,
+    /// The profile was changed after the import, so it was left as it is
+    #[serde(default)]
+    pub profile_conflict: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportModel {
+    pub import_id: String,
+    pub user_id: String,
+    /// Profile updates go through the email of the user
+    pub user_email: String,
+    pub source: String,
+    pub status: ImportStatus,
+    /// Where the uploaded file is kept until the import is done
+    pub file_path: String,
+    pub progress: ImportProgress,
+    #[serde(default)]
+    pub result: Option<MyWodResponse>,
+    #[serde(default)]
+    pub report: Vec<RowReport>,
+    /// Why the import failed, if it did
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The profile before the import changed it, if it did
+    #[serde(default)]
+    pub previous_profile: Option<ProfileSnapshot>,
+    /// The profile as the import left it, so a rollback can tell whether
+    /// the user changed it since
+    #[serde(default)]
+    pub imported_profile: Option<ProfileSnapshot>,
+    #[serde(default)]
+    pub rollback: Option<RollbackResult>,
+    /// The worker processing the import, so no two workers run it at once
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    /// When the claim runs out, in milliseconds since the epoch. A claim
+    /// that ran out was left behind by a worker that stopped.
+    #[serde(default)]
+    pub claim_expires_at: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportResponse {
+    pub import_id: String,
+    pub source: String,
+    pub status: ImportStatus,
+    pub progress: ImportProgress,
+    pub result: Option<MyWodResponse>,
+    pub report: Vec<RowReport>,
+    pub error: Option<String>,
+    pub rollback: Option<RollbackResult>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl ImportResponse {
+    pub fn from_model(model: ImportModel) -> Self {
+        ImportResponse {
+            import_id: model.import_id,
+            source: model.source,
+            status: model.status,
+            progress: model.progress,
+            result: model.result,
+            report: model.report,
+            error: model.error,
+            rollback: model.rollback,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_to_string() {
+        assert_eq!(ImportStatus::Pending.to_string(), "pending");
+        assert_eq!(ImportStatus::Running.to_string(), "running");
+        assert_eq!(ImportStatus::Completed.to_string(), "completed");
+        assert_eq!(ImportStatus::Failed.to_string(), "failed");
+        assert_eq!(ImportStatus::RolledBack.to_string(), "rolled_back");
+    }
+}
@@ -1,5 +1,9 @@
+pub mod feed;
+pub mod import;
 pub mod movement;
 pub mod mywod;
 pub mod response;
+pub mod training_log;
+pub mod unit;
 pub mod user;
 pub mod workout;
@@ -1,4 +1,7 @@
+use crate::models::unit::{convert_to_system, Unit, UnitSystem};
+use crate::utils::resources::{parse_set_time, round_to_hundredths};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
 use std::vec::Vec;
 
@@ -15,16 +18,62 @@ fn default_as_one() -> u32 {
     1
 }
 
+fn default_as_true() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum MovementMeasurement {
-    Time, // fka Distance
+    /// Time for a set distance, e.g. 2000m row
+    Time,
+    /// Distance covered in a set time, e.g. 20 min run
+    Distance,
+    /// Calories in a set time, e.g. 1 min assault bike
+    Calories,
     Weight,
     Reps,
     Height,
     None,
 }
 
+impl MovementMeasurement {
+    /// The unit scores for this measurement are logged in when none is given.
+    pub fn default_unit(&self, system: UnitSystem) -> Option<Unit> {
+        match self {
+            MovementMeasurement::Weight => Some(Unit::Kg.in_system(system)),
+            MovementMeasurement::Height => Some(Unit::Cm.in_system(system)),
+            MovementMeasurement::Distance => Some(Unit::M.in_system(system)),
+            _ => None,
+        }
+    }
+
+    /// Whether a lower score is better, which decides how scores are sorted.
+    pub fn lower_is_better(&self) -> bool {
+        *self == MovementMeasurement::Time
+    }
+
+    /// How a monostructural movement, like rowing or running, is measured
+    /// going by its name. Calories for "1 min Assault Bike Calories", distance
+    /// for a set time like "20 min Run" and otherwise time, e.g. "1000m Rowing".
+    pub fn for_monostructural(name: &str) -> MovementMeasurement {
+        let mentions_calories = name.split_whitespace().any(|word| {
+            matches!(
+                word.to_lowercase().as_str(),
+                "cal" | "cals" | "calorie" | "calories"
+            )
+        });
+
+        if mentions_calories {
+            MovementMeasurement::Calories
+        } else if parse_set_time(name).is_some() {
+            MovementMeasurement::Distance
+        } else {
+            MovementMeasurement::Time
+        }
+    }
+}
+
 // TODO: Find a nicer way of serializing into strings without the quotes
 impl fmt::Display for MovementMeasurement {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -33,10 +82,19 @@ impl fmt::Display for MovementMeasurement {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MovementModel {
     pub movement_id: String,
     pub user_id: String,
+    /// The movement this one was copied from, if it is a fork
+    #[serde(default)]
+    pub forked_from: Option<String>,
+    /// Where an imported record came from, e.g. `mywod:<client id>:<record id>`
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// The import that added the record, so the import can be rolled back
+    #[serde(default)]
+    pub import_id: Option<String>,
     pub name: String,
     pub measurement: MovementMeasurement,
     pub is_public: bool,
@@ -47,18 +105,20 @@ pub struct MovementModel {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MovementResponse {
     pub movement_id: String,
+    pub forked_from: Option<String>,
     pub name: String,
     pub measurement: MovementMeasurement,
-    pub scores: Vec<MovementScoreModel>,
+    pub scores: Vec<MovementScoreResponse>,
     pub is_public: bool,
     pub created_at: String,
     pub updated_at: String,
 }
 
 impl MovementResponse {
-    pub fn from_model(model: MovementModel, scores: Vec<MovementScoreModel>) -> Self {
+    pub fn from_model(model: MovementModel, scores: Vec<MovementScoreResponse>) -> Self {
         MovementResponse {
             movement_id: model.movement_id,
+            forked_from: model.forked_from,
             name: model.name,
             measurement: model.measurement,
             scores,
@@ -74,34 +134,86 @@ pub struct ManyMovementsResponse {
     pub data: Vec<MovementModel>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ManyMovementScoresResponse {
-    pub data: Vec<MovementScoreModel>,
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateMovement {
     pub name: String,
     pub measurement: MovementMeasurement,
     #[serde(default = "default_as_false")]
     pub is_public: bool,
+    /// Set by importers to recognize records they added before
+    #[serde(skip)]
+    pub source_id: Option<String>,
+    /// Set by importers to roll back what an import added
+    #[serde(skip)]
+    pub import_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateMovement {
     pub name: Option<String>,
+    pub measurement: Option<MovementMeasurement>,
+    pub is_public: Option<bool>,
+}
+
+/// A single set within a movement score, e.g. one of the sets in a 5x5.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct MovementSet {
+    pub reps: u32,
+    #[serde(default)]
+    pub load: f64,
+    pub rpe: Option<f64>,
+    #[serde(default = "default_as_true")]
+    pub completed: bool,
+}
+
+impl MovementSet {
+    /// The value this set would be scored by for the given measurement.
+    pub fn score(&self, measurement: MovementMeasurement) -> f64 {
+        match measurement {
+            MovementMeasurement::Reps => self.reps as f64,
+            _ => self.load,
+        }
+    }
+}
+
+/// Finds the top set, the one a movement score is sorted by. Only completed
+/// sets count, unless none of them were completed.
+pub fn top_set(measurement: MovementMeasurement, sets: &[MovementSet]) -> Option<&MovementSet> {
+    let by_score = |a: &&MovementSet, b: &&MovementSet| {
+        a.score(measurement)
+            .partial_cmp(&b.score(measurement))
+            .unwrap_or(Ordering::Equal)
+            .then(a.reps.cmp(&b.reps))
+    };
+
+    sets.iter()
+        .filter(|set| set.completed)
+        .max_by(by_score)
+        .or_else(|| sets.iter().max_by(by_score))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateMovementScore {
-    pub score: f64,
+    pub score: Option<f64>,
     #[serde(default = "default_as_one")]
     pub sets: u32,
     #[serde(default = "default_as_one")]
     pub reps: u32,
+    #[serde(default)]
+    pub set_details: Vec<MovementSet>,
+    pub unit: Option<Unit>,
+    pub distance: Option<f64>,
+    pub distance_unit: Option<Unit>,
+    pub duration: Option<f64>,
     #[serde(default = "default_as_empty_string")]
     pub notes: String,
     pub created_at: Option<String>,
+    /// Set by importers to recognize records they added before
+    #[serde(skip)]
+    pub source_id: Option<String>,
+    /// Set by importers to roll back what an import added
+    #[serde(skip)]
+    pub import_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -109,10 +221,39 @@ pub struct UpdateMovementScore {
     pub score: Option<f64>,
     pub sets: Option<u32>,
     pub reps: Option<u32>,
+    pub set_details: Option<Vec<MovementSet>>,
+    pub unit: Option<Unit>,
+    pub distance: Option<f64>,
+    pub distance_unit: Option<Unit>,
+    pub duration: Option<f64>,
     pub notes: Option<String>,
+    /// Set by importers to tag a score imported before sources were tracked
+    #[serde(skip)]
+    pub source_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl From<CreateMovementScore> for UpdateMovementScore {
+    /// The update that brings a score in line with `score`, e.g. when it is
+    /// imported again. A list of sets decides the score, sets and reps, so
+    /// they are only given for scores without one.
+    fn from(score: CreateMovementScore) -> Self {
+        let has_sets = !score.set_details.is_empty();
+        UpdateMovementScore {
+            score: score.score.filter(|_| !has_sets),
+            sets: Some(score.sets).filter(|_| !has_sets),
+            reps: Some(score.reps).filter(|_| !has_sets),
+            set_details: Some(score.set_details),
+            unit: score.unit,
+            distance: score.distance,
+            distance_unit: score.distance_unit,
+            duration: score.duration,
+            notes: Some(score.notes),
+            source_id: score.source_id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MovementScoreModel {
     pub movement_score_id: String,
     pub movement_id: String,
@@ -120,11 +261,172 @@ pub struct MovementScoreModel {
     pub score: f64,
     pub sets: u32,
     pub reps: u32,
+    #[serde(default)]
+    pub set_details: Vec<MovementSet>,
+    #[serde(default)]
+    pub unit: Option<Unit>,
+    /// The set distance of a `time` score
+    #[serde(default)]
+    pub distance: Option<f64>,
+    #[serde(default)]
+    pub distance_unit: Option<Unit>,
+    /// The set time, in seconds, of a `distance` or `calories` score
+    #[serde(default)]
+    pub duration: Option<f64>,
     pub notes: String,
+    /// Where an imported record came from, e.g. `mywod:<client id>:<record id>`
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// The import that added the record, so the import can be rolled back
+    #[serde(default)]
+    pub import_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+impl MovementScoreModel {
+    /// Converts the score, the loads of its sets and its distance into the
+    /// given unit system.
+    pub fn convert_to(mut self, system: UnitSystem) -> Self {
+        let (score, unit) = convert_to_system(self.score, self.unit, system);
+        for set in self.set_details.iter_mut() {
+            set.load = convert_to_system(set.load, self.unit, system).0;
+        }
+        if let Some(distance) = self.distance {
+            let (distance, distance_unit) = convert_to_system(distance, self.distance_unit, system);
+            self.distance = Some(distance);
+            self.distance_unit = distance_unit;
+        }
+        self.score = score;
+        self.unit = unit;
+        self
+    }
+
+    /// The distance covered and the time it took, for monostructural scores.
+    fn distance_and_time(&self, measurement: MovementMeasurement) -> Option<(f64, Unit, f64)> {
+        match measurement {
+            MovementMeasurement::Time => Some((self.distance?, self.distance_unit?, self.score)),
+            MovementMeasurement::Distance => Some((self.score, self.unit?, self.duration?)),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a score when its movement changes measurement. Only time and
+/// distance scores can be turned into each other, since they record both the
+/// time and the distance. Changing to `none` keeps scores as they are.
+pub fn convert_score_measurement(
+    score: &MovementScoreModel,
+    from: MovementMeasurement,
+    to: MovementMeasurement,
+) -> Result<MovementScoreModel, String> {
+    let mut converted = score.clone();
+
+    match (from, to) {
+        (from, to) if from == to => {}
+        (_, MovementMeasurement::None) => {}
+        (MovementMeasurement::Time, MovementMeasurement::Distance) => {
+            let distance = score
+                .distance
+                .ok_or_else(|| "The score has no distance".to_owned())?;
+            converted.score = distance;
+            converted.unit = score.distance_unit;
+            converted.duration = Some(score.score);
+            converted.distance = None;
+            converted.distance_unit = None;
+        }
+        (MovementMeasurement::Distance, MovementMeasurement::Time) => {
+            let duration = score
+                .duration
+                .ok_or_else(|| "The score has no duration".to_owned())?;
+            converted.score = duration;
+            converted.unit = None;
+            converted.distance = Some(score.score);
+            converted.distance_unit = score.unit;
+            converted.duration = None;
+        }
+        (from, to) => return Err(format!("A {} score can not be converted to {}", from, to)),
+    }
+
+    Ok(converted)
+}
+
+/// How fast a distance was covered, in seconds.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Pace {
+    /// Seconds per 500m, the split used for rowing and skiing
+    pub split_500m: f64,
+    /// Seconds per kilometer or mile, depending on the unit system
+    pub per_distance: f64,
+    pub distance_unit: Unit,
+}
+
+impl Pace {
+    pub fn calculate(meters: f64, seconds: f64, system: UnitSystem) -> Option<Self> {
+        if meters <= 0.0 || seconds <= 0.0 {
+            return None;
+        }
+
+        let distance_unit = Unit::Km.in_system(system);
+        let seconds_per_meter = seconds / meters;
+
+        Some(Pace {
+            split_500m: round_to_hundredths(seconds_per_meter * 500.0),
+            per_distance: round_to_hundredths(seconds_per_meter * distance_unit.factor()),
+            distance_unit,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MovementScoreResponse {
+    #[serde(flatten)]
+    pub score: MovementScoreModel,
+    pub pace: Option<Pace>,
+}
+
+impl MovementScoreResponse {
+    pub fn from_model(
+        score: MovementScoreModel,
+        measurement: MovementMeasurement,
+        system: UnitSystem,
+    ) -> Self {
+        let pace = score
+            .distance_and_time(measurement)
+            .and_then(|(distance, unit, seconds)| {
+                let meters = unit.convert(distance, Unit::M)?;
+                Pace::calculate(meters, seconds, system)
+            });
+
+        MovementScoreResponse { score, pace }
+    }
+}
+
+/// Converts scores into the reader's unit system. Scores logged in different
+/// units get sorted again since the database sorted them by their raw values.
+pub fn convert_movement_scores(
+    measurement: MovementMeasurement,
+    scores: Vec<MovementScoreModel>,
+    system: UnitSystem,
+) -> Vec<MovementScoreModel> {
+    let mut scores: Vec<MovementScoreModel> = scores
+        .into_iter()
+        .map(|score| score.convert_to(system))
+        .collect();
+
+    // ascending for timed, descending for the rest
+    scores.sort_by(|a, b| {
+        let ordering = a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal);
+        if measurement.lower_is_better() {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+
+    scores
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,9 +434,239 @@ mod tests {
     #[test]
     fn test_measurement_to_string() {
         assert_eq!(MovementMeasurement::Time.to_string(), "time");
+        assert_eq!(MovementMeasurement::Distance.to_string(), "distance");
+        assert_eq!(MovementMeasurement::Calories.to_string(), "calories");
         assert_eq!(MovementMeasurement::Weight.to_string(), "weight");
         assert_eq!(MovementMeasurement::Reps.to_string(), "reps");
         assert_eq!(MovementMeasurement::Height.to_string(), "height");
         assert_eq!(MovementMeasurement::None.to_string(), "none");
     }
+
+    fn set(reps: u32, load: f64, completed: bool) -> MovementSet {
+        MovementSet {
+            reps,
+            load,
+            rpe: None,
+            completed,
+        }
+    }
+
+    #[test]
+    fn test_update_from_imported_score() {
+        let score = |set_details: Vec<MovementSet>| CreateMovementScore {
+            score: Some(100.0),
+            sets: 1,
+            reps: 5,
+            set_details,
+            unit: Some(Unit::Kg),
+            distance: None,
+            distance_unit: None,
+            duration: None,
+            notes: String::new(),
+            created_at: None,
+            source_id: None,
+            import_id: None,
+        };
+
+        let update = UpdateMovementScore::from(score(vec![]));
+        assert_eq!(update.score, Some(100.0));
+        assert_eq!(update.reps, Some(5));
+
+        let update = UpdateMovementScore::from(score(vec![set(5, 100.0, true)]));
+        assert_eq!(update.score, None);
+        assert_eq!(update.sets, None);
+        assert_eq!(update.reps, None);
+        assert_eq!(update.set_details.map(|sets| sets.len()), Some(1));
+    }
+
+    #[test]
+    fn test_for_monostructural() {
+        let measurement = MovementMeasurement::for_monostructural;
+        assert_eq!(measurement("1000m Rowing"), MovementMeasurement::Time);
+        assert_eq!(measurement("20 min Run"), MovementMeasurement::Distance);
+        assert_eq!(
+            measurement("1 min Assault Bike Calories"),
+            MovementMeasurement::Calories
+        );
+        assert_eq!(measurement("Rowing"), MovementMeasurement::Time);
+    }
+
+    #[test]
+    fn test_default_unit() {
+        assert_eq!(
+            MovementMeasurement::Weight.default_unit(UnitSystem::Metric),
+            Some(Unit::Kg)
+        );
+        assert_eq!(
+            MovementMeasurement::Height.default_unit(UnitSystem::Imperial),
+            Some(Unit::In)
+        );
+        assert_eq!(
+            MovementMeasurement::Reps.default_unit(UnitSystem::Imperial),
+            None
+        );
+    }
+
+    fn score(score: f64, unit: Option<Unit>) -> MovementScoreModel {
+        MovementScoreModel {
+            movement_score_id: "movement_score_id".to_owned(),
+            movement_id: "movement_id".to_owned(),
+            user_id: "user_id".to_owned(),
+            score,
+            sets: 1,
+            reps: 1,
+            set_details: vec![set(1, score, true)],
+            unit,
+            distance: None,
+            distance_unit: None,
+            duration: None,
+            notes: "".to_owned(),
+            source_id: None,
+            import_id: None,
+            created_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_convert_movement_scores() {
+        let scores = vec![score(225.0, Some(Unit::Lb)), score(100.0, Some(Unit::Kg))];
+        let res = convert_movement_scores(MovementMeasurement::Weight, scores, UnitSystem::Metric);
+        assert_eq!(res[0].score, 102.06);
+        assert_eq!(res[0].unit, Some(Unit::Kg));
+        assert_eq!(res[0].set_details[0].load, 102.06);
+        assert_eq!(res[1].score, 100.0);
+
+        let scores = vec![score(100.0, Some(Unit::Kg)), score(225.0, Some(Unit::Lb))];
+        let res =
+            convert_movement_scores(MovementMeasurement::Weight, scores, UnitSystem::Imperial);
+        assert_eq!(res[0].score, 225.0);
+        assert_eq!(res[1].score, 220.46);
+        assert_eq!(res[1].unit, Some(Unit::Lb));
+    }
+
+    #[test]
+    fn test_convert_score_measurement() {
+        let mut time_score = score(420.0, None);
+        time_score.distance = Some(2000.0);
+        time_score.distance_unit = Some(Unit::M);
+
+        let res = convert_score_measurement(
+            &time_score,
+            MovementMeasurement::Time,
+            MovementMeasurement::Distance,
+        )
+        .unwrap();
+        assert_eq!(res.score, 2000.0);
+        assert_eq!(res.unit, Some(Unit::M));
+        assert_eq!(res.duration, Some(420.0));
+        assert_eq!(res.distance, None);
+
+        let res = convert_score_measurement(
+            &res,
+            MovementMeasurement::Distance,
+            MovementMeasurement::Time,
+        )
+        .unwrap();
+        assert_eq!(res.score, 420.0);
+        assert_eq!(res.distance, Some(2000.0));
+        assert_eq!(res.distance_unit, Some(Unit::M));
+
+        let res = convert_score_measurement(
+            &score(420.0, None),
+            MovementMeasurement::Time,
+            MovementMeasurement::Distance,
+        );
+        assert!(res.is_err());
+
+        let res = convert_score_measurement(
+            &score(100.0, Some(Unit::Kg)),
+            MovementMeasurement::Weight,
+            MovementMeasurement::Reps,
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            "A weight score can not be converted to reps"
+        );
+
+        let res = convert_score_measurement(
+            &score(100.0, Some(Unit::Kg)),
+            MovementMeasurement::Weight,
+            MovementMeasurement::None,
+        );
+        assert_eq!(res.unwrap().score, 100.0);
+    }
+
+    #[test]
+    fn test_pace() {
+        // 2000m row in 7:00
+        let pace = Pace::calculate(2000.0, 420.0, UnitSystem::Metric).unwrap();
+        assert_eq!(pace.split_500m, 105.0);
+        assert_eq!(pace.per_distance, 210.0);
+        assert_eq!(pace.distance_unit, Unit::Km);
+
+        // 1 mile run in 6:00
+        let pace = Pace::calculate(1609.344, 360.0, UnitSystem::Imperial).unwrap();
+        assert_eq!(pace.per_distance, 360.0);
+        assert_eq!(pace.distance_unit, Unit::Mi);
+
+        assert!(Pace::calculate(0.0, 360.0, UnitSystem::Metric).is_none());
+    }
+
+    #[test]
+    fn test_score_response_pace() {
+        let mut time_score = score(194.1, None);
+        time_score.distance = Some(1000.0);
+        time_score.distance_unit = Some(Unit::M);
+        let res = MovementScoreResponse::from_model(
+            time_score,
+            MovementMeasurement::Time,
+            UnitSystem::Metric,
+        );
+        assert_eq!(res.pace.unwrap().split_500m, 97.05);
+
+        let mut distance_score = score(5.0, Some(Unit::Km));
+        distance_score.duration = Some(1200.0);
+        let res = MovementScoreResponse::from_model(
+            distance_score,
+            MovementMeasurement::Distance,
+            UnitSystem::Metric,
+        );
+        assert_eq!(res.pace.unwrap().per_distance, 240.0);
+
+        let res = MovementScoreResponse::from_model(
+            score(100.0, Some(Unit::Kg)),
+            MovementMeasurement::Weight,
+            UnitSystem::Metric,
+        );
+        assert!(res.pace.is_none());
+    }
+
+    #[test]
+    fn test_top_set_by_load() {
+        let sets = vec![
+            set(5, 100.0, true),
+            set(5, 110.0, true),
+            set(5, 120.0, true),
+            set(3, 125.0, false),
+        ];
+        let top = top_set(MovementMeasurement::Weight, &sets).unwrap();
+        assert_eq!(top.load, 120.0);
+        assert_eq!(top.reps, 5);
+    }
+
+    #[test]
+    fn test_top_set_by_reps() {
+        let sets = vec![set(12, 0.0, true), set(15, 0.0, true), set(9, 0.0, true)];
+        let top = top_set(MovementMeasurement::Reps, &sets).unwrap();
+        assert_eq!(top.score(MovementMeasurement::Reps), 15.0);
+    }
+
+    #[test]
+    fn test_top_set_none_completed() {
+        let sets = vec![set(1, 140.0, false), set(1, 145.0, false)];
+        let top = top_set(MovementMeasurement::Weight, &sets).unwrap();
+        assert_eq!(top.load, 145.0);
+        assert!(top_set(MovementMeasurement::Weight, &[]).is_none());
+    }
 }
@@ -1,36 +1,49 @@
-use serde::Serialize;
+use crate::models::unit::UnitSystem;
+use crate::models::user::Gender;
+use crate::utils::mywod::source_id;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Athlete {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    /// Height in centimeters, like users keep it
     pub height: i32,
+    /// Weight in grams, like users keep it
     pub weight: i32,
     pub date_of_birth: String,
     pub box_name: String,
     pub avatar: Vec<u8>,
+    pub unit_system: UnitSystem,
+    pub gender: Option<Gender>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct CustomWOD {
+    pub primary_client_id: String,
+    pub primary_record_id: i32,
     pub title: String,
     pub score_type: String,
     pub description: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct MyWOD {
+    pub primary_client_id: String,
+    pub primary_record_id: i32,
     pub title: String,
     pub date: String,
     pub score_type: String,
     pub score: String,
-    pub as_prescribed: i32, // 0 or 1
+    pub as_prescribed: i32,   // 0 or 1
+    pub personal_record: i32, // 0 or 1
     pub description: String,
     pub notes: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Movement {
     pub primary_client_id: String,
     pub primary_record_id: i32,
@@ -38,8 +51,10 @@ pub struct Movement {
     pub score_type: i32, // 0, 1, 2, 3
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct MovementSession {
+    pub primary_client_id: String,
+    pub primary_record_id: i32,
     pub foreign_movement_client_id: String,
     pub foreign_movement_record_id: i32, // primary key of movement
     pub date: String,
@@ -50,19 +65,261 @@ pub struct MovementSession {
     pub notes: String,
 }
 
+/// A box WOD feed the athlete followed in myWOD.
+#[derive(Debug, PartialEq)]
+pub struct Feed {
+    pub primary_client_id: String,
+    pub primary_record_id: i32,
+    pub title: String,
+    pub url: String,
+}
+
 pub struct MyWodData {
     pub athlete: Athlete,
     pub workouts: Vec<CustomWOD>,
     pub movements: Vec<Movement>,
     pub movement_scores: Vec<MovementSession>,
     pub workout_scores: Vec<MyWOD>,
+    pub feeds: Vec<Feed>,
+    /// Rows that could not be read from the backup
+    pub unreadable: Vec<RowReport>,
+}
+
+/// Query parameters when importing a myWOD backup.
+#[derive(Deserialize, Debug)]
+pub struct MyWodQuery {
+    /// Only show what the import would do, without saving anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// How many records of one kind an import added, updated or left alone
+/// because they were imported before.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ImportCount {
+    pub added: u32,
+    pub updated: u32,
+    pub skipped: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOutcome {
+    /// Not imported, e.g. as a workout with the same name already exists
+    Skipped,
+    /// Imported with changes, e.g. a score that could not be parsed
+    Altered,
+    /// Not imported because of an error
+    Failed,
+}
+
+/// A row of the backup that was not imported as it is, along with its
+/// original values so the user can fix it by hand.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RowReport {
+    pub table: String,
+    pub source_id: String,
+    pub outcome: RowOutcome,
+    pub reason: String,
+    pub original: BTreeMap<String, String>,
+}
+
+impl RowReport {
+    pub fn new(row: &impl BackupRow, outcome: RowOutcome, reason: &str) -> Self {
+        RowReport {
+            table: row.table().to_owned(),
+            source_id: row.source_id(),
+            outcome,
+            reason: reason.to_owned(),
+            original: row.original_values(),
+        }
+    }
+}
+
+/// A row of a myWOD backup table that can end up in an import report.
+pub trait BackupRow {
+    fn table(&self) -> &'static str;
+    fn source_id(&self) -> String;
+    /// The values of the row the user would recognize it by
+    fn original_values(&self) -> BTreeMap<String, String>;
+}
+
+fn values(pairs: &[(&str, String)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_owned()))
+        .collect()
+}
+
+impl BackupRow for CustomWOD {
+    fn table(&self) -> &'static str {
+        "CustomWODs"
+    }
+
+    fn source_id(&self) -> String {
+        source_id(&self.primary_client_id, self.primary_record_id)
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        values(&[
+            ("title", self.title.to_owned()),
+            ("scoreType", self.score_type.to_owned()),
+            ("description", self.description.to_owned()),
+        ])
+    }
+}
+
+impl BackupRow for MyWOD {
+    fn table(&self) -> &'static str {
+        "MyWODs"
+    }
+
+    fn source_id(&self) -> String {
+        source_id(&self.primary_client_id, self.primary_record_id)
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        values(&[
+            ("title", self.title.to_owned()),
+            ("date", self.date.to_owned()),
+            ("scoreType", self.score_type.to_owned()),
+            ("score", self.score.to_owned()),
+            ("asPrescribed", self.as_prescribed.to_string()),
+            ("personalRecord", self.personal_record.to_string()),
+            ("notes", self.notes.to_owned()),
+        ])
+    }
+}
+
+impl BackupRow for Feed {
+    fn table(&self) -> &'static str {
+        "Feeds"
+    }
+
+    fn source_id(&self) -> String {
+        source_id(&self.primary_client_id, self.primary_record_id)
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        values(&[
+            ("title", self.title.to_owned()),
+            ("url", self.url.to_owned()),
+        ])
+    }
+}
+
+impl BackupRow for Movement {
+    fn table(&self) -> &'static str {
+        "Movement"
+    }
+
+    fn source_id(&self) -> String {
+        source_id(&self.primary_client_id, self.primary_record_id)
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        values(&[
+            ("name", self.name.to_owned()),
+            ("type", self.score_type.to_string()),
+        ])
+    }
+}
+
+impl BackupRow for MovementSession {
+    fn table(&self) -> &'static str {
+        "MovementSessions"
+    }
+
+    fn source_id(&self) -> String {
+        source_id(&self.primary_client_id, self.primary_record_id)
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        values(&[
+            ("date", self.date.to_owned()),
+            ("measurementAValue", self.measurement_a_value.to_string()),
+            (
+                "measurementAUnitsCode",
+                self.measurement_a_units_code.to_string(),
+            ),
+            ("measurementB", self.measurement_b.to_owned()),
+            ("sets", self.sets.to_owned()),
+            ("notes", self.notes.to_owned()),
+        ])
+    }
 }
 
+/// What importing workouts or movements, along with their scores, did or
+/// would do.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub records: ImportCount,
+    pub scores: ImportCount,
+    /// Names of the workouts or movements that are added
+    pub added_names: Vec<String>,
+    pub report: Vec<RowReport>,
+}
+
+/// The profile fields an import would set for the user.
 #[derive(Serialize, Debug)]
+pub struct AthletePreview {
+    pub first_name: String,
+    pub last_name: String,
+    pub date_of_birth: String,
+    pub height: i32,
+    pub weight: i32,
+    pub box_name: String,
+    pub unit_system: UnitSystem,
+    pub gender: Option<Gender>,
+    pub has_avatar: bool,
+}
+
+impl From<&Athlete> for AthletePreview {
+    fn from(athlete: &Athlete) -> Self {
+        AthletePreview {
+            first_name: athlete.first_name.trim().to_owned(),
+            last_name: athlete.last_name.trim().to_owned(),
+            date_of_birth: athlete.date_of_birth.to_owned(),
+            height: athlete.height,
+            weight: athlete.weight,
+            box_name: athlete.box_name.trim().to_owned(),
+            unit_system: athlete.unit_system,
+            gender: athlete.gender,
+            has_avatar: !athlete.avatar.is_empty(),
+        }
+    }
+}
+
+/// What importing a backup would do, returned for a dry run.
+#[derive(Serialize, Debug)]
+pub struct MyWodPreviewResponse {
+    pub athlete: AthletePreview,
+    pub workouts: ImportCount,
+    pub workout_scores: ImportCount,
+    pub movements: ImportCount,
+    pub movement_scores: ImportCount,
+    pub feeds: ImportCount,
+    pub new_workouts: Vec<String>,
+    pub new_movements: Vec<String>,
+    pub report: Vec<RowReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MyWodResponse {
     pub user_updated: bool,
     pub added_workouts: u32,
+    pub updated_workouts: u32,
+    pub skipped_workouts: u32,
     pub added_workout_scores: u32,
+    pub updated_workout_scores: u32,
+    pub skipped_workout_scores: u32,
     pub added_movements: u32,
+    pub skipped_movements: u32,
     pub added_movement_scores: u32,
+    pub updated_movement_scores: u32,
+    pub skipped_movement_scores: u32,
+    #[serde(default)]
+    pub added_feeds: u32,
+    #[serde(default)]
+    pub skipped_feeds: u32,
 }
@@ -1,4 +1,7 @@
-use crate::models::{movement::MovementScoreModel, workout::WorkoutScoreModel};
+use crate::models::{
+    movement::{CreateMovementScore, MovementScoreModel},
+    workout::{CreateWorkoutScore, WorkoutScoreModel},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -12,20 +15,69 @@ pub struct TokenResponse {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UserResponse {
+pub struct UserScoreResponse {
+    pub movement_scores: Vec<MovementScoreModel>,
+    pub workout_scores: Vec<WorkoutScoreModel>,
+}
+
+/// How many scores can be logged with a single bulk request.
+pub const MAX_BULK_SCORES: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct BulkWorkoutScore {
+    pub workout_id: String,
+    #[serde(flatten)]
+    pub score: CreateWorkoutScore,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkMovementScore {
+    pub movement_id: String,
+    #[serde(flatten)]
+    pub score: CreateMovementScore,
+}
+
+/// Scores of several workouts and movements logged at once, e.g. by a
+/// client syncing the scores that were logged offline.
+#[derive(Deserialize, Debug)]
+pub struct CreateUserScores {
+    #[serde(default)]
+    pub workout_scores: Vec<BulkWorkoutScore>,
+    #[serde(default)]
+    pub movement_scores: Vec<BulkMovementScore>,
+}
+
+/// Query parameters when updating a workout or movement.
+#[derive(Deserialize, Debug)]
+pub struct UpdateQuery {
+    /// Only show what a measurement change would do to the scores
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// Query parameters when forking a workout or movement.
+#[derive(Deserialize, Debug)]
+pub struct ForkQuery {
+    /// Name of the copy, defaults to the name of the original with a number
+    /// added if the user already has one by that name
+    pub name: Option<String>,
+    /// Move the scores the user logged on the original over to the copy
+    #[serde(default)]
+    pub move_scores: bool,
+}
+
+/// A score that would not survive changing the measurement of its workout or movement.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InvalidScore {
+    pub score_id: String,
     pub user_id: String,
-    pub email: String,
-    pub first_name: String,
-    pub last_name: String,
-    pub date_of_birth: String,
-    pub height: i32,
-    pub weight: i32,
-    pub box_name: String,
-    pub avatar_url: String,
+    pub score: f64,
+    pub reason: String,
 }
 
+/// What changing the measurement of a workout or movement would do to its scores.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UserScoreResponse {
-    pub movement_scores: Vec<MovementScoreModel>,
-    pub workout_scores: Vec<WorkoutScoreModel>,
+pub struct MeasurementChangePreview {
+    pub converted_scores: u32,
+    pub invalid_scores: Vec<InvalidScore>,
 }
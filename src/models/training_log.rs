@@ -0,0 +1,111 @@
+use crate::errors::AppError;
+use crate::models::movement::{CreateMovementScore, MovementMeasurement};
+use crate::models::mywod::{BackupRow, RowReport};
+use crate::models::workout::{CreateWorkoutScore, WorkoutMeasurement};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Training log apps whose CSV exports can be imported.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSource {
+    Sugarwod,
+    /// Beyond the Whiteboard
+    Btwb,
+    Strong,
+}
+
+// TODO: Find a nicer way of serializing into strings without the quotes
+impl fmt::Display for LogSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let string_val = serde_json::to_string(self).unwrap_or_else(|_| "".to_owned());
+        write!(f, "{}", string_val.trim_matches('"'))
+    }
+}
+
+impl FromStr for LogSource {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_owned()))
+            .map_err(|_| AppError::BadRequest(format!("Can not import from '{}'", s)))
+    }
+}
+
+/// A row of a CSV export, kept to recognize it in the import report.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LogRow {
+    pub source: LogSource,
+    /// Identifies the record the row belongs to, see `utils::training_log::log_source_id`
+    pub source_id: String,
+    pub values: BTreeMap<String, String>,
+}
+
+impl BackupRow for LogRow {
+    fn table(&self) -> &'static str {
+        match self.source {
+            LogSource::Sugarwod => "sugarwod",
+            LogSource::Btwb => "btwb",
+            LogSource::Strong => "strong",
+        }
+    }
+
+    fn source_id(&self) -> String {
+        self.source_id.to_owned()
+    }
+
+    fn original_values(&self) -> BTreeMap<String, String> {
+        self.values.clone()
+    }
+}
+
+/// A workout score from a training log, along with the workout it is for.
+#[derive(Debug)]
+pub struct LogWorkoutScore {
+    pub row: LogRow,
+    pub name: String,
+    pub description: String,
+    pub measurement: WorkoutMeasurement,
+    pub score: CreateWorkoutScore,
+}
+
+/// A movement score from a training log, along with the movement it is for.
+/// Apps that log every set on its own row have all sets in one score.
+#[derive(Debug)]
+pub struct LogMovementScore {
+    pub row: LogRow,
+    pub name: String,
+    pub measurement: MovementMeasurement,
+    pub score: CreateMovementScore,
+}
+
+/// The scores of a training log export, mapped from the format of the app
+/// they were exported from.
+#[derive(Debug, Default)]
+pub struct TrainingLog {
+    pub workout_scores: Vec<LogWorkoutScore>,
+    pub movement_scores: Vec<LogMovementScore>,
+    /// Rows that could not be mapped as they are
+    pub report: Vec<RowReport>,
+    /// How many rows the export has
+    pub rows: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_source_from_str() {
+        assert_eq!(
+            "sugarwod".parse::<LogSource>().ok(),
+            Some(LogSource::Sugarwod)
+        );
+        assert_eq!("btwb".parse::<LogSource>().ok(), Some(LogSource::Btwb));
+        assert_eq!("strong".parse::<LogSource>().ok(), Some(LogSource::Strong));
+        assert!("mywod".parse::<LogSource>().is_err());
+        assert_eq!(LogSource::Btwb.to_string(), "btwb");
+    }
+}
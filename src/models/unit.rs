@@ -0,0 +1,199 @@
+use crate::utils::resources::round_to_hundredths;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The unit system a user prefers to see their scores in.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    // Loads
+    Kg,
+    Lb,
+    // Distances
+    M,
+    Km,
+    Mi,
+    Ft,
+    // Heights
+    Cm,
+    In,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Dimension {
+    Mass,
+    Length,
+}
+
+// TODO: Find a nicer way of serializing into strings without the quotes
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let string_val = serde_json::to_string(self).unwrap_or_else(|_| "".to_owned());
+        write!(f, "{}", string_val.trim_matches('"'))
+    }
+}
+
+impl Unit {
+    fn dimension(self) -> Dimension {
+        match self {
+            Unit::Kg | Unit::Lb => Dimension::Mass,
+            _ => Dimension::Length,
+        }
+    }
+
+    /// Whether this unit measures a length, like a distance or a height.
+    pub fn is_length(self) -> bool {
+        self.dimension() == Dimension::Length
+    }
+
+    /// How many kilograms or meters one of this unit is.
+    pub(crate) fn factor(self) -> f64 {
+        match self {
+            Unit::Kg => 1.0,
+            Unit::Lb => 0.453_592_37,
+            Unit::M => 1.0,
+            Unit::Km => 1000.0,
+            Unit::Mi => 1609.344,
+            Unit::Ft => 0.3048,
+            Unit::Cm => 0.01,
+            Unit::In => 0.0254,
+        }
+    }
+
+    /// The counterpart of this unit in the given unit system, e.g. `kg` becomes `lb`
+    /// for imperial.
+    pub fn in_system(self, system: UnitSystem) -> Unit {
+        match (self, system) {
+            (Unit::Kg, UnitSystem::Imperial) => Unit::Lb,
+            (Unit::Lb, UnitSystem::Metric) => Unit::Kg,
+            (Unit::M, UnitSystem::Imperial) => Unit::Ft,
+            (Unit::Ft, UnitSystem::Metric) => Unit::M,
+            (Unit::Km, UnitSystem::Imperial) => Unit::Mi,
+            (Unit::Mi, UnitSystem::Metric) => Unit::Km,
+            (Unit::Cm, UnitSystem::Imperial) => Unit::In,
+            (Unit::In, UnitSystem::Metric) => Unit::Cm,
+            (unit, _) => unit,
+        }
+    }
+
+    /// Converts `value` from this unit to `to`. Returns `None` if the units
+    /// measure different things, like a load and a distance.
+    pub fn convert(self, value: f64, to: Unit) -> Option<f64> {
+        if self == to {
+            return Some(value);
+        }
+        if self.dimension() != to.dimension() {
+            return None;
+        }
+
+        // Two decimals is plenty for scores and avoids 220.46226218487757 lb
+        Some(round_to_hundredths(value * self.factor() / to.factor()))
+    }
+}
+
+/// Parses a distance like `1000m` or `21.1km` from the start of a string,
+/// which is how distances are put into movement names, e.g. "500m Rowing".
+pub fn parse_distance(s: &str) -> Option<(f64, Unit)> {
+    let token = s.split_whitespace().next()?;
+    let split_at = token.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (value, unit) = token.split_at(split_at);
+    let value = value.parse::<f64>().ok()?;
+
+    let unit = match unit.to_lowercase().as_str() {
+        "m" => Unit::M,
+        "km" => Unit::Km,
+        "mi" => Unit::Mi,
+        "ft" => Unit::Ft,
+        _ => return None,
+    };
+
+    Some((value, unit))
+}
+
+/// Converts a value with an optional unit into the given unit system. Values
+/// without a unit are returned as they are.
+pub fn convert_to_system(
+    value: f64,
+    unit: Option<Unit>,
+    system: UnitSystem,
+) -> (f64, Option<Unit>) {
+    match unit {
+        Some(unit) => {
+            let target = unit.in_system(system);
+            match unit.convert(value, target) {
+                Some(converted) => (converted, Some(target)),
+                None => (value, Some(unit)),
+            }
+        }
+        None => (value, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_to_string() {
+        assert_eq!(Unit::Kg.to_string(), "kg");
+        assert_eq!(Unit::Lb.to_string(), "lb");
+        assert_eq!(Unit::Km.to_string(), "km");
+        assert_eq!(Unit::In.to_string(), "in");
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(Unit::Kg.convert(100.0, Unit::Lb), Some(220.46));
+        assert_eq!(Unit::Lb.convert(225.0, Unit::Kg), Some(102.06));
+        assert_eq!(Unit::Km.convert(5.0, Unit::Mi), Some(3.11));
+        assert_eq!(Unit::Cm.convert(76.2, Unit::In), Some(30.0));
+        assert_eq!(Unit::M.convert(1000.0, Unit::M), Some(1000.0));
+        assert_eq!(Unit::Kg.convert(100.0, Unit::M), None);
+    }
+
+    #[test]
+    fn test_is_length() {
+        assert!(Unit::Km.is_length());
+        assert!(Unit::In.is_length());
+        assert!(!Unit::Kg.is_length());
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        assert_eq!(parse_distance("1000m Rowing"), Some((1000.0, Unit::M)));
+        assert_eq!(parse_distance("21.1km Rowing"), Some((21.1, Unit::Km)));
+        assert_eq!(parse_distance("1mi Run"), Some((1.0, Unit::Mi)));
+        assert_eq!(parse_distance("Rowing"), None);
+        assert_eq!(parse_distance("1000 Rowing"), None);
+        assert_eq!(parse_distance("5x Rowing"), None);
+        assert_eq!(parse_distance(""), None);
+    }
+
+    #[test]
+    fn test_convert_to_system() {
+        assert_eq!(
+            convert_to_system(100.0, Some(Unit::Kg), UnitSystem::Imperial),
+            (220.46, Some(Unit::Lb))
+        );
+        assert_eq!(
+            convert_to_system(100.0, Some(Unit::Kg), UnitSystem::Metric),
+            (100.0, Some(Unit::Kg))
+        );
+        assert_eq!(
+            convert_to_system(24.0, Some(Unit::In), UnitSystem::Metric),
+            (60.96, Some(Unit::Cm))
+        );
+        assert_eq!(
+            convert_to_system(12.0, None, UnitSystem::Imperial),
+            (12.0, None)
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::models::unit::{convert_to_system, Unit, UnitSystem};
 use serde::{Deserialize, Serialize};
 
 // https://github.com/serde-rs/serde/issues/1030#issuecomment-522278006
@@ -13,7 +14,28 @@ fn default_as_zero() -> i32 {
     0
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Gender {
+    Female,
+    Male,
+}
+
+/// Who can see the avatar of a user. Anyone with a signed path to the avatar
+/// can see it until the path expires.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AvatarPrivacy {
+    /// Anyone with the path to the avatar
+    #[default]
+    Public,
+    /// Logged in users
+    Users,
+    /// Only the user
+    Private,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub user_id: String,
     pub email: String,
@@ -23,10 +45,67 @@ pub struct User {
     pub first_name: String,
     pub last_name: String,
     pub date_of_birth: String,
+    /// Height in centimeters
     pub height: i32,
+    /// Weight in grams
     pub weight: i32,
     pub box_name: String,
     pub avatar_url: String,
+    #[serde(default)]
+    pub avatar_privacy: AvatarPrivacy,
+    #[serde(default)]
+    pub unit_system: UnitSystem,
+    #[serde(default)]
+    pub gender: Option<Gender>,
+}
+
+impl User {
+    /// The height and weight of the user in the unit system of the user.
+    pub fn body_measurements(&self) -> BodyMeasurements {
+        let (height, height_unit) =
+            convert_to_system(self.height as f64, Some(Unit::Cm), self.unit_system);
+        let (weight, weight_unit) = convert_to_system(
+            self.weight as f64 / 1000.0,
+            Some(Unit::Kg),
+            self.unit_system,
+        );
+
+        BodyMeasurements {
+            height,
+            height_unit: height_unit.unwrap_or(Unit::Cm),
+            weight,
+            weight_unit: weight_unit.unwrap_or(Unit::Kg),
+        }
+    }
+}
+
+/// The height and weight of a user with their units. Users are stored with
+/// the height in whole centimeters and the weight in grams.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BodyMeasurements {
+    pub height: f64,
+    pub height_unit: Unit,
+    pub weight: f64,
+    pub weight_unit: Unit,
+}
+
+/// A user as returned to the user, with the height and weight also in the
+/// unit system of the user.
+#[derive(Serialize, Debug)]
+pub struct UserResponse {
+    #[serde(flatten)]
+    pub user: User,
+    pub body_measurements: BodyMeasurements,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        let body_measurements = user.body_measurements();
+        UserResponse {
+            user,
+            body_measurements,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -64,6 +143,10 @@ pub struct CreateUser {
     pub box_name: String,
     #[serde(default = "default_as_empty_string")]
     pub avatar_url: String,
+    #[serde(default)]
+    pub unit_system: UnitSystem,
+    #[serde(default)]
+    pub gender: Option<Gender>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -75,5 +158,60 @@ pub struct UpdateUser {
     pub height: Option<i32>,
     pub weight: Option<i32>,
     pub box_name: Option<String>,
+    /// Only set by the server, avatars are uploaded on their own
+    #[serde(skip_deserializing)]
     pub avatar_url: Option<String>,
+    pub avatar_privacy: Option<AvatarPrivacy>,
+    pub unit_system: Option<UnitSystem>,
+    pub gender: Option<Gender>,
+}
+
+/// A signed path to an avatar, see `utils::avatar::signed_avatar_url`.
+#[derive(Deserialize, Debug)]
+pub struct SignedAvatarQuery {
+    pub expires: Option<i64>,
+    pub signature: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateSignedAvatarQuery {
+    /// Seconds until the signed path expires
+    pub expires_in: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignedAvatarResponse {
+    pub avatar_url: String,
+    pub expires_at: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_measurements() {
+        let mut user: User = serde_json::from_value(serde_json::json!({
+            "user_id": "user-id",
+            "email": "athlete@wodbook.com",
+            "password": "",
+            "first_name": "",
+            "last_name": "",
+            "date_of_birth": "",
+            "height": 185,
+            "weight": 85000,
+            "box_name": "",
+            "avatar_url": "",
+        }))
+        .unwrap();
+
+        let metric = user.body_measurements();
+        assert_eq!((metric.height, metric.height_unit), (185.0, Unit::Cm));
+        assert_eq!((metric.weight, metric.weight_unit), (85.0, Unit::Kg));
+
+        user.unit_system = UnitSystem::Imperial;
+        let imperial = user.body_measurements();
+        assert_eq!((imperial.height, imperial.height_unit), (72.83, Unit::In));
+        assert_eq!((imperial.weight, imperial.weight_unit), (187.39, Unit::Lb));
+    }
 }
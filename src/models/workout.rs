@@ -1,4 +1,6 @@
+use crate::models::unit::{convert_to_system, Unit, UnitSystem};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
 use std::vec::Vec;
 
@@ -26,6 +28,40 @@ pub enum WorkoutMeasurement {
     None,
 }
 
+/// What kind of value a workout score holds.
+#[derive(Debug, PartialEq)]
+enum ScoreKind {
+    Duration,
+    Mass,
+    Length,
+    Count,
+    Unscored,
+}
+
+impl WorkoutMeasurement {
+    fn score_kind(&self) -> ScoreKind {
+        match self {
+            WorkoutMeasurement::Time | WorkoutMeasurement::TimedRounds => ScoreKind::Duration,
+            WorkoutMeasurement::Load => ScoreKind::Mass,
+            WorkoutMeasurement::Distance => ScoreKind::Length,
+            WorkoutMeasurement::Repetitions
+            | WorkoutMeasurement::Rounds
+            | WorkoutMeasurement::Tabata
+            | WorkoutMeasurement::Total => ScoreKind::Count,
+            WorkoutMeasurement::Unknown | WorkoutMeasurement::None => ScoreKind::Unscored,
+        }
+    }
+
+    /// The unit scores for this measurement are logged in when none is given.
+    pub fn default_unit(&self, system: UnitSystem) -> Option<Unit> {
+        match self {
+            WorkoutMeasurement::Load => Some(Unit::Kg.in_system(system)),
+            WorkoutMeasurement::Distance => Some(Unit::M.in_system(system)),
+            _ => None,
+        }
+    }
+}
+
 // TODO: Find a nicer way of serializing into strings without the quotes
 impl fmt::Display for WorkoutMeasurement {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -34,10 +70,19 @@ impl fmt::Display for WorkoutMeasurement {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkoutModel {
     pub workout_id: String,
     pub user_id: String,
+    /// The workout this one was copied from, if it is a fork
+    #[serde(default)]
+    pub forked_from: Option<String>,
+    /// Where an imported record came from, e.g. `mywod:<client id>:<record id>`
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// The import that added the record, so the import can be rolled back
+    #[serde(default)]
+    pub import_id: Option<String>,
     pub name: String,
     pub measurement: WorkoutMeasurement,
     pub description: String,
@@ -49,6 +94,7 @@ pub struct WorkoutModel {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WorkoutResponse {
     pub workout_id: String,
+    pub forked_from: Option<String>,
     pub name: String,
     pub measurement: WorkoutMeasurement,
     pub description: String,
@@ -62,6 +108,7 @@ impl WorkoutResponse {
     pub fn from_model(model: WorkoutModel, scores: Vec<WorkoutScoreModel>) -> Self {
         WorkoutResponse {
             workout_id: model.workout_id,
+            forked_from: model.forked_from,
             name: model.name,
             measurement: model.measurement,
             description: model.description,
@@ -78,11 +125,6 @@ pub struct ManyWorkoutsResponse {
     pub data: Vec<WorkoutModel>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ManyWorkoutScoresResponse {
-    pub data: Vec<WorkoutScoreModel>,
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateWorkout {
     pub name: String,
@@ -90,44 +132,145 @@ pub struct CreateWorkout {
     pub measurement: WorkoutMeasurement,
     #[serde(default = "default_as_false")]
     pub is_public: bool,
+    /// Set by importers to recognize records they added before
+    #[serde(skip)]
+    pub source_id: Option<String>,
+    /// Set by importers to roll back what an import added
+    #[serde(skip)]
+    pub import_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateWorkout {
     pub name: Option<String>,
     pub description: Option<String>,
+    pub measurement: Option<WorkoutMeasurement>,
+    pub is_public: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateWorkoutScore {
     pub score: f64,
+    pub unit: Option<Unit>,
     #[serde(default = "default_as_false")]
     pub rx: bool,
     #[serde(default = "default_as_empty_string")]
     pub notes: String,
+    #[serde(default = "default_as_false")]
+    pub personal_record: bool,
     pub created_at: Option<String>,
+    /// Set by importers to recognize records they added before
+    #[serde(skip)]
+    pub source_id: Option<String>,
+    /// Set by importers to roll back what an import added
+    #[serde(skip)]
+    pub import_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateWorkoutScore {
     pub score: Option<f64>,
+    pub unit: Option<Unit>,
     pub rx: Option<bool>,
     pub notes: Option<String>,
+    pub personal_record: Option<bool>,
     pub created_at: Option<String>,
+    /// Set by importers to tag a score imported before sources were tracked
+    #[serde(skip)]
+    pub source_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl From<CreateWorkoutScore> for UpdateWorkoutScore {
+    /// The update that brings a score in line with `score`, e.g. when it is
+    /// imported again.
+    fn from(score: CreateWorkoutScore) -> Self {
+        UpdateWorkoutScore {
+            score: Some(score.score),
+            unit: score.unit,
+            rx: Some(score.rx),
+            notes: Some(score.notes),
+            personal_record: Some(score.personal_record),
+            created_at: None,
+            source_id: score.source_id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkoutScoreModel {
     pub workout_score_id: String,
     pub workout_id: String,
     pub user_id: String,
     pub score: f64,
+    #[serde(default)]
+    pub unit: Option<Unit>,
     pub rx: bool,
     pub notes: String,
+    /// Marked by the user as a personal record
+    #[serde(default)]
+    pub personal_record: bool,
+    /// Where an imported record came from, e.g. `mywod:<client id>:<record id>`
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// The import that added the record, so the import can be rolled back
+    #[serde(default)]
+    pub import_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+impl WorkoutScoreModel {
+    /// Converts the score into the given unit system.
+    pub fn convert_to(mut self, system: UnitSystem) -> Self {
+        let (score, unit) = convert_to_system(self.score, self.unit, system);
+        self.score = score;
+        self.unit = unit;
+        self
+    }
+}
+
+/// Checks if a score still makes sense when its workout changes measurement.
+/// Scores are kept as they are between measurements that hold the same kind
+/// of value, e.g. a time, or when the workout is no longer scored.
+pub fn convert_score_measurement(
+    score: &WorkoutScoreModel,
+    from: WorkoutMeasurement,
+    to: WorkoutMeasurement,
+) -> Result<WorkoutScoreModel, String> {
+    let target_kind = to.score_kind();
+    if from.score_kind() == target_kind || target_kind == ScoreKind::Unscored {
+        Ok(score.clone())
+    } else {
+        Err(format!("A {} score can not be converted to {}", from, to))
+    }
+}
+
+/// Converts scores into the reader's unit system. Scores logged in different
+/// units get sorted again since the database sorted them by their raw values.
+pub fn convert_workout_scores(
+    measurement: WorkoutMeasurement,
+    scores: Vec<WorkoutScoreModel>,
+    system: UnitSystem,
+) -> Vec<WorkoutScoreModel> {
+    let mut scores: Vec<WorkoutScoreModel> = scores
+        .into_iter()
+        .map(|score| score.convert_to(system))
+        .collect();
+
+    // ascending for timed, descending for the rest, Rx'd scores first on ties
+    scores.sort_by(|a, b| {
+        let ordering = a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal);
+        let ordering = if measurement == WorkoutMeasurement::Time {
+            ordering
+        } else {
+            ordering.reverse()
+        };
+        ordering.then(b.rx.cmp(&a.rx))
+    });
+
+    scores
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +288,92 @@ mod tests {
         assert_eq!(WorkoutMeasurement::Unknown.to_string(), "unknown");
         assert_eq!(WorkoutMeasurement::None.to_string(), "none");
     }
+
+    #[test]
+    fn test_workout_without_fork_or_source() {
+        let workout: WorkoutModel = serde_json::from_str(
+            r#"{
+                "workout_id": "workout-id",
+                "user_id": "user-id",
+                "name": "Fran",
+                "measurement": "time",
+                "description": "21-15-9 Thrusters / Pull ups",
+                "is_public": true,
+                "created_at": "2021-01-01T00:00:00+00:00",
+                "updated_at": "2021-01-01T00:00:00+00:00"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(workout.forked_from, None);
+        assert_eq!(workout.source_id, None);
+    }
+
+    fn score(score: f64, unit: Option<Unit>, rx: bool) -> WorkoutScoreModel {
+        WorkoutScoreModel {
+            workout_score_id: "workout_score_id".to_owned(),
+            workout_id: "workout_id".to_owned(),
+            user_id: "user_id".to_owned(),
+            score,
+            unit,
+            rx,
+            notes: "".to_owned(),
+            personal_record: false,
+            source_id: None,
+            import_id: None,
+            created_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_convert_score_measurement() {
+        let time_score = score(300.0, None, true);
+        let res = convert_score_measurement(
+            &time_score,
+            WorkoutMeasurement::Time,
+            WorkoutMeasurement::TimedRounds,
+        );
+        assert_eq!(res.unwrap().score, 300.0);
+
+        let res = convert_score_measurement(
+            &time_score,
+            WorkoutMeasurement::Time,
+            WorkoutMeasurement::None,
+        );
+        assert!(res.is_ok());
+
+        let res = convert_score_measurement(
+            &time_score,
+            WorkoutMeasurement::Time,
+            WorkoutMeasurement::Load,
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            "A time score can not be converted to load"
+        );
+
+        let res = convert_score_measurement(
+            &score(20.0, None, true),
+            WorkoutMeasurement::Rounds,
+            WorkoutMeasurement::Repetitions,
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_convert_workout_scores() {
+        let scores = vec![
+            score(100.0, Some(Unit::Kg), false),
+            score(240.0, Some(Unit::Lb), true),
+        ];
+        let res = convert_workout_scores(WorkoutMeasurement::Load, scores, UnitSystem::Imperial);
+        assert_eq!(res[0].score, 240.0);
+        assert_eq!(res[1].score, 220.46);
+        assert_eq!(res[1].unit, Some(Unit::Lb));
+
+        let scores = vec![score(120.0, None, false), score(120.0, None, true)];
+        let res = convert_workout_scores(WorkoutMeasurement::Time, scores, UnitSystem::Metric);
+        assert!(res[0].rx);
+        assert_eq!(res[1].unit, None);
+    }
 }
@@ -0,0 +1,45 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::{is_valid_feed_url, CreateFeed, FeedModel};
+
+use async_trait::async_trait;
+
+/// Keeps the feeds users are subscribed to, feeds are never shared.
+#[async_trait(?Send)]
+pub trait FeedRepository: Send + Sync {
+    /// Gets the feeds the user is subscribed to, sorted by title.
+    async fn get_feeds(&self, user_id: &str) -> WebResult<Vec<FeedModel>>;
+
+    async fn find_feed_by_url(&self, user_id: &str, url: &str) -> WebResult<Option<FeedModel>>;
+
+    async fn create_feed(&self, user_id: &str, feed: CreateFeed) -> WebResult<FeedModel>;
+
+    async fn delete_feed(&self, user_id: &str, feed_id: &str) -> WebResult<()>;
+
+    /// Unsubscribes the user from the feeds an import added.
+    async fn delete_imported_feeds(&self, user_id: &str, import_id: &str) -> WebResult<u64>;
+}
+
+/// A subscription to a feed, which needs an http or https url.
+pub(crate) fn new_feed(user_id: &str, feed: CreateFeed, now: &str) -> WebResult<FeedModel> {
+    let url = feed.url.trim().to_owned();
+    if !is_valid_feed_url(&url) {
+        return Err(AppError::BadRequest(
+            "A feed url has to start with http:// or https://".to_owned(),
+        ));
+    }
+
+    Ok(FeedModel {
+        feed_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        title: feed.title.trim().to_owned(),
+        url,
+        source_id: feed.source_id,
+        import_id: feed.import_id,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
+    })
+}
+
+pub(crate) fn feed_exists_error() -> AppError {
+    AppError::Conflict("You are already subscribed to this feed".to_owned())
+}
@@ -0,0 +1,140 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::{
+    ImportModel, ImportProgress, ImportStatus, ProfileSnapshot, RollbackResult,
+};
+use crate::models::mywod::{MyWodResponse, RowReport};
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+/// Keeps track of the imports of users and how far along they are.
+#[async_trait(?Send)]
+pub trait ImportRepository: Send + Sync {
+    async fn create_import(
+        &self,
+        user_id: &str,
+        user_email: &str,
+        source: &str,
+        file_path: &str,
+    ) -> WebResult<ImportModel>;
+
+    /// Gets an import of the user, imports are never shared.
+    async fn get_import_by_id(&self, user_id: &str, import_id: &str) -> WebResult<ImportModel>;
+
+    /// Gets an import for the worker processing it, regardless of its user.
+    async fn find_import_by_id(&self, import_id: &str) -> WebResult<Option<ImportModel>>;
+
+    /// Gets the imports that were not done when the server stopped.
+    async fn get_unfinished_imports(&self) -> WebResult<Vec<ImportModel>>;
+
+    /// Claims an unfinished import for the worker `owner` until `expires_at`,
+    /// in milliseconds since the epoch, so only one worker processes it. The
+    /// worker holding the claim renews it the same way. Returns `None` when
+    /// another worker holds the claim or the import is done.
+    async fn claim_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        expires_at: i64,
+    ) -> WebResult<Option<ImportModel>>;
+
+    /// The avatar paths in the profiles that rolling back imports would
+    /// restore, so those avatars are kept while the imports can be rolled back.
+    async fn get_snapshot_avatar_urls(&self) -> WebResult<HashSet<String>>;
+
+    /// Sets the status of an import claimed by `owner`, fails with a conflict
+    /// when another worker took over the claim.
+    async fn set_status(
+        &self,
+        import_id: &str,
+        owner: &str,
+        status: ImportStatus,
+        error: Option<String>,
+    ) -> WebResult<()>;
+
+    /// Starts counting progress from the beginning, as an import that was
+    /// interrupted is processed again as a whole.
+    async fn set_total(&self, import_id: &str, total: u32) -> WebResult<()>;
+
+    async fn add_progress(&self, import_id: &str, processed: u32) -> WebResult<()>;
+
+    /// Marks the import claimed by `owner` as completed, fails with a conflict
+    /// when another worker took over the claim. Rows that belong to nothing,
+    /// like sessions of deleted movements, are never processed, so the
+    /// progress is set to the total.
+    async fn complete_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        total: u32,
+        result: &MyWodResponse,
+        report: &[RowReport],
+    ) -> WebResult<()>;
+
+    /// Keeps the profile of the user from before the import. An import that
+    /// is run again after an interruption keeps the profile of the first run,
+    /// as the profile may already have been overwritten by then.
+    async fn set_previous_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()>;
+
+    /// Keeps the profile of the user as the import left it, replacing the one
+    /// of an earlier run.
+    async fn set_imported_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()>;
+
+    async fn complete_rollback(&self, import_id: &str, rollback: &RollbackResult) -> WebResult<()>;
+}
+
+/// Whether `owner` can claim the import at `now`, in milliseconds since the
+/// epoch.
+pub(crate) fn is_claimable(import: &ImportModel, owner: &str, now: i64) -> bool {
+    let unfinished = matches!(import.status, ImportStatus::Pending | ImportStatus::Running);
+    let free = match (&import.claimed_by, import.claim_expires_at) {
+        (None, _) => true,
+        (Some(claimed_by), _) if claimed_by == owner => true,
+        (Some(_), Some(expires_at)) => expires_at < now,
+        (Some(_), None) => false,
+    };
+
+    unfinished && free
+}
+
+/// The error when a worker saves the outcome of an import it no longer holds
+/// the claim on, so the outcome of the worker that took over is kept.
+pub(crate) fn lost_claim(import_id: &str) -> AppError {
+    AppError::Conflict(format!("Import {} is claimed by another worker", import_id))
+}
+
+pub(crate) fn new_import(
+    user_id: &str,
+    user_email: &str,
+    source: &str,
+    file_path: &str,
+    now: &str,
+) -> ImportModel {
+    ImportModel {
+        import_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        user_email: user_email.to_owned(),
+        source: source.to_owned(),
+        status: ImportStatus::Pending,
+        file_path: file_path.to_owned(),
+        progress: ImportProgress::default(),
+        result: None,
+        report: vec![],
+        error: None,
+        previous_profile: None,
+        imported_profile: None,
+        rollback: None,
+        claimed_by: None,
+        claim_expires_at: None,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
+    }
+}
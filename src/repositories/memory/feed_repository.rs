@@ -0,0 +1,79 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::{CreateFeed, FeedModel};
+use crate::repositories::feed_repository::{feed_exists_error, new_feed};
+use crate::repositories::memory::MemoryDatabase;
+use crate::repositories::FeedRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+pub struct MemoryFeedRepository {
+    pub database: MemoryDatabase,
+}
+
+#[async_trait(?Send)]
+impl FeedRepository for MemoryFeedRepository {
+    async fn get_feeds(&self, user_id: &str) -> WebResult<Vec<FeedModel>> {
+        let collections = self.database.lock();
+        let mut feeds: Vec<FeedModel> = collections
+            .feeds
+            .iter()
+            .filter(|feed| feed.user_id == user_id)
+            .cloned()
+            .collect();
+        feeds.sort_by(|a, b| a.title.cmp(&b.title));
+
+        Ok(feeds)
+    }
+
+    async fn find_feed_by_url(&self, user_id: &str, url: &str) -> WebResult<Option<FeedModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .feeds
+            .iter()
+            .find(|feed| feed.user_id == user_id && feed.url == url)
+            .cloned())
+    }
+
+    async fn create_feed(&self, user_id: &str, feed: CreateFeed) -> WebResult<FeedModel> {
+        let new_feed = new_feed(user_id, feed, &Utc::now().to_rfc3339())?;
+
+        let mut collections = self.database.lock();
+        if collections
+            .feeds
+            .iter()
+            .any(|feed| feed.user_id == user_id && feed.url == new_feed.url)
+        {
+            return Err(feed_exists_error());
+        }
+        collections.feeds.push(new_feed.clone());
+
+        Ok(new_feed)
+    }
+
+    async fn delete_feed(&self, user_id: &str, feed_id: &str) -> WebResult<()> {
+        let mut collections = self.database.lock();
+        let before = collections.feeds.len();
+        collections
+            .feeds
+            .retain(|feed| !(feed.feed_id == feed_id && feed.user_id == user_id));
+
+        if collections.feeds.len() == before {
+            return Err(AppError::NotFound(
+                "Feed with this id does not exist".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_imported_feeds(&self, user_id: &str, import_id: &str) -> WebResult<u64> {
+        let mut collections = self.database.lock();
+        let before = collections.feeds.len();
+        collections.feeds.retain(|feed| {
+            !(feed.user_id == user_id && feed.import_id.as_deref() == Some(import_id))
+        });
+
+        Ok((before - collections.feeds.len()) as u64)
+    }
+}
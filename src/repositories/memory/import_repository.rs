@@ -0,0 +1,218 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::{ImportModel, ImportStatus, ProfileSnapshot, RollbackResult};
+use crate::models::mywod::{MyWodResponse, RowReport};
+use crate::repositories::import_repository::{is_claimable, lost_claim, new_import};
+use crate::repositories::memory::MemoryDatabase;
+use crate::repositories::ImportRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use std::collections::HashSet;
+
+pub struct MemoryImportRepository {
+    pub database: MemoryDatabase,
+}
+
+impl MemoryImportRepository {
+    /// Changes the import with the id, if there is one, like an update of a
+    /// single document that matches nothing.
+    fn update(&self, import_id: &str, change: impl FnOnce(&mut ImportModel)) {
+        let mut collections = self.database.lock();
+        if let Some(import) = collections
+            .imports
+            .iter_mut()
+            .find(|import| import.import_id == import_id)
+        {
+            change(import);
+            import.updated_at = Utc::now().to_rfc3339();
+        }
+    }
+
+    /// Changes the import with the id if `owner` holds the claim on it.
+    fn update_claimed(
+        &self,
+        import_id: &str,
+        owner: &str,
+        change: impl FnOnce(&mut ImportModel),
+    ) -> WebResult<()> {
+        let mut collections = self.database.lock();
+        let import = collections.imports.iter_mut().find(|import| {
+            import.import_id == import_id && import.claimed_by.as_deref() == Some(owner)
+        });
+
+        match import {
+            Some(import) => {
+                change(import);
+                import.updated_at = Utc::now().to_rfc3339();
+                Ok(())
+            }
+            None => Err(lost_claim(import_id)),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ImportRepository for MemoryImportRepository {
+    async fn create_import(
+        &self,
+        user_id: &str,
+        user_email: &str,
+        source: &str,
+        file_path: &str,
+    ) -> WebResult<ImportModel> {
+        let import = new_import(
+            user_id,
+            user_email,
+            source,
+            file_path,
+            &Utc::now().to_rfc3339(),
+        );
+        self.database.lock().imports.push(import.clone());
+
+        Ok(import)
+    }
+
+    async fn get_import_by_id(&self, user_id: &str, import_id: &str) -> WebResult<ImportModel> {
+        let collections = self.database.lock();
+        match collections
+            .imports
+            .iter()
+            .find(|import| import.import_id == import_id && import.user_id == user_id)
+        {
+            Some(import) => Ok(import.clone()),
+            None => Err(AppError::NotFound(
+                "Import with this id does not exist".to_string(),
+            )),
+        }
+    }
+
+    async fn find_import_by_id(&self, import_id: &str) -> WebResult<Option<ImportModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .imports
+            .iter()
+            .find(|import| import.import_id == import_id)
+            .cloned())
+    }
+
+    async fn get_unfinished_imports(&self) -> WebResult<Vec<ImportModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .imports
+            .iter()
+            .filter(|import| matches!(import.status, ImportStatus::Pending | ImportStatus::Running))
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        expires_at: i64,
+    ) -> WebResult<Option<ImportModel>> {
+        let now = Utc::now();
+        let mut collections = self.database.lock();
+        let import = collections.imports.iter_mut().find(|import| {
+            import.import_id == import_id && is_claimable(import, owner, now.timestamp_millis())
+        });
+
+        Ok(import.map(|import| {
+            import.claimed_by = Some(owner.to_owned());
+            import.claim_expires_at = Some(expires_at);
+            import.updated_at = now.to_rfc3339();
+            import.clone()
+        }))
+    }
+
+    async fn get_snapshot_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .imports
+            .iter()
+            .filter(|import| import.status != ImportStatus::RolledBack)
+            .filter_map(|import| import.previous_profile.as_ref())
+            .filter(|profile| !profile.avatar_url.is_empty())
+            .map(|profile| profile.avatar_url.to_owned())
+            .collect())
+    }
+
+    async fn set_status(
+        &self,
+        import_id: &str,
+        owner: &str,
+        status: ImportStatus,
+        error: Option<String>,
+    ) -> WebResult<()> {
+        self.update_claimed(import_id, owner, |import| {
+            import.status = status;
+            import.error = error;
+        })
+    }
+
+    async fn set_total(&self, import_id: &str, total: u32) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.progress.processed = 0;
+            import.progress.total = total;
+        });
+
+        Ok(())
+    }
+
+    async fn add_progress(&self, import_id: &str, processed: u32) -> WebResult<()> {
+        self.update(import_id, |import| import.progress.processed += processed);
+
+        Ok(())
+    }
+
+    async fn complete_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        total: u32,
+        result: &MyWodResponse,
+        report: &[RowReport],
+    ) -> WebResult<()> {
+        self.update_claimed(import_id, owner, |import| {
+            import.status = ImportStatus::Completed;
+            import.progress.processed = total;
+            import.result = Some(result.clone());
+            import.report = report.to_vec();
+        })
+    }
+
+    async fn set_previous_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        self.update(import_id, |import| {
+            if import.previous_profile.is_none() {
+                import.previous_profile = Some(profile.clone());
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn set_imported_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.imported_profile = Some(profile.clone());
+        });
+
+        Ok(())
+    }
+
+    async fn complete_rollback(&self, import_id: &str, rollback: &RollbackResult) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.status = ImportStatus::RolledBack;
+            import.rollback = Some(rollback.clone());
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::models::feed::FeedModel;
+use crate::models::import::ImportModel;
+use crate::models::movement::{MovementModel, MovementScoreModel};
+use crate::models::user::User;
+use crate::models::workout::{WorkoutModel, WorkoutScoreModel};
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod feed_repository;
+mod import_repository;
+mod movement_repository;
+mod user_repository;
+mod workout_repository;
+
+pub use feed_repository::MemoryFeedRepository;
+pub use import_repository::MemoryImportRepository;
+pub use movement_repository::MemoryMovementRepository;
+pub use user_repository::MemoryUserRepository;
+pub use workout_repository::MemoryWorkoutRepository;
+
+#[derive(Default)]
+pub(crate) struct Collections {
+    pub users: Vec<User>,
+    pub workouts: Vec<WorkoutModel>,
+    pub workout_scores: Vec<WorkoutScoreModel>,
+    pub movements: Vec<MovementModel>,
+    pub movement_scores: Vec<MovementScoreModel>,
+    pub feeds: Vec<FeedModel>,
+    pub imports: Vec<ImportModel>,
+}
+
+/// Keeps everything in memory, which is gone once the server stops. Meant for
+/// tests, where each test can start with a database of its own. Clones share
+/// their data.
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    collections: Arc<Mutex<Collections>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lock is never held across an await, so it is only poisoned when
+    /// a repository panicked halfway through a change.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Collections> {
+        self.collections
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Whether a user can see a resource, which are their own and the public ones.
+pub(crate) fn is_visible(owner_id: &str, is_public: bool, user_id: &str) -> bool {
+    owner_id == user_id || is_public
+}
@@ -0,0 +1,540 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::movement::{
+    CreateMovement, CreateMovementScore, MovementMeasurement, MovementModel, MovementScoreModel,
+    UpdateMovement, UpdateMovementScore,
+};
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::repositories::memory::{is_visible, Collections, MemoryDatabase};
+use crate::repositories::movement_repository::{
+    forked_movement, new_movement, new_movement_score, plan_measurement_change,
+    updated_movement_score,
+};
+use crate::repositories::{
+    imported_score_keys, is_imported_score, scored_by_others_error, unconvertible_scores_error,
+    MovementRepository,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use std::cmp::Ordering;
+
+pub struct MemoryMovementRepository {
+    pub database: MemoryDatabase,
+}
+
+impl MemoryMovementRepository {
+    /// Whether the user created a movement with this name. Public movements of
+    /// others do not count, a user can have a copy of one with the same name.
+    fn has_own_movement_named(&self, user_id: &str, name: &str) -> bool {
+        self.database
+            .lock()
+            .movements
+            .iter()
+            .any(|movement| movement.name == name && movement.user_id == user_id)
+    }
+
+    fn find_free_movement_name(&self, user_id: &str, name: &str) -> String {
+        let is_taken = |candidate: &str| self.has_own_movement_named(user_id, candidate);
+
+        let mut candidate = name.to_owned();
+        let mut n = 2;
+        while is_taken(&candidate) {
+            candidate = format!("{} ({})", name, n);
+            n += 1;
+        }
+
+        candidate
+    }
+
+    async fn get_own_movement(&self, user_id: &str, movement_id: &str) -> WebResult<MovementModel> {
+        let movement = match self.find_movement_by_id(user_id, movement_id).await? {
+            Some(movement) => movement,
+            None => return Err(AppError::NotFound("Movement not found".to_owned())),
+        };
+
+        if movement.user_id != user_id {
+            return Err(AppError::Forbidden(
+                "Only the creator of a movement can change it".to_owned(),
+            ));
+        }
+
+        Ok(movement)
+    }
+
+    /// Converts the scores of a movement to a new measurement, with the lock
+    /// held until the converted scores are saved.
+    fn plan_measurement_change(
+        collections: &Collections,
+        movement: &MovementModel,
+        measurement: MovementMeasurement,
+    ) -> (Vec<MovementScoreModel>, MeasurementChangePreview) {
+        let scores = collections
+            .movement_scores
+            .iter()
+            .filter(|score| score.movement_id == movement.movement_id)
+            .cloned()
+            .collect();
+
+        plan_measurement_change(movement, scores, measurement)
+    }
+
+    fn get_movement_scores_where(
+        &self,
+        filter: impl Fn(&MovementScoreModel) -> bool,
+        order: impl Fn(&MovementScoreModel, &MovementScoreModel) -> Ordering,
+    ) -> Vec<MovementScoreModel> {
+        let mut scores: Vec<MovementScoreModel> = self
+            .database
+            .lock()
+            .movement_scores
+            .iter()
+            .filter(|score| filter(score))
+            .cloned()
+            .collect();
+        scores.sort_by(order);
+
+        scores
+    }
+}
+
+#[async_trait(?Send)]
+impl MovementRepository for MemoryMovementRepository {
+    async fn find_movement_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .movements
+            .iter()
+            .find(|movement| {
+                movement.name == name && is_visible(&movement.user_id, movement.is_public, user_id)
+            })
+            .cloned())
+    }
+
+    async fn find_movement_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .movements
+            .iter()
+            .find(|movement| {
+                movement.user_id == user_id && movement.source_id.as_deref() == Some(source_id)
+            })
+            .cloned())
+    }
+
+    async fn find_imported_movement_scores(
+        &self,
+        user_id: &str,
+        movement_scores: &[&CreateMovementScore],
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            movement_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        let collections = self.database.lock();
+        Ok(collections
+            .movement_scores
+            .iter()
+            .filter(|score| {
+                score.user_id == user_id
+                    && is_imported_score(&score.source_id, &score.created_at, &sources, &dates)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn find_movement_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .movements
+            .iter()
+            .find(|movement| {
+                movement.movement_id == movement_id
+                    && is_visible(&movement.user_id, movement.is_public, user_id)
+            })
+            .cloned())
+    }
+
+    async fn get_movements(&self, user_id: &str) -> WebResult<Vec<MovementModel>> {
+        let collections = self.database.lock();
+        let mut movements: Vec<MovementModel> = collections
+            .movements
+            .iter()
+            .filter(|movement| is_visible(&movement.user_id, movement.is_public, user_id))
+            .cloned()
+            .collect();
+        movements.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(movements)
+    }
+
+    async fn create_movement(
+        &self,
+        user_id: &str,
+        movement: CreateMovement,
+    ) -> WebResult<MovementModel> {
+        // A name of a public movement is taken as well, the bulk creation of
+        // imports only checks the names of the user
+        if self
+            .find_movement_by_name(user_id, &movement.name)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(
+                "A movement with this name already exists".to_string(),
+            ));
+        }
+
+        self.create_movements(user_id, vec![movement])
+            .await
+            .map(|mut movements| movements.remove(0))
+    }
+
+    async fn create_movements(
+        &self,
+        user_id: &str,
+        movements: Vec<CreateMovement>,
+    ) -> WebResult<Vec<MovementModel>> {
+        let mut collections = self.database.lock();
+        let names: Vec<&str> = movements.iter().map(|w| w.name.as_str()).collect();
+        let has_duplicates = names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| names[..i].contains(name));
+        if has_duplicates
+            || collections.movements.iter().any(|movement| {
+                names.contains(&movement.name.as_str()) && movement.user_id == user_id
+            })
+        {
+            return Err(AppError::Conflict(
+                "A movement with this name already exists".to_string(),
+            ));
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let movements: Vec<MovementModel> = movements
+            .into_iter()
+            .map(|movement| new_movement(user_id, movement, &now))
+            .collect();
+        collections.movements.extend(movements.iter().cloned());
+
+        Ok(movements)
+    }
+
+    async fn fork_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<MovementModel> {
+        let original = self.get_movement_by_id(user_id, movement_id).await?;
+
+        // Copies are made of the movements of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only movements of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if self.has_own_movement_named(user_id, &name) {
+                    return Err(AppError::Conflict(
+                        "A movement with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => self.find_free_movement_name(user_id, &original.name),
+        };
+
+        let movement = forked_movement(&original, user_id, name, &Utc::now().to_rfc3339());
+
+        let mut collections = self.database.lock();
+        collections.movements.push(movement.clone());
+        if fork.move_scores {
+            for score in collections.movement_scores.iter_mut().filter(|score| {
+                score.movement_id == original.movement_id && score.user_id == user_id
+            }) {
+                score.movement_id = movement.movement_id.to_owned();
+            }
+        }
+
+        Ok(movement)
+    }
+
+    async fn preview_movement_update(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MeasurementChangePreview> {
+        let movement = self.get_own_movement(user_id, movement_id).await?;
+        let measurement = movement_update.measurement.unwrap_or(movement.measurement);
+
+        Ok(Self::plan_measurement_change(&self.database.lock(), &movement, measurement).1)
+    }
+
+    async fn update_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MovementModel> {
+        let existing_movement = self.get_own_movement(user_id, movement_id).await?;
+        let new_measurement = movement_update
+            .measurement
+            .unwrap_or(existing_movement.measurement);
+
+        let new_name = movement_update
+            .name
+            .unwrap_or_else(|| existing_movement.name.to_owned());
+
+        // Check if there exists a movement with the new name
+        if let Some(conflicting_movement) = self.find_movement_by_name(user_id, &new_name).await? {
+            if conflicting_movement.movement_id != movement_id {
+                return Err(AppError::Conflict(
+                    "Movement with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        let updated_movement = MovementModel {
+            name: new_name,
+            measurement: new_measurement,
+            is_public: movement_update
+                .is_public
+                .unwrap_or(existing_movement.is_public),
+            updated_at: Utc::now().to_rfc3339(),
+            ..existing_movement.clone()
+        };
+
+        // Planned while holding the lock, so scores logged in the meantime
+        // are converted as well
+        let mut collections = self.database.lock();
+        let (converted_scores, preview) =
+            Self::plan_measurement_change(&collections, &existing_movement, new_measurement);
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        let scored_by_others = collections
+            .movement_scores
+            .iter()
+            .any(|score| score.movement_id == movement_id && score.user_id != user_id);
+        if existing_movement.is_public && !updated_movement.is_public && scored_by_others {
+            return Err(scored_by_others_error("movement"));
+        }
+
+        for movement in collections.movements.iter_mut() {
+            if movement.movement_id == movement_id {
+                *movement = updated_movement.clone();
+            }
+        }
+        for converted in converted_scores {
+            for score in collections.movement_scores.iter_mut() {
+                if score.movement_score_id == converted.movement_score_id {
+                    *score = converted.clone();
+                }
+            }
+        }
+
+        Ok(updated_movement)
+    }
+
+    async fn delete_movement(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
+        let movement = self.find_movement_by_id(user_id, movement_id).await?;
+
+        if movement.is_none() {
+            return Err(AppError::NotFound("Movement does not exist".to_owned()));
+        }
+
+        let mut collections = self.database.lock();
+        collections
+            .movements
+            .retain(|movement| movement.movement_id != movement_id);
+        collections
+            .movement_scores
+            .retain(|score| !(score.movement_id == movement_id && score.user_id == user_id));
+
+        Ok(())
+    }
+
+    async fn create_movement_score(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_score: CreateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let movement_score =
+            new_movement_score(user_id, movement, movement_score, &Utc::now().to_rfc3339())?;
+        self.database
+            .lock()
+            .movement_scores
+            .push(movement_score.clone());
+
+        Ok(movement_score)
+    }
+
+    async fn create_movement_scores(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_scores: Vec<CreateMovementScore>,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let now = Utc::now().to_rfc3339();
+        let movement_scores = movement_scores
+            .into_iter()
+            .map(|score| new_movement_score(user_id, movement, score, &now))
+            .collect::<WebResult<Vec<MovementScoreModel>>>()?;
+        self.database
+            .lock()
+            .movement_scores
+            .extend(movement_scores.iter().cloned());
+
+        Ok(movement_scores)
+    }
+
+    async fn get_movement_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        Ok(self.get_movement_scores_where(
+            |score| score.user_id == user_id,
+            |a, b| a.created_at.cmp(&b.created_at),
+        ))
+    }
+
+    async fn get_movement_scores_for_movement(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        // ascending for timed, descending for the rest
+        let lower_is_better = movement.measurement.lower_is_better();
+        Ok(self.get_movement_scores_where(
+            |score| score.user_id == user_id && score.movement_id == movement.movement_id,
+            |a, b| {
+                let by_score = a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal);
+                if lower_is_better {
+                    by_score
+                } else {
+                    by_score.reverse()
+                }
+            },
+        ))
+    }
+
+    async fn get_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<MovementScoreModel> {
+        let collections = self.database.lock();
+        match collections.movement_scores.iter().find(|score| {
+            score.movement_id == movement_id
+                && score.movement_score_id == movement_score_id
+                && score.user_id == user_id
+        }) {
+            Some(score) => Ok(score.clone()),
+            None => Err(AppError::NotFound("Entity not found".to_string())),
+        }
+    }
+
+    async fn update_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+        new_score: UpdateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let score = self
+            .get_movement_score_by_id(user_id, movement_id, movement_score_id)
+            .await?;
+
+        let movement = self.get_movement_by_id(user_id, movement_id).await?;
+        let updated =
+            updated_movement_score(&movement, score, new_score, &Utc::now().to_rfc3339())?;
+
+        let mut collections = self.database.lock();
+        for score in collections.movement_scores.iter_mut() {
+            if score.movement_score_id == movement_score_id {
+                *score = updated.clone();
+            }
+        }
+
+        Ok(updated)
+    }
+
+    async fn delete_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<()> {
+        // Ensure the score exists for the user
+        self.get_movement_score_by_id(user_id, movement_id, movement_score_id)
+            .await?;
+
+        self.database
+            .lock()
+            .movement_scores
+            .retain(|score| score.movement_score_id != movement_score_id);
+
+        Ok(())
+    }
+
+    async fn delete_imported_movement_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let mut collections = self.database.lock();
+        let before = collections.movement_scores.len();
+        collections.movement_scores.retain(|score| {
+            !(score.user_id == user_id && score.import_id.as_deref() == Some(import_id))
+        });
+
+        Ok((before - collections.movement_scores.len()) as u64)
+    }
+
+    async fn delete_imported_movements(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let mut collections = self.database.lock();
+        let collections = &mut *collections;
+        let before = collections.movements.len();
+        let mut kept = 0;
+        let scores = &collections.movement_scores;
+        collections.movements.retain(|movement| {
+            if movement.user_id != user_id || movement.import_id.as_deref() != Some(import_id) {
+                return true;
+            }
+            let has_scores = scores
+                .iter()
+                .any(|score| score.movement_id == movement.movement_id);
+            if has_scores {
+                kept += 1;
+            }
+            has_scores
+        });
+
+        Ok(((before - collections.movements.len()) as u64, kept))
+    }
+}
@@ -0,0 +1,113 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::ProfileSnapshot;
+use crate::models::user::{UpdateUser, User};
+use crate::repositories::memory::MemoryDatabase;
+use crate::repositories::user_repository::updated_user;
+use crate::repositories::UserRepository;
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+pub struct MemoryUserRepository {
+    pub database: MemoryDatabase,
+}
+
+#[async_trait(?Send)]
+impl UserRepository for MemoryUserRepository {
+    async fn find_user_with_email(&self, email: &str) -> WebResult<User> {
+        let collections = self.database.lock();
+        match collections.users.iter().find(|user| user.email == email) {
+            Some(user) => Ok(user.clone()),
+            None => Err(AppError::NotFound("User not found".to_owned())),
+        }
+    }
+
+    async fn find_user_with_avatar(&self, avatar_url: &str) -> WebResult<User> {
+        let collections = self.database.lock();
+        match collections
+            .users
+            .iter()
+            .find(|user| user.avatar_url == avatar_url)
+        {
+            Some(user) => Ok(user.clone()),
+            None => Err(AppError::NotFound("Avatar not found".to_owned())),
+        }
+    }
+
+    async fn is_avatar_shared(&self, user_id: &str, avatar_url: &str) -> WebResult<bool> {
+        let collections = self.database.lock();
+        Ok(collections
+            .users
+            .iter()
+            .any(|user| user.avatar_url == avatar_url && user.user_id != user_id))
+    }
+
+    async fn get_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .users
+            .iter()
+            .filter(|user| !user.avatar_url.is_empty())
+            .map(|user| user.avatar_url.to_owned())
+            .collect())
+    }
+
+    async fn insert_user(&self, user: User) -> WebResult<()> {
+        let mut collections = self.database.lock();
+        if collections
+            .users
+            .iter()
+            .any(|existing| existing.email == user.email)
+        {
+            return Err(AppError::Conflict(
+                "Entity already exists: a user with this email exists".to_owned(),
+            ));
+        }
+        collections.users.push(user);
+
+        Ok(())
+    }
+
+    async fn update_user_with_email(
+        &self,
+        email: &str,
+        user_update: UpdateUser,
+    ) -> WebResult<User> {
+        let mut collections = self.database.lock();
+        let user = match collections
+            .users
+            .iter_mut()
+            .find(|user| user.email == email)
+        {
+            Some(user) => user,
+            None => return Err(AppError::NotFound("User not found".to_owned())),
+        };
+        *user = updated_user(user.clone(), user_update);
+
+        Ok(user.clone())
+    }
+
+    async fn restore_profile(&self, email: &str, profile: &ProfileSnapshot) -> WebResult<User> {
+        let mut collections = self.database.lock();
+        let user = match collections
+            .users
+            .iter_mut()
+            .find(|user| user.email == email)
+        {
+            Some(user) => user,
+            None => return Err(AppError::NotFound("User not found".to_owned())),
+        };
+        let profile = profile.clone();
+        user.first_name = profile.first_name;
+        user.last_name = profile.last_name;
+        user.date_of_birth = profile.date_of_birth;
+        user.height = profile.height;
+        user.weight = profile.weight;
+        user.box_name = profile.box_name;
+        user.avatar_url = profile.avatar_url;
+        user.unit_system = profile.unit_system;
+        user.gender = profile.gender;
+
+        Ok(user.clone())
+    }
+}
@@ -0,0 +1,547 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::models::workout::{
+    CreateWorkout, CreateWorkoutScore, UpdateWorkout, UpdateWorkoutScore, WorkoutMeasurement,
+    WorkoutModel, WorkoutScoreModel,
+};
+use crate::repositories::memory::{is_visible, Collections, MemoryDatabase};
+use crate::repositories::workout_repository::{
+    forked_workout, new_workout, new_workout_score, plan_measurement_change, updated_workout_score,
+};
+use crate::repositories::{
+    imported_score_keys, is_imported_score, scored_by_others_error, unconvertible_scores_error,
+    WorkoutRepository,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use std::cmp::Ordering;
+
+pub struct MemoryWorkoutRepository {
+    pub database: MemoryDatabase,
+}
+
+impl MemoryWorkoutRepository {
+    /// Whether the user created a workout with this name. Public workouts of
+    /// others do not count, a user can have a copy of one with the same name.
+    fn has_own_workout_named(&self, user_id: &str, name: &str) -> bool {
+        self.database
+            .lock()
+            .workouts
+            .iter()
+            .any(|workout| workout.name == name && workout.user_id == user_id)
+    }
+
+    fn find_free_workout_name(&self, user_id: &str, name: &str) -> String {
+        let is_taken = |candidate: &str| self.has_own_workout_named(user_id, candidate);
+
+        let mut candidate = name.to_owned();
+        let mut n = 2;
+        while is_taken(&candidate) {
+            candidate = format!("{} ({})", name, n);
+            n += 1;
+        }
+
+        candidate
+    }
+
+    async fn get_own_workout(&self, user_id: &str, workout_id: &str) -> WebResult<WorkoutModel> {
+        let workout = match self.find_workout_by_id(user_id, workout_id).await? {
+            Some(workout) => workout,
+            None => return Err(AppError::NotFound("Workout not found".to_owned())),
+        };
+
+        if workout.user_id != user_id {
+            return Err(AppError::Forbidden(
+                "Only the creator of a workout can change it".to_owned(),
+            ));
+        }
+
+        Ok(workout)
+    }
+
+    /// Converts the scores of a workout to a new measurement, with the lock
+    /// held until the converted scores are saved.
+    fn plan_measurement_change(
+        collections: &Collections,
+        workout: &WorkoutModel,
+        measurement: WorkoutMeasurement,
+    ) -> (Vec<WorkoutScoreModel>, MeasurementChangePreview) {
+        let scores = collections
+            .workout_scores
+            .iter()
+            .filter(|score| score.workout_id == workout.workout_id)
+            .cloned()
+            .collect();
+
+        plan_measurement_change(workout, scores, measurement)
+    }
+
+    fn get_workout_scores_where(
+        &self,
+        filter: impl Fn(&WorkoutScoreModel) -> bool,
+        order: impl Fn(&WorkoutScoreModel, &WorkoutScoreModel) -> Ordering,
+    ) -> Vec<WorkoutScoreModel> {
+        let mut scores: Vec<WorkoutScoreModel> = self
+            .database
+            .lock()
+            .workout_scores
+            .iter()
+            .filter(|score| filter(score))
+            .cloned()
+            .collect();
+        scores.sort_by(order);
+
+        scores
+    }
+}
+
+#[async_trait(?Send)]
+impl WorkoutRepository for MemoryWorkoutRepository {
+    async fn find_workout_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .workouts
+            .iter()
+            .find(|workout| {
+                workout.name == name && is_visible(&workout.user_id, workout.is_public, user_id)
+            })
+            .cloned())
+    }
+
+    async fn find_workout_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .workouts
+            .iter()
+            .find(|workout| {
+                workout.user_id == user_id && workout.source_id.as_deref() == Some(source_id)
+            })
+            .cloned())
+    }
+
+    async fn find_imported_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: &[&CreateWorkoutScore],
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            workout_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        let collections = self.database.lock();
+        Ok(collections
+            .workout_scores
+            .iter()
+            .filter(|score| {
+                score.user_id == user_id
+                    && is_imported_score(&score.source_id, &score.created_at, &sources, &dates)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn find_workout_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let collections = self.database.lock();
+        Ok(collections
+            .workouts
+            .iter()
+            .find(|workout| {
+                workout.workout_id == workout_id
+                    && is_visible(&workout.user_id, workout.is_public, user_id)
+            })
+            .cloned())
+    }
+
+    async fn get_workouts(&self, user_id: &str) -> WebResult<Vec<WorkoutModel>> {
+        let collections = self.database.lock();
+        let mut workouts: Vec<WorkoutModel> = collections
+            .workouts
+            .iter()
+            .filter(|workout| is_visible(&workout.user_id, workout.is_public, user_id))
+            .cloned()
+            .collect();
+        workouts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(workouts)
+    }
+
+    async fn create_workout(
+        &self,
+        user_id: &str,
+        workout: CreateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        // A name of a public workout is taken as well, the bulk creation of
+        // imports only checks the names of the user
+        if self
+            .find_workout_by_name(user_id, &workout.name)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(
+                "A workout with this name already exists".to_string(),
+            ));
+        }
+
+        self.create_workouts(user_id, vec![workout])
+            .await
+            .map(|mut workouts| workouts.remove(0))
+    }
+
+    async fn create_workouts(
+        &self,
+        user_id: &str,
+        workouts: Vec<CreateWorkout>,
+    ) -> WebResult<Vec<WorkoutModel>> {
+        let mut collections = self.database.lock();
+        let names: Vec<&str> = workouts.iter().map(|w| w.name.as_str()).collect();
+        let has_duplicates = names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| names[..i].contains(name));
+        if has_duplicates
+            || collections
+                .workouts
+                .iter()
+                .any(|workout| names.contains(&workout.name.as_str()) && workout.user_id == user_id)
+        {
+            return Err(AppError::Conflict(
+                "A workout with this name already exists".to_string(),
+            ));
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let workouts: Vec<WorkoutModel> = workouts
+            .into_iter()
+            .map(|workout| new_workout(user_id, workout, &now))
+            .collect();
+        collections.workouts.extend(workouts.iter().cloned());
+
+        Ok(workouts)
+    }
+
+    async fn fork_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<WorkoutModel> {
+        let original = self.get_workout_by_id(user_id, workout_id).await?;
+
+        // Copies are made of the workouts of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only workouts of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if self.has_own_workout_named(user_id, &name) {
+                    return Err(AppError::Conflict(
+                        "A workout with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => self.find_free_workout_name(user_id, &original.name),
+        };
+
+        let workout = forked_workout(&original, user_id, name, &Utc::now().to_rfc3339());
+
+        let mut collections = self.database.lock();
+        collections.workouts.push(workout.clone());
+        if fork.move_scores {
+            for score in collections
+                .workout_scores
+                .iter_mut()
+                .filter(|score| score.workout_id == original.workout_id && score.user_id == user_id)
+            {
+                score.workout_id = workout.workout_id.to_owned();
+            }
+        }
+
+        Ok(workout)
+    }
+
+    async fn preview_workout_update(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<MeasurementChangePreview> {
+        let workout = self.get_own_workout(user_id, workout_id).await?;
+        let measurement = workout_update.measurement.unwrap_or(workout.measurement);
+
+        Ok(Self::plan_measurement_change(&self.database.lock(), &workout, measurement).1)
+    }
+
+    async fn update_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        let existing_workout = self.get_own_workout(user_id, workout_id).await?;
+        let new_measurement = workout_update
+            .measurement
+            .unwrap_or(existing_workout.measurement);
+
+        let new_name = workout_update
+            .name
+            .unwrap_or_else(|| existing_workout.name.to_owned());
+
+        // Check if there exists a workout with the new name
+        if let Some(conflicting_workout) = self.find_workout_by_name(user_id, &new_name).await? {
+            if conflicting_workout.workout_id != workout_id {
+                return Err(AppError::Conflict(
+                    "Workout with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        let updated_workout = WorkoutModel {
+            name: new_name,
+            description: workout_update
+                .description
+                .unwrap_or_else(|| existing_workout.description.to_owned()),
+            measurement: new_measurement,
+            is_public: workout_update
+                .is_public
+                .unwrap_or(existing_workout.is_public),
+            updated_at: Utc::now().to_rfc3339(),
+            ..existing_workout.clone()
+        };
+
+        // Planned while holding the lock, so scores logged in the meantime
+        // are converted as well
+        let mut collections = self.database.lock();
+        let (converted_scores, preview) =
+            Self::plan_measurement_change(&collections, &existing_workout, new_measurement);
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        let scored_by_others = collections
+            .workout_scores
+            .iter()
+            .any(|score| score.workout_id == workout_id && score.user_id != user_id);
+        if existing_workout.is_public && !updated_workout.is_public && scored_by_others {
+            return Err(scored_by_others_error("workout"));
+        }
+
+        for workout in collections.workouts.iter_mut() {
+            if workout.workout_id == workout_id {
+                *workout = updated_workout.clone();
+            }
+        }
+        for converted in converted_scores {
+            for score in collections.workout_scores.iter_mut() {
+                if score.workout_score_id == converted.workout_score_id {
+                    *score = converted.clone();
+                }
+            }
+        }
+
+        Ok(updated_workout)
+    }
+
+    async fn delete_workout(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
+        let workout = self.find_workout_by_id(user_id, workout_id).await?;
+
+        if workout.is_none() {
+            return Err(AppError::NotFound("Workout does not exist".to_owned()));
+        }
+
+        let mut collections = self.database.lock();
+        collections
+            .workouts
+            .retain(|workout| workout.workout_id != workout_id);
+        collections
+            .workout_scores
+            .retain(|score| !(score.workout_id == workout_id && score.user_id == user_id));
+
+        Ok(())
+    }
+
+    async fn create_workout_score(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+        workout_score: CreateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let workout_score = new_workout_score(
+            user_id,
+            &workout.workout_id,
+            workout_score,
+            &Utc::now().to_rfc3339(),
+        );
+        self.database
+            .lock()
+            .workout_scores
+            .push(workout_score.clone());
+
+        Ok(workout_score)
+    }
+
+    async fn create_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: Vec<(String, CreateWorkoutScore)>,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let now = Utc::now().to_rfc3339();
+        let workout_scores: Vec<WorkoutScoreModel> = workout_scores
+            .into_iter()
+            .map(|(workout_id, score)| new_workout_score(user_id, &workout_id, score, &now))
+            .collect();
+        self.database
+            .lock()
+            .workout_scores
+            .extend(workout_scores.iter().cloned());
+
+        Ok(workout_scores)
+    }
+
+    async fn get_workout_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        Ok(self.get_workout_scores_where(
+            |score| score.user_id == user_id,
+            |a, b| a.created_at.cmp(&b.created_at),
+        ))
+    }
+
+    async fn get_workout_scores_for_workout(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        // ascending for timed, descending for the rest
+        let lower_is_better = workout.measurement == WorkoutMeasurement::Time;
+        Ok(self.get_workout_scores_where(
+            |score| score.user_id == user_id && score.workout_id == workout.workout_id,
+            |a, b| {
+                let by_score = a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal);
+                let by_score = if lower_is_better {
+                    by_score
+                } else {
+                    by_score.reverse()
+                };
+                by_score.then(b.rx.cmp(&a.rx))
+            },
+        ))
+    }
+
+    async fn get_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<WorkoutScoreModel> {
+        let collections = self.database.lock();
+        match collections.workout_scores.iter().find(|score| {
+            score.workout_id == workout_id
+                && score.workout_score_id == workout_score_id
+                && score.user_id == user_id
+        }) {
+            Some(score) => Ok(score.clone()),
+            None => Err(AppError::NotFound("Entity not found".to_string())),
+        }
+    }
+
+    async fn update_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+        new_score: UpdateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let score = self
+            .get_workout_score_by_id(user_id, workout_id, workout_score_id)
+            .await?;
+
+        let updated = updated_workout_score(score, new_score, &Utc::now().to_rfc3339());
+
+        let mut collections = self.database.lock();
+        for score in collections.workout_scores.iter_mut() {
+            if score.workout_score_id == workout_score_id {
+                *score = updated.clone();
+            }
+        }
+
+        Ok(updated)
+    }
+
+    async fn delete_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<()> {
+        // Ensure the score exists for the user
+        self.get_workout_score_by_id(user_id, workout_id, workout_score_id)
+            .await?;
+
+        self.database
+            .lock()
+            .workout_scores
+            .retain(|score| score.workout_score_id != workout_score_id);
+
+        Ok(())
+    }
+
+    async fn delete_imported_workout_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let mut collections = self.database.lock();
+        let before = collections.workout_scores.len();
+        collections.workout_scores.retain(|score| {
+            !(score.user_id == user_id && score.import_id.as_deref() == Some(import_id))
+        });
+
+        Ok((before - collections.workout_scores.len()) as u64)
+    }
+
+    async fn delete_imported_workouts(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let mut collections = self.database.lock();
+        let collections = &mut *collections;
+        let before = collections.workouts.len();
+        let mut kept = 0;
+        let scores = &collections.workout_scores;
+        collections.workouts.retain(|workout| {
+            if workout.user_id != user_id || workout.import_id.as_deref() != Some(import_id) {
+                return true;
+            }
+            let has_scores = scores
+                .iter()
+                .any(|score| score.workout_id == workout.workout_id);
+            if has_scores {
+                kept += 1;
+            }
+            has_scores
+        });
+
+        Ok(((before - collections.workouts.len()) as u64, kept))
+    }
+}
@@ -1,7 +1,175 @@
-mod movement_repository;
-mod user_repository;
-mod workout_repository;
+use crate::errors::{AppError, WebResult};
+use crate::models::response::MeasurementChangePreview;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::Arc;
 
+pub mod memory;
+pub mod mongo;
+pub mod sqlite;
+pub mod transfer;
+
+pub(crate) mod feed_repository;
+pub(crate) mod import_repository;
+pub(crate) mod movement_repository;
+pub(crate) mod user_repository;
+pub(crate) mod workout_repository;
+
+pub use feed_repository::FeedRepository;
+pub use import_repository::ImportRepository;
+pub use memory::MemoryDatabase;
 pub use movement_repository::MovementRepository;
+pub use sqlite::SqliteDatabase;
 pub use user_repository::UserRepository;
 pub use workout_repository::WorkoutRepository;
+
+use memory::{
+    MemoryFeedRepository, MemoryImportRepository, MemoryMovementRepository, MemoryUserRepository,
+    MemoryWorkoutRepository,
+};
+use mongo::{
+    MongoFeedRepository, MongoImportRepository, MongoMovementRepository, MongoUserRepository,
+    MongoWorkoutRepository,
+};
+use sqlite::{
+    SqliteFeedRepository, SqliteImportRepository, SqliteMovementRepository, SqliteUserRepository,
+    SqliteWorkoutRepository,
+};
+
+/// The database the repositories keep their data in, MongoDB or an embedded
+/// SQLite file when the server runs and possibly memory in tests.
+#[derive(Clone)]
+pub enum Database {
+    Mongo(mongodb::Database),
+    Memory(MemoryDatabase),
+    Sqlite(SqliteDatabase),
+}
+
+/// The repositories of a database. They are created once and shared by the
+/// handlers, which get each of them as `web::Data<dyn ...Repository>`, and the
+/// background jobs.
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub workouts: Arc<dyn WorkoutRepository>,
+    pub movements: Arc<dyn MovementRepository>,
+    pub feeds: Arc<dyn FeedRepository>,
+    pub imports: Arc<dyn ImportRepository>,
+}
+
+impl Repositories {
+    pub fn new(database: &Database) -> Self {
+        match database {
+            Database::Mongo(database) => Repositories {
+                users: Arc::new(MongoUserRepository::new(database)),
+                workouts: Arc::new(MongoWorkoutRepository::new(database)),
+                movements: Arc::new(MongoMovementRepository::new(database)),
+                feeds: Arc::new(MongoFeedRepository::new(database)),
+                imports: Arc::new(MongoImportRepository::new(database)),
+            },
+            Database::Memory(database) => Repositories {
+                users: Arc::new(MemoryUserRepository {
+                    database: database.clone(),
+                }),
+                workouts: Arc::new(MemoryWorkoutRepository {
+                    database: database.clone(),
+                }),
+                movements: Arc::new(MemoryMovementRepository {
+                    database: database.clone(),
+                }),
+                feeds: Arc::new(MemoryFeedRepository {
+                    database: database.clone(),
+                }),
+                imports: Arc::new(MemoryImportRepository {
+                    database: database.clone(),
+                }),
+            },
+            Database::Sqlite(database) => Repositories {
+                users: Arc::new(SqliteUserRepository {
+                    database: database.clone(),
+                }),
+                workouts: Arc::new(SqliteWorkoutRepository {
+                    database: database.clone(),
+                }),
+                movements: Arc::new(SqliteMovementRepository {
+                    database: database.clone(),
+                }),
+                feeds: Arc::new(SqliteFeedRepository {
+                    database: database.clone(),
+                }),
+                imports: Arc::new(SqliteImportRepository {
+                    database: database.clone(),
+                }),
+            },
+        }
+    }
+}
+
+impl Database {
+    /// Whether the database can be reached.
+    pub async fn ping(&self) -> WebResult<()> {
+        match self {
+            Database::Mongo(database) => {
+                database.run_command(doc! {"ping": 1}, None).await?;
+                Ok(())
+            }
+            Database::Memory(_) => Ok(()),
+            Database::Sqlite(database) => {
+                database.lock().execute_batch("SELECT 1")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The sources and dates of the scores of an import, to look up the scores
+/// earlier imports added for them.
+pub(crate) fn imported_score_keys<'a>(
+    scores: impl Iterator<Item = (&'a Option<String>, &'a Option<String>)>,
+) -> (HashSet<&'a str>, HashSet<&'a str>) {
+    let mut sources = HashSet::new();
+    let mut dates = HashSet::new();
+    for (source_id, created_at) in scores {
+        sources.extend(source_id.as_deref());
+        dates.extend(created_at.as_deref());
+    }
+
+    (sources, dates)
+}
+
+/// Whether a score may have been added by an earlier import of a score with
+/// one of `sources`, or one of `dates` for scores from before sources were
+/// tracked.
+pub(crate) fn is_imported_score(
+    source_id: &Option<String>,
+    created_at: &str,
+    sources: &HashSet<&str>,
+    dates: &HashSet<&str>,
+) -> bool {
+    match source_id {
+        Some(source_id) => sources.contains(source_id.as_str()),
+        None => dates.contains(created_at),
+    }
+}
+
+/// A workout or movement is only made private while nobody else has scores
+/// for it, they would be left with scores for something they can not see.
+pub(crate) fn scored_by_others_error(kind: &str) -> AppError {
+    AppError::Conflict(format!(
+        "Other users have scores for this {}, so it can not be made private",
+        kind
+    ))
+}
+
+/// Changing the measurement of a workout or movement is refused while some of
+/// its scores can not be converted.
+pub(crate) fn unconvertible_scores_error(
+    preview: &MeasurementChangePreview,
+    measurement: impl Display,
+) -> AppError {
+    AppError::Conflict(format!(
+        "{} scores can not be converted to {}, use ?preview=true to see which",
+        preview.invalid_scores.len(),
+        measurement
+    ))
+}
@@ -0,0 +1,86 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::{CreateFeed, FeedModel};
+use crate::repositories::feed_repository::{feed_exists_error, new_feed};
+use crate::repositories::FeedRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::{options::FindOptions, Collection, Database};
+use std::vec::Vec;
+
+static COLLECTION_NAME: &str = "feeds";
+
+pub struct MongoFeedRepository {
+    pub(crate) collection: Collection<FeedModel>,
+}
+
+impl MongoFeedRepository {
+    pub fn new(database: &Database) -> Self {
+        MongoFeedRepository {
+            collection: database.collection(COLLECTION_NAME),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl FeedRepository for MongoFeedRepository {
+    async fn get_feeds(&self, user_id: &str) -> WebResult<Vec<FeedModel>> {
+        let query = doc! { "user_id": user_id };
+        let find_options = FindOptions::builder().sort(doc! { "title": 1 }).build();
+        let mut cursor = self.collection.find(query, find_options).await?;
+
+        let mut vec: Vec<FeedModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            match result {
+                Ok(feed) => vec.push(feed),
+                Err(e) => warn!("Error reading feed: {:?}", e),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn find_feed_by_url(&self, user_id: &str, url: &str) -> WebResult<Option<FeedModel>> {
+        let query = doc! { "user_id": user_id, "url": url };
+        let feed = self.collection.find_one(query, None).await?;
+
+        Ok(feed)
+    }
+
+    async fn create_feed(&self, user_id: &str, feed: CreateFeed) -> WebResult<FeedModel> {
+        let new_feed = new_feed(user_id, feed, &Utc::now().to_rfc3339())?;
+        if self
+            .find_feed_by_url(user_id, &new_feed.url)
+            .await?
+            .is_some()
+        {
+            return Err(feed_exists_error());
+        }
+
+        self.collection.insert_one(&new_feed, None).await?;
+
+        Ok(new_feed)
+    }
+
+    async fn delete_feed(&self, user_id: &str, feed_id: &str) -> WebResult<()> {
+        let query = doc! { "feed_id": feed_id, "user_id": user_id };
+        let res = self.collection.delete_one(query, None).await?;
+
+        if res.deleted_count == 0 {
+            return Err(AppError::NotFound(
+                "Feed with this id does not exist".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_imported_feeds(&self, user_id: &str, import_id: &str) -> WebResult<u64> {
+        let query = doc! { "user_id": user_id, "import_id": import_id };
+        let res = self.collection.delete_many(query, None).await?;
+
+        Ok(res.deleted_count)
+    }
+}
@@ -0,0 +1,271 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::{ImportModel, ImportStatus, ProfileSnapshot, RollbackResult};
+use crate::models::mywod::{MyWodResponse, RowReport};
+use crate::repositories::import_repository::{lost_claim, new_import};
+use crate::repositories::ImportRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::{Collection, Database};
+use std::collections::HashSet;
+use std::vec::Vec;
+
+static COLLECTION_NAME: &str = "imports";
+
+pub struct MongoImportRepository {
+    pub(crate) collection: Collection<ImportModel>,
+}
+
+impl MongoImportRepository {
+    pub fn new(database: &Database) -> Self {
+        MongoImportRepository {
+            collection: database.collection(COLLECTION_NAME),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ImportRepository for MongoImportRepository {
+    async fn create_import(
+        &self,
+        user_id: &str,
+        user_email: &str,
+        source: &str,
+        file_path: &str,
+    ) -> WebResult<ImportModel> {
+        let import = new_import(
+            user_id,
+            user_email,
+            source,
+            file_path,
+            &Utc::now().to_rfc3339(),
+        );
+        let id = import.import_id.to_owned();
+
+        self.collection.insert_one(import, None).await?;
+
+        self.get_import_by_id(user_id, &id).await
+    }
+
+    async fn get_import_by_id(&self, user_id: &str, import_id: &str) -> WebResult<ImportModel> {
+        let query = doc! { "import_id": import_id, "user_id": user_id };
+
+        match self.collection.find_one(query, None).await? {
+            Some(import) => Ok(import),
+            None => Err(AppError::NotFound(
+                "Import with this id does not exist".to_string(),
+            )),
+        }
+    }
+
+    async fn find_import_by_id(&self, import_id: &str) -> WebResult<Option<ImportModel>> {
+        let query = doc! { "import_id": import_id };
+        let import = self.collection.find_one(query, None).await?;
+
+        Ok(import)
+    }
+
+    async fn get_unfinished_imports(&self) -> WebResult<Vec<ImportModel>> {
+        let query = doc! { "status": { "$in": ["pending", "running"] } };
+        let mut cursor = self.collection.find(query, None).await?;
+
+        let mut vec: Vec<ImportModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            match result {
+                Ok(import) => vec.push(import),
+                Err(e) => warn!("Error reading import: {:?}", e),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn claim_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        expires_at: i64,
+    ) -> WebResult<Option<ImportModel>> {
+        let query = doc! {
+            "import_id": import_id,
+            "status": { "$in": ["pending", "running"] },
+            "$or": [
+                { "claimed_by": null },
+                { "claimed_by": owner },
+                { "claim_expires_at": { "$lt": Utc::now().timestamp_millis() } },
+            ],
+        };
+        let update = doc! {
+            "$set": {
+                "claimed_by": owner,
+                "claim_expires_at": expires_at,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let import = self
+            .collection
+            .find_one_and_update(query, update, options)
+            .await?;
+
+        Ok(import)
+    }
+
+    async fn get_snapshot_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let query = doc! {
+            "status": { "$ne": "rolled_back" },
+            "previous_profile.avatar_url": { "$nin": [null, ""] },
+        };
+        let urls = self
+            .collection
+            .distinct("previous_profile.avatar_url", query, None)
+            .await?;
+
+        Ok(urls
+            .into_iter()
+            .filter_map(|url| url.as_str().map(|url| url.to_owned()))
+            .collect())
+    }
+
+    async fn set_status(
+        &self,
+        import_id: &str,
+        owner: &str,
+        status: ImportStatus,
+        error: Option<String>,
+    ) -> WebResult<()> {
+        let query = doc! { "import_id": import_id, "claimed_by": owner };
+        let update = doc! {
+            "$set": {
+                "status": status.to_string(),
+                "error": error,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        let result = self.collection.update_one(query, update, None).await?;
+        if result.matched_count == 0 {
+            return Err(lost_claim(import_id));
+        }
+
+        Ok(())
+    }
+
+    async fn set_total(&self, import_id: &str, total: u32) -> WebResult<()> {
+        let query = doc! { "import_id": import_id };
+        let update = doc! {
+            "$set": {
+                "progress.processed": 0,
+                "progress.total": total,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        self.collection.update_one(query, update, None).await?;
+
+        Ok(())
+    }
+
+    async fn add_progress(&self, import_id: &str, processed: u32) -> WebResult<()> {
+        let query = doc! { "import_id": import_id };
+        let update = doc! {
+            "$inc": { "progress.processed": processed },
+            "$set": { "updated_at": Utc::now().to_rfc3339() },
+        };
+
+        self.collection.update_one(query, update, None).await?;
+
+        Ok(())
+    }
+
+    async fn complete_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        total: u32,
+        result: &MyWodResponse,
+        report: &[RowReport],
+    ) -> WebResult<()> {
+        let query = doc! { "import_id": import_id, "claimed_by": owner };
+        let update = doc! {
+            "$set": {
+                "status": ImportStatus::Completed.to_string(),
+                // Rows that belong to nothing, like sessions of deleted movements,
+                // are never processed
+                "progress.processed": total,
+                "result": bson::to_bson(result)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "report": bson::to_bson(report)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        let updated = self.collection.update_one(query, update, None).await?;
+        if updated.matched_count == 0 {
+            return Err(lost_claim(import_id));
+        }
+
+        Ok(())
+    }
+
+    async fn set_previous_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        let query = doc! { "import_id": import_id, "previous_profile": null };
+        let update = doc! {
+            "$set": {
+                "previous_profile": bson::to_bson(profile)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        self.collection.update_one(query, update, None).await?;
+
+        Ok(())
+    }
+
+    async fn set_imported_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        let query = doc! { "import_id": import_id };
+        let update = doc! {
+            "$set": {
+                "imported_profile": bson::to_bson(profile)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        self.collection.update_one(query, update, None).await?;
+
+        Ok(())
+    }
+
+    async fn complete_rollback(&self, import_id: &str, rollback: &RollbackResult) -> WebResult<()> {
+        let query = doc! { "import_id": import_id };
+        let update = doc! {
+            "$set": {
+                "status": ImportStatus::RolledBack.to_string(),
+                "rollback": bson::to_bson(rollback)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "updated_at": Utc::now().to_rfc3339(),
+            }
+        };
+
+        self.collection.update_one(query, update, None).await?;
+
+        Ok(())
+    }
+}
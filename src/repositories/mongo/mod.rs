@@ -0,0 +1,11 @@
+mod feed_repository;
+mod import_repository;
+mod movement_repository;
+mod user_repository;
+mod workout_repository;
+
+pub use feed_repository::MongoFeedRepository;
+pub use import_repository::MongoImportRepository;
+pub use movement_repository::MongoMovementRepository;
+pub use user_repository::MongoUserRepository;
+pub use workout_repository::MongoWorkoutRepository;
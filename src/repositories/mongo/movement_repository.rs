@@ -0,0 +1,691 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::movement::{
+    CreateMovement, CreateMovementScore, MovementMeasurement, MovementModel, MovementScoreModel,
+    UpdateMovement, UpdateMovementScore,
+};
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::repositories::movement_repository::{
+    forked_movement, new_movement, new_movement_score, plan_measurement_change,
+    updated_movement_score,
+};
+use crate::repositories::{
+    imported_score_keys, scored_by_others_error, unconvertible_scores_error, MovementRepository,
+};
+use crate::utils::query_utils;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use std::vec::Vec;
+
+static WORKOUT_COLLECTION_NAME: &str = "movements";
+static SCORE_COLLECTION_NAME: &str = "movementscores";
+
+pub struct MongoMovementRepository {
+    pub(crate) movements: Collection<MovementModel>,
+    pub(crate) scores: Collection<MovementScoreModel>,
+}
+
+impl MongoMovementRepository {
+    pub fn new(database: &Database) -> Self {
+        MongoMovementRepository {
+            movements: database.collection(WORKOUT_COLLECTION_NAME),
+            scores: database.collection(SCORE_COLLECTION_NAME),
+        }
+    }
+
+    /// Finds one of the movements the user created by its name. Public movements of
+    /// others do not count, a user can have a copy of one with the same name.
+    async fn find_own_movement_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let query = doc! { "user_id": user_id, "name": name };
+        let movement = self.movements.find_one(query, None).await?;
+
+        Ok(movement)
+    }
+
+    /// Finds a name for a copy of a movement that does not clash with the ones the
+    /// user created, e.g. "Fran (2)" when the user already has a "Fran".
+    async fn find_free_movement_name(&self, user_id: &str, name: &str) -> WebResult<String> {
+        let mut candidate = name.to_owned();
+        let mut n = 2;
+
+        while self
+            .find_own_movement_by_name(user_id, &candidate)
+            .await?
+            .is_some()
+        {
+            candidate = format!("{} ({})", name, n);
+            n += 1;
+        }
+
+        Ok(candidate)
+    }
+
+    /// Gets a movement the user is allowed to change, which are only the ones they created.
+    async fn get_own_movement(&self, user_id: &str, movement_id: &str) -> WebResult<MovementModel> {
+        let movement = match self.find_movement_by_id(user_id, movement_id).await? {
+            Some(movement) => movement,
+            None => return Err(AppError::NotFound("Movement not found".to_owned())),
+        };
+
+        if movement.user_id != user_id {
+            return Err(AppError::Forbidden(
+                "Only the creator of a movement can change it".to_owned(),
+            ));
+        }
+
+        Ok(movement)
+    }
+
+    /// Converts the scores of a movement to a new measurement, which are only
+    /// read when the measurement changes.
+    async fn plan_measurement_change(
+        &self,
+        movement: &MovementModel,
+        measurement: MovementMeasurement,
+    ) -> WebResult<(Vec<MovementScoreModel>, MeasurementChangePreview)> {
+        let scores = if measurement != movement.measurement {
+            let query = doc! { "movement_id": &movement.movement_id };
+            self.get_movement_scores_with_query(query, FindOptions::default())
+                .await?
+        } else {
+            vec![]
+        };
+
+        Ok(plan_measurement_change(movement, scores, measurement))
+    }
+
+    /// Converts the scores of a movement that was changed to a new measurement at
+    /// `changed_at`. Scores are read until none from before the change are
+    /// left, which includes the ones logged while converting. When a score can
+    /// not be converted or saved, the scores converted so far are put back.
+    async fn convert_scores(
+        &self,
+        movement: &MovementModel,
+        measurement: MovementMeasurement,
+        changed_at: &str,
+    ) -> WebResult<()> {
+        let mut originals = Vec::new();
+        let result = self
+            .convert_scores_before(movement, measurement, changed_at, &mut originals)
+            .await;
+
+        if result.is_err() {
+            for original in originals {
+                let query = doc! { "movement_score_id": &original.movement_score_id };
+                if let Err(e) = self.scores.replace_one(query, &original, None).await {
+                    error!(
+                        "Could not put back score {} of movement {}: {}",
+                        original.movement_score_id, movement.movement_id, e
+                    );
+                }
+            }
+        }
+
+        result
+    }
+
+    async fn convert_scores_before(
+        &self,
+        movement: &MovementModel,
+        measurement: MovementMeasurement,
+        changed_at: &str,
+        originals: &mut Vec<MovementScoreModel>,
+    ) -> WebResult<()> {
+        loop {
+            let query = doc! {
+                "movement_id": &movement.movement_id,
+                "updated_at": { "$lt": changed_at },
+            };
+            let scores = self
+                .get_movement_scores_with_query(query, FindOptions::default())
+                .await?;
+            if scores.is_empty() {
+                return Ok(());
+            }
+
+            let (converted_scores, preview) =
+                plan_measurement_change(movement, scores.clone(), measurement);
+            if !preview.invalid_scores.is_empty() {
+                return Err(unconvertible_scores_error(&preview, measurement));
+            }
+
+            for (original, mut score) in scores.into_iter().zip(converted_scores) {
+                score.updated_at = changed_at.to_owned();
+                let query = doc! { "movement_score_id": &score.movement_score_id };
+                self.scores.replace_one(query, &score, None).await?;
+                originals.push(original);
+            }
+        }
+    }
+
+    pub async fn get_movement_scores_with_query(
+        &self,
+        query: bson::Document,
+        find_options: FindOptions,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let mut cursor = self.scores.find(query, find_options).await.unwrap();
+
+        let mut vec: Vec<MovementScoreModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            match result {
+                Ok(document) => vec.push(document),
+                Err(e) => warn!("Error reading movement: {:?}", e),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn delete_movement_scores(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
+        let query = query_utils::for_many_with_filter(doc! { "movement_id": movement_id }, user_id);
+        self.scores.delete_many(query, None).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl MovementRepository for MongoMovementRepository {
+    async fn find_movement_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let query = query_utils::for_one(doc! {"name": name }, user_id);
+
+        match self.movements.find_one(query, None).await {
+            Ok(movement) => Ok(movement),
+            Err(e) => Err(AppError::Internal(e.to_string())),
+        }
+    }
+
+    async fn find_movement_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let query = doc! { "user_id": user_id, "source_id": source_id };
+        let movement = self.movements.find_one(query, None).await?;
+
+        Ok(movement)
+    }
+
+    async fn find_imported_movement_scores(
+        &self,
+        user_id: &str,
+        movement_scores: &[&CreateMovementScore],
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            movement_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        if sources.is_empty() && dates.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let sources: Vec<&str> = sources.into_iter().collect();
+        let dates: Vec<&str> = dates.into_iter().collect();
+        let query = doc! {
+            "user_id": user_id,
+            "$or": [
+                { "source_id": { "$in": sources } },
+                { "source_id": null, "created_at": { "$in": dates } },
+            ],
+        };
+
+        self.get_movement_scores_with_query(query, FindOptions::default())
+            .await
+    }
+
+    async fn find_movement_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        let query = query_utils::for_one(doc! {"movement_id": movement_id }, user_id);
+
+        match self.movements.find_one(query, None).await {
+            Ok(movement) => Ok(movement),
+            Err(e) => Err(AppError::Internal(e.to_string())),
+        }
+    }
+
+    async fn get_movements(&self, user_id: &str) -> WebResult<Vec<MovementModel>> {
+        let query = query_utils::for_many(user_id);
+        let find_options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let mut cursor = self.movements.find(query, find_options).await?;
+
+        let mut vec: Vec<MovementModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            match result {
+                Ok(result) => vec.push(result),
+                // Should this really be swallowing the errors?
+                // Is it because I still want to return _some_ data?
+                Err(e) => warn!("Error reading movement: {:?}", e.to_string()),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn create_movement(
+        &self,
+        user_id: &str,
+        movement: CreateMovement,
+    ) -> WebResult<MovementModel> {
+        let movement_name = movement.name.as_ref();
+        let existing_movement = self.find_movement_by_name(user_id, movement_name).await?;
+
+        if existing_movement.is_some() {
+            return Err(AppError::Conflict(
+                "A movement with this name already exists".to_string(),
+            ));
+        }
+
+        let coll = &self.movements;
+        let movement = new_movement(user_id, movement, &Utc::now().to_rfc3339());
+        coll.insert_one(&movement, None).await?;
+
+        Ok(movement)
+    }
+
+    async fn create_movements(
+        &self,
+        user_id: &str,
+        movements: Vec<CreateMovement>,
+    ) -> WebResult<Vec<MovementModel>> {
+        if movements.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let names: Vec<&str> = movements.iter().map(|m| m.name.as_str()).collect();
+        let has_duplicates = names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| names[..i].contains(name));
+        // Only names of the user are taken, the importers add to public
+        // ones they find by name
+        let query = doc! { "user_id": user_id, "name": { "$in": &names } };
+        if has_duplicates || self.movements.count_documents(query, None).await? > 0 {
+            return Err(AppError::Conflict(
+                "A movement with this name already exists".to_string(),
+            ));
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let movements: Vec<MovementModel> = movements
+            .into_iter()
+            .map(|movement| new_movement(user_id, movement, &now))
+            .collect();
+        self.movements.insert_many(&movements, None).await?;
+
+        Ok(movements)
+    }
+
+    async fn fork_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<MovementModel> {
+        let original = self.get_movement_by_id(user_id, movement_id).await?;
+
+        // Copies are made of the movements of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only movements of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if self
+                    .find_own_movement_by_name(user_id, &name)
+                    .await?
+                    .is_some()
+                {
+                    return Err(AppError::Conflict(
+                        "A movement with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => {
+                self.find_free_movement_name(user_id, &original.name)
+                    .await?
+            }
+        };
+
+        let movement = forked_movement(&original, user_id, name, &Utc::now().to_rfc3339());
+        let id = movement.movement_id.to_owned();
+
+        self.movements.insert_one(movement, None).await?;
+
+        if fork.move_scores {
+            let query = doc! { "movement_id": &original.movement_id, "user_id": user_id };
+            let update = doc! { "$set": { "movement_id": &id } };
+            if let Err(e) = self.scores.update_many(query, update, None).await {
+                // Takes the fork back, with the scores that were moved already
+                let query = doc! { "movement_id": &id, "user_id": user_id };
+                let update = doc! { "$set": { "movement_id": &original.movement_id } };
+                if let Err(e) = self.scores.update_many(query, update, None).await {
+                    error!("Could not move scores back from fork {}: {}", id, e);
+                } else if let Err(e) = self
+                    .movements
+                    .delete_one(doc! { "movement_id": &id }, None)
+                    .await
+                {
+                    error!("Could not delete fork {}: {}", id, e);
+                }
+                return Err(e.into());
+            }
+        }
+
+        self.get_movement_by_id(user_id, &id).await
+    }
+
+    async fn preview_movement_update(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MeasurementChangePreview> {
+        let movement = self.get_own_movement(user_id, movement_id).await?;
+        let measurement = movement_update.measurement.unwrap_or(movement.measurement);
+
+        self.plan_measurement_change(&movement, measurement)
+            .await
+            .map(|(_, preview)| preview)
+    }
+
+    async fn update_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MovementModel> {
+        let existing_movement = self.get_own_movement(user_id, movement_id).await?;
+        let new_measurement = movement_update
+            .measurement
+            .unwrap_or(existing_movement.measurement);
+        let new_is_public = movement_update
+            .is_public
+            .unwrap_or(existing_movement.is_public);
+
+        let (_, preview) = self
+            .plan_measurement_change(&existing_movement, new_measurement)
+            .await?;
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        if existing_movement.is_public && !new_is_public {
+            let query = doc! { "movement_id": movement_id, "user_id": { "$ne": user_id } };
+            if self.scores.count_documents(query, None).await? > 0 {
+                return Err(scored_by_others_error("movement"));
+            }
+        }
+
+        let new_name = movement_update
+            .name
+            .unwrap_or_else(|| existing_movement.name.to_owned());
+
+        // Check if there exists a movement with the new name
+        if let Some(conflicting_movement) = self.find_movement_by_name(user_id, &new_name).await? {
+            if conflicting_movement.movement_id != movement_id {
+                return Err(AppError::Conflict(
+                    "Movement with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        // Only changed when nobody changed it since it was read, so the scores
+        // are never converted by two updates at once
+        let changed_at = Utc::now().to_rfc3339();
+        let query =
+            doc! { "movement_id": movement_id, "updated_at": &existing_movement.updated_at };
+        let update = doc! {
+            "$set": {
+                "name": new_name,
+                "measurement": bson::to_bson(&new_measurement)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "is_public": new_is_public,
+                "updated_at": &changed_at
+            }
+        };
+
+        let coll = &self.movements;
+        if coll.update_one(query, update, None).await?.matched_count == 0 {
+            return Err(AppError::Conflict(
+                "The movement was changed in the meantime, try again".to_owned(),
+            ));
+        }
+
+        if new_measurement != existing_movement.measurement {
+            if let Err(e) = self
+                .convert_scores(&existing_movement, new_measurement, &changed_at)
+                .await
+            {
+                let query = doc! { "movement_id": movement_id };
+                if let Err(e) = coll.replace_one(query, &existing_movement, None).await {
+                    error!("Could not put back movement {}: {}", movement_id, e);
+                }
+                return Err(e);
+            }
+        }
+
+        self.get_movement_by_id(user_id, movement_id).await
+    }
+
+    async fn delete_movement(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
+        let movement = self.find_movement_by_id(user_id, movement_id).await?;
+
+        if movement.is_none() {
+            return Err(AppError::NotFound("Movement does not exist".to_owned()));
+        }
+
+        let coll = &self.movements;
+        coll.delete_one(doc! { "movement_id": movement_id }, None)
+            .await?;
+
+        self.delete_movement_scores(user_id, movement_id).await?;
+
+        Ok(())
+    }
+
+    async fn create_movement_score(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_score: CreateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let coll = &self.scores;
+        let new_score =
+            new_movement_score(user_id, movement, movement_score, &Utc::now().to_rfc3339())?;
+        coll.insert_one(&new_score, None).await?;
+
+        Ok(new_score)
+    }
+
+    async fn create_movement_scores(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_scores: Vec<CreateMovementScore>,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        if movement_scores.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let movement_scores = movement_scores
+            .into_iter()
+            .map(|score| new_movement_score(user_id, movement, score, &now))
+            .collect::<WebResult<Vec<MovementScoreModel>>>()?;
+        self.scores.insert_many(&movement_scores, None).await?;
+
+        Ok(movement_scores)
+    }
+
+    async fn get_movement_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let query = query_utils::for_many_with_filter(doc! { "user_id": user_id }, user_id);
+        let find_options: FindOptions = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+
+        self.get_movement_scores_with_query(query, find_options)
+            .await
+    }
+
+    async fn get_movement_scores_for_movement(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let query = query_utils::for_many_with_filter(
+            doc! { "user_id": user_id, "movement_id": &movement.movement_id },
+            user_id,
+        );
+
+        // ascending for timed, descending for the rest
+        let score_filter =
+            doc! { "score": if movement.measurement.lower_is_better() { 1 } else { -1 } };
+        let find_options = FindOptions::builder().sort(score_filter).build();
+
+        self.get_movement_scores_with_query(query, find_options)
+            .await
+    }
+
+    async fn get_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<MovementScoreModel> {
+        let query = query_utils::for_one(
+            doc! { "movement_id":  movement_id, "movement_score_id": movement_score_id },
+            user_id,
+        );
+        let cursor = self.scores.find_one(query, None).await?;
+
+        match cursor {
+            Some(model) => Ok(model),
+            None => Err(AppError::NotFound("Entity not found".to_string())),
+        }
+    }
+
+    async fn update_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+        new_score: UpdateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let movement = self.get_movement_by_id(user_id, movement_id).await?;
+        let score = self
+            .get_movement_score_by_id(user_id, movement_id, movement_score_id)
+            .await?;
+
+        let updated =
+            updated_movement_score(&movement, score, new_score, &Utc::now().to_rfc3339())?;
+
+        let query = query_utils::for_one(doc! { "movement_score_id": movement_score_id }, user_id);
+        let update = doc! {
+            "$set": {
+                "score": updated.score,
+                "reps": updated.reps,
+                "sets": updated.sets,
+                "set_details": bson::to_bson(&updated.set_details)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "unit": bson::to_bson(&updated.unit)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "distance": updated.distance,
+                "distance_unit": bson::to_bson(&updated.distance_unit)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "duration": updated.duration,
+                "notes": updated.notes,
+                "source_id": updated.source_id,
+                "updated_at": updated.updated_at,
+            }
+        };
+
+        let _ = self.scores.update_one(query, update, None).await?;
+
+        let res = self
+            .get_movement_score_by_id(user_id, movement_id, movement_score_id)
+            .await?;
+
+        Ok(res)
+    }
+
+    async fn delete_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<()> {
+        // Ensure the score exists for the user
+        self.get_movement_score_by_id(user_id, movement_id, movement_score_id)
+            .await?;
+
+        let query = query_utils::for_one(doc! { "movement_score_id": movement_score_id }, user_id);
+        let _ = self.scores.delete_one(query, None).await?;
+
+        Ok(())
+    }
+
+    async fn delete_imported_movement_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let query = doc! { "user_id": user_id, "import_id": import_id };
+        let res = self.scores.delete_many(query, None).await?;
+
+        Ok(res.deleted_count)
+    }
+
+    async fn delete_imported_movements(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let query = doc! { "user_id": user_id, "import_id": import_id };
+        let mut cursor = self.movements.find(query, None).await?;
+
+        let mut removed = 0;
+        let mut kept = 0;
+        while let Some(result) = cursor.next().await {
+            let movement = result?;
+            let scores = self
+                .scores
+                .count_documents(doc! { "movement_id": &movement.movement_id }, None)
+                .await?;
+            if scores > 0 {
+                kept += 1;
+                continue;
+            }
+
+            self.movements
+                .delete_one(doc! { "movement_id": &movement.movement_id }, None)
+                .await?;
+            removed += 1;
+        }
+
+        Ok((removed, kept))
+    }
+}
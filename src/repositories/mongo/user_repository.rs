@@ -0,0 +1,128 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::ProfileSnapshot;
+use crate::models::user::{UpdateUser, User};
+use crate::repositories::user_repository::updated_user;
+use crate::repositories::UserRepository;
+
+use async_trait::async_trait;
+use mongodb::{Collection, Database};
+use std::collections::HashSet;
+
+static COLLECTION_NAME: &str = "users";
+
+pub struct MongoUserRepository {
+    pub(crate) collection: Collection<User>,
+}
+
+impl MongoUserRepository {
+    pub fn new(database: &Database) -> Self {
+        MongoUserRepository {
+            collection: database.collection(COLLECTION_NAME),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl UserRepository for MongoUserRepository {
+    async fn update_user_with_email(
+        &self,
+        email: &str,
+        user_update: UpdateUser,
+    ) -> WebResult<User> {
+        let user = updated_user(self.find_user_with_email(email).await?, user_update);
+
+        let query = doc! { "user_id": user.user_id.to_owned() };
+        let update = doc! {
+            "$set": {
+                "password": &user.password,
+                "first_name": &user.first_name,
+                "last_name": &user.last_name,
+                "date_of_birth": &user.date_of_birth,
+                "height": user.height,
+                "weight": user.weight,
+                "box_name": &user.box_name,
+                "avatar_url": &user.avatar_url,
+                "avatar_privacy": bson::to_bson(&user.avatar_privacy)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "unit_system": bson::to_bson(&user.unit_system)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "gender": bson::to_bson(&user.gender)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+            }
+        };
+        let coll = &self.collection;
+        coll.update_one(query, update, None).await?;
+
+        self.find_user_with_email(email).await
+    }
+
+    async fn find_user_with_email(&self, email: &str) -> WebResult<User> {
+        let coll = &self.collection;
+        let cursor = coll.find_one(doc! {"email": email}, None).await?;
+
+        match cursor {
+            Some(model) => Ok(model),
+            None => Err(AppError::NotFound("User not found".to_owned())),
+        }
+    }
+
+    async fn find_user_with_avatar(&self, avatar_url: &str) -> WebResult<User> {
+        let coll = &self.collection;
+        let cursor = coll.find_one(doc! {"avatar_url": avatar_url}, None).await?;
+
+        match cursor {
+            Some(model) => Ok(model),
+            None => Err(AppError::NotFound("Avatar not found".to_owned())),
+        }
+    }
+
+    async fn is_avatar_shared(&self, user_id: &str, avatar_url: &str) -> WebResult<bool> {
+        let coll = &self.collection;
+        let others = coll
+            .count_documents(
+                doc! {"avatar_url": avatar_url, "user_id": {"$ne": user_id}},
+                None,
+            )
+            .await?;
+        Ok(others > 0)
+    }
+
+    async fn get_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let coll = &self.collection;
+        let urls = coll
+            .distinct("avatar_url", doc! {"avatar_url": {"$ne": ""}}, None)
+            .await?;
+        Ok(urls
+            .into_iter()
+            .filter_map(|url| url.as_str().map(|url| url.to_owned()))
+            .collect())
+    }
+
+    async fn insert_user(&self, user: User) -> WebResult<()> {
+        self.collection.insert_one(user, None).await?;
+
+        Ok(())
+    }
+
+    async fn restore_profile(&self, email: &str, profile: &ProfileSnapshot) -> WebResult<User> {
+        let query = doc! { "email": email };
+        let update = doc! {
+            "$set": {
+                "first_name": &profile.first_name,
+                "last_name": &profile.last_name,
+                "date_of_birth": &profile.date_of_birth,
+                "height": profile.height,
+                "weight": profile.weight,
+                "box_name": &profile.box_name,
+                "avatar_url": &profile.avatar_url,
+                "unit_system": bson::to_bson(&profile.unit_system)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "gender": bson::to_bson(&profile.gender)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+            }
+        };
+        self.collection.update_one(query, update, None).await?;
+
+        self.find_user_with_email(email).await
+    }
+}
@@ -0,0 +1,690 @@
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::models::workout::{
+    CreateWorkout, CreateWorkoutScore, UpdateWorkout, UpdateWorkoutScore, WorkoutModel,
+    WorkoutScoreModel,
+};
+use crate::repositories::workout_repository::{
+    forked_workout, new_workout, new_workout_score, plan_measurement_change, updated_workout_score,
+};
+use crate::repositories::{
+    imported_score_keys, scored_by_others_error, unconvertible_scores_error, WorkoutRepository,
+};
+use crate::utils::query_utils;
+use crate::{
+    errors::{AppError, WebResult},
+    models::workout::WorkoutMeasurement,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use std::vec::Vec;
+
+static WORKOUT_COLLECTION_NAME: &str = "workouts";
+static SCORE_COLLECTION_NAME: &str = "workoutscores";
+
+pub struct MongoWorkoutRepository {
+    pub(crate) workouts: Collection<WorkoutModel>,
+    pub(crate) scores: Collection<WorkoutScoreModel>,
+}
+
+impl MongoWorkoutRepository {
+    pub fn new(database: &Database) -> Self {
+        MongoWorkoutRepository {
+            workouts: database.collection(WORKOUT_COLLECTION_NAME),
+            scores: database.collection(SCORE_COLLECTION_NAME),
+        }
+    }
+
+    /// Finds one of the workouts the user created by its name. Public workouts of
+    /// others do not count, a user can have a copy of one with the same name.
+    async fn find_own_workout_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let query = doc! { "user_id": user_id, "name": name };
+        let workout = self.workouts.find_one(query, None).await?;
+
+        Ok(workout)
+    }
+
+    /// Finds a name for a copy of a workout that does not clash with the ones the
+    /// user created, e.g. "Fran (2)" when the user already has a "Fran".
+    async fn find_free_workout_name(&self, user_id: &str, name: &str) -> WebResult<String> {
+        let mut candidate = name.to_owned();
+        let mut n = 2;
+
+        while self
+            .find_own_workout_by_name(user_id, &candidate)
+            .await?
+            .is_some()
+        {
+            candidate = format!("{} ({})", name, n);
+            n += 1;
+        }
+
+        Ok(candidate)
+    }
+
+    /// Gets a workout the user is allowed to change, which are only the ones they created.
+    async fn get_own_workout(&self, user_id: &str, workout_id: &str) -> WebResult<WorkoutModel> {
+        let workout = match self.find_workout_by_id(user_id, workout_id).await? {
+            Some(workout) => workout,
+            None => return Err(AppError::NotFound("Workout not found".to_owned())),
+        };
+
+        if workout.user_id != user_id {
+            return Err(AppError::Forbidden(
+                "Only the creator of a workout can change it".to_owned(),
+            ));
+        }
+
+        Ok(workout)
+    }
+
+    /// Converts the scores of a workout to a new measurement, which are only
+    /// read when the measurement changes.
+    async fn plan_measurement_change(
+        &self,
+        workout: &WorkoutModel,
+        measurement: WorkoutMeasurement,
+    ) -> WebResult<(Vec<WorkoutScoreModel>, MeasurementChangePreview)> {
+        let scores = if measurement != workout.measurement {
+            let query = doc! { "workout_id": &workout.workout_id };
+            self.get_workout_scores_with_query(query, FindOptions::default())
+                .await?
+        } else {
+            vec![]
+        };
+
+        Ok(plan_measurement_change(workout, scores, measurement))
+    }
+
+    /// Converts the scores of a workout that was changed to a new measurement at
+    /// `changed_at`. Scores are read until none from before the change are
+    /// left, which includes the ones logged while converting. When a score can
+    /// not be converted or saved, the scores converted so far are put back.
+    async fn convert_scores(
+        &self,
+        workout: &WorkoutModel,
+        measurement: WorkoutMeasurement,
+        changed_at: &str,
+    ) -> WebResult<()> {
+        let mut originals = Vec::new();
+        let result = self
+            .convert_scores_before(workout, measurement, changed_at, &mut originals)
+            .await;
+
+        if result.is_err() {
+            for original in originals {
+                let query = doc! { "workout_score_id": &original.workout_score_id };
+                if let Err(e) = self.scores.replace_one(query, &original, None).await {
+                    error!(
+                        "Could not put back score {} of workout {}: {}",
+                        original.workout_score_id, workout.workout_id, e
+                    );
+                }
+            }
+        }
+
+        result
+    }
+
+    async fn convert_scores_before(
+        &self,
+        workout: &WorkoutModel,
+        measurement: WorkoutMeasurement,
+        changed_at: &str,
+        originals: &mut Vec<WorkoutScoreModel>,
+    ) -> WebResult<()> {
+        loop {
+            let query = doc! {
+                "workout_id": &workout.workout_id,
+                "updated_at": { "$lt": changed_at },
+            };
+            let scores = self
+                .get_workout_scores_with_query(query, FindOptions::default())
+                .await?;
+            if scores.is_empty() {
+                return Ok(());
+            }
+
+            let (converted_scores, preview) =
+                plan_measurement_change(workout, scores.clone(), measurement);
+            if !preview.invalid_scores.is_empty() {
+                return Err(unconvertible_scores_error(&preview, measurement));
+            }
+
+            for (original, mut score) in scores.into_iter().zip(converted_scores) {
+                score.updated_at = changed_at.to_owned();
+                let query = doc! { "workout_score_id": &score.workout_score_id };
+                self.scores.replace_one(query, &score, None).await?;
+                originals.push(original);
+            }
+        }
+    }
+
+    pub async fn get_workout_scores_with_query(
+        &self,
+        query: bson::Document,
+        find_options: FindOptions,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let mut cursor = self.scores.find(query, find_options).await.unwrap();
+
+        let mut vec: Vec<WorkoutScoreModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            match result {
+                Ok(document) => vec.push(document),
+                Err(e) => warn!("Error reading workout: {:?}", e),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn delete_workout_scores(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
+        let query = query_utils::for_many_with_filter(doc! { "workout_id": workout_id }, user_id);
+        self.scores.delete_many(query, None).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl WorkoutRepository for MongoWorkoutRepository {
+    async fn find_workout_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let query = query_utils::for_one(doc! {"name": name }, user_id);
+
+        match self.workouts.find_one(query, None).await {
+            Ok(workout) => Ok(workout),
+            Err(e) => Err(AppError::Internal(e.to_string())),
+        }
+    }
+
+    async fn find_workout_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let query = doc! { "user_id": user_id, "source_id": source_id };
+        let workout = self.workouts.find_one(query, None).await?;
+
+        Ok(workout)
+    }
+
+    async fn find_imported_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: &[&CreateWorkoutScore],
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            workout_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        if sources.is_empty() && dates.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let sources: Vec<&str> = sources.into_iter().collect();
+        let dates: Vec<&str> = dates.into_iter().collect();
+        let query = doc! {
+            "user_id": user_id,
+            "$or": [
+                { "source_id": { "$in": sources } },
+                { "source_id": null, "created_at": { "$in": dates } },
+            ],
+        };
+
+        self.get_workout_scores_with_query(query, FindOptions::default())
+            .await
+    }
+
+    async fn find_workout_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        let query = query_utils::for_one(doc! {"workout_id": workout_id }, user_id);
+        let cursor = self.workouts.find_one(query, None).await;
+
+        match cursor {
+            Ok(workout) => Ok(workout),
+            Err(e) => Err(AppError::Internal(e.to_string())),
+        }
+    }
+
+    async fn get_workouts(&self, user_id: &str) -> WebResult<Vec<WorkoutModel>> {
+        let query = query_utils::for_many(user_id);
+        let find_options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let mut cursor = self.workouts.find(query, find_options).await?;
+
+        let mut vec: Vec<WorkoutModel> = Vec::new();
+
+        while let Some(result) = cursor.next().await {
+            if let Ok(result) = result {
+                vec.push(result);
+            } else {
+                warn!("Error reading workout: {:?}", result.unwrap_err())
+            }
+        }
+
+        Ok(vec)
+    }
+
+    async fn create_workout(
+        &self,
+        user_id: &str,
+        workout: CreateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        let workout_name = workout.name.as_ref();
+        let existing_workout = self.find_workout_by_name(user_id, workout_name).await?;
+
+        if existing_workout.is_some() {
+            return Err(AppError::Conflict(
+                "A workout with this name already exists".to_string(),
+            ));
+        }
+
+        let coll = &self.workouts;
+        let workout = new_workout(user_id, workout, &Utc::now().to_rfc3339());
+        coll.insert_one(&workout, None).await?;
+
+        Ok(workout)
+    }
+
+    async fn create_workouts(
+        &self,
+        user_id: &str,
+        workouts: Vec<CreateWorkout>,
+    ) -> WebResult<Vec<WorkoutModel>> {
+        if workouts.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let names: Vec<&str> = workouts.iter().map(|w| w.name.as_str()).collect();
+        let has_duplicates = names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| names[..i].contains(name));
+        // Only names of the user are taken, the importers add to public
+        // ones they find by name
+        let query = doc! { "user_id": user_id, "name": { "$in": &names } };
+        if has_duplicates || self.workouts.count_documents(query, None).await? > 0 {
+            return Err(AppError::Conflict(
+                "A workout with this name already exists".to_string(),
+            ));
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let workouts: Vec<WorkoutModel> = workouts
+            .into_iter()
+            .map(|workout| new_workout(user_id, workout, &now))
+            .collect();
+        self.workouts.insert_many(&workouts, None).await?;
+
+        Ok(workouts)
+    }
+
+    async fn fork_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<WorkoutModel> {
+        let original = self.get_workout_by_id(user_id, workout_id).await?;
+
+        // Copies are made of the workouts of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only workouts of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if self
+                    .find_own_workout_by_name(user_id, &name)
+                    .await?
+                    .is_some()
+                {
+                    return Err(AppError::Conflict(
+                        "A workout with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => self.find_free_workout_name(user_id, &original.name).await?,
+        };
+
+        let workout = forked_workout(&original, user_id, name, &Utc::now().to_rfc3339());
+        let id = workout.workout_id.to_owned();
+
+        self.workouts.insert_one(workout, None).await?;
+
+        if fork.move_scores {
+            let query = doc! { "workout_id": &original.workout_id, "user_id": user_id };
+            let update = doc! { "$set": { "workout_id": &id } };
+            if let Err(e) = self.scores.update_many(query, update, None).await {
+                // Takes the fork back, with the scores that were moved already
+                let query = doc! { "workout_id": &id, "user_id": user_id };
+                let update = doc! { "$set": { "workout_id": &original.workout_id } };
+                if let Err(e) = self.scores.update_many(query, update, None).await {
+                    error!("Could not move scores back from fork {}: {}", id, e);
+                } else if let Err(e) = self
+                    .workouts
+                    .delete_one(doc! { "workout_id": &id }, None)
+                    .await
+                {
+                    error!("Could not delete fork {}: {}", id, e);
+                }
+                return Err(e.into());
+            }
+        }
+
+        self.get_workout_by_id(user_id, &id).await
+    }
+
+    async fn preview_workout_update(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<MeasurementChangePreview> {
+        let workout = self.get_own_workout(user_id, workout_id).await?;
+        let measurement = workout_update.measurement.unwrap_or(workout.measurement);
+
+        self.plan_measurement_change(&workout, measurement)
+            .await
+            .map(|(_, preview)| preview)
+    }
+
+    async fn update_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        let existing_workout = self.get_own_workout(user_id, workout_id).await?;
+        let new_measurement = workout_update
+            .measurement
+            .unwrap_or(existing_workout.measurement);
+        let new_is_public = workout_update
+            .is_public
+            .unwrap_or(existing_workout.is_public);
+
+        let (_, preview) = self
+            .plan_measurement_change(&existing_workout, new_measurement)
+            .await?;
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        if existing_workout.is_public && !new_is_public {
+            let query = doc! { "workout_id": workout_id, "user_id": { "$ne": user_id } };
+            if self.scores.count_documents(query, None).await? > 0 {
+                return Err(scored_by_others_error("workout"));
+            }
+        }
+
+        let new_name = workout_update
+            .name
+            .unwrap_or_else(|| existing_workout.name.to_owned());
+        let new_desc = workout_update
+            .description
+            .unwrap_or_else(|| existing_workout.description.to_owned());
+
+        // Check if there exists a workout with the new name
+        if let Some(conflicting_workout) = self.find_workout_by_name(user_id, &new_name).await? {
+            if conflicting_workout.workout_id != workout_id {
+                return Err(AppError::Conflict(
+                    "Workout with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        // Only changed when nobody changed it since it was read, so the scores
+        // are never converted by two updates at once
+        let changed_at = Utc::now().to_rfc3339();
+        let query = doc! { "workout_id": workout_id, "updated_at": &existing_workout.updated_at };
+        let update = doc! {
+            "$set": {
+                "name": new_name,
+                "description": new_desc,
+                "measurement": bson::to_bson(&new_measurement)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "is_public": new_is_public,
+                "updated_at": &changed_at
+            }
+        };
+
+        let coll = &self.workouts;
+        if coll.update_one(query, update, None).await?.matched_count == 0 {
+            return Err(AppError::Conflict(
+                "The workout was changed in the meantime, try again".to_owned(),
+            ));
+        }
+
+        if new_measurement != existing_workout.measurement {
+            if let Err(e) = self
+                .convert_scores(&existing_workout, new_measurement, &changed_at)
+                .await
+            {
+                let query = doc! { "workout_id": workout_id };
+                if let Err(e) = coll.replace_one(query, &existing_workout, None).await {
+                    error!("Could not put back workout {}: {}", workout_id, e);
+                }
+                return Err(e);
+            }
+        }
+
+        self.get_workout_by_id(user_id, workout_id).await
+    }
+
+    async fn delete_workout(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
+        let workout = self.find_workout_by_id(user_id, workout_id).await?;
+
+        if workout.is_none() {
+            return Err(AppError::NotFound("Workout does not exist".to_owned()));
+        }
+
+        let coll = &self.workouts;
+        coll.delete_one(doc! { "workout_id": workout_id }, None)
+            .await?;
+
+        self.delete_workout_scores(user_id, workout_id).await?;
+
+        Ok(())
+    }
+
+    async fn create_workout_score(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+        workout_score: CreateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let coll = &self.scores;
+        let workout_score = new_workout_score(
+            user_id,
+            &workout.workout_id,
+            workout_score,
+            &Utc::now().to_rfc3339(),
+        );
+        coll.insert_one(&workout_score, None).await?;
+
+        Ok(workout_score)
+    }
+
+    async fn create_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: Vec<(String, CreateWorkoutScore)>,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        if workout_scores.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let workout_scores: Vec<WorkoutScoreModel> = workout_scores
+            .into_iter()
+            .map(|(workout_id, score)| new_workout_score(user_id, &workout_id, score, &now))
+            .collect();
+        self.scores.insert_many(&workout_scores, None).await?;
+
+        Ok(workout_scores)
+    }
+
+    async fn get_workout_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let query = query_utils::for_many_with_filter(doc! { "user_id": user_id }, user_id);
+        let find_options: FindOptions = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+
+        self.get_workout_scores_with_query(query, find_options)
+            .await
+    }
+
+    async fn get_workout_scores_for_workout(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let query = query_utils::for_many_with_filter(
+            doc! { "user_id": user_id, "workout_id": &workout.workout_id },
+            user_id,
+        );
+
+        // ascending for timed, descending for the rest
+        let score_filter = doc! {
+            "score": if workout.measurement == WorkoutMeasurement::Time { 1 } else { -1 },
+            "rx": -1,
+        };
+        let find_options = FindOptions::builder().sort(score_filter).build();
+
+        self.get_workout_scores_with_query(query, find_options)
+            .await
+    }
+
+    async fn get_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<WorkoutScoreModel> {
+        let query = query_utils::for_one(
+            doc! { "workout_id":  workout_id, "workout_score_id": workout_score_id },
+            user_id,
+        );
+        let cursor = self.scores.find_one(query, None).await?;
+
+        match cursor {
+            Some(model) => Ok(model),
+            None => Err(AppError::NotFound("Entity not found".to_string())),
+        }
+    }
+
+    async fn update_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+        new_score: UpdateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let score = self
+            .get_workout_score_by_id(user_id, workout_id, workout_score_id)
+            .await?;
+
+        let updated = updated_workout_score(score, new_score, &Utc::now().to_rfc3339());
+
+        let query = query_utils::for_one(doc! { "workout_score_id": workout_score_id }, user_id);
+        let update = doc! {
+            "$set": {
+                "score": updated.score,
+                "unit": bson::to_bson(&updated.unit)
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
+                "rx": updated.rx,
+                "notes": updated.notes,
+                "personal_record": updated.personal_record,
+                "source_id": updated.source_id,
+                "updated_at": updated.updated_at,
+            }
+        };
+
+        let _ = self.scores.update_one(query, update, None).await?;
+
+        let res = self
+            .get_workout_score_by_id(user_id, workout_id, workout_score_id)
+            .await?;
+
+        Ok(res)
+    }
+
+    async fn delete_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<()> {
+        // Ensure the score exists for the user
+        self.get_workout_score_by_id(user_id, workout_id, workout_score_id)
+            .await?;
+
+        let query = query_utils::for_one(doc! { "workout_score_id": workout_score_id }, user_id);
+        self.scores.delete_one(query, None).await?;
+
+        Ok(())
+    }
+
+    async fn delete_imported_workout_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let query = doc! { "user_id": user_id, "import_id": import_id };
+        let res = self.scores.delete_many(query, None).await?;
+
+        Ok(res.deleted_count)
+    }
+
+    async fn delete_imported_workouts(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let query = doc! { "user_id": user_id, "import_id": import_id };
+        let mut cursor = self.workouts.find(query, None).await?;
+
+        let mut removed = 0;
+        let mut kept = 0;
+        while let Some(result) = cursor.next().await {
+            let workout = result?;
+            let scores = self
+                .scores
+                .count_documents(doc! { "workout_id": &workout.workout_id }, None)
+                .await?;
+            if scores > 0 {
+                kept += 1;
+                continue;
+            }
+
+            self.workouts
+                .delete_one(doc! { "workout_id": &workout.workout_id }, None)
+                .await?;
+            removed += 1;
+        }
+
+        Ok((removed, kept))
+    }
+}
@@ -1,90 +1,48 @@
+use crate::errors::{AppError, WebResult};
 use crate::models::movement::{
-    CreateMovement, CreateMovementScore, MovementModel, MovementScoreModel, UpdateMovement,
-    UpdateMovementScore,
+    convert_score_measurement, top_set, CreateMovement, CreateMovementScore, MovementMeasurement,
+    MovementModel, MovementScoreModel, UpdateMovement, UpdateMovementScore,
 };
-use crate::utils::{query_utils, Config};
-use crate::{
-    errors::{AppError, WebResult},
-    models::movement::MovementMeasurement,
-};
-
-use chrono::Utc;
-use futures::stream::StreamExt;
-use mongodb::options::FindOptions;
-use mongodb::{Client, Collection};
-use std::vec::Vec;
+use crate::models::response::{ForkQuery, InvalidScore, MeasurementChangePreview};
 
-static WORKOUT_COLLECTION_NAME: &str = "movements";
-static SCORE_COLLECTION_NAME: &str = "movementscores";
-
-pub struct MovementRepository {
-    pub mongo_client: Client,
-}
+use async_trait::async_trait;
 
-impl MovementRepository {
-    fn get_score_collection(&self) -> Collection<MovementScoreModel> {
-        let config = Config::from_env().unwrap();
-        let database_name = config.mongo.db_name;
-        let db = self.mongo_client.database(database_name.as_str());
-        db.collection(SCORE_COLLECTION_NAME)
-    }
-
-    fn get_movement_collection(&self) -> Collection<MovementModel> {
-        let config = Config::from_env().unwrap();
-        let database_name = config.mongo.db_name;
-        let db = self.mongo_client.database(database_name.as_str());
-        db.collection(WORKOUT_COLLECTION_NAME)
-    }
-
-    pub async fn find_movement_by_name(
+/// Keeps the movements and the scores logged for them. Users see their own
+/// movements and the public ones, but only their own scores.
+#[async_trait(?Send)]
+pub trait MovementRepository: Send + Sync {
+    async fn find_movement_by_name(
         &self,
         user_id: &str,
         name: &str,
-    ) -> WebResult<Option<MovementModel>> {
-        let query = query_utils::for_one(doc! {"name": name }, user_id);
-
-        match self.get_movement_collection().find_one(query, None).await {
-            Ok(movement) => Ok(movement),
-            Err(e) => Err(AppError::Internal(e.to_string())),
-        }
-    }
+    ) -> WebResult<Option<MovementModel>>;
 
-    pub async fn find_movement_by_id(
+    /// Finds a movement the user imported from the given source record.
+    async fn find_movement_by_source(
         &self,
         user_id: &str,
-        movement_id: &str,
-    ) -> WebResult<Option<MovementModel>> {
-        let query = query_utils::for_one(doc! {"movement_id": movement_id }, user_id);
+        source_id: &str,
+    ) -> WebResult<Option<MovementModel>>;
 
-        match self.get_movement_collection().find_one(query, None).await {
-            Ok(movement) => Ok(movement),
-            Err(e) => Err(AppError::Internal(e.to_string())),
-        }
-    }
+    /// Finds the scores earlier imports added for `movement_scores` in one
+    /// lookup, the scores with the same source and, from before sources were
+    /// tracked, the scores on the same dates.
+    async fn find_imported_movement_scores(
+        &self,
+        user_id: &str,
+        movement_scores: &[&CreateMovementScore],
+    ) -> WebResult<Vec<MovementScoreModel>>;
 
-    pub async fn get_movements(&self, user_id: &str) -> WebResult<Vec<MovementModel>> {
-        let query = query_utils::for_many(user_id);
-        let find_options = FindOptions::builder().sort(doc! { "name": 1 }).build();
-        let mut cursor = self
-            .get_movement_collection()
-            .find(query, find_options)
-            .await?;
-
-        let mut vec: Vec<MovementModel> = Vec::new();
-
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(result) => vec.push(result),
-                // Should this really be swallowing the errors?
-                // Is it because I still want to return _some_ data?
-                Err(e) => warn!("Error reading movement: {:?}", e.to_string()),
-            }
-        }
+    async fn find_movement_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+    ) -> WebResult<Option<MovementModel>>;
 
-        Ok(vec)
-    }
+    /// The movements the user can see, sorted by name.
+    async fn get_movements(&self, user_id: &str) -> WebResult<Vec<MovementModel>>;
 
-    pub async fn get_movement_by_id(
+    async fn get_movement_by_id(
         &self,
         user_id: &str,
         movement_id: &str,
@@ -97,257 +55,310 @@ impl MovementRepository {
         }
     }
 
-    pub async fn create_movement(
+    async fn create_movement(
         &self,
         user_id: &str,
         movement: CreateMovement,
-    ) -> WebResult<MovementModel> {
-        let movement_name = movement.name.as_ref();
-        let existing_movement = self.find_movement_by_name(user_id, movement_name).await?;
+    ) -> WebResult<MovementModel>;
 
-        if existing_movement.is_some() {
-            return Err(AppError::Conflict(
-                "A movement with this name already exists".to_string(),
-            ));
-        }
+    /// Adds many movements with a single write, e.g. for imports. Fails when
+    /// a name is used twice or taken by a movement of the user.
+    async fn create_movements(
+        &self,
+        user_id: &str,
+        movements: Vec<CreateMovement>,
+    ) -> WebResult<Vec<MovementModel>>;
 
-        let coll = self.get_movement_collection();
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
-        let movement = MovementModel {
-            movement_id: id,
-            user_id: user_id.to_owned(),
-            name: movement.name.to_owned(),
-            measurement: movement.measurement,
-            is_public: movement.is_public,
-            created_at: now.to_owned(),
-            updated_at: now.to_owned(),
-        };
-
-        coll.insert_one(movement, None).await?;
-
-        match self.find_movement_by_name(user_id, movement_name).await? {
-            Some(new_movement) => Ok(new_movement),
-            None => Err(AppError::Internal(
-                "New movement not found after inserting".to_string(),
-            )),
-        }
-    }
+    /// Copies a movement the user can see into their own library. Scores the user
+    /// logged on the original can be moved over to the copy.
+    async fn fork_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<MovementModel>;
 
-    pub async fn update_movement(
+    async fn preview_movement_update(
         &self,
         user_id: &str,
         movement_id: &str,
         movement_update: UpdateMovement,
-    ) -> WebResult<MovementModel> {
-        let existing_movement = self.find_movement_by_id(user_id, movement_id).await?;
-
-        if existing_movement.is_none() {
-            return Err(AppError::NotFound("Movement not found".to_owned()));
-        }
-
-        let existing_movement = existing_movement.unwrap();
-        let new_name = movement_update.name.unwrap_or(existing_movement.name);
-        let new_movement = existing_movement.measurement;
-
-        let query = doc! { "movement_id": movement_id };
-        let update = doc! {
-        "$set": {
-                "name": new_name,
-                "measurement": bson::to_bson(&new_movement).expect("Could not convert movement to bson"),
-                "is_public": existing_movement.is_public.to_owned(),
-                "updated_at": Utc::now().to_rfc3339()
-            }
-        };
-
-        let coll = self.get_movement_collection();
-        coll.update_one(query, update, None).await?;
+    ) -> WebResult<MeasurementChangePreview>;
 
-        let model = self
-            .find_movement_by_id(user_id, movement_id)
-            .await?
-            .unwrap();
-
-        Ok(model)
-    }
-
-    pub async fn delete_movement(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
-        let movement = self.find_movement_by_id(user_id, movement_id).await?;
-
-        if movement.is_none() {
-            return Err(AppError::NotFound("Movement does not exist".to_owned()));
-        }
-
-        let coll = self.get_movement_collection();
-        coll.delete_one(doc! { "movement_id": movement_id }, None)
-            .await?;
-
-        self.delete_movement_scores(user_id, movement_id).await?;
+    async fn update_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MovementModel>;
 
-        Ok(())
-    }
+    /// Removes a movement along with the scores the user logged for it.
+    async fn delete_movement(&self, user_id: &str, movement_id: &str) -> WebResult<()>;
 
-    pub async fn create_movement_score(
+    async fn create_movement_score(
         &self,
         user_id: &str,
         movement: &MovementModel,
         movement_score: CreateMovementScore,
-    ) -> WebResult<MovementScoreModel> {
-        let coll = self.get_score_collection();
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
-        let movement_id = movement.movement_id.to_owned();
-        let new_score = MovementScoreModel {
-            movement_score_id: id.to_owned(),
-            movement_id: movement_id.to_owned(),
-            user_id: user_id.to_owned(),
-            score: movement_score.score,
-            sets: movement_score.sets,
-            reps: movement_score.reps,
-            notes: movement_score.notes,
-            // This is for mywod items, as they have their own created at date which prefer to keep
-            created_at: movement_score.created_at.unwrap_or_else(|| now.to_owned()),
-            updated_at: now.to_owned(),
-        };
-
-        coll.insert_one(new_score, None).await?;
-
-        self.get_movement_score_by_id(user_id, &movement_id, &id)
-            .await
-    }
+    ) -> WebResult<MovementScoreModel>;
 
-    pub async fn get_movement_scores_with_query(
+    /// Adds many scores of a movement with a single write, e.g. for imports.
+    /// Nothing is added when one of the scores is invalid. The scores are not
+    /// read back, as their ids are generated up front.
+    async fn create_movement_scores(
         &self,
-        query: bson::Document,
-        find_options: FindOptions,
-    ) -> WebResult<Vec<MovementScoreModel>> {
-        let mut cursor = self
-            .get_score_collection()
-            .find(query, find_options)
-            .await
-            .unwrap();
-
-        let mut vec: Vec<MovementScoreModel> = Vec::new();
-
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(document) => vec.push(document),
-                Err(e) => warn!("Error reading movement: {:?}", e),
-            }
-        }
-
-        Ok(vec)
-    }
+        user_id: &str,
+        movement: &MovementModel,
+        movement_scores: Vec<CreateMovementScore>,
+    ) -> WebResult<Vec<MovementScoreModel>>;
 
-    pub async fn get_movement_scores_for_user(
+    /// The scores of the user, oldest first.
+    async fn get_movement_scores_for_user(
         &self,
         user_id: &str,
-    ) -> WebResult<Vec<MovementScoreModel>> {
-        let query = query_utils::for_many_with_filter(doc! { "user_id": user_id }, user_id);
-        let find_options: FindOptions = FindOptions::builder()
-            .sort(doc! { "created_at": 1 })
-            .build();
-
-        self.get_movement_scores_with_query(query, find_options)
-            .await
-    }
+    ) -> WebResult<Vec<MovementScoreModel>>;
 
-    pub async fn get_movement_scores_for_movement(
+    /// The scores of the user for a movement, best first.
+    async fn get_movement_scores_for_movement(
         &self,
         user_id: &str,
         movement: &MovementModel,
-    ) -> WebResult<Vec<MovementScoreModel>> {
-        let query = query_utils::for_many_with_filter(
-            doc! { "user_id": user_id, "movement_id": &movement.movement_id },
-            user_id,
-        );
-
-        // ascending for timed, descending for the rest
-        let score_filter = doc! { "score": if movement.measurement == MovementMeasurement::Time { 1 } else { -1 } };
-        let find_options = FindOptions::builder().sort(score_filter).build();
+    ) -> WebResult<Vec<MovementScoreModel>>;
 
-        self.get_movement_scores_with_query(query, find_options)
-            .await
-    }
-
-    pub async fn get_movement_score_by_id(
+    async fn get_movement_score_by_id(
         &self,
         user_id: &str,
         movement_id: &str,
         movement_score_id: &str,
-    ) -> WebResult<MovementScoreModel> {
-        let query = query_utils::for_one(
-            doc! { "movement_id":  movement_id, "movement_score_id": movement_score_id },
-            user_id,
-        );
-        let cursor = self.get_score_collection().find_one(query, None).await?;
+    ) -> WebResult<MovementScoreModel>;
 
-        match cursor {
-            Some(model) => Ok(model),
-            None => Err(AppError::NotFound("Entity not found".to_string())),
-        }
-    }
-
-    pub async fn update_movement_score_by_id(
+    async fn update_movement_score_by_id(
         &self,
         user_id: &str,
         movement_id: &str,
         movement_score_id: &str,
         new_score: UpdateMovementScore,
-    ) -> WebResult<MovementScoreModel> {
-        let score = self
-            .get_movement_score_by_id(user_id, movement_id, movement_score_id)
-            .await?;
-
-        let updated_score = new_score.score.unwrap_or(score.score);
-        let updated_reps = new_score.reps.unwrap_or(score.reps);
-        let updated_sets = new_score.sets.unwrap_or(score.sets);
-        let updated_notes = new_score.notes.unwrap_or(score.notes);
-        let updated_updated_at = Utc::now().to_rfc3339();
-
-        let query = query_utils::for_one(doc! { "movement_score_id": movement_score_id }, user_id);
-        let update = doc! {
-        "$set": {
-             "score": updated_score,
-                "reps": updated_reps,
-                "sets": updated_sets,
-                "notes": updated_notes,
-                "updated_at": updated_updated_at,
-            }
-        };
+    ) -> WebResult<MovementScoreModel>;
 
-        let _ = self
-            .get_score_collection()
-            .update_one(query, update, None)
-            .await?;
-
-        let res = self
-            .get_movement_score_by_id(user_id, movement_id, movement_score_id)
-            .await?;
-
-        Ok(res)
-    }
-
-    pub async fn delete_movement_score_by_id(
+    async fn delete_movement_score_by_id(
         &self,
         user_id: &str,
         movement_id: &str,
         movement_score_id: &str,
-    ) -> WebResult<()> {
-        // Ensure the score exists for the user
-        self.get_movement_score_by_id(user_id, movement_id, movement_score_id)
-            .await?;
+    ) -> WebResult<()>;
 
-        let query = query_utils::for_one(doc! { "movement_score_id": movement_score_id }, user_id);
-        let _ = self.get_score_collection().delete_one(query, None).await?;
+    /// Removes the movement scores an import added.
+    async fn delete_imported_movement_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64>;
 
-        Ok(())
+    /// Removes the movements an import added, unless the user logged scores for
+    /// them that were not part of the import. Returns how many movements were
+    /// removed and how many were kept.
+    async fn delete_imported_movements(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)>;
+}
+
+pub(crate) fn new_movement(user_id: &str, movement: CreateMovement, now: &str) -> MovementModel {
+    MovementModel {
+        movement_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        forked_from: None,
+        source_id: movement.source_id,
+        import_id: movement.import_id,
+        name: movement.name,
+        measurement: movement.measurement,
+        is_public: movement.is_public,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
     }
+}
 
-    async fn delete_movement_scores(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
-        let query = query_utils::for_many_with_filter(doc! { "movement_id": movement_id }, user_id);
-        self.get_score_collection().delete_many(query, None).await?;
+pub(crate) fn new_movement_score(
+    user_id: &str,
+    movement: &MovementModel,
+    movement_score: CreateMovementScore,
+    now: &str,
+) -> WebResult<MovementScoreModel> {
+    // The top set is what the score gets sorted by when a set list is given
+    let (score, sets, reps) = match top_set(movement.measurement, &movement_score.set_details) {
+        Some(top) => (
+            top.score(movement.measurement),
+            movement_score.set_details.len() as u32,
+            top.reps,
+        ),
+        None => match movement_score.score {
+            Some(score) => (score, movement_score.sets, movement_score.reps),
+            None => {
+                return Err(AppError::BadRequest(
+                    "A score or a list of sets is required".to_owned(),
+                ))
+            }
+        },
+    };
+
+    Ok(MovementScoreModel {
+        movement_score_id: uuid::Uuid::new_v4().to_string(),
+        movement_id: movement.movement_id.to_owned(),
+        user_id: user_id.to_owned(),
+        score,
+        sets,
+        reps,
+        set_details: movement_score.set_details,
+        unit: movement_score.unit,
+        distance: movement_score.distance,
+        distance_unit: movement_score.distance_unit,
+        duration: movement_score.duration,
+        notes: movement_score.notes,
+        source_id: movement_score.source_id,
+        import_id: movement_score.import_id,
+        // This is for mywod items, as they have their own created at date which prefer to keep
+        created_at: movement_score.created_at.unwrap_or_else(|| now.to_owned()),
+        updated_at: now.to_owned(),
+    })
+}
+
+/// A private copy of a movement for the user, named `name`.
+pub(crate) fn forked_movement(
+    original: &MovementModel,
+    user_id: &str,
+    name: String,
+    now: &str,
+) -> MovementModel {
+    MovementModel {
+        movement_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        forked_from: Some(original.movement_id.to_owned()),
+        source_id: None,
+        import_id: None,
+        name,
+        measurement: original.measurement,
+        is_public: false,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
+    }
+}
+
+/// The score with the changes of `new_score` applied. A list of sets decides
+/// the score, sets and reps, the same as when the score is created, so they
+/// can not be changed on their own while the score has a list of sets.
+pub(crate) fn updated_movement_score(
+    movement: &MovementModel,
+    score: MovementScoreModel,
+    new_score: UpdateMovementScore,
+    now: &str,
+) -> WebResult<MovementScoreModel> {
+    let changes_top_set =
+        new_score.score.is_some() || new_score.sets.is_some() || new_score.reps.is_some();
+    let mut updated = MovementScoreModel {
+        score: new_score.score.unwrap_or(score.score),
+        reps: new_score.reps.unwrap_or(score.reps),
+        sets: new_score.sets.unwrap_or(score.sets),
+        set_details: new_score.set_details.unwrap_or(score.set_details),
+        unit: new_score.unit.or(score.unit),
+        distance: new_score.distance.or(score.distance),
+        distance_unit: new_score.distance_unit.or(score.distance_unit),
+        duration: new_score.duration.or(score.duration),
+        notes: new_score.notes.unwrap_or(score.notes),
+        source_id: new_score.source_id.or(score.source_id),
+        updated_at: now.to_owned(),
+        ..score
+    };
+
+    if changes_top_set && !updated.set_details.is_empty() {
+        return Err(AppError::BadRequest(
+            "The score, sets and reps come from the list of sets, change the sets instead"
+                .to_owned(),
+        ));
+    }
+
+    if let Some(top) = top_set(movement.measurement, &updated.set_details) {
+        updated.score = top.score(movement.measurement);
+        updated.reps = top.reps;
+        updated.sets = updated.set_details.len() as u32;
+    }
+
+    Ok(updated)
+}
 
-        Ok(())
+/// Converts the scores of a movement to a new measurement. Scores of all
+/// users are included, as public movements are scored by others as well.
+pub(crate) fn plan_measurement_change(
+    movement: &MovementModel,
+    scores: Vec<MovementScoreModel>,
+    measurement: MovementMeasurement,
+) -> (Vec<MovementScoreModel>, MeasurementChangePreview) {
+    let mut converted_scores: Vec<MovementScoreModel> = Vec::new();
+    let mut invalid_scores: Vec<InvalidScore> = Vec::new();
+
+    if measurement != movement.measurement {
+        for score in scores {
+            match convert_score_measurement(&score, movement.measurement, measurement) {
+                Ok(converted) => converted_scores.push(converted),
+                Err(reason) => invalid_scores.push(InvalidScore {
+                    score_id: score.movement_score_id,
+                    user_id: score.user_id,
+                    score: score.score,
+                    reason,
+                }),
+            }
+        }
+    }
+
+    let preview = MeasurementChangePreview {
+        converted_scores: converted_scores.len() as u32,
+        invalid_scores,
+    };
+
+    (converted_scores, preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn from_json<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_updated_movement_score_with_sets() {
+        let now = Utc::now().to_rfc3339();
+        let movement = new_movement(
+            "user",
+            from_json(serde_json::json!({"name": "Back Squat", "measurement": "weight"})),
+            &now,
+        );
+        let score = new_movement_score(
+            "user",
+            &movement,
+            from_json(serde_json::json!({
+                "set_details": [{"reps": 5, "load": 100.0}, {"reps": 3, "load": 110.0}]
+            })),
+            &now,
+        )
+        .unwrap();
+        assert_eq!(score.score, 110.0);
+
+        // The list decides the score, so it can not be changed on its own
+        let update = from_json(serde_json::json!({"score": 120.0}));
+        let res = updated_movement_score(&movement, score.clone(), update, &now);
+        assert!(matches!(res, Err(AppError::BadRequest(_))));
+
+        let update = from_json(serde_json::json!({"set_details": [{"reps": 1, "load": 120.0}]}));
+        let updated = updated_movement_score(&movement, score.clone(), update, &now).unwrap();
+        assert_eq!(updated.score, 120.0);
+        assert_eq!(updated.sets, 1);
+
+        // Clearing the list makes the score editable again
+        let update = from_json(serde_json::json!({"score": 120.0, "set_details": []}));
+        let updated = updated_movement_score(&movement, score, update, &now).unwrap();
+        assert_eq!(updated.score, 120.0);
     }
 }
@@ -0,0 +1,93 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::{CreateFeed, FeedModel};
+use crate::repositories::feed_repository::{feed_exists_error, new_feed};
+use crate::repositories::sqlite::{query_data, query_one, to_data, SqliteDatabase};
+use crate::repositories::FeedRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+pub struct SqliteFeedRepository {
+    pub database: SqliteDatabase,
+}
+
+/// Adds the feed or replaces the feed with the same id.
+pub(crate) fn save_feed(connection: &Connection, feed: &FeedModel) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO feeds (feed_id, user_id, title, url, import_id, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (feed_id) DO UPDATE SET
+                user_id = excluded.user_id, title = excluded.title, url = excluded.url,
+                import_id = excluded.import_id, data = excluded.data",
+        )?
+        .execute(params![
+            feed.feed_id,
+            feed.user_id,
+            feed.title,
+            feed.url,
+            feed.import_id,
+            to_data(feed)?
+        ])?;
+
+    Ok(())
+}
+
+#[async_trait(?Send)]
+impl FeedRepository for SqliteFeedRepository {
+    async fn get_feeds(&self, user_id: &str) -> WebResult<Vec<FeedModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM feeds WHERE user_id = ?1 ORDER BY title",
+            params![user_id],
+        )
+    }
+
+    async fn find_feed_by_url(&self, user_id: &str, url: &str) -> WebResult<Option<FeedModel>> {
+        query_one(
+            &self.database.lock(),
+            "SELECT data FROM feeds WHERE user_id = ?1 AND url = ?2",
+            params![user_id, url],
+        )
+    }
+
+    async fn create_feed(&self, user_id: &str, feed: CreateFeed) -> WebResult<FeedModel> {
+        let new_feed = new_feed(user_id, feed, &Utc::now().to_rfc3339())?;
+        if self
+            .find_feed_by_url(user_id, &new_feed.url)
+            .await?
+            .is_some()
+        {
+            return Err(feed_exists_error());
+        }
+
+        save_feed(&self.database.lock(), &new_feed)?;
+
+        Ok(new_feed)
+    }
+
+    async fn delete_feed(&self, user_id: &str, feed_id: &str) -> WebResult<()> {
+        let deleted = self.database.lock().execute(
+            "DELETE FROM feeds WHERE feed_id = ?1 AND user_id = ?2",
+            params![feed_id, user_id],
+        )?;
+
+        if deleted == 0 {
+            return Err(AppError::NotFound(
+                "Feed with this id does not exist".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_imported_feeds(&self, user_id: &str, import_id: &str) -> WebResult<u64> {
+        let deleted = self.database.lock().execute(
+            "DELETE FROM feeds WHERE user_id = ?1 AND import_id = ?2",
+            params![user_id, import_id],
+        )?;
+
+        Ok(deleted as u64)
+    }
+}
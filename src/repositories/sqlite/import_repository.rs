@@ -0,0 +1,248 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::{ImportModel, ImportStatus, ProfileSnapshot, RollbackResult};
+use crate::models::mywod::{MyWodResponse, RowReport};
+use crate::repositories::import_repository::{is_claimable, lost_claim, new_import};
+use crate::repositories::sqlite::{query_data, query_one, to_data, SqliteDatabase};
+use crate::repositories::ImportRepository;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::collections::HashSet;
+
+pub struct SqliteImportRepository {
+    pub database: SqliteDatabase,
+}
+
+/// Adds the import or replaces the import with the same id.
+pub(crate) fn save_import(connection: &Connection, import: &ImportModel) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO imports (import_id, user_id, status, data) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (import_id) DO UPDATE SET
+                user_id = excluded.user_id, status = excluded.status, data = excluded.data",
+        )?
+        .execute(params![
+            import.import_id,
+            import.user_id,
+            import.status.to_string(),
+            to_data(import)?
+        ])?;
+
+    Ok(())
+}
+
+impl SqliteImportRepository {
+    /// Changes the import with the id, if there is one, like an update of a
+    /// single document that matches nothing.
+    fn update(&self, import_id: &str, change: impl FnOnce(&mut ImportModel)) -> WebResult<()> {
+        let connection = self.database.lock();
+        let import: Option<ImportModel> = query_one(
+            &connection,
+            "SELECT data FROM imports WHERE import_id = ?1",
+            params![import_id],
+        )?;
+
+        if let Some(mut import) = import {
+            change(&mut import);
+            import.updated_at = Utc::now().to_rfc3339();
+            save_import(&connection, &import)?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the import with the id if `owner` holds the claim on it.
+    fn update_claimed(
+        &self,
+        import_id: &str,
+        owner: &str,
+        change: impl FnOnce(&mut ImportModel),
+    ) -> WebResult<()> {
+        let mut connection = self.database.lock();
+        // Other processes may take over the claim, so it is checked while
+        // holding the write lock
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let import: Option<ImportModel> = query_one(
+            &transaction,
+            "SELECT data FROM imports WHERE import_id = ?1",
+            params![import_id],
+        )?;
+
+        let mut import = match import {
+            Some(import) if import.claimed_by.as_deref() == Some(owner) => import,
+            _ => return Err(lost_claim(import_id)),
+        };
+        change(&mut import);
+        import.updated_at = Utc::now().to_rfc3339();
+        save_import(&transaction, &import)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl ImportRepository for SqliteImportRepository {
+    async fn create_import(
+        &self,
+        user_id: &str,
+        user_email: &str,
+        source: &str,
+        file_path: &str,
+    ) -> WebResult<ImportModel> {
+        let import = new_import(
+            user_id,
+            user_email,
+            source,
+            file_path,
+            &Utc::now().to_rfc3339(),
+        );
+        save_import(&self.database.lock(), &import)?;
+
+        Ok(import)
+    }
+
+    async fn get_import_by_id(&self, user_id: &str, import_id: &str) -> WebResult<ImportModel> {
+        match query_one(
+            &self.database.lock(),
+            "SELECT data FROM imports WHERE import_id = ?1 AND user_id = ?2",
+            params![import_id, user_id],
+        )? {
+            Some(import) => Ok(import),
+            None => Err(AppError::NotFound(
+                "Import with this id does not exist".to_string(),
+            )),
+        }
+    }
+
+    async fn find_import_by_id(&self, import_id: &str) -> WebResult<Option<ImportModel>> {
+        query_one(
+            &self.database.lock(),
+            "SELECT data FROM imports WHERE import_id = ?1",
+            params![import_id],
+        )
+    }
+
+    async fn get_unfinished_imports(&self) -> WebResult<Vec<ImportModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM imports WHERE status IN ('pending', 'running')",
+            params![],
+        )
+    }
+
+    async fn claim_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        expires_at: i64,
+    ) -> WebResult<Option<ImportModel>> {
+        let now = Utc::now();
+        let mut connection = self.database.lock();
+        // Other processes may share the file, so the claim is checked and
+        // taken while holding the write lock
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let import: Option<ImportModel> = query_one(
+            &transaction,
+            "SELECT data FROM imports WHERE import_id = ?1",
+            params![import_id],
+        )?;
+
+        let mut import = match import {
+            Some(import) if is_claimable(&import, owner, now.timestamp_millis()) => import,
+            _ => return Ok(None),
+        };
+        import.claimed_by = Some(owner.to_owned());
+        import.claim_expires_at = Some(expires_at);
+        import.updated_at = now.to_rfc3339();
+        save_import(&transaction, &import)?;
+        transaction.commit()?;
+
+        Ok(Some(import))
+    }
+
+    async fn get_snapshot_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let imports: Vec<ImportModel> = query_data(
+            &self.database.lock(),
+            "SELECT data FROM imports WHERE status != 'rolled_back'",
+            params![],
+        )?;
+
+        Ok(imports
+            .into_iter()
+            .filter_map(|import| import.previous_profile)
+            .filter(|profile| !profile.avatar_url.is_empty())
+            .map(|profile| profile.avatar_url)
+            .collect())
+    }
+
+    async fn set_status(
+        &self,
+        import_id: &str,
+        owner: &str,
+        status: ImportStatus,
+        error: Option<String>,
+    ) -> WebResult<()> {
+        self.update_claimed(import_id, owner, |import| {
+            import.status = status;
+            import.error = error;
+        })
+    }
+
+    async fn set_total(&self, import_id: &str, total: u32) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.progress.processed = 0;
+            import.progress.total = total;
+        })
+    }
+
+    async fn add_progress(&self, import_id: &str, processed: u32) -> WebResult<()> {
+        self.update(import_id, |import| import.progress.processed += processed)
+    }
+
+    async fn complete_import(
+        &self,
+        import_id: &str,
+        owner: &str,
+        total: u32,
+        result: &MyWodResponse,
+        report: &[RowReport],
+    ) -> WebResult<()> {
+        self.update_claimed(import_id, owner, |import| {
+            import.status = ImportStatus::Completed;
+            import.progress.processed = total;
+            import.result = Some(result.clone());
+            import.report = report.to_vec();
+        })
+    }
+
+    async fn set_previous_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        self.update(import_id, |import| {
+            if import.previous_profile.is_none() {
+                import.previous_profile = Some(profile.clone());
+            }
+        })
+    }
+
+    async fn set_imported_profile(
+        &self,
+        import_id: &str,
+        profile: &ProfileSnapshot,
+    ) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.imported_profile = Some(profile.clone());
+        })
+    }
+
+    async fn complete_rollback(&self, import_id: &str, rollback: &RollbackResult) -> WebResult<()> {
+        self.update(import_id, |import| {
+            import.status = ImportStatus::RolledBack;
+            import.rollback = Some(rollback.clone());
+        })
+    }
+}
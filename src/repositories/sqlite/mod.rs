@@ -0,0 +1,365 @@
+use crate::errors::{AppError, WebResult};
+
+use rusqlite::types::Type;
+use rusqlite::{Connection, Params, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod feed_repository;
+mod import_repository;
+mod movement_repository;
+mod user_repository;
+mod workout_repository;
+
+pub use feed_repository::SqliteFeedRepository;
+pub use import_repository::SqliteImportRepository;
+pub use movement_repository::SqliteMovementRepository;
+pub use user_repository::SqliteUserRepository;
+pub use workout_repository::SqliteWorkoutRepository;
+
+pub(crate) use feed_repository::save_feed;
+pub(crate) use import_repository::save_import;
+pub(crate) use movement_repository::{save_movement, save_movement_score};
+pub(crate) use user_repository::save_user;
+pub(crate) use workout_repository::{save_workout, save_workout_score};
+
+/// Every record is kept as JSON in the `data` column. The columns next to it
+/// are copies of the fields records are looked up by, and carry the same
+/// uniqueness guarantees as the MongoDB indexes in `db::migrations`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    user_id TEXT PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    avatar_url TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS users_avatar_index ON users (avatar_url);
+
+CREATE TABLE IF NOT EXISTS workouts (
+    workout_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    measurement TEXT NOT NULL,
+    is_public INTEGER NOT NULL,
+    source_id TEXT,
+    import_id TEXT,
+    data TEXT NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS workout_scores (
+    workout_score_id TEXT PRIMARY KEY,
+    workout_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    score REAL NOT NULL,
+    rx INTEGER NOT NULL,
+    source_id TEXT,
+    import_id TEXT,
+    created_at TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS workout_scores_index ON workout_scores (user_id, workout_id);
+
+CREATE TABLE IF NOT EXISTS movements (
+    movement_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    measurement TEXT NOT NULL,
+    is_public INTEGER NOT NULL,
+    source_id TEXT,
+    import_id TEXT,
+    data TEXT NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS movement_scores (
+    movement_score_id TEXT PRIMARY KEY,
+    movement_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    score REAL NOT NULL,
+    source_id TEXT,
+    import_id TEXT,
+    created_at TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS movement_scores_index ON movement_scores (user_id, movement_id);
+
+CREATE TABLE IF NOT EXISTS feeds (
+    feed_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    url TEXT NOT NULL,
+    import_id TEXT,
+    data TEXT NOT NULL,
+    UNIQUE (user_id, url)
+);
+
+CREATE TABLE IF NOT EXISTS imports (
+    import_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS imports_status_index ON imports (status);
+";
+
+/// Keeps everything in a single SQLite file, for running the server without
+/// MongoDB, e.g. when self-hosting for a single athlete. Clones share the
+/// connection. Queries are small and run while the connection is locked, so
+/// the lock is never held across an await.
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDatabase {
+    /// Opens the database file at `path`, creating it and its tables when
+    /// they do not exist yet.
+    pub fn open(path: &str) -> WebResult<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A database that is gone once it is dropped.
+    #[cfg(test)]
+    pub fn open_in_memory() -> WebResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> WebResult<Self> {
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(SqliteDatabase {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Only poisoned when a repository panicked halfway through a change,
+    /// which a transaction would have rolled back.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub(crate) fn to_data<T: Serialize>(model: &T) -> WebResult<String> {
+    serde_json::to_string(model).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+fn from_data<T: DeserializeOwned>(row: &Row) -> rusqlite::Result<T> {
+    let data: String = row.get("data")?;
+    serde_json::from_str(&data)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+/// The records of a query that selects their `data` column.
+pub(crate) fn query_data<T: DeserializeOwned, P: Params>(
+    connection: &Connection,
+    sql: &str,
+    params: P,
+) -> WebResult<Vec<T>> {
+    let mut statement = connection.prepare_cached(sql)?;
+    let records = statement
+        .query_map(params, |row| from_data(row))?
+        .collect::<Result<Vec<T>, _>>()?;
+
+    Ok(records)
+}
+
+/// The first record of a query that selects their `data` column.
+pub(crate) fn query_one<T: DeserializeOwned, P: Params>(
+    connection: &Connection,
+    sql: &str,
+    params: P,
+) -> WebResult<Option<T>> {
+    let mut statement = connection.prepare_cached(sql)?;
+    let mut rows = statement.query(params)?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(from_data(row)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::import::ImportStatus;
+    use crate::models::response::ForkQuery;
+    use crate::models::workout::{CreateWorkout, CreateWorkoutScore, WorkoutModel};
+    use crate::repositories::{ImportRepository, WorkoutRepository};
+
+    fn create_workout(name: &str, is_public: bool) -> CreateWorkout {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "description": "For time",
+            "measurement": "time",
+            "is_public": is_public,
+        }))
+        .unwrap()
+    }
+
+    fn create_score(score: f64) -> CreateWorkoutScore {
+        serde_json::from_value(serde_json::json!({ "score": score })).unwrap()
+    }
+
+    #[test]
+    fn test_unique_columns_conflict() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let connection = database.lock();
+        let workout: WorkoutModel = serde_json::from_value(serde_json::json!({
+            "workout_id": "1",
+            "user_id": "user",
+            "name": "Fran",
+            "description": "",
+            "measurement": "time",
+            "is_public": false,
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "updated_at": "2024-01-01T00:00:00+00:00",
+        }))
+        .unwrap();
+        save_workout(&connection, &workout).unwrap();
+        // Saving it again updates it
+        save_workout(&connection, &workout).unwrap();
+
+        let copy = WorkoutModel {
+            workout_id: "2".to_owned(),
+            ..workout
+        };
+        let result = save_workout(&connection, &copy);
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_workouts_and_scores() {
+        let repository = SqliteWorkoutRepository {
+            database: SqliteDatabase::open_in_memory().unwrap(),
+        };
+        let fran = repository
+            .create_workout("owner", create_workout("Fran", true))
+            .await
+            .unwrap();
+        repository
+            .create_workout("owner", create_workout("Cindy", false))
+            .await
+            .unwrap();
+
+        let result = repository
+            .create_workout("other", create_workout("Fran", false))
+            .await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        let names: Vec<String> = repository
+            .get_workouts("owner")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|workout| workout.name)
+            .collect();
+        assert_eq!(names, vec!["Cindy", "Fran"]);
+        assert_eq!(repository.get_workouts("other").await.unwrap().len(), 1);
+
+        for score in [300.0, 240.0, 420.0].iter().copied() {
+            repository
+                .create_workout_score("other", &fran, create_score(score))
+                .await
+                .unwrap();
+        }
+        let scores: Vec<f64> = repository
+            .get_workout_scores_for_workout("other", &fran)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|score| score.score)
+            .collect();
+        assert_eq!(scores, vec![240.0, 300.0, 420.0]);
+
+        let fork = ForkQuery {
+            name: None,
+            move_scores: true,
+        };
+        let copy = repository
+            .fork_workout("other", &fran.workout_id, fork)
+            .await
+            .unwrap();
+        // Only names of own workouts are taken
+        assert_eq!(copy.name, "Fran");
+        let moved = repository
+            .get_workout_scores_for_workout("other", &copy)
+            .await
+            .unwrap();
+        assert_eq!(moved.len(), 3);
+        assert!(moved
+            .iter()
+            .all(|score| score.workout_id == copy.workout_id));
+
+        let fork = || ForkQuery {
+            name: None,
+            move_scores: false,
+        };
+        let second = repository
+            .fork_workout("other", &fran.workout_id, fork())
+            .await
+            .unwrap();
+        assert_eq!(second.name, "Fran (2)");
+        let res = repository
+            .fork_workout("other", &copy.workout_id, fork())
+            .await;
+        assert!(matches!(res, Err(AppError::BadRequest(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_claim_import() {
+        let repository = SqliteImportRepository {
+            database: SqliteDatabase::open_in_memory().unwrap(),
+        };
+        let import = repository
+            .create_import("user", "user@example.com", "mywod", "uploads/backup")
+            .await
+            .unwrap();
+        let id = import.import_id.as_str();
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let claimed = repository.claim_import(id, "first", now + 1000).await;
+        assert_eq!(
+            claimed.unwrap().unwrap().claimed_by.as_deref(),
+            Some("first")
+        );
+        // Held by the first worker, which can renew it
+        assert!(repository
+            .claim_import(id, "second", now + 1000)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repository
+            .claim_import(id, "first", now + 2000)
+            .await
+            .unwrap()
+            .is_some());
+
+        // A claim that ran out can be taken over
+        repository.claim_import(id, "first", now - 1).await.unwrap();
+        assert!(repository
+            .claim_import(id, "second", now + 1000)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Only the worker holding the claim saves the outcome
+        let lost = repository
+            .set_status(id, "first", ImportStatus::Completed, None)
+            .await;
+        assert!(matches!(lost, Err(AppError::Conflict(_))));
+
+        // Imports that are done are not claimed
+        repository
+            .set_status(id, "second", ImportStatus::Completed, None)
+            .await
+            .unwrap();
+        assert!(repository
+            .claim_import(id, "second", now + 1000)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
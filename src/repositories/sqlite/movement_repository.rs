@@ -0,0 +1,625 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::movement::{
+    CreateMovement, CreateMovementScore, MovementMeasurement, MovementModel, MovementScoreModel,
+    UpdateMovement, UpdateMovementScore,
+};
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::repositories::movement_repository::{
+    forked_movement, new_movement, new_movement_score, plan_measurement_change,
+    updated_movement_score,
+};
+use crate::repositories::sqlite::{query_data, query_one, to_data, SqliteDatabase};
+use crate::repositories::{
+    imported_score_keys, is_imported_score, scored_by_others_error, unconvertible_scores_error,
+    MovementRepository,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+pub struct SqliteMovementRepository {
+    pub database: SqliteDatabase,
+}
+
+/// Adds the movement or replaces the movement with the same id.
+pub(crate) fn save_movement(connection: &Connection, movement: &MovementModel) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO movements
+                (movement_id, user_id, name, measurement, is_public, source_id, import_id, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (movement_id) DO UPDATE SET
+                user_id = excluded.user_id, name = excluded.name,
+                measurement = excluded.measurement, is_public = excluded.is_public,
+                source_id = excluded.source_id, import_id = excluded.import_id,
+                data = excluded.data",
+        )?
+        .execute(params![
+            movement.movement_id,
+            movement.user_id,
+            movement.name,
+            movement.measurement.to_string(),
+            movement.is_public,
+            movement.source_id,
+            movement.import_id,
+            to_data(movement)?
+        ])?;
+
+    Ok(())
+}
+
+/// Adds the score or replaces the score with the same id.
+pub(crate) fn save_movement_score(
+    connection: &Connection,
+    score: &MovementScoreModel,
+) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO movement_scores
+                (movement_score_id, movement_id, user_id, score, source_id, import_id,
+                 created_at, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (movement_score_id) DO UPDATE SET
+                movement_id = excluded.movement_id, user_id = excluded.user_id,
+                score = excluded.score, source_id = excluded.source_id,
+                import_id = excluded.import_id, created_at = excluded.created_at,
+                data = excluded.data",
+        )?
+        .execute(params![
+            score.movement_score_id,
+            score.movement_id,
+            score.user_id,
+            score.score,
+            score.source_id,
+            score.import_id,
+            score.created_at,
+            to_data(score)?
+        ])?;
+
+    Ok(())
+}
+
+fn find_movement_by_name(
+    connection: &Connection,
+    user_id: &str,
+    name: &str,
+) -> WebResult<Option<MovementModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM movements WHERE name = ?2 AND (user_id = ?1 OR is_public = 1)",
+        params![user_id, name],
+    )
+}
+
+/// Finds one of the movements the user created by its name. Public movements of
+/// others do not count, a user can have a copy of one with the same name.
+fn find_own_movement_by_name(
+    connection: &Connection,
+    user_id: &str,
+    name: &str,
+) -> WebResult<Option<MovementModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM movements WHERE user_id = ?1 AND name = ?2",
+        params![user_id, name],
+    )
+}
+
+fn find_movement_by_id(
+    connection: &Connection,
+    user_id: &str,
+    movement_id: &str,
+) -> WebResult<Option<MovementModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM movements WHERE movement_id = ?2 AND (user_id = ?1 OR is_public = 1)",
+        params![user_id, movement_id],
+    )
+}
+
+fn get_movement_score_by_id(
+    connection: &Connection,
+    user_id: &str,
+    movement_id: &str,
+    movement_score_id: &str,
+) -> WebResult<MovementScoreModel> {
+    let score = query_one(
+        connection,
+        "SELECT data FROM movement_scores
+         WHERE movement_id = ?2 AND movement_score_id = ?3 AND user_id = ?1",
+        params![user_id, movement_id, movement_score_id],
+    )?;
+
+    match score {
+        Some(score) => Ok(score),
+        None => Err(AppError::NotFound("Entity not found".to_string())),
+    }
+}
+
+/// Gets a movement the user is allowed to change, which are only the ones they created.
+fn get_own_movement(
+    connection: &Connection,
+    user_id: &str,
+    movement_id: &str,
+) -> WebResult<MovementModel> {
+    let movement = match find_movement_by_id(connection, user_id, movement_id)? {
+        Some(movement) => movement,
+        None => return Err(AppError::NotFound("Movement not found".to_owned())),
+    };
+
+    if movement.user_id != user_id {
+        return Err(AppError::Forbidden(
+            "Only the creator of a movement can change it".to_owned(),
+        ));
+    }
+
+    Ok(movement)
+}
+
+/// Converts the scores of a movement to a new measurement, which are only
+/// read when the measurement changes.
+fn plan_movement_measurement_change(
+    connection: &Connection,
+    movement: &MovementModel,
+    measurement: MovementMeasurement,
+) -> WebResult<(Vec<MovementScoreModel>, MeasurementChangePreview)> {
+    let scores = if measurement != movement.measurement {
+        query_data(
+            connection,
+            "SELECT data FROM movement_scores WHERE movement_id = ?1",
+            params![movement.movement_id],
+        )?
+    } else {
+        vec![]
+    };
+
+    Ok(plan_measurement_change(movement, scores, measurement))
+}
+
+#[async_trait(?Send)]
+impl MovementRepository for SqliteMovementRepository {
+    async fn find_movement_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        find_movement_by_name(&self.database.lock(), user_id, name)
+    }
+
+    async fn find_movement_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        query_one(
+            &self.database.lock(),
+            "SELECT data FROM movements WHERE user_id = ?1 AND source_id = ?2",
+            params![user_id, source_id],
+        )
+    }
+
+    async fn find_imported_movement_scores(
+        &self,
+        user_id: &str,
+        movement_scores: &[&CreateMovementScore],
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            movement_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        let scores: Vec<MovementScoreModel> = query_data(
+            &self.database.lock(),
+            "SELECT data FROM movement_scores WHERE user_id = ?1",
+            params![user_id],
+        )?;
+
+        Ok(scores
+            .into_iter()
+            .filter(|score| {
+                is_imported_score(&score.source_id, &score.created_at, &sources, &dates)
+            })
+            .collect())
+    }
+
+    async fn find_movement_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+    ) -> WebResult<Option<MovementModel>> {
+        find_movement_by_id(&self.database.lock(), user_id, movement_id)
+    }
+
+    async fn get_movements(&self, user_id: &str) -> WebResult<Vec<MovementModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM movements WHERE user_id = ?1 OR is_public = 1 ORDER BY name",
+            params![user_id],
+        )
+    }
+
+    async fn create_movement(
+        &self,
+        user_id: &str,
+        movement: CreateMovement,
+    ) -> WebResult<MovementModel> {
+        // A name of a public movement is taken as well, the bulk creation of
+        // imports only checks the names of the user
+        if self
+            .find_movement_by_name(user_id, &movement.name)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(
+                "A movement with this name already exists".to_string(),
+            ));
+        }
+
+        self.create_movements(user_id, vec![movement])
+            .await
+            .map(|mut movements| movements.remove(0))
+    }
+
+    async fn create_movements(
+        &self,
+        user_id: &str,
+        movements: Vec<CreateMovement>,
+    ) -> WebResult<Vec<MovementModel>> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let names: Vec<&str> = movements.iter().map(|w| w.name.as_str()).collect();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name)
+                || find_own_movement_by_name(&transaction, user_id, name)?.is_some()
+            {
+                return Err(AppError::Conflict(
+                    "A movement with this name already exists".to_string(),
+                ));
+            }
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let movements: Vec<MovementModel> = movements
+            .into_iter()
+            .map(|movement| new_movement(user_id, movement, &now))
+            .collect();
+        for movement in &movements {
+            save_movement(&transaction, movement)?;
+        }
+        transaction.commit()?;
+
+        Ok(movements)
+    }
+
+    async fn fork_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<MovementModel> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let original = match find_movement_by_id(&transaction, user_id, movement_id)? {
+            Some(movement) => movement,
+            None => {
+                return Err(AppError::NotFound(
+                    "Movement with this id does not exist".to_string(),
+                ))
+            }
+        };
+
+        // Copies are made of the movements of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only movements of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if find_own_movement_by_name(&transaction, user_id, &name)?.is_some() {
+                    return Err(AppError::Conflict(
+                        "A movement with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => {
+                // e.g. "Fran (2)" when the user already has a "Fran"
+                let mut candidate = original.name.to_owned();
+                let mut n = 2;
+                while find_own_movement_by_name(&transaction, user_id, &candidate)?.is_some() {
+                    candidate = format!("{} ({})", original.name, n);
+                    n += 1;
+                }
+                candidate
+            }
+        };
+
+        let movement = forked_movement(&original, user_id, name, &Utc::now().to_rfc3339());
+        save_movement(&transaction, &movement)?;
+
+        if fork.move_scores {
+            let scores: Vec<MovementScoreModel> = query_data(
+                &transaction,
+                "SELECT data FROM movement_scores WHERE movement_id = ?1 AND user_id = ?2",
+                params![original.movement_id, user_id],
+            )?;
+            for score in scores {
+                let score = MovementScoreModel {
+                    movement_id: movement.movement_id.to_owned(),
+                    ..score
+                };
+                save_movement_score(&transaction, &score)?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(movement)
+    }
+
+    async fn preview_movement_update(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MeasurementChangePreview> {
+        let connection = self.database.lock();
+        let movement = get_own_movement(&connection, user_id, movement_id)?;
+        let measurement = movement_update.measurement.unwrap_or(movement.measurement);
+
+        plan_movement_measurement_change(&connection, &movement, measurement)
+            .map(|(_, preview)| preview)
+    }
+
+    async fn update_movement(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_update: UpdateMovement,
+    ) -> WebResult<MovementModel> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let existing_movement = get_own_movement(&transaction, user_id, movement_id)?;
+        let new_measurement = movement_update
+            .measurement
+            .unwrap_or(existing_movement.measurement);
+
+        let (converted_scores, preview) =
+            plan_movement_measurement_change(&transaction, &existing_movement, new_measurement)?;
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        if existing_movement.is_public && movement_update.is_public == Some(false) {
+            let others: i64 = transaction.query_row(
+                "SELECT COUNT(*) FROM movement_scores WHERE movement_id = ?1 AND user_id != ?2",
+                params![movement_id, user_id],
+                |row| row.get(0),
+            )?;
+            if others > 0 {
+                return Err(scored_by_others_error("movement"));
+            }
+        }
+
+        let new_name = movement_update
+            .name
+            .unwrap_or_else(|| existing_movement.name.to_owned());
+
+        // Check if there exists a movement with the new name
+        if let Some(conflicting_movement) = find_movement_by_name(&transaction, user_id, &new_name)?
+        {
+            if conflicting_movement.movement_id != movement_id {
+                return Err(AppError::Conflict(
+                    "Movement with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        let updated_movement = MovementModel {
+            name: new_name,
+            measurement: new_measurement,
+            is_public: movement_update
+                .is_public
+                .unwrap_or(existing_movement.is_public),
+            updated_at: Utc::now().to_rfc3339(),
+            ..existing_movement
+        };
+        save_movement(&transaction, &updated_movement)?;
+        for score in &converted_scores {
+            save_movement_score(&transaction, score)?;
+        }
+        transaction.commit()?;
+
+        Ok(updated_movement)
+    }
+
+    async fn delete_movement(&self, user_id: &str, movement_id: &str) -> WebResult<()> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        if find_movement_by_id(&transaction, user_id, movement_id)?.is_none() {
+            return Err(AppError::NotFound("Movement does not exist".to_owned()));
+        }
+
+        transaction.execute(
+            "DELETE FROM movements WHERE movement_id = ?1",
+            params![movement_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM movement_scores WHERE movement_id = ?1 AND user_id = ?2",
+            params![movement_id, user_id],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn create_movement_score(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_score: CreateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let movement_score =
+            new_movement_score(user_id, movement, movement_score, &Utc::now().to_rfc3339())?;
+        save_movement_score(&self.database.lock(), &movement_score)?;
+
+        Ok(movement_score)
+    }
+
+    async fn create_movement_scores(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+        movement_scores: Vec<CreateMovementScore>,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        let now = Utc::now().to_rfc3339();
+        let movement_scores = movement_scores
+            .into_iter()
+            .map(|score| new_movement_score(user_id, movement, score, &now))
+            .collect::<WebResult<Vec<MovementScoreModel>>>()?;
+
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+        for score in &movement_scores {
+            save_movement_score(&transaction, score)?;
+        }
+        transaction.commit()?;
+
+        Ok(movement_scores)
+    }
+
+    async fn get_movement_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM movement_scores WHERE user_id = ?1 ORDER BY created_at",
+            params![user_id],
+        )
+    }
+
+    async fn get_movement_scores_for_movement(
+        &self,
+        user_id: &str,
+        movement: &MovementModel,
+    ) -> WebResult<Vec<MovementScoreModel>> {
+        // ascending for timed, descending for the rest
+        let sql = if movement.measurement.lower_is_better() {
+            "SELECT data FROM movement_scores WHERE user_id = ?1 AND movement_id = ?2
+             ORDER BY score ASC"
+        } else {
+            "SELECT data FROM movement_scores WHERE user_id = ?1 AND movement_id = ?2
+             ORDER BY score DESC"
+        };
+
+        query_data(
+            &self.database.lock(),
+            sql,
+            params![user_id, movement.movement_id],
+        )
+    }
+
+    async fn get_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<MovementScoreModel> {
+        get_movement_score_by_id(
+            &self.database.lock(),
+            user_id,
+            movement_id,
+            movement_score_id,
+        )
+    }
+
+    async fn update_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+        new_score: UpdateMovementScore,
+    ) -> WebResult<MovementScoreModel> {
+        let connection = self.database.lock();
+        let score = get_movement_score_by_id(&connection, user_id, movement_id, movement_score_id)?;
+
+        let movement = match find_movement_by_id(&connection, user_id, movement_id)? {
+            Some(movement) => movement,
+            None => {
+                return Err(AppError::NotFound(
+                    "Movement with this id does not exist".to_string(),
+                ))
+            }
+        };
+        let updated =
+            updated_movement_score(&movement, score, new_score, &Utc::now().to_rfc3339())?;
+        save_movement_score(&connection, &updated)?;
+
+        Ok(updated)
+    }
+
+    async fn delete_movement_score_by_id(
+        &self,
+        user_id: &str,
+        movement_id: &str,
+        movement_score_id: &str,
+    ) -> WebResult<()> {
+        let connection = self.database.lock();
+        // Ensure the score exists for the user
+        get_movement_score_by_id(&connection, user_id, movement_id, movement_score_id)?;
+
+        connection.execute(
+            "DELETE FROM movement_scores WHERE movement_score_id = ?1",
+            params![movement_score_id],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_imported_movement_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let deleted = self.database.lock().execute(
+            "DELETE FROM movement_scores WHERE user_id = ?1 AND import_id = ?2",
+            params![user_id, import_id],
+        )?;
+
+        Ok(deleted as u64)
+    }
+
+    async fn delete_imported_movements(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let removed = transaction.execute(
+            "DELETE FROM movements WHERE user_id = ?1 AND import_id = ?2 AND NOT EXISTS (
+                SELECT 1 FROM movement_scores
+                WHERE movement_scores.movement_id = movements.movement_id
+             )",
+            params![user_id, import_id],
+        )?;
+        // The ones that are left have scores
+        let kept: i64 = transaction.query_row(
+            "SELECT COUNT(*) FROM movements WHERE user_id = ?1 AND import_id = ?2",
+            params![user_id, import_id],
+            |row| row.get(0),
+        )?;
+        transaction.commit()?;
+
+        Ok((removed as u64, kept as u64))
+    }
+}
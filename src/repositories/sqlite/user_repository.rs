@@ -0,0 +1,129 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::ProfileSnapshot;
+use crate::models::user::{UpdateUser, User};
+use crate::repositories::sqlite::{query_data, query_one, to_data, SqliteDatabase};
+use crate::repositories::user_repository::updated_user;
+use crate::repositories::UserRepository;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+
+pub struct SqliteUserRepository {
+    pub database: SqliteDatabase,
+}
+
+/// Adds the user or replaces the user with the same id.
+pub(crate) fn save_user(connection: &Connection, user: &User) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO users (user_id, email, avatar_url, data) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (user_id) DO UPDATE SET
+                email = excluded.email, avatar_url = excluded.avatar_url, data = excluded.data",
+        )?
+        .execute(params![
+            user.user_id,
+            user.email,
+            user.avatar_url,
+            to_data(user)?
+        ])?;
+
+    Ok(())
+}
+
+fn find_user_with_email(connection: &Connection, email: &str) -> WebResult<User> {
+    match query_one(
+        connection,
+        "SELECT data FROM users WHERE email = ?1",
+        params![email],
+    )? {
+        Some(user) => Ok(user),
+        None => Err(AppError::NotFound("User not found".to_owned())),
+    }
+}
+
+#[async_trait(?Send)]
+impl UserRepository for SqliteUserRepository {
+    async fn find_user_with_email(&self, email: &str) -> WebResult<User> {
+        find_user_with_email(&self.database.lock(), email)
+    }
+
+    async fn find_user_with_avatar(&self, avatar_url: &str) -> WebResult<User> {
+        match query_one(
+            &self.database.lock(),
+            "SELECT data FROM users WHERE avatar_url = ?1",
+            params![avatar_url],
+        )? {
+            Some(user) => Ok(user),
+            None => Err(AppError::NotFound("Avatar not found".to_owned())),
+        }
+    }
+
+    async fn is_avatar_shared(&self, user_id: &str, avatar_url: &str) -> WebResult<bool> {
+        let others: i64 = self.database.lock().query_row(
+            "SELECT COUNT(*) FROM users WHERE avatar_url = ?1 AND user_id != ?2",
+            params![avatar_url, user_id],
+            |row| row.get(0),
+        )?;
+
+        Ok(others > 0)
+    }
+
+    async fn get_avatar_urls(&self) -> WebResult<HashSet<String>> {
+        let users: Vec<User> = query_data(
+            &self.database.lock(),
+            "SELECT data FROM users WHERE avatar_url != ''",
+            params![],
+        )?;
+
+        Ok(users.into_iter().map(|user| user.avatar_url).collect())
+    }
+
+    async fn insert_user(&self, user: User) -> WebResult<()> {
+        self.database
+            .lock()
+            .prepare_cached(
+                "INSERT INTO users (user_id, email, avatar_url, data) VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![
+                user.user_id,
+                user.email,
+                user.avatar_url,
+                to_data(&user)?
+            ])?;
+
+        Ok(())
+    }
+
+    async fn update_user_with_email(
+        &self,
+        email: &str,
+        user_update: UpdateUser,
+    ) -> WebResult<User> {
+        let connection = self.database.lock();
+        let user = updated_user(find_user_with_email(&connection, email)?, user_update);
+        save_user(&connection, &user)?;
+
+        Ok(user)
+    }
+
+    async fn restore_profile(&self, email: &str, profile: &ProfileSnapshot) -> WebResult<User> {
+        let connection = self.database.lock();
+        let profile = profile.clone();
+        let user = User {
+            first_name: profile.first_name,
+            last_name: profile.last_name,
+            date_of_birth: profile.date_of_birth,
+            height: profile.height,
+            weight: profile.weight,
+            box_name: profile.box_name,
+            avatar_url: profile.avatar_url,
+            unit_system: profile.unit_system,
+            gender: profile.gender,
+            ..find_user_with_email(&connection, email)?
+        };
+        save_user(&connection, &user)?;
+
+        Ok(user)
+    }
+}
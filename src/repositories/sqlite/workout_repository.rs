@@ -0,0 +1,616 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::response::{ForkQuery, MeasurementChangePreview};
+use crate::models::workout::{
+    CreateWorkout, CreateWorkoutScore, UpdateWorkout, UpdateWorkoutScore, WorkoutMeasurement,
+    WorkoutModel, WorkoutScoreModel,
+};
+use crate::repositories::sqlite::{query_data, query_one, to_data, SqliteDatabase};
+use crate::repositories::workout_repository::{
+    forked_workout, new_workout, new_workout_score, plan_measurement_change, updated_workout_score,
+};
+use crate::repositories::{
+    imported_score_keys, is_imported_score, scored_by_others_error, unconvertible_scores_error,
+    WorkoutRepository,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+pub struct SqliteWorkoutRepository {
+    pub database: SqliteDatabase,
+}
+
+/// Adds the workout or replaces the workout with the same id.
+pub(crate) fn save_workout(connection: &Connection, workout: &WorkoutModel) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO workouts
+                (workout_id, user_id, name, measurement, is_public, source_id, import_id, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (workout_id) DO UPDATE SET
+                user_id = excluded.user_id, name = excluded.name,
+                measurement = excluded.measurement, is_public = excluded.is_public,
+                source_id = excluded.source_id, import_id = excluded.import_id,
+                data = excluded.data",
+        )?
+        .execute(params![
+            workout.workout_id,
+            workout.user_id,
+            workout.name,
+            workout.measurement.to_string(),
+            workout.is_public,
+            workout.source_id,
+            workout.import_id,
+            to_data(workout)?
+        ])?;
+
+    Ok(())
+}
+
+/// Adds the score or replaces the score with the same id.
+pub(crate) fn save_workout_score(
+    connection: &Connection,
+    score: &WorkoutScoreModel,
+) -> WebResult<()> {
+    connection
+        .prepare_cached(
+            "INSERT INTO workout_scores
+                (workout_score_id, workout_id, user_id, score, rx, source_id, import_id,
+                 created_at, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT (workout_score_id) DO UPDATE SET
+                workout_id = excluded.workout_id, user_id = excluded.user_id,
+                score = excluded.score, rx = excluded.rx, source_id = excluded.source_id,
+                import_id = excluded.import_id, created_at = excluded.created_at,
+                data = excluded.data",
+        )?
+        .execute(params![
+            score.workout_score_id,
+            score.workout_id,
+            score.user_id,
+            score.score,
+            score.rx,
+            score.source_id,
+            score.import_id,
+            score.created_at,
+            to_data(score)?
+        ])?;
+
+    Ok(())
+}
+
+fn find_workout_by_name(
+    connection: &Connection,
+    user_id: &str,
+    name: &str,
+) -> WebResult<Option<WorkoutModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM workouts WHERE name = ?2 AND (user_id = ?1 OR is_public = 1)",
+        params![user_id, name],
+    )
+}
+
+/// Finds one of the workouts the user created by its name. Public workouts of
+/// others do not count, a user can have a copy of one with the same name.
+fn find_own_workout_by_name(
+    connection: &Connection,
+    user_id: &str,
+    name: &str,
+) -> WebResult<Option<WorkoutModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM workouts WHERE user_id = ?1 AND name = ?2",
+        params![user_id, name],
+    )
+}
+
+fn find_workout_by_id(
+    connection: &Connection,
+    user_id: &str,
+    workout_id: &str,
+) -> WebResult<Option<WorkoutModel>> {
+    query_one(
+        connection,
+        "SELECT data FROM workouts WHERE workout_id = ?2 AND (user_id = ?1 OR is_public = 1)",
+        params![user_id, workout_id],
+    )
+}
+
+fn get_workout_score_by_id(
+    connection: &Connection,
+    user_id: &str,
+    workout_id: &str,
+    workout_score_id: &str,
+) -> WebResult<WorkoutScoreModel> {
+    let score = query_one(
+        connection,
+        "SELECT data FROM workout_scores
+         WHERE workout_id = ?2 AND workout_score_id = ?3 AND user_id = ?1",
+        params![user_id, workout_id, workout_score_id],
+    )?;
+
+    match score {
+        Some(score) => Ok(score),
+        None => Err(AppError::NotFound("Entity not found".to_string())),
+    }
+}
+
+/// Gets a workout the user is allowed to change, which are only the ones they created.
+fn get_own_workout(
+    connection: &Connection,
+    user_id: &str,
+    workout_id: &str,
+) -> WebResult<WorkoutModel> {
+    let workout = match find_workout_by_id(connection, user_id, workout_id)? {
+        Some(workout) => workout,
+        None => return Err(AppError::NotFound("Workout not found".to_owned())),
+    };
+
+    if workout.user_id != user_id {
+        return Err(AppError::Forbidden(
+            "Only the creator of a workout can change it".to_owned(),
+        ));
+    }
+
+    Ok(workout)
+}
+
+/// Converts the scores of a workout to a new measurement, which are only
+/// read when the measurement changes.
+fn plan_workout_measurement_change(
+    connection: &Connection,
+    workout: &WorkoutModel,
+    measurement: WorkoutMeasurement,
+) -> WebResult<(Vec<WorkoutScoreModel>, MeasurementChangePreview)> {
+    let scores = if measurement != workout.measurement {
+        query_data(
+            connection,
+            "SELECT data FROM workout_scores WHERE workout_id = ?1",
+            params![workout.workout_id],
+        )?
+    } else {
+        vec![]
+    };
+
+    Ok(plan_measurement_change(workout, scores, measurement))
+}
+
+#[async_trait(?Send)]
+impl WorkoutRepository for SqliteWorkoutRepository {
+    async fn find_workout_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        find_workout_by_name(&self.database.lock(), user_id, name)
+    }
+
+    async fn find_workout_by_source(
+        &self,
+        user_id: &str,
+        source_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        query_one(
+            &self.database.lock(),
+            "SELECT data FROM workouts WHERE user_id = ?1 AND source_id = ?2",
+            params![user_id, source_id],
+        )
+    }
+
+    async fn find_imported_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: &[&CreateWorkoutScore],
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let (sources, dates) = imported_score_keys(
+            workout_scores
+                .iter()
+                .map(|score| (&score.source_id, &score.created_at)),
+        );
+        let scores: Vec<WorkoutScoreModel> = query_data(
+            &self.database.lock(),
+            "SELECT data FROM workout_scores WHERE user_id = ?1",
+            params![user_id],
+        )?;
+
+        Ok(scores
+            .into_iter()
+            .filter(|score| {
+                is_imported_score(&score.source_id, &score.created_at, &sources, &dates)
+            })
+            .collect())
+    }
+
+    async fn find_workout_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+    ) -> WebResult<Option<WorkoutModel>> {
+        find_workout_by_id(&self.database.lock(), user_id, workout_id)
+    }
+
+    async fn get_workouts(&self, user_id: &str) -> WebResult<Vec<WorkoutModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM workouts WHERE user_id = ?1 OR is_public = 1 ORDER BY name",
+            params![user_id],
+        )
+    }
+
+    async fn create_workout(
+        &self,
+        user_id: &str,
+        workout: CreateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        // A name of a public workout is taken as well, the bulk creation of
+        // imports only checks the names of the user
+        if self
+            .find_workout_by_name(user_id, &workout.name)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(
+                "A workout with this name already exists".to_string(),
+            ));
+        }
+
+        self.create_workouts(user_id, vec![workout])
+            .await
+            .map(|mut workouts| workouts.remove(0))
+    }
+
+    async fn create_workouts(
+        &self,
+        user_id: &str,
+        workouts: Vec<CreateWorkout>,
+    ) -> WebResult<Vec<WorkoutModel>> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let names: Vec<&str> = workouts.iter().map(|w| w.name.as_str()).collect();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name)
+                || find_own_workout_by_name(&transaction, user_id, name)?.is_some()
+            {
+                return Err(AppError::Conflict(
+                    "A workout with this name already exists".to_string(),
+                ));
+            }
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let workouts: Vec<WorkoutModel> = workouts
+            .into_iter()
+            .map(|workout| new_workout(user_id, workout, &now))
+            .collect();
+        for workout in &workouts {
+            save_workout(&transaction, workout)?;
+        }
+        transaction.commit()?;
+
+        Ok(workouts)
+    }
+
+    async fn fork_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<WorkoutModel> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let original = match find_workout_by_id(&transaction, user_id, workout_id)? {
+            Some(workout) => workout,
+            None => {
+                return Err(AppError::NotFound(
+                    "Workout with this id does not exist".to_string(),
+                ))
+            }
+        };
+
+        // Copies are made of the workouts of others, own ones are changed instead
+        if original.user_id == user_id {
+            return Err(AppError::BadRequest(
+                "Only workouts of other users can be forked".to_owned(),
+            ));
+        }
+
+        let name = match fork.name {
+            Some(name) => {
+                if find_own_workout_by_name(&transaction, user_id, &name)?.is_some() {
+                    return Err(AppError::Conflict(
+                        "A workout with this name already exists".to_string(),
+                    ));
+                }
+                name
+            }
+            None => {
+                // e.g. "Fran (2)" when the user already has a "Fran"
+                let mut candidate = original.name.to_owned();
+                let mut n = 2;
+                while find_own_workout_by_name(&transaction, user_id, &candidate)?.is_some() {
+                    candidate = format!("{} ({})", original.name, n);
+                    n += 1;
+                }
+                candidate
+            }
+        };
+
+        let workout = forked_workout(&original, user_id, name, &Utc::now().to_rfc3339());
+        save_workout(&transaction, &workout)?;
+
+        if fork.move_scores {
+            let scores: Vec<WorkoutScoreModel> = query_data(
+                &transaction,
+                "SELECT data FROM workout_scores WHERE workout_id = ?1 AND user_id = ?2",
+                params![original.workout_id, user_id],
+            )?;
+            for score in scores {
+                let score = WorkoutScoreModel {
+                    workout_id: workout.workout_id.to_owned(),
+                    ..score
+                };
+                save_workout_score(&transaction, &score)?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(workout)
+    }
+
+    async fn preview_workout_update(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<MeasurementChangePreview> {
+        let connection = self.database.lock();
+        let workout = get_own_workout(&connection, user_id, workout_id)?;
+        let measurement = workout_update.measurement.unwrap_or(workout.measurement);
+
+        plan_workout_measurement_change(&connection, &workout, measurement)
+            .map(|(_, preview)| preview)
+    }
+
+    async fn update_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<WorkoutModel> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let existing_workout = get_own_workout(&transaction, user_id, workout_id)?;
+        let new_measurement = workout_update
+            .measurement
+            .unwrap_or(existing_workout.measurement);
+
+        let (converted_scores, preview) =
+            plan_workout_measurement_change(&transaction, &existing_workout, new_measurement)?;
+
+        if !preview.invalid_scores.is_empty() {
+            return Err(unconvertible_scores_error(&preview, new_measurement));
+        }
+
+        if existing_workout.is_public && workout_update.is_public == Some(false) {
+            let others: i64 = transaction.query_row(
+                "SELECT COUNT(*) FROM workout_scores WHERE workout_id = ?1 AND user_id != ?2",
+                params![workout_id, user_id],
+                |row| row.get(0),
+            )?;
+            if others > 0 {
+                return Err(scored_by_others_error("workout"));
+            }
+        }
+
+        let new_name = workout_update
+            .name
+            .unwrap_or_else(|| existing_workout.name.to_owned());
+
+        // Check if there exists a workout with the new name
+        if let Some(conflicting_workout) = find_workout_by_name(&transaction, user_id, &new_name)? {
+            if conflicting_workout.workout_id != workout_id {
+                return Err(AppError::Conflict(
+                    "Workout with this name already exists".to_owned(),
+                ));
+            }
+        }
+
+        let updated_workout = WorkoutModel {
+            name: new_name,
+            description: workout_update
+                .description
+                .unwrap_or(existing_workout.description),
+            measurement: new_measurement,
+            is_public: workout_update
+                .is_public
+                .unwrap_or(existing_workout.is_public),
+            updated_at: Utc::now().to_rfc3339(),
+            ..existing_workout
+        };
+        save_workout(&transaction, &updated_workout)?;
+        for score in &converted_scores {
+            save_workout_score(&transaction, score)?;
+        }
+        transaction.commit()?;
+
+        Ok(updated_workout)
+    }
+
+    async fn delete_workout(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        if find_workout_by_id(&transaction, user_id, workout_id)?.is_none() {
+            return Err(AppError::NotFound("Workout does not exist".to_owned()));
+        }
+
+        transaction.execute(
+            "DELETE FROM workouts WHERE workout_id = ?1",
+            params![workout_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM workout_scores WHERE workout_id = ?1 AND user_id = ?2",
+            params![workout_id, user_id],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn create_workout_score(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+        workout_score: CreateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let workout_score = new_workout_score(
+            user_id,
+            &workout.workout_id,
+            workout_score,
+            &Utc::now().to_rfc3339(),
+        );
+        save_workout_score(&self.database.lock(), &workout_score)?;
+
+        Ok(workout_score)
+    }
+
+    async fn create_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: Vec<(String, CreateWorkoutScore)>,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        let now = Utc::now().to_rfc3339();
+        let workout_scores: Vec<WorkoutScoreModel> = workout_scores
+            .into_iter()
+            .map(|(workout_id, score)| new_workout_score(user_id, &workout_id, score, &now))
+            .collect();
+
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+        for score in &workout_scores {
+            save_workout_score(&transaction, score)?;
+        }
+        transaction.commit()?;
+
+        Ok(workout_scores)
+    }
+
+    async fn get_workout_scores_for_user(
+        &self,
+        user_id: &str,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        query_data(
+            &self.database.lock(),
+            "SELECT data FROM workout_scores WHERE user_id = ?1 ORDER BY created_at",
+            params![user_id],
+        )
+    }
+
+    async fn get_workout_scores_for_workout(
+        &self,
+        user_id: &str,
+        workout: &WorkoutModel,
+    ) -> WebResult<Vec<WorkoutScoreModel>> {
+        // ascending for timed, descending for the rest
+        let sql = if workout.measurement == WorkoutMeasurement::Time {
+            "SELECT data FROM workout_scores WHERE user_id = ?1 AND workout_id = ?2
+             ORDER BY score ASC, rx DESC"
+        } else {
+            "SELECT data FROM workout_scores WHERE user_id = ?1 AND workout_id = ?2
+             ORDER BY score DESC, rx DESC"
+        };
+
+        query_data(
+            &self.database.lock(),
+            sql,
+            params![user_id, workout.workout_id],
+        )
+    }
+
+    async fn get_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<WorkoutScoreModel> {
+        get_workout_score_by_id(&self.database.lock(), user_id, workout_id, workout_score_id)
+    }
+
+    async fn update_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+        new_score: UpdateWorkoutScore,
+    ) -> WebResult<WorkoutScoreModel> {
+        let connection = self.database.lock();
+        let score = get_workout_score_by_id(&connection, user_id, workout_id, workout_score_id)?;
+
+        let updated = updated_workout_score(score, new_score, &Utc::now().to_rfc3339());
+        save_workout_score(&connection, &updated)?;
+
+        Ok(updated)
+    }
+
+    async fn delete_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<()> {
+        let connection = self.database.lock();
+        // Ensure the score exists for the user
+        get_workout_score_by_id(&connection, user_id, workout_id, workout_score_id)?;
+
+        connection.execute(
+            "DELETE FROM workout_scores WHERE workout_score_id = ?1",
+            params![workout_score_id],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_imported_workout_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64> {
+        let deleted = self.database.lock().execute(
+            "DELETE FROM workout_scores WHERE user_id = ?1 AND import_id = ?2",
+            params![user_id, import_id],
+        )?;
+
+        Ok(deleted as u64)
+    }
+
+    async fn delete_imported_workouts(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)> {
+        let mut connection = self.database.lock();
+        let transaction = connection.transaction()?;
+
+        let removed = transaction.execute(
+            "DELETE FROM workouts WHERE user_id = ?1 AND import_id = ?2 AND NOT EXISTS (
+                SELECT 1 FROM workout_scores
+                WHERE workout_scores.workout_id = workouts.workout_id
+             )",
+            params![user_id, import_id],
+        )?;
+        // The ones that are left have scores
+        let kept: i64 = transaction.query_row(
+            "SELECT COUNT(*) FROM workouts WHERE user_id = ?1 AND import_id = ?2",
+            params![user_id, import_id],
+            |row| row.get(0),
+        )?;
+        transaction.commit()?;
+
+        Ok((removed as u64, kept as u64))
+    }
+}
@@ -0,0 +1,310 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::FeedModel;
+use crate::models::import::ImportModel;
+use crate::models::movement::{MovementModel, MovementScoreModel};
+use crate::models::user::User;
+use crate::models::workout::{WorkoutModel, WorkoutScoreModel};
+use crate::repositories::mongo::{
+    MongoFeedRepository, MongoImportRepository, MongoMovementRepository, MongoUserRepository,
+    MongoWorkoutRepository,
+};
+use crate::repositories::sqlite::{
+    query_data, save_feed, save_import, save_movement, save_movement_score, save_user,
+    save_workout, save_workout_score,
+};
+use crate::repositories::Database;
+
+use futures::stream::StreamExt;
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// Everything a database holds, used to move the data from one database to
+/// another, e.g. from MongoDB to SQLite.
+#[derive(Default)]
+pub struct Snapshot {
+    pub users: Vec<User>,
+    pub workouts: Vec<WorkoutModel>,
+    pub workout_scores: Vec<WorkoutScoreModel>,
+    pub movements: Vec<MovementModel>,
+    pub movement_scores: Vec<MovementScoreModel>,
+    pub feeds: Vec<FeedModel>,
+    pub imports: Vec<ImportModel>,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} users, {} workouts, {} workout scores, {} movements, {} movement scores, {} feeds and {} imports",
+            self.users.len(),
+            self.workouts.len(),
+            self.workout_scores.len(),
+            self.movements.len(),
+            self.movement_scores.len(),
+            self.feeds.len(),
+            self.imports.len()
+        )
+    }
+}
+
+/// Copies all data of `source` into `target`, which has to be empty so
+/// nothing is overwritten or mixed up. Returns what was copied.
+pub async fn transfer(source: &Database, target: &Database) -> WebResult<Snapshot> {
+    if !is_empty(target).await? {
+        return Err(AppError::Conflict(
+            "The database to move the data to is not empty".to_owned(),
+        ));
+    }
+
+    let snapshot = read_snapshot(source).await?;
+    write_snapshot(target, &snapshot).await?;
+
+    Ok(snapshot)
+}
+
+pub async fn read_snapshot(database: &Database) -> WebResult<Snapshot> {
+    match database {
+        Database::Mongo(database) => {
+            let (workouts, movements) = mongo_repositories(database);
+            Ok(Snapshot {
+                users: read_collection(mongo_users(database).collection).await?,
+                workouts: read_collection(workouts.workouts).await?,
+                workout_scores: read_collection(workouts.scores).await?,
+                movements: read_collection(movements.movements).await?,
+                movement_scores: read_collection(movements.scores).await?,
+                feeds: read_collection(mongo_feeds(database).collection).await?,
+                imports: read_collection(mongo_imports(database).collection).await?,
+            })
+        }
+        Database::Memory(database) => {
+            let collections = database.lock();
+            Ok(Snapshot {
+                users: collections.users.clone(),
+                workouts: collections.workouts.clone(),
+                workout_scores: collections.workout_scores.clone(),
+                movements: collections.movements.clone(),
+                movement_scores: collections.movement_scores.clone(),
+                feeds: collections.feeds.clone(),
+                imports: collections.imports.clone(),
+            })
+        }
+        Database::Sqlite(database) => {
+            let connection = database.lock();
+            Ok(Snapshot {
+                users: query_data(&connection, "SELECT data FROM users", [])?,
+                workouts: query_data(&connection, "SELECT data FROM workouts", [])?,
+                workout_scores: query_data(&connection, "SELECT data FROM workout_scores", [])?,
+                movements: query_data(&connection, "SELECT data FROM movements", [])?,
+                movement_scores: query_data(&connection, "SELECT data FROM movement_scores", [])?,
+                feeds: query_data(&connection, "SELECT data FROM feeds", [])?,
+                imports: query_data(&connection, "SELECT data FROM imports", [])?,
+            })
+        }
+    }
+}
+
+/// Adds the data of the snapshot to the database. In SQLite nothing is added
+/// when part of it fails, MongoDB keeps what was written up to that point.
+pub async fn write_snapshot(database: &Database, snapshot: &Snapshot) -> WebResult<()> {
+    match database {
+        Database::Mongo(database) => {
+            let (workouts, movements) = mongo_repositories(database);
+            write_collection(mongo_users(database).collection, &snapshot.users).await?;
+            write_collection(workouts.workouts, &snapshot.workouts).await?;
+            write_collection(workouts.scores, &snapshot.workout_scores).await?;
+            write_collection(movements.movements, &snapshot.movements).await?;
+            write_collection(movements.scores, &snapshot.movement_scores).await?;
+            write_collection(mongo_feeds(database).collection, &snapshot.feeds).await?;
+            write_collection(mongo_imports(database).collection, &snapshot.imports).await?;
+        }
+        Database::Memory(database) => {
+            let mut collections = database.lock();
+            collections.users.extend(snapshot.users.iter().cloned());
+            collections
+                .workouts
+                .extend(snapshot.workouts.iter().cloned());
+            collections
+                .workout_scores
+                .extend(snapshot.workout_scores.iter().cloned());
+            collections
+                .movements
+                .extend(snapshot.movements.iter().cloned());
+            collections
+                .movement_scores
+                .extend(snapshot.movement_scores.iter().cloned());
+            collections.feeds.extend(snapshot.feeds.iter().cloned());
+            collections.imports.extend(snapshot.imports.iter().cloned());
+        }
+        Database::Sqlite(database) => {
+            let mut connection = database.lock();
+            let transaction = connection.transaction()?;
+            for user in &snapshot.users {
+                save_user(&transaction, user)?;
+            }
+            for workout in &snapshot.workouts {
+                save_workout(&transaction, workout)?;
+            }
+            for score in &snapshot.workout_scores {
+                save_workout_score(&transaction, score)?;
+            }
+            for movement in &snapshot.movements {
+                save_movement(&transaction, movement)?;
+            }
+            for score in &snapshot.movement_scores {
+                save_movement_score(&transaction, score)?;
+            }
+            for feed in &snapshot.feeds {
+                save_feed(&transaction, feed)?;
+            }
+            for import in &snapshot.imports {
+                save_import(&transaction, import)?;
+            }
+            transaction.commit()?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn is_empty(database: &Database) -> WebResult<bool> {
+    match database {
+        Database::Mongo(database) => {
+            let (workouts, movements) = mongo_repositories(database);
+            let counts = [
+                mongo_users(database)
+                    .collection
+                    .estimated_document_count(None)
+                    .await?,
+                workouts.workouts.estimated_document_count(None).await?,
+                workouts.scores.estimated_document_count(None).await?,
+                movements.movements.estimated_document_count(None).await?,
+                movements.scores.estimated_document_count(None).await?,
+                mongo_feeds(database)
+                    .collection
+                    .estimated_document_count(None)
+                    .await?,
+                mongo_imports(database)
+                    .collection
+                    .estimated_document_count(None)
+                    .await?,
+            ];
+            Ok(counts.iter().all(|count| *count == 0))
+        }
+        Database::Memory(_) | Database::Sqlite(_) => {
+            let snapshot = read_snapshot(database).await?;
+            Ok(snapshot.users.is_empty()
+                && snapshot.workouts.is_empty()
+                && snapshot.workout_scores.is_empty()
+                && snapshot.movements.is_empty()
+                && snapshot.movement_scores.is_empty()
+                && snapshot.feeds.is_empty()
+                && snapshot.imports.is_empty())
+        }
+    }
+}
+
+async fn read_collection<T>(collection: Collection<T>) -> WebResult<Vec<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let mut cursor = collection.find(None, None).await?;
+    let mut documents = Vec::new();
+    while let Some(result) = cursor.next().await {
+        documents.push(result?);
+    }
+
+    Ok(documents)
+}
+
+async fn write_collection<T: Serialize>(
+    collection: Collection<T>,
+    documents: &[T],
+) -> WebResult<()> {
+    // insert_many refuses an empty list
+    if !documents.is_empty() {
+        collection.insert_many(documents, None).await?;
+    }
+
+    Ok(())
+}
+
+fn mongo_repositories(
+    database: &mongodb::Database,
+) -> (MongoWorkoutRepository, MongoMovementRepository) {
+    (
+        MongoWorkoutRepository::new(database),
+        MongoMovementRepository::new(database),
+    )
+}
+
+fn mongo_users(database: &mongodb::Database) -> MongoUserRepository {
+    MongoUserRepository::new(database)
+}
+
+fn mongo_feeds(database: &mongodb::Database) -> MongoFeedRepository {
+    MongoFeedRepository::new(database)
+}
+
+fn mongo_imports(database: &mongodb::Database) -> MongoImportRepository {
+    MongoImportRepository::new(database)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::workout::{CreateWorkout, CreateWorkoutScore, WorkoutMeasurement};
+    use crate::repositories::{MemoryDatabase, Repositories, SqliteDatabase};
+
+    fn create_workout(name: &str) -> CreateWorkout {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "description": "21-15-9",
+            "measurement": "time",
+        }))
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_round_trip() {
+        let memory = Database::Memory(MemoryDatabase::new());
+        let workouts = Repositories::new(&memory).workouts;
+        let fran = workouts
+            .create_workout("user", create_workout("Fran"))
+            .await
+            .unwrap();
+        let score: CreateWorkoutScore =
+            serde_json::from_value(serde_json::json!({"score": 300.0, "rx": true})).unwrap();
+        workouts
+            .create_workout_score("user", &fran, score)
+            .await
+            .unwrap();
+
+        let sqlite = Database::Sqlite(SqliteDatabase::open_in_memory().unwrap());
+        let copied = transfer(&memory, &sqlite).await.unwrap();
+        assert_eq!(copied.workouts.len(), 1);
+        assert_eq!(copied.workout_scores.len(), 1);
+
+        let sqlite_workouts = Repositories::new(&sqlite).workouts;
+        let workout = sqlite_workouts
+            .get_workout_by_id("user", &fran.workout_id)
+            .await
+            .unwrap();
+        assert_eq!(workout.name, "Fran");
+        assert_eq!(workout.measurement, WorkoutMeasurement::Time);
+        let scores = sqlite_workouts
+            .get_workout_scores_for_workout("user", &workout)
+            .await
+            .unwrap();
+        assert_eq!(scores.len(), 1);
+
+        // Moving the data back works, but not into a database that has data
+        let result = transfer(&sqlite, &memory).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        let back = Database::Memory(MemoryDatabase::new());
+        transfer(&sqlite, &back).await.unwrap();
+        let back_workouts = Repositories::new(&back).workouts;
+        assert_eq!(back_workouts.get_workouts("user").await.unwrap().len(), 1);
+    }
+}
@@ -1,15 +1,71 @@
 use crate::errors::{AppError, WebResult};
-use crate::models::user::{Claims, CreateUser, Login, UpdateUser, User};
-use crate::utils::{resources, Config};
+use crate::models::import::ProfileSnapshot;
+use crate::models::unit::UnitSystem;
+use crate::models::user::{AvatarPrivacy, Claims, CreateUser, Login, UpdateUser, User};
+use crate::utils::resources;
 
+use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use jsonwebtoken::{encode, EncodingKey, Header};
-use mongodb::{Client, Collection};
+use std::collections::HashSet;
 
-static COLLECTION_NAME: &str = "users";
+/// Keeps the users and their profiles. Users are found by their email, which
+/// is unique.
+#[async_trait(?Send)]
+pub trait UserRepository: Send + Sync {
+    async fn find_user_with_email(&self, email: &str) -> WebResult<User>;
 
-pub struct UserRepository {
-    pub mongo_client: Client,
+    /// The user whose avatar is at `avatar_url`.
+    async fn find_user_with_avatar(&self, avatar_url: &str) -> WebResult<User>;
+
+    /// Whether users other than `user_id` have the avatar at `avatar_url`, in
+    /// which case its files are kept when the user removes it.
+    async fn is_avatar_shared(&self, user_id: &str, avatar_url: &str) -> WebResult<bool>;
+
+    /// The avatar paths that users have, to tell which avatar files are in use.
+    async fn get_avatar_urls(&self) -> WebResult<HashSet<String>>;
+
+    /// Adds a user, which fails with a conflict when the email is taken.
+    async fn insert_user(&self, user: User) -> WebResult<()>;
+
+    async fn update_user_with_email(&self, email: &str, user_update: UpdateUser)
+        -> WebResult<User>;
+
+    /// Sets the profile fields back to how they were before an import.
+    async fn restore_profile(&self, email: &str, profile: &ProfileSnapshot) -> WebResult<User>;
+
+    /// The unit system the user wants to see scores in.
+    async fn get_unit_system(&self, email: &str) -> WebResult<UnitSystem> {
+        self.find_user_with_email(email)
+            .await
+            .map(|user| user.unit_system)
+    }
+
+    /// Signs the token with `key`, the secret of the auth config.
+    async fn login(&self, user_login: Login, key: &[u8]) -> WebResult<String> {
+        let user_email: &str = user_login.email.as_ref();
+
+        let user = self.find_user_with_email(user_email).await.map_err(|_| {
+            AppError::BadRequest("Check your user information (user not found)".to_string())
+        })?;
+
+        if user.password != resources::create_hash(&user_login.password) {
+            return Err(AppError::BadRequest(
+                "Check your user information".to_string(),
+            ));
+        }
+
+        gen_token(key, user, user_email, user_login.remember_me)
+    }
+
+    async fn register(&self, create_user: CreateUser, key: &[u8]) -> WebResult<String> {
+        let user_email = create_user.email.to_owned();
+
+        self.insert_user(new_user(create_user)).await?;
+
+        let user = self.find_user_with_email(&user_email).await?;
+        gen_token(key, user, &user_email, false)
+    }
 }
 
 /// Generates a JWT based on user data and if the user wants to stay logged in (makes it expire in a year).
@@ -36,107 +92,44 @@ fn gen_token(key: &[u8], user: User, email: &str, remember_me: bool) -> WebResul
     Ok(token)
 }
 
-impl UserRepository {
-    fn get_collection(&self) -> Collection<User> {
-        let config = Config::from_env().unwrap();
-        let database_name = config.mongo.db_name;
-        let db = self.mongo_client.database(database_name.as_str());
-        db.collection(COLLECTION_NAME)
-    }
-
-    pub async fn update_user_with_email(
-        &self,
-        email: &str,
-        user_update: UpdateUser,
-    ) -> WebResult<User> {
-        let user = self.find_user_with_email(email).await?;
-
-        let updated_password = if user_update.password.is_some() {
-            resources::create_hash(&user_update.password.unwrap())
-        } else {
-            user.password
-        };
-        let updated_first_name = user_update.first_name.unwrap_or(user.first_name);
-        let updated_last_name = user_update.last_name.unwrap_or(user.last_name);
-        let updated_date_of_birth = user_update.date_of_birth.unwrap_or(user.date_of_birth);
-        let updated_height = user_update.height.unwrap_or(user.height);
-        let updated_weight = user_update.weight.unwrap_or(user.weight);
-        let updated_box_name = user_update.box_name.unwrap_or(user.box_name);
-        let updated_avatar_url = user_update.avatar_url.unwrap_or(user.avatar_url);
-
-        let query = doc! { "user_id": user.user_id.to_owned() };
-        let update = doc! {
-            "$set": {
-                "password": updated_password,
-                "first_name": updated_first_name,
-                "last_name": updated_last_name,
-                "date_of_birth": updated_date_of_birth,
-                "height": updated_height,
-                "weight": updated_weight,
-                "box_name": updated_box_name,
-                "avatar_url": updated_avatar_url,
-            }
-        };
-        let coll = self.get_collection();
-        coll.update_one(query, update, None).await?;
-
-        self.find_user_with_email(email).await
-    }
-
-    pub async fn find_user_with_email(&self, email: &str) -> WebResult<User> {
-        let coll = self.get_collection();
-        let cursor = coll.find_one(doc! {"email": email}, None).await?;
-
-        match cursor {
-            Some(model) => Ok(model),
-            None => Err(AppError::NotFound("User not found".to_owned())),
-        }
-    }
-
-    pub async fn login(&self, user_login: Login) -> WebResult<String> {
-        let config = Config::from_env().unwrap();
-        let key = config.auth.secret.as_bytes();
-        let user_email: &str = user_login.email.as_ref();
-
-        let user = self.find_user_with_email(user_email).await.map_err(|_| {
-            AppError::BadRequest("Check your user information (user not found)".to_string())
-        })?;
-
-        if user.password != resources::create_hash(&user_login.password) {
-            return Err(AppError::BadRequest(
-                "Check your user information".to_string(),
-            ));
-        }
-
-        gen_token(key, user, user_email, user_login.remember_me)
+fn new_user(create_user: CreateUser) -> User {
+    User {
+        user_id: uuid::Uuid::new_v4().to_string(),
+        admin: false,
+        email: create_user.email,
+        password: resources::create_hash(&create_user.password),
+        first_name: create_user.first_name,
+        last_name: create_user.last_name,
+        date_of_birth: create_user.date_of_birth,
+        height: create_user.height,
+        weight: create_user.weight,
+        box_name: create_user.box_name,
+        avatar_url: "".to_owned(),
+        avatar_privacy: AvatarPrivacy::default(),
+        unit_system: create_user.unit_system,
+        gender: create_user.gender,
     }
+}
 
-    pub async fn register(&self, create_user: CreateUser) -> WebResult<String> {
-        let config = Config::from_env().unwrap();
-        let key = config.auth.secret.as_bytes();
-        let user_email: &str = create_user.email.as_ref();
-
-        let coll = self.get_collection();
-        let hash_pw = resources::create_hash(&create_user.password);
-        let id = uuid::Uuid::new_v4().to_string();
-        let user_doc = User {
-            user_id: id,
-            admin: false,
-            email: user_email.to_owned(),
-            password: hash_pw,
-            first_name: create_user.first_name,
-            last_name: create_user.last_name,
-            date_of_birth: create_user.date_of_birth,
-            height: create_user.height,
-            weight: create_user.weight,
-            box_name: create_user.box_name,
-            avatar_url: "".to_owned(),
-        };
-
-        coll.insert_one(user_doc, None).await?;
-
-        let user = self.find_user_with_email(user_email).await?;
-        gen_token(key, user, user_email, false)
+/// The user with the changes of `user_update` applied. A new password is
+/// stored hashed.
+pub(crate) fn updated_user(user: User, user_update: UpdateUser) -> User {
+    User {
+        password: match user_update.password {
+            Some(password) => resources::create_hash(&password),
+            None => user.password,
+        },
+        first_name: user_update.first_name.unwrap_or(user.first_name),
+        last_name: user_update.last_name.unwrap_or(user.last_name),
+        date_of_birth: user_update.date_of_birth.unwrap_or(user.date_of_birth),
+        height: user_update.height.unwrap_or(user.height),
+        weight: user_update.weight.unwrap_or(user.weight),
+        box_name: user_update.box_name.unwrap_or(user.box_name),
+        avatar_url: user_update.avatar_url.unwrap_or(user.avatar_url),
+        avatar_privacy: user_update.avatar_privacy.unwrap_or(user.avatar_privacy),
+        unit_system: user_update.unit_system.unwrap_or(user.unit_system),
+        gender: user_update.gender.or(user.gender),
+        ..user
     }
 }
 
@@ -163,6 +156,9 @@ mod tests {
                 weight: 85000,
                 box_name: "box_name".to_owned(),
                 avatar_url: "avatar_url".to_owned(),
+                avatar_privacy: AvatarPrivacy::Public,
+                unit_system: UnitSystem::Metric,
+                gender: None,
             },
             "email",
             false,
@@ -184,6 +180,9 @@ mod tests {
                 weight: 85000,
                 box_name: "box_name".to_owned(),
                 avatar_url: "avatar_url".to_owned(),
+                avatar_privacy: AvatarPrivacy::Public,
+                unit_system: UnitSystem::Metric,
+                gender: None,
             },
             "email",
             true,
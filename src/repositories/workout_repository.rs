@@ -1,94 +1,48 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::response::{ForkQuery, InvalidScore, MeasurementChangePreview};
 use crate::models::workout::{
-    CreateWorkout, CreateWorkoutScore, UpdateWorkout, UpdateWorkoutScore, WorkoutModel,
-    WorkoutScoreModel,
+    convert_score_measurement, CreateWorkout, CreateWorkoutScore, UpdateWorkout,
+    UpdateWorkoutScore, WorkoutMeasurement, WorkoutModel, WorkoutScoreModel,
 };
-use crate::utils::{query_utils, Config};
-use crate::{
-    errors::{AppError, WebResult},
-    models::workout::WorkoutMeasurement,
-};
-
-use chrono::Utc;
-use futures::stream::StreamExt;
-use mongodb::options::FindOptions;
-use mongodb::{Client, Collection};
-use std::vec::Vec;
 
-static WORKOUT_COLLECTION_NAME: &str = "workouts";
-static SCORE_COLLECTION_NAME: &str = "workoutscores";
-
-pub struct WorkoutRepository {
-    pub mongo_client: Client,
-}
+use async_trait::async_trait;
 
-impl WorkoutRepository {
-    fn get_score_collection(&self) -> Collection<WorkoutScoreModel> {
-        let config = Config::from_env().unwrap();
-        let database_name = config.mongo.db_name;
-        let db = self.mongo_client.database(database_name.as_str());
-        db.collection(SCORE_COLLECTION_NAME)
-    }
-
-    fn get_workout_collection(&self) -> Collection<WorkoutModel> {
-        let config = Config::from_env().unwrap();
-        let database_name = config.mongo.db_name;
-        let db = self.mongo_client.database(database_name.as_str());
-        db.collection(WORKOUT_COLLECTION_NAME)
-    }
-
-    pub async fn find_workout_by_name(
+/// Keeps the workouts and the scores logged for them. Users see their own
+/// workouts and the public ones, but only their own scores.
+#[async_trait(?Send)]
+pub trait WorkoutRepository: Send + Sync {
+    async fn find_workout_by_name(
         &self,
         user_id: &str,
         name: &str,
-    ) -> WebResult<Option<WorkoutModel>> {
-        let query = query_utils::for_one(doc! {"name": name }, user_id);
-
-        match self.get_workout_collection().find_one(query, None).await {
-            Ok(workout) => Ok(workout),
-            Err(e) => Err(AppError::Internal(e.to_string())),
-        }
-    }
+    ) -> WebResult<Option<WorkoutModel>>;
 
-    pub async fn find_workout_by_id(
+    /// Finds a workout the user imported from the given source record.
+    async fn find_workout_by_source(
         &self,
         user_id: &str,
-        workout_id: &str,
-    ) -> WebResult<Option<WorkoutModel>> {
-        let query = query_utils::for_one(doc! {"workout_id": workout_id }, user_id);
-        let cursor = self.get_workout_collection().find_one(query, None).await;
+        source_id: &str,
+    ) -> WebResult<Option<WorkoutModel>>;
 
-        match cursor {
-            Ok(workout) => Ok(workout),
-            Err(e) => Err(AppError::Internal(e.to_string())),
-        }
-    }
-
-    pub async fn get_workouts(&self, user_id: &str) -> WebResult<Vec<WorkoutModel>> {
-        let query = query_utils::for_many(user_id);
-        let find_options = FindOptions::builder().sort(doc! { "name": 1 }).build();
-        let mut cursor = self
-            .get_workout_collection()
-            .find(query, find_options)
-            .await?;
-
-        let mut vec: Vec<WorkoutModel> = Vec::new();
-
-        while let Some(result) = cursor.next().await {
-            if let Ok(result) = result {
-                vec.push(result);
-            } else {
-                warn!("Error reading workout: {:?}", result.unwrap_err())
-            }
-        }
-
-        Ok(vec)
-    }
+    /// Finds the scores earlier imports added for `workout_scores` in one
+    /// lookup, the scores with the same source and, from before sources were
+    /// tracked, the scores on the same dates.
+    async fn find_imported_workout_scores(
+        &self,
+        user_id: &str,
+        workout_scores: &[&CreateWorkoutScore],
+    ) -> WebResult<Vec<WorkoutScoreModel>>;
 
-    pub async fn get_workout_by_id(
+    async fn find_workout_by_id(
         &self,
         user_id: &str,
         workout_id: &str,
-    ) -> WebResult<WorkoutModel> {
+    ) -> WebResult<Option<WorkoutModel>>;
+
+    /// The workouts the user can see, sorted by name.
+    async fn get_workouts(&self, user_id: &str) -> WebResult<Vec<WorkoutModel>>;
+
+    async fn get_workout_by_id(&self, user_id: &str, workout_id: &str) -> WebResult<WorkoutModel> {
         match self.find_workout_by_id(user_id, workout_id).await? {
             Some(workout) => Ok(workout),
             None => Err(AppError::NotFound(
@@ -97,265 +51,219 @@ impl WorkoutRepository {
         }
     }
 
-    pub async fn create_workout(
+    async fn create_workout(
         &self,
         user_id: &str,
         workout: CreateWorkout,
-    ) -> WebResult<WorkoutModel> {
-        let workout_name = workout.name.as_ref();
-        let existing_workout = self.find_workout_by_name(user_id, workout_name).await?;
+    ) -> WebResult<WorkoutModel>;
 
-        if existing_workout.is_some() {
-            return Err(AppError::Conflict(
-                "A workout with this name already exists".to_string(),
-            ));
-        }
-
-        let coll = self.get_workout_collection();
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
-        let workout = WorkoutModel {
-            workout_id: id,
-            user_id: user_id.to_owned(),
-            name: workout.name.to_owned(),
-            description: workout.description,
-            measurement: workout.measurement,
-            is_public: workout.is_public,
-            created_at: now.to_owned(),
-            updated_at: now,
-        };
-
-        coll.insert_one(workout, None).await?;
+    /// Adds many workouts with a single write, e.g. for imports. Fails when
+    /// a name is used twice or taken by a workout of the user.
+    async fn create_workouts(
+        &self,
+        user_id: &str,
+        workouts: Vec<CreateWorkout>,
+    ) -> WebResult<Vec<WorkoutModel>>;
 
-        match self.find_workout_by_name(user_id, workout_name).await? {
-            Some(new_workout) => Ok(new_workout),
-            None => Err(AppError::Internal(
-                "New workout not found after inserting".to_string(),
-            )),
-        }
-    }
+    /// Copies a workout the user can see into their own library. Scores the user
+    /// logged on the original can be moved over to the copy.
+    async fn fork_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        fork: ForkQuery,
+    ) -> WebResult<WorkoutModel>;
 
-    pub async fn update_workout(
+    async fn preview_workout_update(
         &self,
         user_id: &str,
         workout_id: &str,
         workout_update: UpdateWorkout,
-    ) -> WebResult<WorkoutModel> {
-        let existing_workout = self.find_workout_by_id(user_id, workout_id).await?;
-
-        if existing_workout.is_none() {
-            return Err(AppError::NotFound("Workout not found".to_owned()));
-        }
-
-        let existing_workout = existing_workout.unwrap();
-
-        let new_name = workout_update.name.unwrap_or(existing_workout.name);
-        let new_desc = workout_update
-            .description
-            .unwrap_or(existing_workout.description);
-
-        // Check if there exists a workout with the new name
-        let conflicting_workout = self.find_workout_by_name(user_id, &new_name).await?;
-
-        if conflicting_workout.is_some() {
-            return Err(AppError::Conflict(
-                "Workout with this name already exists".to_owned(),
-            ));
-        }
-
-        let query = doc! { "workout_id": workout_id };
-        let update = doc! {
-            "$set": {
-                "name": new_name,
-                "description": new_desc,
-                "updated_at": Utc::now().to_rfc3339()
-            }
-        };
-
-        let coll = self.get_workout_collection();
-        coll.update_one(query, update, None).await?;
-
-        let model = self.find_workout_by_id(user_id, workout_id).await?.unwrap();
-
-        Ok(model)
-    }
-
-    pub async fn delete_workout(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
-        let workout = self.find_workout_by_id(user_id, workout_id).await?;
+    ) -> WebResult<MeasurementChangePreview>;
 
-        if workout.is_none() {
-            return Err(AppError::NotFound("Workout does not exist".to_owned()));
-        }
-
-        let coll = self.get_workout_collection();
-        coll.delete_one(doc! { "workout_id": workout_id }, None)
-            .await?;
+    async fn update_workout(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_update: UpdateWorkout,
+    ) -> WebResult<WorkoutModel>;
 
-        self.delete_workout_scores(user_id, workout_id).await?;
+    /// Removes a workout along with the scores the user logged for it.
+    async fn delete_workout(&self, user_id: &str, workout_id: &str) -> WebResult<()>;
 
-        Ok(())
-    }
-
-    pub async fn create_workout_score(
+    async fn create_workout_score(
         &self,
         user_id: &str,
         workout: &WorkoutModel,
         workout_score: CreateWorkoutScore,
-    ) -> WebResult<WorkoutScoreModel> {
-        let coll = self.get_score_collection();
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
-        let workout_id = workout.workout_id.to_owned();
-        let workout_score = WorkoutScoreModel {
-            workout_score_id: id.to_owned(),
-            workout_id: workout_id.to_owned(),
-            user_id: user_id.to_owned(),
-            score: workout_score.score,
-            rx: workout_score.rx,
-            notes: workout_score.notes,
-            // This is for mywod items, as they have their own created at date which prefer to keep
-            created_at: workout_score.created_at.unwrap_or_else(|| now.to_owned()),
-            updated_at: now.to_owned(),
-        };
-
-        coll.insert_one(workout_score, None).await?;
-        self.get_workout_score_by_id(user_id, &workout_id, &id)
-            .await
-    }
+    ) -> WebResult<WorkoutScoreModel>;
 
-    pub async fn get_workout_scores_with_query(
-        &self,
-        query: bson::Document,
-        find_options: FindOptions,
-    ) -> WebResult<Vec<WorkoutScoreModel>> {
-        let mut cursor = self
-            .get_score_collection()
-            .find(query, find_options)
-            .await
-            .unwrap();
-
-        let mut vec: Vec<WorkoutScoreModel> = Vec::new();
-
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(document) => vec.push(document),
-                Err(e) => warn!("Error reading workout: {:?}", e),
-            }
-        }
-
-        Ok(vec)
-    }
-
-    pub async fn get_workout_scores_for_user(
+    /// Adds many scores, each given with the id of its workout, with a single
+    /// write, e.g. for imports. The scores are not read back, as their ids are
+    /// generated up front.
+    async fn create_workout_scores(
         &self,
         user_id: &str,
-    ) -> WebResult<Vec<WorkoutScoreModel>> {
-        let query = query_utils::for_many_with_filter(doc! { "user_id": user_id }, user_id);
-        let find_options: FindOptions = FindOptions::builder()
-            .sort(doc! { "created_at": 1 })
-            .build();
+        workout_scores: Vec<(String, CreateWorkoutScore)>,
+    ) -> WebResult<Vec<WorkoutScoreModel>>;
 
-        self.get_workout_scores_with_query(query, find_options)
-            .await
-    }
+    /// The scores of the user, oldest first.
+    async fn get_workout_scores_for_user(&self, user_id: &str)
+        -> WebResult<Vec<WorkoutScoreModel>>;
 
-    pub async fn get_workout_scores_for_workout(
+    /// The scores of the user for a workout, best first.
+    async fn get_workout_scores_for_workout(
         &self,
         user_id: &str,
         workout: &WorkoutModel,
-    ) -> WebResult<Vec<WorkoutScoreModel>> {
-        let query = query_utils::for_many_with_filter(
-            doc! { "user_id": user_id, "workout_id": &workout.workout_id },
-            user_id,
-        );
+    ) -> WebResult<Vec<WorkoutScoreModel>>;
 
-        // ascending for timed, descending for the rest
-        let score_filter = doc! {
-            "score": if workout.measurement == WorkoutMeasurement::Time { 1 } else { -1 },
-            "rx": -1,
-        };
-        let find_options = FindOptions::builder().sort(score_filter).build();
-
-        self.get_workout_scores_with_query(query, find_options)
-            .await
-    }
-
-    pub async fn get_workout_score_by_id(
+    async fn get_workout_score_by_id(
         &self,
         user_id: &str,
         workout_id: &str,
         workout_score_id: &str,
-    ) -> WebResult<WorkoutScoreModel> {
-        let query = query_utils::for_one(
-            doc! { "workout_id":  workout_id, "workout_score_id": workout_score_id },
-            user_id,
-        );
-        let cursor = self.get_score_collection().find_one(query, None).await?;
-
-        match cursor {
-            Some(model) => Ok(model),
-            None => Err(AppError::NotFound("Entity not found".to_string())),
-        }
-    }
+    ) -> WebResult<WorkoutScoreModel>;
 
-    pub async fn update_workout_score_by_id(
+    async fn update_workout_score_by_id(
         &self,
         user_id: &str,
         workout_id: &str,
         workout_score_id: &str,
         new_score: UpdateWorkoutScore,
-    ) -> WebResult<WorkoutScoreModel> {
-        let score = self
-            .get_workout_score_by_id(user_id, workout_id, workout_score_id)
-            .await?;
+    ) -> WebResult<WorkoutScoreModel>;
 
-        let updated_score = new_score.score.unwrap_or(score.score);
-        let updated_rx = new_score.rx.unwrap_or(score.rx);
-        let updated_notes = new_score.notes.unwrap_or(score.notes);
-        let updated_updated_at = Utc::now().to_rfc3339();
+    async fn delete_workout_score_by_id(
+        &self,
+        user_id: &str,
+        workout_id: &str,
+        workout_score_id: &str,
+    ) -> WebResult<()>;
 
-        let query = query_utils::for_one(doc! { "workout_score_id": workout_score_id }, user_id);
-        let update = doc! {
-            "$set": {
-                "score": updated_score,
-                "rx": updated_rx,
-                "notes": updated_notes,
-                "updated_at": updated_updated_at,
-            }
-        };
+    /// Removes the workout scores an import added.
+    async fn delete_imported_workout_scores(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<u64>;
 
-        let _ = self
-            .get_score_collection()
-            .update_one(query, update, None)
-            .await?;
+    /// Removes the workouts an import added, unless the user logged scores for
+    /// them that were not part of the import. Returns how many workouts were
+    /// removed and how many were kept.
+    async fn delete_imported_workouts(
+        &self,
+        user_id: &str,
+        import_id: &str,
+    ) -> WebResult<(u64, u64)>;
+}
 
-        let res = self
-            .get_workout_score_by_id(user_id, workout_id, workout_score_id)
-            .await?;
+pub(crate) fn new_workout(user_id: &str, workout: CreateWorkout, now: &str) -> WorkoutModel {
+    WorkoutModel {
+        workout_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        forked_from: None,
+        source_id: workout.source_id,
+        import_id: workout.import_id,
+        name: workout.name,
+        description: workout.description,
+        measurement: workout.measurement,
+        is_public: workout.is_public,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
+    }
+}
 
-        Ok(res)
+pub(crate) fn new_workout_score(
+    user_id: &str,
+    workout_id: &str,
+    workout_score: CreateWorkoutScore,
+    now: &str,
+) -> WorkoutScoreModel {
+    WorkoutScoreModel {
+        workout_score_id: uuid::Uuid::new_v4().to_string(),
+        workout_id: workout_id.to_owned(),
+        user_id: user_id.to_owned(),
+        score: workout_score.score,
+        unit: workout_score.unit,
+        rx: workout_score.rx,
+        notes: workout_score.notes,
+        personal_record: workout_score.personal_record,
+        source_id: workout_score.source_id,
+        import_id: workout_score.import_id,
+        // This is for mywod items, as they have their own created at date which prefer to keep
+        created_at: workout_score.created_at.unwrap_or_else(|| now.to_owned()),
+        updated_at: now.to_owned(),
     }
+}
 
-    pub async fn delete_workout_score_by_id(
-        &self,
-        user_id: &str,
-        workout_id: &str,
-        workout_score_id: &str,
-    ) -> WebResult<()> {
-        // Ensure the score exists for the user
-        self.get_workout_score_by_id(user_id, workout_id, workout_score_id)
-            .await?;
+/// A private copy of a workout for the user, named `name`.
+pub(crate) fn forked_workout(
+    original: &WorkoutModel,
+    user_id: &str,
+    name: String,
+    now: &str,
+) -> WorkoutModel {
+    WorkoutModel {
+        workout_id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        forked_from: Some(original.workout_id.to_owned()),
+        source_id: None,
+        import_id: None,
+        name,
+        description: original.description.to_owned(),
+        measurement: original.measurement,
+        is_public: false,
+        created_at: now.to_owned(),
+        updated_at: now.to_owned(),
+    }
+}
 
-        let query = query_utils::for_one(doc! { "workout_score_id": workout_score_id }, user_id);
-        self.get_score_collection().delete_one(query, None).await?;
+/// The score with the changes of `new_score` applied.
+pub(crate) fn updated_workout_score(
+    score: WorkoutScoreModel,
+    new_score: UpdateWorkoutScore,
+    now: &str,
+) -> WorkoutScoreModel {
+    WorkoutScoreModel {
+        score: new_score.score.unwrap_or(score.score),
+        unit: new_score.unit.or(score.unit),
+        rx: new_score.rx.unwrap_or(score.rx),
+        notes: new_score.notes.unwrap_or(score.notes),
+        personal_record: new_score.personal_record.unwrap_or(score.personal_record),
+        source_id: new_score.source_id.or(score.source_id),
+        updated_at: now.to_owned(),
+        ..score
+    }
+}
 
-        Ok(())
+/// Converts the scores of a workout to a new measurement. Scores of all
+/// users are included, as public workouts are scored by others as well.
+pub(crate) fn plan_measurement_change(
+    workout: &WorkoutModel,
+    scores: Vec<WorkoutScoreModel>,
+    measurement: WorkoutMeasurement,
+) -> (Vec<WorkoutScoreModel>, MeasurementChangePreview) {
+    let mut converted_scores: Vec<WorkoutScoreModel> = Vec::new();
+    let mut invalid_scores: Vec<InvalidScore> = Vec::new();
+
+    if measurement != workout.measurement {
+        for score in scores {
+            match convert_score_measurement(&score, workout.measurement, measurement) {
+                Ok(converted) => converted_scores.push(converted),
+                Err(reason) => invalid_scores.push(InvalidScore {
+                    score_id: score.workout_score_id,
+                    user_id: score.user_id,
+                    score: score.score,
+                    reason,
+                }),
+            }
+        }
     }
 
-    async fn delete_workout_scores(&self, user_id: &str, workout_id: &str) -> WebResult<()> {
-        let query = query_utils::for_many_with_filter(doc! { "workout_id": workout_id }, user_id);
-        self.get_score_collection().delete_many(query, None).await?;
+    let preview = MeasurementChangePreview {
+        converted_scores: converted_scores.len() as u32,
+        invalid_scores,
+    };
 
-        Ok(())
-    }
+    (converted_scores, preview)
 }
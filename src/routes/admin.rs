@@ -0,0 +1,23 @@
+use crate::errors::AppError;
+use crate::models::user::Claims;
+use crate::utils::AppState;
+use actix_web::{get, web, HttpResponse, Responder};
+
+/// The configuration the server runs with, without its secrets.
+#[get("/config")]
+async fn get_config(
+    state: web::Data<AppState>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    if !claims.admin {
+        return Err(AppError::Forbidden(
+            "Only admins can see the configuration".to_owned(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(state.config.redacted()))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_config);
+}
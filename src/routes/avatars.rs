@@ -0,0 +1,87 @@
+use crate::errors::AppError;
+use crate::models::user::{AvatarPrivacy, Claims, SignedAvatarQuery};
+use crate::repositories::UserRepository;
+use crate::storage::Storages;
+use crate::utils::avatar::{avatar_url_for_file, read_avatar, verify_avatar_signature};
+use crate::utils::AppState;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse};
+use chrono::Utc;
+
+/// Avatar paths change with the image, so they can be cached for a year
+const AVATAR_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// Serves an avatar or one of its thumbnails, depending on the privacy
+/// setting of its user. Signed paths are served until they expire.
+#[get("/{filename}")]
+async fn get_avatar(
+    state: web::Data<AppState>,
+    storages: web::Data<Storages>,
+    user_repo: web::Data<dyn UserRepository>,
+    filename: web::Path<String>,
+    query: web::Query<SignedAvatarQuery>,
+    claims: Option<Claims>,
+) -> Result<HttpResponse, AppError> {
+    let filename = filename.into_inner();
+    let avatar_url = avatar_url_for_file(&filename)
+        .ok_or_else(|| AppError::NotFound("Avatar not found".to_owned()))?;
+
+    let user = user_repo.find_user_with_avatar(&avatar_url).await?;
+
+    let now = Utc::now().timestamp();
+    let cache_control = match (query.expires, &query.signature) {
+        (Some(expires), Some(signature)) => {
+            let secret = state.config.auth.secret.as_bytes();
+            if expires <= now || !verify_avatar_signature(&avatar_url, expires, signature, secret) {
+                return Err(AppError::Forbidden(
+                    "The avatar link is invalid or has expired".to_owned(),
+                ));
+            }
+            vec![
+                CacheDirective::Private,
+                CacheDirective::MaxAge((expires - now) as u32),
+            ]
+        }
+        _ => {
+            let allowed = match (user.avatar_privacy, &claims) {
+                (AvatarPrivacy::Public, _) => true,
+                (AvatarPrivacy::Users, Some(_)) => true,
+                (AvatarPrivacy::Private, Some(claims)) => claims.user_id == user.user_id,
+                (_, None) => {
+                    return Err(AppError::Unauthorized(
+                        "Log in to see this avatar".to_owned(),
+                    ))
+                }
+            };
+            if !allowed {
+                return Err(AppError::NotFound("Avatar not found".to_owned()));
+            }
+
+            let visibility = match user.avatar_privacy {
+                AvatarPrivacy::Public => CacheDirective::Public,
+                _ => CacheDirective::Private,
+            };
+            vec![
+                visibility,
+                CacheDirective::MaxAge(AVATAR_MAX_AGE),
+                CacheDirective::Extension("immutable".to_owned(), None),
+            ]
+        }
+    };
+
+    let image = read_avatar(storages.avatars.as_ref(), &format!("/avatars/{}", filename)).await?;
+    if image.is_empty() {
+        return Err(AppError::NotFound("Avatar not found".to_owned()));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(CacheControl(cache_control))
+        .insert_header((header::VARY, "Authorization"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(image))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_avatar);
+}
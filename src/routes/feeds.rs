@@ -0,0 +1,48 @@
+use crate::errors::AppError;
+use crate::models::feed::{CreateFeed, ManyFeedsResponse};
+use crate::models::user::Claims;
+use crate::repositories::FeedRepository;
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+
+#[get("")]
+async fn get_feeds(
+    feed_repo: web::Data<dyn FeedRepository>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.user_id.as_ref();
+    let result = feed_repo.get_feeds(user_id).await;
+
+    result.map(|feeds| HttpResponse::Ok().json(ManyFeedsResponse { data: feeds }))
+}
+
+#[post("")]
+async fn subscribe_to_feed(
+    feed_repo: web::Data<dyn FeedRepository>,
+    claims: Claims,
+    feed: web::Json<CreateFeed>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.user_id.as_ref();
+    let result = feed_repo.create_feed(user_id, feed.into_inner()).await;
+
+    result.map(|feed| HttpResponse::Created().json(feed))
+}
+
+#[delete("/{id}")]
+async fn unsubscribe_from_feed(
+    feed_repo: web::Data<dyn FeedRepository>,
+    info: web::Path<String>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let feed_id = info.into_inner();
+
+    let user_id = claims.user_id.as_ref();
+    let result = feed_repo.delete_feed(user_id, &feed_id).await;
+
+    result.map(|_| HttpResponse::NoContent())
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_feeds);
+    cfg.service(subscribe_to_feed);
+    cfg.service(unsubscribe_from_feed);
+}
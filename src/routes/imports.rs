@@ -0,0 +1,46 @@
+use crate::errors::AppError;
+use crate::models::import::ImportResponse;
+use crate::models::user::Claims;
+use crate::repositories::ImportRepository;
+use crate::services::imports;
+use crate::utils::AppState;
+use actix_web::{delete, get, web, HttpResponse, Responder};
+
+#[get("/{id}")]
+async fn get_import_by_id(
+    import_repo: web::Data<dyn ImportRepository>,
+    info: web::Path<String>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let import_id = info.into_inner();
+
+    let user_id = claims.user_id.as_ref();
+    let result = import_repo.get_import_by_id(user_id, &import_id).await;
+
+    result.map(|import| HttpResponse::Ok().json(ImportResponse::from_model(import)))
+}
+
+/// Rolls back an import, removing what it added.
+#[delete("/{id}")]
+async fn rollback_import(
+    state: web::Data<AppState>,
+    info: web::Path<String>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let import_id = info.into_inner();
+
+    let result = imports::rollback_import(
+        &state.repositories,
+        &claims.user_id,
+        &claims.sub,
+        &import_id,
+    )
+    .await;
+
+    result.map(|import| HttpResponse::Ok().json(ImportResponse::from_model(import)))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_import_by_id);
+    cfg.service(rollback_import);
+}
@@ -6,12 +6,7 @@ use actix_web::{get, web, HttpResponse, Responder};
 
 #[get("/health")]
 pub async fn health(state: web::Data<AppState>) -> HttpResponse {
-    let client = state.mongo_client.clone();
-    let ping_res = client
-        .database("admin")
-        .run_command(doc! {"ping": 1}, None)
-        .await;
-    match ping_res {
+    match state.database.ping().await {
         Ok(_) => HttpResponse::Ok().json(HealthResponse {
             status: "ok".to_owned(),
         }),
@@ -1,4 +1,530 @@
+pub mod admin;
+pub mod avatars;
+pub mod feeds;
+pub mod imports;
 pub mod index;
 pub mod movements;
 pub mod users;
 pub mod workouts;
+
+use crate::utils::AppState;
+use actix_web::web;
+
+/// Shares the state and each of the repositories with the handlers, which
+/// take a repository as `web::Data<dyn UserRepository>` and so on.
+pub fn init_app_data(cfg: &mut web::ServiceConfig, state: &AppState) {
+    let repositories = &state.repositories;
+    cfg.app_data(web::Data::new(state.clone()))
+        .app_data(web::Data::from(repositories.users.clone()))
+        .app_data(web::Data::from(repositories.workouts.clone()))
+        .app_data(web::Data::from(repositories.movements.clone()))
+        .app_data(web::Data::from(repositories.feeds.clone()))
+        .app_data(web::Data::from(repositories.imports.clone()))
+        .app_data(web::Data::new(state.storages.clone()));
+}
+
+/// Sets up the endpoints of the API (strictest matcher first).
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/avatars").configure(avatars::init_routes))
+        .service(web::scope("/v1/users").configure(users::init_routes))
+        .service(web::scope("/v1/movements").configure(movements::init_routes))
+        .service(web::scope("/v1/workouts").configure(workouts::init_routes))
+        .service(web::scope("/v1/imports").configure(imports::init_routes))
+        .service(web::scope("/v1/feeds").configure(feeds::init_routes))
+        .service(web::scope("/v1/admin").configure(admin::init_routes))
+        .service(web::scope("").configure(index::init_routes));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::movement::{MovementModel, MovementResponse};
+    use crate::models::response::TokenResponse;
+    use crate::models::user::Claims;
+    use crate::models::workout::{ManyWorkoutsResponse, WorkoutModel, WorkoutResponse};
+    use crate::repositories::{Database, MemoryDatabase};
+    use crate::storage::Storages;
+    use crate::utils::Config;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn app_state() -> AppState {
+        let config = Config::for_tests();
+        AppState::new(
+            Database::Memory(MemoryDatabase::new()),
+            Storages::new(&config.storage).unwrap(),
+            Arc::new(config),
+        )
+    }
+
+    fn register(email: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/v1/users/register")
+            .set_json(json!({ "email": email, "password": "password" }))
+    }
+
+    fn authorized(request: TestRequest, token: &str) -> TestRequest {
+        request.insert_header(("Authorization", format!("Bearer {}", token)))
+    }
+
+    #[actix_web::test]
+    async fn test_register_and_login() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+
+        let res = test::call_service(&app, register("athlete@wodbook.com").to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = test::call_service(&app, register("athlete@wodbook.com").to_request()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let login = |password: &str| {
+            TestRequest::post()
+                .uri("/v1/users/login")
+                .set_json(json!({ "email": "athlete@wodbook.com", "password": password }))
+                .to_request()
+        };
+        let res = test::call_service(&app, login("wrong")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let token: TokenResponse = test::call_and_read_body_json(&app, login("password")).await;
+
+        let res =
+            test::call_service(&app, TestRequest::get().uri("/v1/users/me").to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let me: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            authorized(TestRequest::get().uri("/v1/users/me"), &token.token).to_request(),
+        )
+        .await;
+        assert_eq!(me["email"], "athlete@wodbook.com");
+        assert_eq!(me["body_measurements"]["height_unit"], "cm");
+    }
+
+    #[actix_web::test]
+    async fn test_workouts_and_scores() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+        let owner: TokenResponse =
+            test::call_and_read_body_json(&app, register("owner@wodbook.com").to_request()).await;
+        let other: TokenResponse =
+            test::call_and_read_body_json(&app, register("other@wodbook.com").to_request()).await;
+
+        let create = |name: &str, is_public: bool| {
+            authorized(TestRequest::post().uri("/v1/workouts"), &owner.token).set_json(json!({
+                "name": name,
+                "description": "For time",
+                "measurement": "time",
+                "is_public": is_public,
+            }))
+        };
+        let fran: WorkoutModel =
+            test::call_and_read_body_json(&app, create("Fran", true).to_request()).await;
+        let res = test::call_service(&app, create("Grace", false).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = test::call_service(&app, create("Fran", false).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // Others only see the public workouts
+        let list = |token: &str| authorized(TestRequest::get().uri("/v1/workouts"), token);
+        let workouts: ManyWorkoutsResponse =
+            test::call_and_read_body_json(&app, list(&owner.token).to_request()).await;
+        let names: Vec<&str> = workouts.data.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, vec!["Fran", "Grace"]);
+        let workouts: ManyWorkoutsResponse =
+            test::call_and_read_body_json(&app, list(&other.token).to_request()).await;
+        assert_eq!(workouts.data.len(), 1);
+
+        // Only the creator can change a workout
+        let uri = format!("/v1/workouts/{}", fran.workout_id);
+        let update = authorized(TestRequest::patch().uri(&uri), &other.token)
+            .set_json(json!({ "name": "Not Fran" }));
+        let res = test::call_service(&app, update.to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Scores of timed workouts are sorted fastest first
+        for score in [300.0, 180.0, 240.0].iter() {
+            let req = authorized(TestRequest::post().uri(&uri), &other.token)
+                .set_json(json!({ "score": score }));
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+        let workout: WorkoutResponse = test::call_and_read_body_json(
+            &app,
+            authorized(TestRequest::get().uri(&uri), &other.token).to_request(),
+        )
+        .await;
+        let scores: Vec<f64> = workout.scores.iter().map(|s| s.score).collect();
+        assert_eq!(scores, vec![180.0, 240.0, 300.0]);
+        let workout: WorkoutResponse = test::call_and_read_body_json(
+            &app,
+            authorized(TestRequest::get().uri(&uri), &owner.token).to_request(),
+        )
+        .await;
+        assert!(workout.scores.is_empty());
+
+        // Others would lose sight of their scores if it became private
+        let update = authorized(TestRequest::patch().uri(&uri), &owner.token)
+            .set_json(json!({ "is_public": false }));
+        let res = test::call_service(&app, update.to_request()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_fork_workouts_and_movements() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+        let owner: TokenResponse =
+            test::call_and_read_body_json(&app, register("owner@wodbook.com").to_request()).await;
+        let other: TokenResponse =
+            test::call_and_read_body_json(&app, register("other@wodbook.com").to_request()).await;
+
+        let create = |name: &str, is_public: bool| {
+            authorized(TestRequest::post().uri("/v1/workouts"), &owner.token).set_json(json!({
+                "name": name,
+                "description": "For time",
+                "measurement": "time",
+                "is_public": is_public,
+            }))
+        };
+        let fran: WorkoutModel =
+            test::call_and_read_body_json(&app, create("Fran", true).to_request()).await;
+        let grace: WorkoutModel =
+            test::call_and_read_body_json(&app, create("Grace", false).to_request()).await;
+        let fork = |id: &str, query: &str, token: &str| {
+            let uri = format!("/v1/workouts/{}/fork{}", id, query);
+            authorized(TestRequest::post().uri(&uri), token).to_request()
+        };
+
+        // Others get a copy of their own, numbered when the name is taken
+        let res = test::call_service(&app, fork(&fran.workout_id, "", &other.token)).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let copy: WorkoutModel = test::read_body_json(res).await;
+        assert_eq!(copy.name, "Fran");
+        assert_eq!(copy.forked_from, Some(fran.workout_id.to_owned()));
+        assert_ne!(copy.user_id, fran.user_id);
+        let copy: WorkoutModel =
+            test::call_and_read_body_json(&app, fork(&fran.workout_id, "", &other.token)).await;
+        assert_eq!(copy.name, "Fran (2)");
+        let res =
+            test::call_service(&app, fork(&fran.workout_id, "?name=Fran", &other.token)).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // Own and private workouts are not forked
+        let res = test::call_service(&app, fork(&fran.workout_id, "", &owner.token)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = test::call_service(&app, fork(&grace.workout_id, "", &other.token)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Scores logged on the original can move to the copy
+        let uri = format!("/v1/workouts/{}", fran.workout_id);
+        let req = authorized(TestRequest::post().uri(&uri), &other.token)
+            .set_json(json!({ "score": 180 }));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let copy: WorkoutModel = test::call_and_read_body_json(
+            &app,
+            fork(
+                &fran.workout_id,
+                "?name=My%20Fran&move_scores=true",
+                &other.token,
+            ),
+        )
+        .await;
+        let get = |id: &str| {
+            let uri = format!("/v1/workouts/{}", id);
+            authorized(TestRequest::get().uri(&uri), &other.token).to_request()
+        };
+        let original: WorkoutResponse =
+            test::call_and_read_body_json(&app, get(&fran.workout_id)).await;
+        assert!(original.scores.is_empty());
+        let moved: WorkoutResponse =
+            test::call_and_read_body_json(&app, get(&copy.workout_id)).await;
+        assert_eq!(moved.scores.len(), 1);
+
+        let req = authorized(TestRequest::post().uri("/v1/movements"), &owner.token)
+            .set_json(json!({ "name": "Back Squat", "measurement": "weight", "is_public": true }));
+        let squat: MovementModel = test::call_and_read_body_json(&app, req.to_request()).await;
+        let uri = format!("/v1/movements/{}/fork", squat.movement_id);
+        let res = test::call_service(
+            &app,
+            authorized(TestRequest::post().uri(&uri), &other.token).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let copy: MovementModel = test::read_body_json(res).await;
+        assert_eq!(copy.forked_from, Some(squat.movement_id.to_owned()));
+        let res = test::call_service(
+            &app,
+            authorized(TestRequest::post().uri(&uri), &owner.token).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_movements_and_scores() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+        let owner: TokenResponse =
+            test::call_and_read_body_json(&app, register("owner@wodbook.com").to_request()).await;
+
+        let create = |name: &str| {
+            authorized(TestRequest::post().uri("/v1/movements"), &owner.token)
+                .set_json(json!({ "name": name, "measurement": "weight" }))
+        };
+        let squat: MovementModel =
+            test::call_and_read_body_json(&app, create("Back Squat").to_request()).await;
+        let res = test::call_service(&app, create("Back Squat").to_request()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // The top set of a list of sets is the score
+        let uri = format!("/v1/movements/{}", squat.movement_id);
+        let log = |score: serde_json::Value| {
+            authorized(TestRequest::post().uri(&uri), &owner.token)
+                .set_json(score)
+                .to_request()
+        };
+        let res = test::call_service(&app, log(json!({ "score": 100, "reps": 5 }))).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let sets: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            log(json!({ "set_details": [
+                { "reps": 5, "load": 100 },
+                { "reps": 3, "load": 110 },
+            ] })),
+        )
+        .await;
+        assert_eq!(sets["score"], 110.0);
+        assert_eq!(sets["reps"], 3);
+        assert_eq!(sets["sets"], 2);
+        assert_eq!(sets["unit"], "kg");
+
+        let get = || authorized(TestRequest::get().uri(&uri), &owner.token).to_request();
+        let movement: MovementResponse = test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(movement.scores.len(), 2);
+
+        // Scores with a list of sets are changed through the sets
+        let score_uri = format!("{}/{}", uri, sets["movement_score_id"].as_str().unwrap());
+        let update = |change: serde_json::Value| {
+            authorized(TestRequest::patch().uri(&score_uri), &owner.token)
+                .set_json(change)
+                .to_request()
+        };
+        let res = test::call_service(&app, update(json!({ "score": 120 }))).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let updated: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            update(json!({ "set_details": [{ "reps": 1, "load": 130 }] })),
+        )
+        .await;
+        assert_eq!(updated["score"], 130.0);
+        assert_eq!(updated["sets"], 1);
+
+        let res = test::call_service(
+            &app,
+            authorized(TestRequest::delete().uri(&score_uri), &owner.token).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let movement: MovementResponse = test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(movement.scores.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_import_and_rollback() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+        let owner: TokenResponse =
+            test::call_and_read_body_json(&app, register("owner@wodbook.com").to_request()).await;
+        let other: TokenResponse =
+            test::call_and_read_body_json(&app, register("other@wodbook.com").to_request()).await;
+
+        let csv = [
+            "date,title,description,best_result_raw,best_result_display,score_type,barbell_lift,set_details,notes,rx_or_scaled,pr",
+            "03/14/2022,Fran,21-15-9,245,4:05,Time,,,,RX,PR",
+            "03/15/2022,Grace,30 clean and jerks,150,2:30,Time,,,,RX,",
+        ]
+        .join("\n");
+        let body = format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"sugarwod.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n--boundary--\r\n",
+            csv
+        );
+        let req = authorized(
+            TestRequest::post().uri("/v1/users/import/sugarwod"),
+            &owner.token,
+        )
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload(body);
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let import: serde_json::Value = test::read_body_json(res).await;
+        let uri = format!("/v1/imports/{}", import["import_id"].as_str().unwrap());
+
+        // The import runs in the background
+        let mut import = import;
+        for _ in 0..100 {
+            if import["status"] == "completed" || import["status"] == "failed" {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+            import = test::call_and_read_body_json(
+                &app,
+                authorized(TestRequest::get().uri(&uri), &owner.token).to_request(),
+            )
+            .await;
+        }
+        assert_eq!(import["status"], "completed");
+        assert_eq!(import["result"]["added_workouts"], 2);
+        assert_eq!(import["result"]["added_workout_scores"], 2);
+
+        // Imports are only visible to their user
+        let res = test::call_service(
+            &app,
+            authorized(TestRequest::get().uri(&uri), &other.token).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let rollback = || authorized(TestRequest::delete().uri(&uri), &owner.token).to_request();
+        let rolled_back: serde_json::Value = test::call_and_read_body_json(&app, rollback()).await;
+        assert_eq!(rolled_back["status"], "rolled_back");
+        assert_eq!(rolled_back["rollback"]["removed_workouts"], 2);
+        assert_eq!(rolled_back["rollback"]["removed_workout_scores"], 2);
+        let workouts: ManyWorkoutsResponse = test::call_and_read_body_json(
+            &app,
+            authorized(TestRequest::get().uri("/v1/workouts"), &owner.token).to_request(),
+        )
+        .await;
+        assert!(workouts.data.is_empty());
+
+        let res = test::call_service(&app, rollback()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_bulk_scores() {
+        let state = app_state();
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+        let owner: TokenResponse =
+            test::call_and_read_body_json(&app, register("owner@wodbook.com").to_request()).await;
+
+        let req = authorized(TestRequest::post().uri("/v1/workouts"), &owner.token)
+            .set_json(json!({ "name": "Fran", "description": "For time", "measurement": "time" }));
+        let fran: WorkoutModel = test::call_and_read_body_json(&app, req.to_request()).await;
+        let req = authorized(TestRequest::post().uri("/v1/movements"), &owner.token)
+            .set_json(json!({ "name": "Deadlift", "measurement": "weight" }));
+        let deadlift: MovementModel = test::call_and_read_body_json(&app, req.to_request()).await;
+
+        let log = |scores: serde_json::Value| {
+            authorized(TestRequest::post().uri("/v1/users/me/scores"), &owner.token)
+                .set_json(scores)
+                .to_request()
+        };
+        let res = test::call_service(
+            &app,
+            log(json!({
+                "workout_scores": [{ "workout_id": fran.workout_id, "score": 245 }],
+                "movement_scores": [
+                    { "movement_id": deadlift.movement_id, "score": 180 },
+                    { "movement_id": deadlift.movement_id, "set_details": [{ "reps": 1, "load": 190 }] },
+                ],
+            })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let logged: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(logged["workout_scores"].as_array().unwrap().len(), 1);
+        assert_eq!(logged["movement_scores"].as_array().unwrap().len(), 2);
+
+        // Nothing is logged when one of the scores is invalid
+        let res = test::call_service(
+            &app,
+            log(json!({
+                "workout_scores": [{ "workout_id": fran.workout_id, "score": 230 }],
+                "movement_scores": [{ "movement_id": deadlift.movement_id }],
+            })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = test::call_service(
+            &app,
+            log(json!({
+                "workout_scores": [{ "workout_id": "unknown", "score": 230 }],
+            })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let scores: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            authorized(TestRequest::get().uri("/v1/users/me/scores"), &owner.token).to_request(),
+        )
+        .await;
+        assert_eq!(scores["workout_scores"].as_array().unwrap().len(), 1);
+        assert_eq!(scores["movement_scores"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_config() {
+        let state = app_state();
+        let token = |admin: bool| {
+            let claims = Claims {
+                sub: "admin@wodbook.com".to_owned(),
+                exp: (chrono::Utc::now().timestamp() + 60) as usize,
+                admin,
+                user_id: "admin".to_owned(),
+            };
+            let key = EncodingKey::from_secret(state.config.auth.secret.as_bytes());
+            encode(&Header::default(), &claims, &key).unwrap()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| init_app_data(cfg, &state))
+                .configure(init_routes),
+        )
+        .await;
+
+        let get_config = |token: &str| {
+            authorized(TestRequest::get().uri("/v1/admin/config"), token).to_request()
+        };
+        let res = test::call_service(&app, get_config(&token(false))).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let config: serde_json::Value =
+            test::call_and_read_body_json(&app, get_config(&token(true))).await;
+        assert_eq!(config["auth"]["secret"], "[redacted]");
+        assert_eq!(config["port"], 43210);
+    }
+}
@@ -1,22 +1,19 @@
 use crate::errors::AppError;
 use crate::models::movement::{
-    CreateMovement, CreateMovementScore, ManyMovementsResponse, MovementResponse, UpdateMovement,
-    UpdateMovementScore,
+    convert_movement_scores, CreateMovement, CreateMovementScore, ManyMovementsResponse,
+    MovementResponse, MovementScoreResponse, UpdateMovement, UpdateMovementScore,
 };
+use crate::models::response::{ForkQuery, UpdateQuery};
 use crate::models::user::Claims;
-use crate::repositories::MovementRepository;
-use crate::utils::AppState;
+use crate::repositories::{MovementRepository, UserRepository};
 use actix_web::{delete, get, patch, post, web, HttpResponse, Responder};
 
 #[get("")]
 async fn get_movements(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     info!("Getting all movements");
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let result = movement_repo.get_movements(user_id).await;
@@ -26,14 +23,11 @@ async fn get_movements(
 
 #[post("")]
 async fn create_movement(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     claims: Claims,
     movement: web::Json<CreateMovement>,
 ) -> Result<impl Responder, AppError> {
     info!("Creating a new movement");
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let result = movement_repo
@@ -45,32 +39,52 @@ async fn create_movement(
 
 #[patch("/{id}")]
 async fn update_movement(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     info: web::Path<String>,
+    query: web::Query<UpdateQuery>,
     claims: Claims,
     movement: web::Json<UpdateMovement>,
-) -> Result<impl Responder, AppError> {
-    let movement_id = info.to_owned();
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
+) -> Result<HttpResponse, AppError> {
+    let movement_id = info.into_inner();
+
+    let user_id = claims.user_id.as_ref();
+    if query.preview {
+        return movement_repo
+            .preview_movement_update(user_id, &movement_id, movement.into_inner())
+            .await
+            .map(|preview| HttpResponse::Ok().json(preview));
+    }
 
     movement_repo
-        .update_movement(claims.user_id.as_ref(), &movement_id, movement.into_inner())
+        .update_movement(user_id, &movement_id, movement.into_inner())
         .await
         .map(|movement| HttpResponse::Ok().json(movement))
 }
 
+#[post("/{id}/fork")]
+async fn fork_movement(
+    movement_repo: web::Data<dyn MovementRepository>,
+    info: web::Path<String>,
+    query: web::Query<ForkQuery>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let movement_id = info.into_inner();
+
+    let user_id = claims.user_id.as_ref();
+    let result = movement_repo
+        .fork_movement(user_id, &movement_id, query.into_inner())
+        .await;
+
+    result.map(|movement| HttpResponse::Created().json(movement))
+}
+
 #[delete("/{id}")]
 async fn delete_movement(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     info: web::Path<String>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let movement_id = info;
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     movement_repo
         .delete_movement(claims.user_id.as_ref(), &movement_id)
@@ -80,65 +94,71 @@ async fn delete_movement(
 
 #[get("/{id}")]
 async fn get_movement_by_id(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
     info: web::Path<String>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let movement_id = info;
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let movement = movement_repo
         .get_movement_by_id(user_id, &movement_id)
         .await?;
 
-    let scores_result = movement_repo
+    let scores = movement_repo
         .get_movement_scores_for_movement(user_id, &movement)
-        .await;
+        .await?;
 
-    scores_result
-        .map(|scores| HttpResponse::Ok().json(MovementResponse::from_model(movement, scores)))
+    let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+    let scores = convert_movement_scores(movement.measurement, scores, unit_system)
+        .into_iter()
+        .map(|score| MovementScoreResponse::from_model(score, movement.measurement, unit_system))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(MovementResponse::from_model(movement, scores)))
 }
 
 #[post("/{id}")]
 async fn create_movement_score(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
     info: web::Path<String>,
     claims: Claims,
     movement_score: web::Json<CreateMovementScore>,
 ) -> Result<impl Responder, AppError> {
     let movement_id = info;
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let movement = movement_repo
         .find_movement_by_id(user_id, &movement_id)
         .await?;
 
-    match movement {
-        Some(movement) => movement_repo
-            .create_movement_score(user_id, &movement, movement_score.into_inner())
-            .await
-            .map(|score| HttpResponse::Created().json(score)),
-        None => Err(AppError::NotFound("Movement not found".to_string())),
+    let movement = match movement {
+        Some(movement) => movement,
+        None => return Err(AppError::NotFound("Movement not found".to_string())),
+    };
+
+    let mut movement_score = movement_score.into_inner();
+    if movement_score.unit.is_none() {
+        let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+        movement_score.unit = movement.measurement.default_unit(unit_system);
     }
+
+    movement_repo
+        .create_movement_score(user_id, &movement, movement_score)
+        .await
+        .map(|score| HttpResponse::Created().json(score))
 }
 
 #[patch("/{movement_id}/{score_id}")]
 async fn update_movement_score(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     params: web::Path<(String, String)>,
     claims: Claims,
     movement_score_update: web::Json<UpdateMovementScore>,
 ) -> Result<impl Responder, AppError> {
     let (movement_id, score_id) = params.into_inner();
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     movement_repo
         .update_movement_score_by_id(
@@ -153,14 +173,11 @@ async fn update_movement_score(
 
 #[delete("/{movement_id}/{score_id}")]
 async fn delete_movement_score(
-    state: web::Data<AppState>,
+    movement_repo: web::Data<dyn MovementRepository>,
     params: web::Path<(String, String)>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let (movement_id, score_id) = params.into_inner();
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     movement_repo
         .delete_movement_score_by_id(claims.user_id.as_ref(), &movement_id, &score_id)
@@ -172,6 +189,7 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_movements);
     cfg.service(create_movement);
     cfg.service(update_movement);
+    cfg.service(fork_movement);
     cfg.service(delete_movement);
     cfg.service(get_movement_by_id);
     cfg.service(create_movement_score);
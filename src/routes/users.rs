@@ -1,26 +1,44 @@
 use crate::errors::AppError;
-use crate::models::mywod::MyWodResponse;
-use crate::models::response::{TokenResponse, UserScoreResponse};
+use crate::models::import::ImportResponse;
+use crate::models::movement::{MovementModel, MovementScoreModel};
+use crate::models::mywod::{AthletePreview, MyWodPreviewResponse, MyWodQuery};
+use crate::models::response::{
+    CreateUserScores, TokenResponse, UserScoreResponse, MAX_BULK_SCORES,
+};
+use crate::models::training_log::LogSource;
 use crate::models::user::Claims;
-use crate::models::user::{CreateUser, Login, UpdateUser};
-use crate::repositories::{MovementRepository, UserRepository, WorkoutRepository};
-use crate::services::mywod;
-use crate::utils::mywod::{delete_payload_file, read_contents, write_payload_to_file};
+use crate::models::user::{
+    CreateSignedAvatarQuery, CreateUser, Login, SignedAvatarResponse, UpdateUser, UserResponse,
+};
+use crate::models::workout::{WorkoutModel, WorkoutScoreModel};
+use crate::repositories::{
+    FeedRepository, ImportRepository, MovementRepository, UserRepository, WorkoutRepository,
+};
+use crate::services::{imports, mywod};
+use crate::storage::Storages;
+use crate::utils::avatar::{
+    delete_avatar, process_avatar, save_avatar, signed_avatar_url, MAX_AVATAR_SIZE,
+};
+use crate::utils::mywod::{
+    read_mywod_contents, validate_mywod_backup, write_mywod_contents, write_payload_to_file,
+    SQLITE_HEADER,
+};
+use crate::utils::upload::read_payload;
 use crate::utils::AppState;
 use actix_multipart::Multipart;
-use actix_web::{get, patch, post, web, HttpResponse, Responder};
+use actix_web::http::header::ContentDisposition;
+use actix_web::{delete, get, patch, post, put, web, HttpResponse, Responder};
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
 
 #[post("/login")]
 async fn login(
     state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
     user: web::Json<Login>,
 ) -> Result<impl Responder, AppError> {
-    let user_repo = UserRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     user_repo
-        .login(user.into_inner())
+        .login(user.into_inner(), state.config.auth.secret.as_bytes())
         .await
         .map(|token| HttpResponse::Ok().json(TokenResponse { token }))
 }
@@ -28,51 +46,48 @@ async fn login(
 #[post("/register")]
 async fn register(
     state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
     user: web::Json<CreateUser>,
 ) -> Result<impl Responder, AppError> {
-    let user_repo = UserRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     user_repo
-        .register(user.into_inner())
+        .register(user.into_inner(), state.config.auth.secret.as_bytes())
         .await
         .map(|token| HttpResponse::Created().json(TokenResponse { token }))
 }
 
 #[get("/me")]
 async fn get_user_information(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
-    let user_repo = UserRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     user_repo
         .find_user_with_email(claims.sub.as_ref())
         .await
-        .map(|user_scores| HttpResponse::Ok().json(user_scores))
+        .map(|user| HttpResponse::Ok().json(UserResponse::from(user)))
 }
 
 #[get("/me/scores")]
 async fn get_user_scores(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
+    let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+
     let movement_scores = movement_repo
         .get_movement_scores_for_user(claims.user_id.as_ref())
-        .await?;
+        .await?
+        .into_iter()
+        .map(|score| score.convert_to(unit_system))
+        .collect();
 
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
     let workout_scores = workout_repo
         .get_workout_scores_for_user(claims.user_id.as_ref())
-        .await?;
+        .await?
+        .into_iter()
+        .map(|score| score.convert_to(unit_system))
+        .collect();
 
     Ok(HttpResponse::Ok().json(UserScoreResponse {
         movement_scores,
@@ -80,83 +95,437 @@ async fn get_user_scores(
     }))
 }
 
+/// Logs scores of several workouts and movements at once. Nothing is logged
+/// when one of the scores is invalid or can not be added.
+#[post("/me/scores")]
+async fn create_user_scores(
+    user_repo: web::Data<dyn UserRepository>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
+    claims: Claims,
+    scores: web::Json<CreateUserScores>,
+) -> Result<impl Responder, AppError> {
+    let scores = scores.into_inner();
+    if scores.workout_scores.len() + scores.movement_scores.len() > MAX_BULK_SCORES {
+        return Err(AppError::BadRequest(format!(
+            "At most {} scores can be logged at once",
+            MAX_BULK_SCORES
+        )));
+    }
+
+    let user_id = claims.user_id.as_ref();
+    let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+
+    let mut workouts: HashMap<String, WorkoutModel> = HashMap::new();
+    let mut workout_scores = Vec::new();
+    for bulk_score in scores.workout_scores {
+        if !workouts.contains_key(&bulk_score.workout_id) {
+            match workout_repo
+                .find_workout_by_id(user_id, &bulk_score.workout_id)
+                .await?
+            {
+                Some(workout) => workouts.insert(bulk_score.workout_id.to_owned(), workout),
+                None => return Err(AppError::NotFound("Workout not found".to_string())),
+            };
+        }
+
+        let mut score = bulk_score.score;
+        if score.unit.is_none() {
+            score.unit = workouts[&bulk_score.workout_id]
+                .measurement
+                .default_unit(unit_system);
+        }
+        workout_scores.push((bulk_score.workout_id, score));
+    }
+
+    let mut movements: HashMap<String, MovementModel> = HashMap::new();
+    let mut movement_scores = HashMap::new();
+    for bulk_score in scores.movement_scores {
+        if !movements.contains_key(&bulk_score.movement_id) {
+            match movement_repo
+                .find_movement_by_id(user_id, &bulk_score.movement_id)
+                .await?
+            {
+                Some(movement) => movements.insert(bulk_score.movement_id.to_owned(), movement),
+                None => return Err(AppError::NotFound("Movement not found".to_string())),
+            };
+        }
+
+        let mut score = bulk_score.score;
+        if score.score.is_none() && score.set_details.is_empty() {
+            return Err(AppError::BadRequest(
+                "A score or a list of sets is required".to_owned(),
+            ));
+        }
+        if score.unit.is_none() {
+            score.unit = movements[&bulk_score.movement_id]
+                .measurement
+                .default_unit(unit_system);
+        }
+        movement_scores
+            .entry(bulk_score.movement_id)
+            .or_insert_with(Vec::new)
+            .push(score);
+    }
+
+    let workout_scores = workout_repo
+        .create_workout_scores(user_id, workout_scores)
+        .await?;
+
+    let mut created_movement_scores = Vec::new();
+    for (movement_id, scores) in movement_scores {
+        match movement_repo
+            .create_movement_scores(user_id, &movements[&movement_id], scores)
+            .await
+        {
+            Ok(created) => created_movement_scores.extend(created),
+            Err(e) => {
+                remove_scores(
+                    &workout_repo,
+                    &movement_repo,
+                    user_id,
+                    &workout_scores,
+                    &created_movement_scores,
+                )
+                .await;
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(HttpResponse::Created().json(UserScoreResponse {
+        movement_scores: created_movement_scores,
+        workout_scores,
+    }))
+}
+
+/// Removes the scores a bulk request added before one of its writes failed.
+async fn remove_scores(
+    workout_repo: &web::Data<dyn WorkoutRepository>,
+    movement_repo: &web::Data<dyn MovementRepository>,
+    user_id: &str,
+    workout_scores: &[WorkoutScoreModel],
+    movement_scores: &[MovementScoreModel],
+) {
+    for score in workout_scores {
+        if let Err(e) = workout_repo
+            .delete_workout_score_by_id(user_id, &score.workout_id, &score.workout_score_id)
+            .await
+        {
+            error!(
+                "Could not remove workout score {}: {}",
+                score.workout_score_id, e
+            );
+        }
+    }
+    for score in movement_scores {
+        if let Err(e) = movement_repo
+            .delete_movement_score_by_id(user_id, &score.movement_id, &score.movement_score_id)
+            .await
+        {
+            error!(
+                "Could not remove movement score {}: {}",
+                score.movement_score_id, e
+            );
+        }
+    }
+}
+
 #[patch("/me")]
 async fn update_user_information(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
     claims: Claims,
     user: web::Json<UpdateUser>,
 ) -> Result<impl Responder, AppError> {
-    let user_repo = UserRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     user_repo
         .update_user_with_email(claims.sub.as_ref(), user.into_inner())
         .await
-        .map(|user| HttpResponse::Ok().json(user))
+        .map(|user| HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+/// The update of a user that changes nothing but the avatar.
+fn avatar_update(avatar_url: String) -> UpdateUser {
+    UpdateUser {
+        password: None,
+        first_name: None,
+        last_name: None,
+        date_of_birth: None,
+        height: None,
+        weight: None,
+        box_name: None,
+        avatar_url: Some(avatar_url),
+        avatar_privacy: None,
+        unit_system: None,
+        gender: None,
+    }
+}
+
+#[put("/me/avatar")]
+async fn update_avatar(
+    storages: web::Data<Storages>,
+    user_repo: web::Data<dyn UserRepository>,
+    claims: Claims,
+    payload: Multipart,
+) -> Result<impl Responder, AppError> {
+    let previous_avatar_url = user_repo
+        .find_user_with_email(claims.sub.as_ref())
+        .await?
+        .avatar_url;
+
+    let avatar = read_payload(payload, MAX_AVATAR_SIZE, b"", "an image").await?;
+    // Decoding and resizing images is too slow for the async runtime
+    let avatar = web::block(move || process_avatar(&avatar))
+        .await
+        .map_err(|_| AppError::Internal("Processing avatar failed".to_owned()))??;
+    let avatar_url = save_avatar(storages.avatars.as_ref(), &claims.user_id, avatar).await?;
+
+    let user = user_repo
+        .update_user_with_email(claims.sub.as_ref(), avatar_update(avatar_url.to_owned()))
+        .await?;
+    if !previous_avatar_url.is_empty()
+        && previous_avatar_url != avatar_url
+        && !user_repo
+            .is_avatar_shared(&claims.user_id, &previous_avatar_url)
+            .await?
+    {
+        delete_avatar(storages.avatars.as_ref(), &previous_avatar_url).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[delete("/me/avatar")]
+async fn delete_user_avatar(
+    storages: web::Data<Storages>,
+    user_repo: web::Data<dyn UserRepository>,
+    claims: Claims,
+) -> Result<impl Responder, AppError> {
+    let avatar_url = user_repo
+        .find_user_with_email(claims.sub.as_ref())
+        .await?
+        .avatar_url;
+    if avatar_url.is_empty() {
+        return Err(AppError::NotFound("User has no avatar".to_owned()));
+    }
+
+    user_repo
+        .update_user_with_email(claims.sub.as_ref(), avatar_update("".to_owned()))
+        .await?;
+    if !user_repo
+        .is_avatar_shared(&claims.user_id, &avatar_url)
+        .await?
+    {
+        delete_avatar(storages.avatars.as_ref(), &avatar_url).await?;
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Default and longest time a signed avatar path is valid, in seconds.
+const SIGNED_AVATAR_EXPIRES_IN: i64 = 60 * 60;
+const MAX_SIGNED_AVATAR_EXPIRES_IN: i64 = 7 * 24 * 60 * 60;
+
+/// Creates a path to the avatar of the logged in user that can be used
+/// without logging in until it expires, regardless of the privacy setting.
+#[get("/me/avatar")]
+async fn get_signed_avatar(
+    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    claims: Claims,
+    query: web::Query<CreateSignedAvatarQuery>,
+) -> Result<impl Responder, AppError> {
+    let expires_in = query.expires_in.unwrap_or(SIGNED_AVATAR_EXPIRES_IN);
+    if !(1..=MAX_SIGNED_AVATAR_EXPIRES_IN).contains(&expires_in) {
+        return Err(AppError::BadRequest(format!(
+            "A signed avatar link can expire in 1 to {} seconds",
+            MAX_SIGNED_AVATAR_EXPIRES_IN
+        )));
+    }
+
+    let avatar_url = user_repo
+        .find_user_with_email(claims.sub.as_ref())
+        .await?
+        .avatar_url;
+    if avatar_url.is_empty() {
+        return Err(AppError::NotFound("User has no avatar".to_owned()));
+    }
+
+    let expires_at = Utc::now() + Duration::seconds(expires_in);
+    Ok(HttpResponse::Ok().json(SignedAvatarResponse {
+        avatar_url: signed_avatar_url(
+            &avatar_url,
+            expires_at.timestamp(),
+            state.config.auth.secret.as_bytes(),
+        ),
+        expires_at: expires_at.to_rfc3339(),
+    }))
 }
 
+// Every repository and storage the import uses is an extractor
+#[allow(clippy::too_many_arguments)]
 #[post("/mywod")]
 async fn sync_mywod(
     state: web::Data<AppState>,
+    storages: web::Data<Storages>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
+    feed_repo: web::Data<dyn FeedRepository>,
+    import_repo: web::Data<dyn ImportRepository>,
+    query: web::Query<MyWodQuery>,
     claims: Claims,
     payload: Multipart,
-) -> Result<impl Responder, AppError> {
+) -> Result<HttpResponse, AppError> {
     let user_id = claims.user_id.as_ref();
     let user_email = claims.sub.as_ref();
-    let user_repo = UserRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
-    let written_filename = write_payload_to_file(payload).await?;
-    info!("File written: {}", written_filename);
 
-    let mywod_data = read_contents(&written_filename).await;
+    let max_size = state.config.upload.max_size;
+    let contents = read_payload(payload, max_size, SQLITE_HEADER, "a myWOD backup").await?;
+    let contents = validate_mywod_backup(contents).await?;
+
+    if !query.dry_run {
+        // The file is kept until the import job has processed it
+        let written_filename = write_payload_to_file(storages.uploads.as_ref(), contents).await?;
+        info!("File written: {}", written_filename);
+
+        let import = import_repo
+            .create_import(user_id, user_email, "mywod", &written_filename)
+            .await?;
+        imports::spawn_import(
+            state.repositories.clone(),
+            storages.get_ref().clone(),
+            import.import_id.to_owned(),
+        );
 
-    let deleted = delete_payload_file(written_filename).await?;
-    info!("File deleted after handling: {}", deleted);
+        return Ok(HttpResponse::Accepted().json(ImportResponse::from_model(import)));
+    }
 
-    let mywod_data = mywod_data?;
+    // A dry run does not store the file, it is read straight away
+    let mywod_data = read_mywod_contents(contents).await?;
 
-    let user_updated =
-        mywod::save_athlete(user_repo, user_id, user_email, mywod_data.athlete).await?;
-    let added_workouts_and_scores = mywod::save_workouts_and_scores(
-        workout_repo,
+    let workouts = mywod::save_workouts_and_scores(
+        workout_repo.get_ref(),
         mywod_data.workouts,
         &mywod_data.workout_scores,
         user_id,
+        true,
+        None,
     )
     .await?;
 
-    let movement_repo = MovementRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
-    let added_movements_and_scores = mywod::save_movements_and_scores(
-        movement_repo,
+    let movements = mywod::save_movements_and_scores(
+        movement_repo.get_ref(),
         &mywod_data.movements,
         &mywod_data.movement_scores,
         user_id,
+        true,
+        None,
     )
     .await?;
 
-    Ok(HttpResponse::Ok().json(MyWodResponse {
-        user_updated,
-        added_workouts: added_workouts_and_scores.0,
-        added_workout_scores: added_workouts_and_scores.1,
-        added_movements: added_movements_and_scores.0,
-        added_movement_scores: added_movements_and_scores.1,
+    let feeds =
+        mywod::save_feeds(feed_repo.get_ref(), &mywod_data.feeds, user_id, true, None).await?;
+
+    let mut report = mywod_data.unreadable;
+    report.extend(workouts.report);
+    report.extend(movements.report);
+    report.extend(feeds.report);
+
+    // A dry run leaves the profile and avatar alone
+    Ok(HttpResponse::Ok().json(MyWodPreviewResponse {
+        athlete: AthletePreview::from(&mywod_data.athlete),
+        workouts: workouts.records,
+        workout_scores: workouts.scores,
+        movements: movements.records,
+        movement_scores: movements.scores,
+        feeds: feeds.records,
+        new_workouts: workouts.added_names,
+        new_movements: movements.added_names,
+        report,
     }))
 }
 
+/// Imports the CSV export of another training log app, like SugarWOD. The
+/// import runs in the background, the same as a myWOD import.
+#[post("/import/{source}")]
+async fn import_training_log(
+    state: web::Data<AppState>,
+    storages: web::Data<Storages>,
+    import_repo: web::Data<dyn ImportRepository>,
+    source: web::Path<String>,
+    claims: Claims,
+    payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let source = source.parse::<LogSource>()?;
+
+    let max_size = state.config.upload.max_size;
+    let contents = read_payload(payload, max_size, b"", "a CSV export").await?;
+    if std::str::from_utf8(&contents).is_err() {
+        return Err(AppError::UnsupportedMediaType(
+            "The file is not a CSV export".to_owned(),
+        ));
+    }
+
+    let written_filename = write_payload_to_file(storages.uploads.as_ref(), contents).await?;
+    info!("File written: {}", written_filename);
+
+    let import = import_repo
+        .create_import(
+            &claims.user_id,
+            &claims.sub,
+            &source.to_string(),
+            &written_filename,
+        )
+        .await?;
+    imports::spawn_import(
+        state.repositories.clone(),
+        storages.get_ref().clone(),
+        import.import_id.to_owned(),
+    );
+
+    Ok(HttpResponse::Accepted().json(ImportResponse::from_model(import)))
+}
+
+/// Exports the data of the user as a myWOD backup, which can be restored in
+/// the myWOD app or imported again.
+#[get("/me/mywod")]
+async fn export_mywod(
+    storages: web::Data<Storages>,
+    user_repo: web::Data<dyn UserRepository>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
+    movement_repo: web::Data<dyn MovementRepository>,
+    feed_repo: web::Data<dyn FeedRepository>,
+    claims: Claims,
+) -> Result<HttpResponse, AppError> {
+    let data = mywod::export_data(
+        user_repo.get_ref(),
+        workout_repo.get_ref(),
+        movement_repo.get_ref(),
+        feed_repo.get_ref(),
+        storages.avatars.as_ref(),
+        claims.sub.as_ref(),
+        claims.user_id.as_ref(),
+    )
+    .await?;
+
+    let contents = write_mywod_contents(data).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-sqlite3")
+        .insert_header(ContentDisposition::attachment("wodbook.mywod"))
+        .body(contents))
+}
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(login);
     cfg.service(register);
     cfg.service(get_user_information);
     cfg.service(get_user_scores);
     cfg.service(update_user_information);
+    cfg.service(create_user_scores);
+    cfg.service(update_avatar);
+    cfg.service(delete_user_avatar);
+    cfg.service(get_signed_avatar);
     cfg.service(sync_mywod);
+    cfg.service(import_training_log);
+    cfg.service(export_mywod);
 }
@@ -1,22 +1,18 @@
 use crate::errors::AppError;
+use crate::models::response::{ForkQuery, UpdateQuery};
 use crate::models::user::Claims;
 use crate::models::workout::{
-    CreateWorkout, CreateWorkoutScore, ManyWorkoutsResponse, UpdateWorkout, UpdateWorkoutScore,
-    WorkoutResponse,
+    convert_workout_scores, CreateWorkout, CreateWorkoutScore, ManyWorkoutsResponse, UpdateWorkout,
+    UpdateWorkoutScore, WorkoutResponse,
 };
-use crate::repositories::WorkoutRepository;
-use crate::utils::AppState;
+use crate::repositories::{UserRepository, WorkoutRepository};
 use actix_web::{delete, get, patch, post, web, HttpResponse, Responder};
 
 #[get("")]
 async fn get_workouts(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     let user_id = claims.user_id.as_ref();
     let result = workout_repo.get_workouts(user_id).await;
 
@@ -25,14 +21,10 @@ async fn get_workouts(
 
 #[post("")]
 async fn create_workout(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     claims: Claims,
     workout: web::Json<CreateWorkout>,
 ) -> Result<impl Responder, AppError> {
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
-
     let user_id = claims.user_id.as_ref();
     let result = workout_repo
         .create_workout(user_id, workout.into_inner())
@@ -43,34 +35,52 @@ async fn create_workout(
 
 #[patch("/{id}")]
 async fn update_workout(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     info: web::Path<String>,
+    query: web::Query<UpdateQuery>,
     claims: Claims,
     workout: web::Json<UpdateWorkout>,
+) -> Result<HttpResponse, AppError> {
+    let workout_id = info.into_inner();
+
+    let user_id = claims.user_id.as_ref();
+    if query.preview {
+        return workout_repo
+            .preview_workout_update(user_id, &workout_id, workout.into_inner())
+            .await
+            .map(|preview| HttpResponse::Ok().json(preview));
+    }
+
+    workout_repo
+        .update_workout(user_id, &workout_id, workout.into_inner())
+        .await
+        .map(|workout| HttpResponse::Ok().json(workout))
+}
+
+#[post("/{id}/fork")]
+async fn fork_workout(
+    workout_repo: web::Data<dyn WorkoutRepository>,
+    info: web::Path<String>,
+    query: web::Query<ForkQuery>,
+    claims: Claims,
 ) -> Result<impl Responder, AppError> {
-    let workout_id = info;
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
+    let workout_id = info.into_inner();
 
     let user_id = claims.user_id.as_ref();
     let result = workout_repo
-        .update_workout(user_id, &workout_id, workout.into_inner())
+        .fork_workout(user_id, &workout_id, query.into_inner())
         .await;
 
-    result.map(|workout| HttpResponse::Ok().json(workout))
+    result.map(|workout| HttpResponse::Created().json(workout))
 }
 
 #[delete("/{id}")]
 async fn delete_workout(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     info: web::Path<String>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let workout_id = info;
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let result = workout_repo.delete_workout(user_id, &workout_id).await;
@@ -80,63 +90,66 @@ async fn delete_workout(
 
 #[get("/{id}")]
 async fn get_workout_by_id(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     info: web::Path<String>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let workout_id = info;
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let workout = workout_repo.get_workout_by_id(user_id, &workout_id).await?;
 
-    let scores_result = workout_repo
+    let scores = workout_repo
         .get_workout_scores_for_workout(user_id, &workout)
-        .await;
+        .await?;
+
+    let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+    let scores = convert_workout_scores(workout.measurement, scores, unit_system);
 
-    scores_result
-        .map(|scores| HttpResponse::Ok().json(WorkoutResponse::from_model(workout, scores)))
+    Ok(HttpResponse::Ok().json(WorkoutResponse::from_model(workout, scores)))
 }
 
 #[post("/{id}")]
 async fn create_workout_score(
-    state: web::Data<AppState>,
+    user_repo: web::Data<dyn UserRepository>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     info: web::Path<String>,
     claims: Claims,
     workout_score: web::Json<CreateWorkoutScore>,
 ) -> Result<impl Responder, AppError> {
     let workout_id = info;
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let workout = workout_repo
         .find_workout_by_id(user_id, &workout_id)
         .await?;
 
-    match workout {
-        Some(workout) => workout_repo
-            .create_workout_score(user_id, &workout, workout_score.into_inner())
-            .await
-            .map(|score| HttpResponse::Created().json(score)),
-        None => Err(AppError::NotFound("Workout not found".to_string())),
+    let workout = match workout {
+        Some(workout) => workout,
+        None => return Err(AppError::NotFound("Workout not found".to_string())),
+    };
+
+    let mut workout_score = workout_score.into_inner();
+    if workout_score.unit.is_none() {
+        let unit_system = user_repo.get_unit_system(claims.sub.as_ref()).await?;
+        workout_score.unit = workout.measurement.default_unit(unit_system);
     }
+
+    workout_repo
+        .create_workout_score(user_id, &workout, workout_score)
+        .await
+        .map(|score| HttpResponse::Created().json(score))
 }
 
 #[patch("/{workout_id}/{score_id}")]
 async fn update_workout_score(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     params: web::Path<(String, String)>,
     claims: Claims,
     workout_score_update: web::Json<UpdateWorkoutScore>,
 ) -> Result<impl Responder, AppError> {
     let (workout_id, score_id) = params.into_inner();
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let scores_result = workout_repo
@@ -153,14 +166,11 @@ async fn update_workout_score(
 
 #[delete("/{workout_id}/{score_id}")]
 async fn delete_workout_score(
-    state: web::Data<AppState>,
+    workout_repo: web::Data<dyn WorkoutRepository>,
     params: web::Path<(String, String)>,
     claims: Claims,
 ) -> Result<impl Responder, AppError> {
     let (workout_id, score_id) = params.into_inner();
-    let workout_repo = WorkoutRepository {
-        mongo_client: state.mongo_client.clone(),
-    };
 
     let user_id = claims.user_id.as_ref();
     let result = workout_repo
@@ -174,6 +184,7 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_workouts);
     cfg.service(create_workout);
     cfg.service(update_workout);
+    cfg.service(fork_workout);
     cfg.service(delete_workout);
     cfg.service(get_workout_by_id);
     cfg.service(create_workout_score);
@@ -0,0 +1,531 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::import::{ImportModel, ImportStatus, ProfileSnapshot, RollbackResult};
+use crate::models::movement::MovementScoreModel;
+use crate::models::mywod::{MyWodResponse, RowReport};
+use crate::models::training_log::LogSource;
+use crate::models::workout::WorkoutScoreModel;
+use crate::repositories::{ImportRepository, Repositories};
+use crate::services::{mywod, training_log};
+use crate::storage::{BlobStorage, Storages};
+use crate::utils::mywod::{delete_payload_file, read_payload_contents, read_payload_file};
+use crate::utils::training_log::parse_training_log;
+
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// How many processed rows are collected before the progress is saved.
+const PROGRESS_BATCH_SIZE: u32 = 25;
+/// How long a worker keeps an import without renewing its claim. Claims are
+/// renewed along with the progress once a third of this has passed.
+const CLAIM_MILLIS: i64 = 5 * 60 * 1000;
+
+/// Identifies this process as the worker of the imports it claims.
+fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Keeps the progress of a running import up to date, saving it in batches
+/// so large backups do not double the number of writes.
+pub struct ImportTracker {
+    import_repo: Arc<dyn ImportRepository>,
+    import_id: String,
+    unsaved: AtomicU32,
+    /// When the claim on the import was last renewed, in milliseconds
+    renewed_at: AtomicI64,
+}
+
+impl ImportTracker {
+    pub fn new(repositories: &Repositories, import_id: &str) -> Self {
+        ImportTracker {
+            import_repo: repositories.imports.clone(),
+            import_id: import_id.to_owned(),
+            unsaved: AtomicU32::new(0),
+            renewed_at: AtomicI64::new(Utc::now().timestamp_millis()),
+        }
+    }
+
+    /// The import the records are added for.
+    pub fn import_id(&self) -> &str {
+        &self.import_id
+    }
+
+    /// Marks `rows` of the backup as processed.
+    pub async fn advance(&self, rows: u32) {
+        let unsaved = self.unsaved.fetch_add(rows, Ordering::SeqCst) + rows;
+        if unsaved >= PROGRESS_BATCH_SIZE {
+            self.flush().await;
+        }
+    }
+
+    /// Saves the progress that has not been saved yet. Progress is only for
+    /// show, so failing to save it does not stop the import.
+    pub async fn flush(&self) {
+        let unsaved = self.unsaved.swap(0, Ordering::SeqCst);
+        if unsaved == 0 {
+            return;
+        }
+
+        if let Err(e) = self
+            .import_repo
+            .add_progress(&self.import_id, unsaved)
+            .await
+        {
+            warn!(
+                "Could not save progress of import {}: {}",
+                self.import_id, e
+            );
+        }
+        self.renew_claim().await;
+    }
+
+    /// Keeps other workers from taking over the import while it is running.
+    async fn renew_claim(&self) {
+        let now = Utc::now().timestamp_millis();
+        if now - self.renewed_at.load(Ordering::SeqCst) < CLAIM_MILLIS / 3 {
+            return;
+        }
+        self.renewed_at.store(now, Ordering::SeqCst);
+
+        match self
+            .import_repo
+            .claim_import(&self.import_id, instance_id(), now + CLAIM_MILLIS)
+            .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => warn!("Import {} was claimed by another worker", self.import_id),
+            Err(e) => warn!(
+                "Could not renew the claim on import {}: {}",
+                self.import_id, e
+            ),
+        }
+    }
+}
+
+/// A score that may have been added by an earlier import.
+pub(crate) trait ImportedScore {
+    /// The workout or movement of the score
+    fn parent_id(&self) -> &str;
+    fn source_id(&self) -> Option<&str>;
+    fn created_at(&self) -> &str;
+}
+
+impl ImportedScore for WorkoutScoreModel {
+    fn parent_id(&self) -> &str {
+        &self.workout_id
+    }
+
+    fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    fn created_at(&self) -> &str {
+        &self.created_at
+    }
+}
+
+impl ImportedScore for MovementScoreModel {
+    fn parent_id(&self) -> &str {
+        &self.movement_id
+    }
+
+    fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    fn created_at(&self) -> &str {
+        &self.created_at
+    }
+}
+
+/// The scores earlier imports added, looked up at once and matched to the
+/// rows of an import. A score is matched by one row only, rows with the same
+/// date would otherwise all match the same score from before sources were
+/// tracked.
+pub(crate) struct ImportedScores<T> {
+    by_source: HashMap<String, T>,
+    /// Scores from before sources were tracked, by their parent and date
+    by_date: HashMap<(String, String), Vec<T>>,
+}
+
+impl<T: ImportedScore> ImportedScores<T> {
+    pub(crate) fn new(scores: Vec<T>) -> Self {
+        let mut imported = ImportedScores {
+            by_source: HashMap::new(),
+            by_date: HashMap::new(),
+        };
+        for score in scores {
+            match score.source_id() {
+                Some(source_id) => {
+                    imported.by_source.insert(source_id.to_owned(), score);
+                }
+                None => imported
+                    .by_date
+                    .entry((score.parent_id().to_owned(), score.created_at().to_owned()))
+                    .or_default()
+                    .push(score),
+            }
+        }
+
+        imported
+    }
+
+    /// Takes the score that a row of `parent_id` was imported as before.
+    pub(crate) fn take(
+        &mut self,
+        parent_id: &str,
+        source_id: Option<&str>,
+        created_at: Option<&str>,
+    ) -> Option<T> {
+        if let Some(score) = source_id.and_then(|source_id| self.by_source.remove(source_id)) {
+            return Some(score);
+        }
+
+        let scores = self
+            .by_date
+            .get_mut(&(parent_id.to_owned(), created_at?.to_owned()))?;
+        if scores.is_empty() {
+            None
+        } else {
+            Some(scores.remove(0))
+        }
+    }
+}
+
+/// Removes everything an import added and restores the profile fields it
+/// overwrote, unless the user changed the profile since. Scores the import
+/// updated keep their new values.
+pub async fn rollback_import(
+    repositories: &Repositories,
+    user_id: &str,
+    user_email: &str,
+    import_id: &str,
+) -> WebResult<ImportModel> {
+    let import_repo = &repositories.imports;
+    let import = import_repo.get_import_by_id(user_id, import_id).await?;
+    match import.status {
+        ImportStatus::Pending | ImportStatus::Running => {
+            return Err(AppError::Conflict("The import is still running".to_owned()))
+        }
+        ImportStatus::RolledBack => {
+            return Err(AppError::Conflict(
+                "The import has already been rolled back".to_owned(),
+            ))
+        }
+        ImportStatus::Completed | ImportStatus::Failed => {}
+    }
+
+    let user_repo = &repositories.users;
+    let workout_repo = &repositories.workouts;
+    let movement_repo = &repositories.movements;
+    let feed_repo = &repositories.feeds;
+
+    // Scores go first, so the workouts and movements left without scores
+    // can be removed with them
+    let mut rollback = RollbackResult {
+        removed_workout_scores: workout_repo
+            .delete_imported_workout_scores(user_id, import_id)
+            .await?,
+        removed_movement_scores: movement_repo
+            .delete_imported_movement_scores(user_id, import_id)
+            .await?,
+        removed_feeds: feed_repo.delete_imported_feeds(user_id, import_id).await?,
+        ..RollbackResult::default()
+    };
+    (rollback.removed_workouts, rollback.kept_workouts) = workout_repo
+        .delete_imported_workouts(user_id, import_id)
+        .await?;
+    (rollback.removed_movements, rollback.kept_movements) = movement_repo
+        .delete_imported_movements(user_id, import_id)
+        .await?;
+
+    if let (Some(previous), Some(imported)) = (&import.previous_profile, &import.imported_profile) {
+        let user = user_repo.find_user_with_email(user_email).await?;
+        if ProfileSnapshot::from(&user) == *imported {
+            user_repo.restore_profile(user_email, previous).await?;
+            rollback.profile_restored = true;
+        } else {
+            rollback.profile_conflict = true;
+        }
+    }
+
+    import_repo.complete_rollback(import_id, &rollback).await?;
+
+    import_repo.get_import_by_id(user_id, import_id).await
+}
+
+/// Runs an import in the background. The outcome is saved on the import, so
+/// it is only logged here.
+pub fn spawn_import(repositories: Repositories, storages: Storages, import_id: String) {
+    spawn_import_after(repositories, storages, import_id, Duration::ZERO);
+}
+
+fn spawn_import_after(
+    repositories: Repositories,
+    storages: Storages,
+    import_id: String,
+    delay: Duration,
+) {
+    actix_web::rt::spawn(async move {
+        if !delay.is_zero() {
+            actix_web::rt::time::sleep(delay).await;
+        }
+        if let Err(e) = run_import(&repositories, &storages, &import_id).await {
+            error!("Import {} could not be finished: {}", import_id, e);
+        }
+    });
+}
+
+/// Picks up the imports that were pending or running when the server stopped.
+/// Importing is idempotent, so an interrupted import is simply run again.
+/// Imports claimed by another worker are tried once the claim runs out, the
+/// worker may have stopped with the server.
+pub async fn resume_imports(repositories: Repositories, storages: &Storages) -> WebResult<usize> {
+    let import_repo = &repositories.imports;
+    let imports = import_repo.get_unfinished_imports().await?;
+    let count = imports.len();
+    let now = Utc::now().timestamp_millis();
+
+    for import in imports {
+        let wait = match (&import.claimed_by, import.claim_expires_at) {
+            (Some(owner), Some(expires_at)) if owner != instance_id() => {
+                Duration::from_millis(expires_at.saturating_sub(now).max(0) as u64)
+            }
+            _ => Duration::ZERO,
+        };
+        spawn_import_after(
+            repositories.clone(),
+            storages.clone(),
+            import.import_id,
+            wait,
+        );
+    }
+
+    Ok(count)
+}
+
+pub async fn run_import(
+    repositories: &Repositories,
+    storages: &Storages,
+    import_id: &str,
+) -> WebResult<()> {
+    let uploads = storages.uploads.as_ref();
+    let import_repo = &repositories.imports;
+    let expires_at = Utc::now().timestamp_millis() + CLAIM_MILLIS;
+    let import = match import_repo
+        .claim_import(import_id, instance_id(), expires_at)
+        .await?
+    {
+        Some(import) => import,
+        None => {
+            return match import_repo.find_import_by_id(import_id).await? {
+                Some(_) => {
+                    info!("Import {} is done or run by another worker", import_id);
+                    Ok(())
+                }
+                None => Err(AppError::NotFound("Import not found".to_owned())),
+            }
+        }
+    };
+
+    import_repo
+        .set_status(import_id, instance_id(), ImportStatus::Running, None)
+        .await?;
+
+    let result = match import.source.as_str() {
+        "mywod" => import_mywod(repositories, storages, &import).await,
+        source => match source.parse::<LogSource>() {
+            Ok(source) => import_training_log(repositories, uploads, &import, source).await,
+            Err(e) => Err(e),
+        },
+    };
+
+    // The upload is kept until the outcome is saved, an import that is still
+    // running when the server stops is run again from it
+    match result {
+        Ok((total, response, report)) => {
+            import_repo
+                .complete_import(import_id, instance_id(), total, &response, &report)
+                .await?
+        }
+        Err(e) => {
+            import_repo
+                .set_status(
+                    import_id,
+                    instance_id(),
+                    ImportStatus::Failed,
+                    Some(e.to_string()),
+                )
+                .await?
+        }
+    }
+
+    if let Err(e) = delete_payload_file(uploads, import.file_path).await {
+        warn!("Could not remove the upload of import {}: {}", import_id, e);
+    }
+
+    Ok(())
+}
+
+async fn import_mywod(
+    repositories: &Repositories,
+    storages: &Storages,
+    import: &ImportModel,
+) -> WebResult<(u32, MyWodResponse, Vec<RowReport>)> {
+    let import_repo = &repositories.imports;
+    let user_repo = &repositories.users;
+    let workout_repo = &repositories.workouts;
+    let movement_repo = &repositories.movements;
+    let feed_repo = &repositories.feeds;
+    let tracker = ImportTracker::new(repositories, &import.import_id);
+
+    let mywod_data = read_payload_contents(storages.uploads.as_ref(), &import.file_path).await?;
+    let total = (1
+        + mywod_data.workouts.len()
+        + mywod_data.workout_scores.len()
+        + mywod_data.movements.len()
+        + mywod_data.movement_scores.len()
+        + mywod_data.feeds.len()) as u32;
+    import_repo.set_total(&import.import_id, total).await?;
+
+    // A run after an interruption finds the profile the first run changed
+    if import.previous_profile.is_none() {
+        let user = user_repo.find_user_with_email(&import.user_email).await?;
+        import_repo
+            .set_previous_profile(&import.import_id, &ProfileSnapshot::from(&user))
+            .await?;
+    }
+    let user_updated = mywod::save_athlete(
+        user_repo.as_ref(),
+        storages.avatars.as_ref(),
+        &import.user_id,
+        &import.user_email,
+        mywod_data.athlete,
+    )
+    .await?;
+    let user = user_repo.find_user_with_email(&import.user_email).await?;
+    import_repo
+        .set_imported_profile(&import.import_id, &ProfileSnapshot::from(&user))
+        .await?;
+    tracker.advance(1).await;
+
+    let workouts = mywod::save_workouts_and_scores(
+        workout_repo.as_ref(),
+        mywod_data.workouts,
+        &mywod_data.workout_scores,
+        &import.user_id,
+        false,
+        Some(&tracker),
+    )
+    .await?;
+
+    let movements = mywod::save_movements_and_scores(
+        movement_repo.as_ref(),
+        &mywod_data.movements,
+        &mywod_data.movement_scores,
+        &import.user_id,
+        false,
+        Some(&tracker),
+    )
+    .await?;
+
+    let feeds = mywod::save_feeds(
+        feed_repo.as_ref(),
+        &mywod_data.feeds,
+        &import.user_id,
+        false,
+        Some(&tracker),
+    )
+    .await?;
+    tracker.flush().await;
+
+    let response = MyWodResponse {
+        user_updated,
+        added_workouts: workouts.records.added,
+        updated_workouts: workouts.records.updated,
+        skipped_workouts: workouts.records.skipped,
+        added_workout_scores: workouts.scores.added,
+        updated_workout_scores: workouts.scores.updated,
+        skipped_workout_scores: workouts.scores.skipped,
+        added_movements: movements.records.added,
+        skipped_movements: movements.records.skipped,
+        added_movement_scores: movements.scores.added,
+        updated_movement_scores: movements.scores.updated,
+        skipped_movement_scores: movements.scores.skipped,
+        added_feeds: feeds.records.added,
+        skipped_feeds: feeds.records.skipped,
+    };
+
+    let mut report = mywod_data.unreadable;
+    report.extend(workouts.report);
+    report.extend(movements.report);
+    report.extend(feeds.report);
+
+    Ok((total, response, report))
+}
+
+async fn import_training_log(
+    repositories: &Repositories,
+    uploads: &dyn BlobStorage,
+    import: &ImportModel,
+    source: LogSource,
+) -> WebResult<(u32, MyWodResponse, Vec<RowReport>)> {
+    let import_repo = &repositories.imports;
+    let user_repo = &repositories.users;
+    let workout_repo = &repositories.workouts;
+    let movement_repo = &repositories.movements;
+    let tracker = ImportTracker::new(repositories, &import.import_id);
+
+    let unit_system = user_repo.get_unit_system(&import.user_email).await?;
+    let contents = read_payload_file(uploads, &import.file_path).await?;
+    let log = parse_training_log(contents.as_slice(), source, unit_system)?;
+    let total = log.rows;
+    import_repo.set_total(&import.import_id, total).await?;
+    // Rows that could not be mapped are done, as are the rows merged into
+    // the score of another row
+    let mapped = (log.workout_scores.len() + log.movement_scores.len()) as u32;
+    tracker.advance(total.saturating_sub(mapped)).await;
+
+    let workouts = training_log::save_workout_scores(
+        workout_repo.as_ref(),
+        log.workout_scores,
+        &import.user_id,
+        Some(&tracker),
+    )
+    .await?;
+
+    let movements = training_log::save_movement_scores(
+        movement_repo.as_ref(),
+        log.movement_scores,
+        &import.user_id,
+        Some(&tracker),
+    )
+    .await?;
+    tracker.flush().await;
+
+    let response = MyWodResponse {
+        user_updated: false,
+        added_workouts: workouts.records.added,
+        updated_workouts: workouts.records.updated,
+        skipped_workouts: workouts.records.skipped,
+        added_workout_scores: workouts.scores.added,
+        updated_workout_scores: workouts.scores.updated,
+        skipped_workout_scores: workouts.scores.skipped,
+        added_movements: movements.records.added,
+        skipped_movements: movements.records.skipped,
+        added_movement_scores: movements.scores.added,
+        updated_movement_scores: movements.scores.updated,
+        skipped_movement_scores: movements.scores.skipped,
+        added_feeds: 0,
+        skipped_feeds: 0,
+    };
+
+    let mut report = log.report;
+    report.extend(workouts.report);
+    report.extend(movements.report);
+
+    Ok((total, response, report))
+}
@@ -0,0 +1,214 @@
+use crate::errors::{AppError, WebResult};
+use crate::repositories::Repositories;
+use crate::storage::{BlobInfo, Storages};
+use crate::utils::avatar::avatar_url_for_file;
+use crate::utils::mywod::upload_key;
+
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What a cleanup removed, by key or file name.
+#[derive(Debug, Default, PartialEq)]
+pub struct CleanupReport {
+    /// Uploads that were never imported, e.g. because the server stopped
+    pub uploads: Vec<String>,
+    /// Copies of myWOD backups made while reading or exporting them
+    pub temporary_files: Vec<String>,
+    /// Avatars and thumbnails that no user has anymore
+    pub avatars: Vec<String>,
+}
+
+/// The blobs last modified before `cutoff` that are not in use.
+fn unused_blobs(
+    blobs: Vec<BlobInfo>,
+    cutoff: DateTime<Utc>,
+    in_use: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    blobs
+        .into_iter()
+        .filter(|blob| blob.modified < cutoff && !in_use(&blob.key))
+        .map(|blob| blob.key)
+        .collect()
+}
+
+/// Removes uploads that no unfinished import is waiting for. An import that
+/// is done removes its upload itself, unless the server stopped before it
+/// could.
+async fn remove_stale_uploads(
+    repositories: Repositories,
+    storages: &Storages,
+    cutoff: DateTime<Utc>,
+) -> WebResult<Vec<String>> {
+    let waiting: HashSet<String> = repositories
+        .imports
+        .get_unfinished_imports()
+        .await?
+        .iter()
+        .map(|import| upload_key(&import.file_path).to_owned())
+        .collect();
+
+    let storage = &storages.uploads;
+    let stale = unused_blobs(storage.list().await?, cutoff, |key| waiting.contains(key));
+    for key in &stale {
+        storage.delete(key).await?;
+        info!("Removed stale upload {}", key);
+    }
+
+    Ok(stale)
+}
+
+/// Removes the avatars and thumbnails of avatars that neither a user nor the
+/// profile an import would restore when rolled back has.
+async fn remove_orphaned_avatars(
+    repositories: Repositories,
+    storages: &Storages,
+    cutoff: DateTime<Utc>,
+) -> WebResult<Vec<String>> {
+    let mut referenced = repositories.users.get_avatar_urls().await?;
+    referenced.extend(repositories.imports.get_snapshot_avatar_urls().await?);
+
+    let storage = &storages.avatars;
+    // Files that are not avatars are left alone
+    let orphaned = unused_blobs(storage.list().await?, cutoff, |key| {
+        avatar_url_for_file(key).is_none_or(|url| referenced.contains(&url))
+    });
+    for key in &orphaned {
+        storage.delete(key).await?;
+        info!("Removed orphaned avatar {}", key);
+    }
+
+    Ok(orphaned)
+}
+
+/// Removes the copies of myWOD backups in `dir` that were modified before
+/// `cutoff`, which are named after a UUID.
+fn remove_stale_temporary_files(dir: &Path, cutoff: DateTime<Utc>) -> io::Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_backup_copy = path
+            .extension()
+            .is_some_and(|extension| extension == "mywod")
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| uuid::Uuid::parse_str(stem).is_ok());
+        if !is_backup_copy {
+            continue;
+        }
+
+        let modified: DateTime<Utc> = entry.metadata()?.modified()?.into();
+        if modified < cutoff {
+            fs::remove_file(&path)?;
+            let filename = path.to_string_lossy().to_string();
+            info!("Removed stale temporary file {}", filename);
+            removed.push(filename);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Removes the uploads, temporary files and avatars that were left behind
+/// and have not been touched for `max_age`.
+pub async fn clean_up(
+    repositories: Repositories,
+    storages: &Storages,
+    max_age: Duration,
+) -> WebResult<CleanupReport> {
+    let cutoff = Utc::now() - max_age;
+    let temp_dir = std::env::temp_dir();
+
+    let temporary_files = web::block(move || remove_stale_temporary_files(&temp_dir, cutoff))
+        .await
+        .map_err(|_| AppError::Internal("Removing temporary files failed".to_owned()))?
+        .map_err(|e| AppError::Internal(format!("Could not remove temporary files: {}", e)))?;
+
+    Ok(CleanupReport {
+        uploads: remove_stale_uploads(repositories.clone(), storages, cutoff).await?,
+        temporary_files,
+        avatars: remove_orphaned_avatars(repositories, storages, cutoff).await?,
+    })
+}
+
+/// Cleans up and logs what was removed. Cleaning up is housekeeping, so
+/// failing to do so is only logged as well.
+pub async fn run_cleanup(repositories: Repositories, storages: &Storages, max_age: Duration) {
+    match clean_up(repositories, storages, max_age).await {
+        Ok(report) => info!(
+            "Cleaned up {} stale uploads, {} temporary files and {} orphaned avatars",
+            report.uploads.len(),
+            report.temporary_files.len(),
+            report.avatars.len()
+        ),
+        Err(e) => error!("Could not clean up files: {}", e),
+    }
+}
+
+/// Cleans up in the background every `interval`.
+pub fn spawn_janitor(
+    repositories: Repositories,
+    storages: Storages,
+    interval: Duration,
+    max_age: Duration,
+) {
+    let interval = match interval.to_std() {
+        Ok(interval) if !interval.is_zero() => interval,
+        _ => return,
+    };
+
+    actix_web::rt::spawn(async move {
+        loop {
+            actix_web::rt::time::sleep(interval).await;
+            run_cleanup(repositories.clone(), &storages, max_age).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_blobs() {
+        let now = Utc::now();
+        let blob = |key: &str, age: i64| BlobInfo {
+            key: key.to_owned(),
+            modified: now - Duration::hours(age),
+        };
+        let blobs = vec![
+            blob("old-unused", 48),
+            blob("old-used", 48),
+            blob("new-unused", 1),
+        ];
+
+        let unused = unused_blobs(blobs, now - Duration::hours(24), |key| key == "old-used");
+        assert_eq!(unused, vec!["old-unused"]);
+    }
+
+    #[test]
+    fn test_remove_stale_temporary_files() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let backup_copy = dir.join(format!("{}.mywod", uuid::Uuid::new_v4()));
+        let other_file = dir.join("data.mywod");
+        fs::write(&backup_copy, b"SQLite format 3\0").unwrap();
+        fs::write(&other_file, b"SQLite format 3\0").unwrap();
+
+        let removed = remove_stale_temporary_files(&dir, Utc::now() - Duration::hours(1)).unwrap();
+        assert!(removed.is_empty());
+        assert!(backup_copy.exists());
+
+        let removed = remove_stale_temporary_files(&dir, Utc::now() + Duration::hours(1)).unwrap();
+        assert_eq!(removed, vec![backup_copy.to_string_lossy().to_string()]);
+        assert!(!backup_copy.exists());
+        assert!(other_file.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1 +1,4 @@
+pub mod imports;
+pub mod janitor;
 pub mod mywod;
+pub mod training_log;
@@ -1,21 +1,59 @@
-use crate::errors::WebResult;
-use crate::models::movement::CreateMovement;
-use crate::models::mywod::{Athlete, CustomWOD, Movement, MovementSession, MyWOD};
+use crate::errors::{AppError, WebResult};
+use crate::models::feed::{is_valid_feed_url, CreateFeed};
+use crate::models::movement::{
+    CreateMovement, CreateMovementScore, MovementScoreModel, UpdateMovementScore,
+};
+use crate::models::mywod::{
+    Athlete, CustomWOD, Feed, ImportSummary, Movement, MovementSession, MyWOD, MyWodData,
+    RowOutcome, RowReport,
+};
 use crate::models::user::UpdateUser;
-use crate::models::workout::CreateWorkout;
-use crate::repositories::{MovementRepository, UserRepository, WorkoutRepository};
+use crate::models::workout::{
+    CreateWorkout, CreateWorkoutScore, UpdateWorkout, UpdateWorkoutScore, WorkoutModel,
+    WorkoutScoreModel,
+};
+use crate::repositories::{FeedRepository, MovementRepository, UserRepository, WorkoutRepository};
+use crate::services::imports::{ImportTracker, ImportedScores};
+use crate::storage::BlobStorage;
+use crate::utils::avatar::{process_avatar, read_avatar, save_avatar};
 use crate::utils::mywod::{
-    get_scores_for_movement, map_movement_measurement, map_workout_measurement,
-    parse_workout_score, save_avatar,
+    date_report, get_scores_for_movement, map_movement, map_workout_measurement, parse_score_value,
+    parse_workout_score, source_id, to_athlete, to_custom_wod, to_feed, to_movement,
+    to_movement_session, to_mywod,
 };
+use actix_web::web;
+use std::collections::HashMap;
 
 pub async fn save_athlete(
-    user_repo: UserRepository,
+    user_repo: &dyn UserRepository,
+    avatars: &dyn BlobStorage,
     user_id: &str,
     user_email: &str,
     athlete: Athlete,
 ) -> WebResult<bool> {
-    let avatar_url = save_avatar(user_id, athlete.avatar).unwrap();
+    // Athletes without a picture have an empty avatar blob, a picture that is
+    // not a valid image leaves the current avatar alone
+    let avatar_url = if athlete.avatar.is_empty() {
+        None
+    } else {
+        let avatar = athlete.avatar;
+        // Decoding and resizing images is too slow for the async runtime
+        let processed = web::block(move || process_avatar(&avatar))
+            .await
+            .map_err(|_| AppError::Internal("Processing avatar failed".to_owned()))
+            .and_then(|processed| processed);
+        let saved = match processed {
+            Ok(avatar) => save_avatar(avatars, user_id, avatar).await,
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok(avatar_url) => Some(avatar_url),
+            Err(e) => {
+                warn!("Skipping myWOD avatar of user {}: {}", user_id, e);
+                None
+            }
+        }
+    };
 
     let user_update = UpdateUser {
         password: None,
@@ -25,7 +63,10 @@ pub async fn save_athlete(
         height: Some(athlete.height),
         weight: Some(athlete.weight),
         box_name: Some(athlete.box_name.trim().to_owned()),
-        avatar_url: Some(avatar_url),
+        avatar_url,
+        avatar_privacy: None,
+        unit_system: Some(athlete.unit_system),
+        gender: athlete.gender,
     };
 
     let _ = user_repo
@@ -35,104 +76,791 @@ pub async fn save_athlete(
     Ok(true)
 }
 
+/// Adds the workouts and scores of a backup, or only counts what would be
+/// added when `dry_run` is set. The `tracker` is advanced for every row.
 pub async fn save_workouts_and_scores(
-    workout_repo: WorkoutRepository,
+    workout_repo: &dyn WorkoutRepository,
     workouts: Vec<CustomWOD>,
     workout_scores: &[MyWOD],
     user_id: &str,
-) -> WebResult<(u32, u32)> {
-    let mut added_workouts = 0u32;
-    let mut added_scores = 0u32;
+    dry_run: bool,
+    tracker: Option<&ImportTracker>,
+) -> WebResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let import_id = tracker.map(|tracker| tracker.import_id().to_owned());
+    // New workouts and scores are added in bulk, as backups can have thousands
+    let mut new_workouts: Vec<(CustomWOD, CreateWorkout)> = Vec::new();
+    let mut new_scores: Vec<(&MyWOD, String, CreateWorkoutScore)> = Vec::new();
+    let mut workouts_by_name: HashMap<String, WorkoutModel> = HashMap::new();
 
     for workout in workouts {
+        if let Some(tracker) = tracker {
+            tracker.advance(1).await;
+        }
         debug!("Processing workout '{}'", workout.title);
+        let workout_source = source_id(&workout.primary_client_id, workout.primary_record_id);
+
+        if let Some(existing) = workout_repo
+            .find_workout_by_source(user_id, &workout_source)
+            .await?
+        {
+            if existing.name == workout.title && existing.description == workout.description {
+                summary.records.skipped += 1;
+                continue;
+            }
+            if dry_run {
+                summary.records.updated += 1;
+                continue;
+            }
+
+            let workout_update = UpdateWorkout {
+                name: Some(workout.title.to_owned()),
+                description: Some(workout.description.to_owned()),
+                measurement: None,
+                is_public: None,
+            };
+            match workout_repo
+                .update_workout(user_id, &existing.workout_id, workout_update)
+                .await
+            {
+                Ok(_) => summary.records.updated += 1,
+                Err(e) => {
+                    warn!(
+                        "Could not update workout from backup \"{}\". Error: {}",
+                        workout.title,
+                        e.to_string()
+                    );
+                    summary.report.push(RowReport::new(
+                        &workout,
+                        RowOutcome::Failed,
+                        &e.to_string(),
+                    ));
+                }
+            }
+            continue;
+        }
+
+        // Imported before sources were tracked, or the name is taken by a public workout
+        if summary.added_names.contains(&workout.title)
+            || new_workouts
+                .iter()
+                .any(|(new, _)| new.title == workout.title)
+            || workout_repo
+                .find_workout_by_name(user_id, &workout.title)
+                .await?
+                .is_some()
+        {
+            summary.records.skipped += 1;
+            summary.report.push(RowReport::new(
+                &workout,
+                RowOutcome::Skipped,
+                &format!("A workout named '{}' already exists", workout.title),
+            ));
+            continue;
+        }
+        if dry_run {
+            summary.records.added += 1;
+            summary.added_names.push(workout.title);
+            continue;
+        }
+
         let new_workout = CreateWorkout {
             name: workout.title.to_owned(),
-            description: workout.description,
+            description: workout.description.to_owned(),
             measurement: map_workout_measurement(workout.score_type.as_ref()),
             is_public: false,
+            source_id: Some(workout_source),
+            import_id: import_id.clone(),
         };
-        let created_workout = workout_repo.create_workout(user_id, new_workout).await;
+        new_workouts.push((workout, new_workout));
+    }
 
-        match created_workout {
-            Ok(_) => added_workouts += 1,
-            Err(e) => warn!(
-                "Could not create new workout from backup \"{}\". Error: {}",
-                workout.title,
+    let (rows, new_workouts): (Vec<CustomWOD>, Vec<CreateWorkout>) =
+        new_workouts.into_iter().unzip();
+    match workout_repo.create_workouts(user_id, new_workouts).await {
+        Ok(created) => {
+            for workout in created {
+                summary.records.added += 1;
+                summary.added_names.push(workout.name.to_owned());
+                workouts_by_name.insert(workout.name.to_owned(), workout);
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Could not create new workouts from backup. Error: {}",
                 e.to_string()
-            ),
+            );
+            for workout in &rows {
+                summary
+                    .report
+                    .push(RowReport::new(workout, RowOutcome::Failed, &e.to_string()));
+            }
         }
     }
 
-    // TODO: Fix duplication with workout scores
-    for score in workout_scores {
+    // Scores imported before are looked up at once for the whole backup
+    let parsed_scores: Vec<CreateWorkoutScore> =
+        workout_scores.iter().map(parse_workout_score).collect();
+    let mut imported = ImportedScores::new(
+        workout_repo
+            .find_imported_workout_scores(user_id, &parsed_scores.iter().collect::<Vec<_>>())
+            .await?,
+    );
+
+    for (score, mut score_data) in workout_scores.iter().zip(parsed_scores) {
+        if let Some(tracker) = tracker {
+            tracker.advance(1).await;
+        }
+        if let Err(e) = parse_score_value(score) {
+            summary.report.push(RowReport::new(
+                score,
+                RowOutcome::Altered,
+                &format!("{}, the score is imported as 0", e),
+            ));
+        }
+        summary.report.extend(date_report(score, &score.date));
+
         let workout_title = score.title.to_owned();
         let workout_description = score.description.to_owned();
 
-        let mut workout = workout_repo
-            .find_workout_by_name(user_id, &workout_title)
-            .await?;
+        let mut workout = match workouts_by_name.get(&workout_title) {
+            Some(workout) => Some(workout.clone()),
+            None => {
+                workout_repo
+                    .find_workout_by_name(user_id, &workout_title)
+                    .await?
+            }
+        };
 
         if workout.is_none() {
+            if dry_run {
+                // The workout does not exist yet, so neither does the score
+                if !summary.added_names.contains(&workout_title) {
+                    summary.records.added += 1;
+                    summary.added_names.push(workout_title);
+                }
+                summary.scores.added += 1;
+                continue;
+            }
+
             let new_workout = CreateWorkout {
-                name: workout_title,
+                name: workout_title.to_owned(),
                 description: workout_description,
                 measurement: map_workout_measurement(&score.score_type),
                 is_public: false,
+                source_id: None,
+                import_id: import_id.clone(),
             };
             workout = Some(workout_repo.create_workout(user_id, new_workout).await?);
-            added_workouts += 1;
+            summary.records.added += 1;
+            summary.added_names.push(workout_title);
+        }
+        if let Some(workout) = &workout {
+            workouts_by_name.insert(workout.name.to_owned(), workout.clone());
         }
 
         if let Some(workout) = workout {
             debug!("Processing scores for '{}", workout.name);
-            let score_data = parse_workout_score(score);
-            let added_score = workout_repo
-                .create_workout_score(user_id, &workout, score_data)
-                .await;
-            if added_score.is_ok() {
-                added_scores += 1;
+            score_data.import_id = import_id.clone();
+            let existing = imported.take(
+                &workout.workout_id,
+                score_data.source_id.as_deref(),
+                score_data.created_at.as_deref(),
+            );
+
+            match existing {
+                Some(existing) => {
+                    let changed = workout_score_changed(&existing, &score_data);
+                    // Scores from before sources were tracked get their source
+                    if !changed && (existing.source_id == score_data.source_id || dry_run) {
+                        summary.scores.skipped += 1;
+                        continue;
+                    }
+                    if dry_run {
+                        summary.scores.updated += 1;
+                        continue;
+                    }
+
+                    let updated_score = workout_repo
+                        .update_workout_score_by_id(
+                            user_id,
+                            &workout.workout_id,
+                            &existing.workout_score_id,
+                            UpdateWorkoutScore::from(score_data),
+                        )
+                        .await;
+                    match updated_score {
+                        Ok(_) if changed => summary.scores.updated += 1,
+                        Ok(_) => summary.scores.skipped += 1,
+                        Err(e) => summary.report.push(RowReport::new(
+                            score,
+                            RowOutcome::Failed,
+                            &e.to_string(),
+                        )),
+                    }
+                }
+                None if dry_run => summary.scores.added += 1,
+                None => new_scores.push((score, workout.workout_id.to_owned(), score_data)),
+            }
+        }
+    }
+
+    let (rows, new_scores): (Vec<&MyWOD>, Vec<(String, CreateWorkoutScore)>) = new_scores
+        .into_iter()
+        .map(|(row, workout_id, score)| (row, (workout_id, score)))
+        .unzip();
+    match workout_repo
+        .create_workout_scores(user_id, new_scores)
+        .await
+    {
+        Ok(created) => summary.scores.added += created.len() as u32,
+        Err(e) => {
+            for score in rows {
+                summary
+                    .report
+                    .push(RowReport::new(score, RowOutcome::Failed, &e.to_string()));
             }
         }
     }
 
-    Ok((added_workouts, added_scores))
+    Ok(summary)
 }
 
+/// Adds the movements and scores of a backup, or only counts what would be
+/// added when `dry_run` is set. The `tracker` is advanced for every row.
 pub async fn save_movements_and_scores(
-    movement_repo: MovementRepository,
+    movement_repo: &dyn MovementRepository,
     movements: &[Movement],
     movement_scores: &[MovementSession],
     user_id: &str,
-) -> WebResult<(u32, u32)> {
-    let mut added_movements = 0u32;
-    let mut added_movement_scores = 0u32;
+    dry_run: bool,
+    tracker: Option<&ImportTracker>,
+) -> WebResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let import_id = tracker.map(|tracker| tracker.import_id().to_owned());
 
     for m in movements {
-        let new_movement = CreateMovement {
-            name: m.name.to_owned(),
-            measurement: map_movement_measurement(m.score_type),
-            is_public: false,
+        let (scores, report) = get_scores_for_movement(m, movement_scores);
+        let failed = report
+            .iter()
+            .filter(|row| row.outcome == RowOutcome::Failed)
+            .count();
+        if let Some(tracker) = tracker {
+            tracker.advance(1 + (scores.len() + failed) as u32).await;
+        }
+        summary.report.extend(report);
+
+        let movement_source = source_id(&m.primary_client_id, m.primary_record_id);
+        let existing = match movement_repo
+            .find_movement_by_source(user_id, &movement_source)
+            .await?
+        {
+            Some(existing) => Some(existing),
+            // Imported before sources were tracked, or a public movement
+            None => {
+                let by_name = movement_repo
+                    .find_movement_by_name(user_id, &m.name)
+                    .await?;
+                if by_name.is_some() {
+                    summary.report.push(RowReport::new(
+                        m,
+                        RowOutcome::Skipped,
+                        &format!(
+                            "A movement named '{}' already exists, the scores are added to it",
+                            m.name
+                        ),
+                    ));
+                }
+                by_name
+            }
+        };
+
+        let movement = match existing {
+            Some(existing) => {
+                summary.records.skipped += 1;
+                existing
+            }
+            None if dry_run => {
+                summary.records.added += 1;
+                summary.added_names.push(m.name.to_owned());
+                summary.scores.added += scores.len() as u32;
+                continue;
+            }
+            None => {
+                let new_movement = CreateMovement {
+                    name: m.name.to_owned(),
+                    measurement: map_movement(m.score_type, &m.name),
+                    is_public: false,
+                    source_id: Some(movement_source),
+                    import_id: import_id.clone(),
+                };
+                match movement_repo.create_movement(user_id, new_movement).await {
+                    Ok(created_movement) => {
+                        summary.records.added += 1;
+                        summary.added_names.push(m.name.to_owned());
+                        created_movement
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Could not add movement {}. Error: {}",
+                            m.name,
+                            e.to_string()
+                        );
+                        summary
+                            .report
+                            .push(RowReport::new(m, RowOutcome::Failed, &e.to_string()));
+                        continue;
+                    }
+                }
+            }
         };
-        let created_movement = movement_repo.create_movement(user_id, new_movement).await;
-        match created_movement {
-            Ok(created_movement) => {
-                added_movements += 1;
-                let all_scores = get_scores_for_movement(m, movement_scores);
-                for score in all_scores {
-                    movement_repo
-                        .create_movement_score(user_id, &created_movement, score)
-                        .await?;
-                    added_movement_scores += 1;
+
+        let mut imported = ImportedScores::new(
+            movement_repo
+                .find_imported_movement_scores(user_id, &scores.iter().collect::<Vec<_>>())
+                .await?,
+        );
+        let mut new_scores: Vec<CreateMovementScore> = Vec::new();
+        for mut score in scores {
+            score.import_id = import_id.clone();
+            let existing = imported.take(
+                &movement.movement_id,
+                score.source_id.as_deref(),
+                score.created_at.as_deref(),
+            );
+
+            match existing {
+                Some(existing) => {
+                    let changed = movement_score_changed(&existing, &score);
+                    // Scores from before sources were tracked get their source
+                    if !changed && (existing.source_id == score.source_id || dry_run) {
+                        summary.scores.skipped += 1;
+                        continue;
+                    }
+                    if dry_run {
+                        summary.scores.updated += 1;
+                        continue;
+                    }
+
+                    let updated_score = movement_repo
+                        .update_movement_score_by_id(
+                            user_id,
+                            &movement.movement_id,
+                            &existing.movement_score_id,
+                            UpdateMovementScore::from(score),
+                        )
+                        .await;
+                    match updated_score {
+                        Ok(_) if changed => summary.scores.updated += 1,
+                        Ok(_) => summary.scores.skipped += 1,
+                        Err(e) => summary.report.push(RowReport::new(
+                            m,
+                            RowOutcome::Failed,
+                            &e.to_string(),
+                        )),
+                    }
                 }
+                None if dry_run => summary.scores.added += 1,
+                None => new_scores.push(score),
             }
-            Err(e) => warn!(
-                "Could not add movement {}. Error: {}",
-                m.name,
-                e.to_string()
-            ),
+        }
+
+        let scores_count = new_scores.len();
+        match movement_repo
+            .create_movement_scores(user_id, &movement, new_scores)
+            .await
+        {
+            Ok(created) => summary.scores.added += created.len() as u32,
+            Err(e) => summary.report.push(RowReport::new(
+                m,
+                RowOutcome::Failed,
+                &format!("Could not add {} scores: {}", scores_count, e),
+            )),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Subscribes the user to the box feeds of a backup, or only counts what
+/// would be added when `dry_run` is set. The `tracker` is advanced for every row.
+pub async fn save_feeds(
+    feed_repo: &dyn FeedRepository,
+    feeds: &[Feed],
+    user_id: &str,
+    dry_run: bool,
+    tracker: Option<&ImportTracker>,
+) -> WebResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let import_id = tracker.map(|tracker| tracker.import_id().to_owned());
+
+    for feed in feeds {
+        if let Some(tracker) = tracker {
+            tracker.advance(1).await;
+        }
+        let url = feed.url.trim();
+
+        if !is_valid_feed_url(url) {
+            summary.records.skipped += 1;
+            summary.report.push(RowReport::new(
+                feed,
+                RowOutcome::Skipped,
+                &format!("'{}' is not the url of a web feed", url),
+            ));
+            continue;
+        }
+        if summary.added_names.iter().any(|added| added == url)
+            || feed_repo.find_feed_by_url(user_id, url).await?.is_some()
+        {
+            summary.records.skipped += 1;
+            continue;
+        }
+        if dry_run {
+            summary.records.added += 1;
+            summary.added_names.push(url.to_owned());
+            continue;
+        }
+
+        let new_feed = CreateFeed {
+            title: feed.title.to_owned(),
+            url: url.to_owned(),
+            source_id: Some(source_id(&feed.primary_client_id, feed.primary_record_id)),
+            import_id: import_id.clone(),
+        };
+        match feed_repo.create_feed(user_id, new_feed).await {
+            Ok(_) => {
+                summary.records.added += 1;
+                summary.added_names.push(url.to_owned());
+            }
+            Err(e) => summary
+                .report
+                .push(RowReport::new(feed, RowOutcome::Failed, &e.to_string())),
         }
     }
 
-    Ok((added_movements, added_movement_scores))
+    Ok(summary)
+}
+
+/// Gathers everything of the user that a myWOD backup can hold. Scores of
+/// public workouts and movements are included, the records themselves are
+/// only included when the user created them.
+pub async fn export_data(
+    user_repo: &dyn UserRepository,
+    workout_repo: &dyn WorkoutRepository,
+    movement_repo: &dyn MovementRepository,
+    feed_repo: &dyn FeedRepository,
+    avatars: &dyn BlobStorage,
+    user_email: &str,
+    user_id: &str,
+) -> WebResult<MyWodData> {
+    let user = user_repo.find_user_with_email(user_email).await?;
+    let avatar = read_avatar(avatars, &user.avatar_url).await?;
+
+    let workouts = workout_repo.get_workouts(user_id).await?;
+    let workout_scores = workout_repo.get_workout_scores_for_user(user_id).await?;
+    let workout_scores = workout_scores
+        .iter()
+        .filter_map(|score| {
+            workouts
+                .iter()
+                .find(|w| w.workout_id == score.workout_id)
+                .map(|workout| to_mywod(workout, score))
+        })
+        .collect();
+    let workouts = workouts
+        .iter()
+        .filter(|w| w.user_id == user_id)
+        .map(to_custom_wod)
+        .collect();
+
+    let movement_models = movement_repo.get_movements(user_id).await?;
+    let movement_score_models = movement_repo.get_movement_scores_for_user(user_id).await?;
+    let mut movements: Vec<Movement> = Vec::new();
+    let mut movement_scores: Vec<MovementSession> = Vec::new();
+
+    for model in &movement_models {
+        let scores: Vec<&MovementScoreModel> = movement_score_models
+            .iter()
+            .filter(|score| score.movement_id == model.movement_id)
+            .collect();
+        if model.user_id != user_id && scores.is_empty() {
+            continue;
+        }
+        let movement = match to_movement(model) {
+            Some(movement) => movement,
+            None => continue,
+        };
+
+        movement_scores.extend(
+            scores
+                .into_iter()
+                .map(|score| to_movement_session(&movement, score)),
+        );
+        movements.push(movement);
+    }
+
+    let feeds = feed_repo
+        .get_feeds(user_id)
+        .await?
+        .iter()
+        .map(to_feed)
+        .collect();
+
+    Ok(MyWodData {
+        athlete: to_athlete(&user, avatar),
+        workouts,
+        movements,
+        movement_scores,
+        workout_scores,
+        feeds,
+        unreadable: Vec::new(),
+    })
+}
+
+/// Whether a workout score was changed in the source since it was imported.
+pub(crate) fn workout_score_changed(
+    existing: &WorkoutScoreModel,
+    imported: &CreateWorkoutScore,
+) -> bool {
+    existing.score != imported.score
+        || existing.rx != imported.rx
+        || existing.notes != imported.notes
+        || existing.personal_record != imported.personal_record
+}
+
+/// Whether a movement score was changed in the source since it was imported,
+/// comparing everything the update from `imported` would write.
+pub(crate) fn movement_score_changed(
+    existing: &MovementScoreModel,
+    imported: &CreateMovementScore,
+) -> bool {
+    // Values the import leaves out are kept as they are
+    fn differs<T: PartialEq>(existing: &Option<T>, imported: &Option<T>) -> bool {
+        imported.is_some() && imported != existing
+    }
+
+    let top_set_changed = imported.set_details.is_empty()
+        && (differs(&Some(existing.score), &imported.score)
+            || existing.sets != imported.sets
+            || existing.reps != imported.reps);
+
+    top_set_changed
+        || existing.set_details != imported.set_details
+        || differs(&existing.unit, &imported.unit)
+        || differs(&existing.distance, &imported.distance)
+        || differs(&existing.distance_unit, &imported.distance_unit)
+        || differs(&existing.duration, &imported.duration)
+        || existing.notes != imported.notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::movement::MovementSet;
+    use crate::models::unit::Unit;
+    use crate::repositories::sqlite::SqliteWorkoutRepository;
+    use crate::repositories::SqliteDatabase;
+
+    fn workout_score(score: f64, rx: bool, notes: &str) -> WorkoutScoreModel {
+        WorkoutScoreModel {
+            workout_score_id: "workout_score_id".to_owned(),
+            workout_id: "workout_id".to_owned(),
+            user_id: "user_id".to_owned(),
+            score,
+            unit: None,
+            rx,
+            notes: notes.to_owned(),
+            personal_record: false,
+            source_id: Some("mywod:client-id:1".to_owned()),
+            import_id: None,
+            created_at: "2017-01-14T00:00:00+00:00".to_owned(),
+            updated_at: "2017-01-14T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    fn imported_workout_score(score: f64, rx: bool, notes: &str) -> CreateWorkoutScore {
+        CreateWorkoutScore {
+            score,
+            unit: None,
+            rx,
+            notes: notes.to_owned(),
+            personal_record: false,
+            created_at: Some("2017-01-14T00:00:00+00:00".to_owned()),
+            source_id: Some("mywod:client-id:1".to_owned()),
+            import_id: None,
+        }
+    }
+
+    fn mywod_score(record_id: i32, score: &str) -> MyWOD {
+        MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: record_id,
+            title: "Fran".to_owned(),
+            date: "2017-01-14".to_owned(),
+            score_type: "Time".to_owned(),
+            score: score.to_owned(),
+            as_prescribed: 1,
+            personal_record: 0,
+            description: "".to_owned(),
+            notes: "".to_owned(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_scores_are_matched_once() {
+        let repository = SqliteWorkoutRepository {
+            database: SqliteDatabase::open_in_memory().unwrap(),
+        };
+        let fran: CreateWorkout = serde_json::from_value(serde_json::json!({
+            "name": "Fran",
+            "description": "",
+            "measurement": "time",
+        }))
+        .unwrap();
+        let fran = repository.create_workout("user", fran).await.unwrap();
+        // Two scores on the same day, imported before sources were tracked
+        for score in [180.0, 200.0].iter().copied() {
+            let mut legacy = imported_workout_score(score, true, "");
+            legacy.source_id = None;
+            repository
+                .create_workout_score("user", &fran, legacy)
+                .await
+                .unwrap();
+        }
+
+        let rows = vec![mywod_score(1, "3:00"), mywod_score(2, "3:20")];
+        let summary = save_workouts_and_scores(&repository, vec![], &rows, "user", false, None)
+            .await
+            .unwrap();
+        assert_eq!(summary.scores.added, 0);
+
+        let mut sources: Vec<Option<String>> = repository
+            .get_workout_scores_for_workout("user", &fran)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|score| score.source_id)
+            .collect();
+        sources.sort();
+        assert_eq!(
+            sources,
+            vec![
+                Some("mywod:client-id:1".to_owned()),
+                Some("mywod:client-id:2".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workout_score_changed() {
+        let existing = workout_score(2505.0, true, "Bar MU");
+        assert!(!workout_score_changed(
+            &existing,
+            &imported_workout_score(2505.0, true, "Bar MU")
+        ));
+        assert!(workout_score_changed(
+            &existing,
+            &imported_workout_score(2510.0, true, "Bar MU")
+        ));
+        assert!(workout_score_changed(
+            &existing,
+            &imported_workout_score(2505.0, false, "Bar MU")
+        ));
+        assert!(workout_score_changed(
+            &existing,
+            &imported_workout_score(2505.0, true, "Ring MU")
+        ));
+
+        let mut personal_record = imported_workout_score(2505.0, true, "Bar MU");
+        personal_record.personal_record = true;
+        assert!(workout_score_changed(&existing, &personal_record));
+    }
+
+    fn set(load: f64) -> MovementSet {
+        MovementSet {
+            reps: 5,
+            load,
+            rpe: None,
+            completed: true,
+        }
+    }
+
+    fn movement_score(load: f64) -> MovementScoreModel {
+        MovementScoreModel {
+            movement_score_id: "movement_score_id".to_owned(),
+            movement_id: "movement_id".to_owned(),
+            user_id: "user_id".to_owned(),
+            score: load,
+            sets: 1,
+            reps: 5,
+            set_details: vec![set(load)],
+            unit: None,
+            distance: None,
+            distance_unit: None,
+            duration: None,
+            notes: "".to_owned(),
+            source_id: Some("mywod:client-id:1".to_owned()),
+            import_id: None,
+            created_at: "2016-09-26T00:00:00+00:00".to_owned(),
+            updated_at: "2016-09-26T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    fn imported_movement_score(load: f64, notes: &str) -> CreateMovementScore {
+        CreateMovementScore {
+            score: Some(load),
+            sets: 1,
+            reps: 5,
+            set_details: vec![set(load)],
+            unit: None,
+            distance: None,
+            distance_unit: None,
+            duration: None,
+            notes: notes.to_owned(),
+            created_at: Some("2016-09-26T00:00:00+00:00".to_owned()),
+            source_id: Some("mywod:client-id:1".to_owned()),
+            import_id: None,
+        }
+    }
+
+    #[test]
+    fn test_movement_score_changed() {
+        let existing = movement_score(100.0);
+        assert!(!movement_score_changed(
+            &existing,
+            &imported_movement_score(100.0, "")
+        ));
+        assert!(movement_score_changed(
+            &existing,
+            &imported_movement_score(105.0, "")
+        ));
+        assert!(movement_score_changed(
+            &existing,
+            &imported_movement_score(100.0, "Felt heavy")
+        ));
+    }
+
+    #[test]
+    fn test_movement_score_changed_duration() {
+        let mut existing = movement_score(100.0);
+        existing.duration = Some(600.0);
+        let mut imported = imported_movement_score(100.0, "");
+        imported.duration = Some(600.0);
+        assert!(!movement_score_changed(&existing, &imported));
+
+        imported.duration = Some(720.0);
+        assert!(movement_score_changed(&existing, &imported));
+    }
+
+    #[test]
+    fn test_movement_score_changed_unit() {
+        let mut existing = movement_score(100.0);
+        existing.unit = Some(Unit::Kg);
+        let mut imported = imported_movement_score(100.0, "");
+        imported.unit = Some(Unit::Kg);
+        assert!(!movement_score_changed(&existing, &imported));
+
+        imported.unit = Some(Unit::Lb);
+        assert!(movement_score_changed(&existing, &imported));
+    }
 }
@@ -0,0 +1,345 @@
+use crate::errors::WebResult;
+use crate::models::movement::{
+    CreateMovement, CreateMovementScore, MovementModel, UpdateMovementScore,
+};
+use crate::models::mywod::{ImportSummary, RowOutcome, RowReport};
+use crate::models::training_log::{LogMovementScore, LogRow, LogWorkoutScore};
+use crate::models::workout::{CreateWorkout, CreateWorkoutScore, UpdateWorkoutScore, WorkoutModel};
+use crate::repositories::{MovementRepository, WorkoutRepository};
+use crate::services::imports::{ImportTracker, ImportedScores};
+use crate::services::mywod::{movement_score_changed, workout_score_changed};
+use std::collections::HashMap;
+
+/// Finds the workouts the scores are for, creating the ones that do not
+/// exist yet in bulk. Scores of workouts that could not be created are
+/// reported.
+async fn resolve_workouts(
+    workout_repo: &dyn WorkoutRepository,
+    workout_scores: &[LogWorkoutScore],
+    user_id: &str,
+    import_id: &Option<String>,
+    summary: &mut ImportSummary,
+) -> WebResult<HashMap<String, WorkoutModel>> {
+    let mut workouts: HashMap<String, WorkoutModel> = HashMap::new();
+    let mut new_workouts: Vec<CreateWorkout> = Vec::new();
+
+    for log_score in workout_scores {
+        if workouts.contains_key(&log_score.name)
+            || new_workouts.iter().any(|new| new.name == log_score.name)
+        {
+            continue;
+        }
+        match workout_repo
+            .find_workout_by_name(user_id, &log_score.name)
+            .await?
+        {
+            Some(workout) => {
+                workouts.insert(log_score.name.to_owned(), workout);
+            }
+            None => new_workouts.push(CreateWorkout {
+                name: log_score.name.to_owned(),
+                description: log_score.description.to_owned(),
+                measurement: log_score.measurement,
+                is_public: false,
+                source_id: None,
+                import_id: import_id.clone(),
+            }),
+        }
+    }
+
+    match workout_repo.create_workouts(user_id, new_workouts).await {
+        Ok(created) => {
+            for workout in created {
+                summary.records.added += 1;
+                summary.added_names.push(workout.name.to_owned());
+                workouts.insert(workout.name.to_owned(), workout);
+            }
+        }
+        Err(e) => {
+            for log_score in workout_scores {
+                if !workouts.contains_key(&log_score.name) {
+                    summary.report.push(RowReport::new(
+                        &log_score.row,
+                        RowOutcome::Failed,
+                        &e.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(workouts)
+}
+
+/// Adds the workout scores of a training log, creating the workouts that do
+/// not exist yet. The `tracker` is advanced for every score.
+pub async fn save_workout_scores(
+    workout_repo: &dyn WorkoutRepository,
+    workout_scores: Vec<LogWorkoutScore>,
+    user_id: &str,
+    tracker: Option<&ImportTracker>,
+) -> WebResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let import_id = tracker.map(|tracker| tracker.import_id().to_owned());
+    let workouts = resolve_workouts(
+        workout_repo,
+        &workout_scores,
+        user_id,
+        &import_id,
+        &mut summary,
+    )
+    .await?;
+
+    let mut new_rows: Vec<LogRow> = Vec::new();
+    let mut new_scores: Vec<(String, CreateWorkoutScore)> = Vec::new();
+    let mut imported = ImportedScores::new(
+        workout_repo
+            .find_imported_workout_scores(
+                user_id,
+                &workout_scores
+                    .iter()
+                    .map(|log_score| &log_score.score)
+                    .collect::<Vec<_>>(),
+            )
+            .await?,
+    );
+
+    for log_score in workout_scores {
+        if let Some(tracker) = tracker {
+            tracker.advance(1).await;
+        }
+        // Reported when the workout could not be created
+        let workout = match workouts.get(&log_score.name) {
+            Some(workout) => workout,
+            None => continue,
+        };
+
+        let mut score = log_score.score;
+        score.import_id = import_id.clone();
+        let existing = imported.take(
+            &workout.workout_id,
+            score.source_id.as_deref(),
+            score.created_at.as_deref(),
+        );
+
+        match existing {
+            Some(existing) => {
+                let changed = workout_score_changed(&existing, &score);
+                // Scores from before sources were tracked get their source
+                if !changed && existing.source_id == score.source_id {
+                    summary.scores.skipped += 1;
+                    continue;
+                }
+
+                let updated = workout_repo
+                    .update_workout_score_by_id(
+                        user_id,
+                        &workout.workout_id,
+                        &existing.workout_score_id,
+                        UpdateWorkoutScore::from(score),
+                    )
+                    .await;
+                match updated {
+                    Ok(_) if changed => summary.scores.updated += 1,
+                    Ok(_) => summary.scores.skipped += 1,
+                    Err(e) => summary.report.push(RowReport::new(
+                        &log_score.row,
+                        RowOutcome::Failed,
+                        &e.to_string(),
+                    )),
+                }
+            }
+            None => {
+                new_rows.push(log_score.row);
+                new_scores.push((workout.workout_id.to_owned(), score));
+            }
+        }
+    }
+
+    match workout_repo
+        .create_workout_scores(user_id, new_scores)
+        .await
+    {
+        Ok(created) => summary.scores.added += created.len() as u32,
+        Err(e) => {
+            for row in &new_rows {
+                summary
+                    .report
+                    .push(RowReport::new(row, RowOutcome::Failed, &e.to_string()));
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Finds the movements the scores are for, creating the ones that do not
+/// exist yet in bulk. Scores of movements that could not be created are
+/// reported.
+async fn resolve_movements(
+    movement_repo: &dyn MovementRepository,
+    movement_scores: &[LogMovementScore],
+    user_id: &str,
+    import_id: &Option<String>,
+    summary: &mut ImportSummary,
+) -> WebResult<HashMap<String, MovementModel>> {
+    let mut movements: HashMap<String, MovementModel> = HashMap::new();
+    let mut new_movements: Vec<CreateMovement> = Vec::new();
+
+    for log_score in movement_scores {
+        if movements.contains_key(&log_score.name)
+            || new_movements.iter().any(|new| new.name == log_score.name)
+        {
+            continue;
+        }
+        match movement_repo
+            .find_movement_by_name(user_id, &log_score.name)
+            .await?
+        {
+            Some(movement) => {
+                movements.insert(log_score.name.to_owned(), movement);
+            }
+            None => new_movements.push(CreateMovement {
+                name: log_score.name.to_owned(),
+                measurement: log_score.measurement,
+                is_public: false,
+                source_id: None,
+                import_id: import_id.clone(),
+            }),
+        }
+    }
+
+    match movement_repo.create_movements(user_id, new_movements).await {
+        Ok(created) => {
+            for movement in created {
+                summary.records.added += 1;
+                summary.added_names.push(movement.name.to_owned());
+                movements.insert(movement.name.to_owned(), movement);
+            }
+        }
+        Err(e) => {
+            for log_score in movement_scores {
+                if !movements.contains_key(&log_score.name) {
+                    summary.report.push(RowReport::new(
+                        &log_score.row,
+                        RowOutcome::Failed,
+                        &e.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(movements)
+}
+
+/// Adds the movement scores of a training log, creating the movements that
+/// do not exist yet. The `tracker` is advanced for every score.
+pub async fn save_movement_scores(
+    movement_repo: &dyn MovementRepository,
+    movement_scores: Vec<LogMovementScore>,
+    user_id: &str,
+    tracker: Option<&ImportTracker>,
+) -> WebResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let import_id = tracker.map(|tracker| tracker.import_id().to_owned());
+    let movements = resolve_movements(
+        movement_repo,
+        &movement_scores,
+        user_id,
+        &import_id,
+        &mut summary,
+    )
+    .await?;
+
+    // Scores are added in bulk per movement
+    let mut new_scores: HashMap<String, (Vec<LogRow>, Vec<CreateMovementScore>)> = HashMap::new();
+    let mut imported = ImportedScores::new(
+        movement_repo
+            .find_imported_movement_scores(
+                user_id,
+                &movement_scores
+                    .iter()
+                    .map(|log_score| &log_score.score)
+                    .collect::<Vec<_>>(),
+            )
+            .await?,
+    );
+
+    for log_score in movement_scores {
+        if let Some(tracker) = tracker {
+            tracker.advance(1).await;
+        }
+        // Reported when the movement could not be created
+        let movement = match movements.get(&log_score.name) {
+            Some(movement) => movement,
+            None => continue,
+        };
+
+        let mut score = log_score.score;
+        score.import_id = import_id.clone();
+        let existing = imported.take(
+            &movement.movement_id,
+            score.source_id.as_deref(),
+            score.created_at.as_deref(),
+        );
+
+        match existing {
+            Some(existing) => {
+                let changed = movement_score_changed(&existing, &score);
+                // Scores from before sources were tracked get their source
+                if !changed && existing.source_id == score.source_id {
+                    summary.scores.skipped += 1;
+                    continue;
+                }
+
+                let updated = movement_repo
+                    .update_movement_score_by_id(
+                        user_id,
+                        &movement.movement_id,
+                        &existing.movement_score_id,
+                        UpdateMovementScore::from(score),
+                    )
+                    .await;
+                match updated {
+                    Ok(_) if changed => summary.scores.updated += 1,
+                    Ok(_) => summary.scores.skipped += 1,
+                    Err(e) => summary.report.push(RowReport::new(
+                        &log_score.row,
+                        RowOutcome::Failed,
+                        &e.to_string(),
+                    )),
+                }
+            }
+            None => {
+                let (rows, scores) = new_scores
+                    .entry(movement.movement_id.to_owned())
+                    .or_default();
+                rows.push(log_score.row);
+                scores.push(score);
+            }
+        }
+    }
+
+    for movement in movements.values() {
+        let (rows, scores) = match new_scores.remove(&movement.movement_id) {
+            Some(new) => new,
+            None => continue,
+        };
+        match movement_repo
+            .create_movement_scores(user_id, movement, scores)
+            .await
+        {
+            Ok(created) => summary.scores.added += created.len() as u32,
+            Err(e) => {
+                for row in &rows {
+                    summary
+                        .report
+                        .push(RowReport::new(row, RowOutcome::Failed, &e.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(summary)
+}
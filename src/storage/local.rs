@@ -0,0 +1,146 @@
+use crate::errors::{AppError, WebResult};
+use crate::storage::{validate_key, BlobInfo, BlobStorage};
+use actix_web::web;
+use async_trait::async_trait;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Keeps blobs as files in a directory on the local disk.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> WebResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+/// Adds the files in `dir` and its subdirectories to `blobs`, with their keys
+/// relative to the root of the storage.
+fn list_files(root: &Path, dir: &Path, blobs: &mut Vec<BlobInfo>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            list_files(root, &entry.path(), blobs)?;
+        } else if metadata.is_file() {
+            let path = entry.path();
+            let key = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            blobs.push(BlobInfo {
+                key,
+                modified: metadata.modified()?.into(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait(?Send)]
+impl BlobStorage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> WebResult<()> {
+        let path = self.path(key)?;
+        // Filesystem operations are blocking, use the thread-pool
+        web::block(move || {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, bytes)
+        })
+        .await
+        .map_err(|_| AppError::Internal("Writing file failed".to_owned()))?
+        .map_err(|e| AppError::Internal(format!("Error creating file {}: {}", key, e)))
+    }
+
+    async fn get(&self, key: &str) -> WebResult<Option<Vec<u8>>> {
+        let path = self.path(key)?;
+        let read = web::block(move || fs::read(path))
+            .await
+            .map_err(|_| AppError::Internal("Reading file failed".to_owned()))?;
+        match read {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(AppError::Internal(format!(
+                "Error reading file {}: {}",
+                key, e
+            ))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> WebResult<()> {
+        let path = self.path(key)?;
+        let removed = web::block(move || fs::remove_file(path))
+            .await
+            .map_err(|_| AppError::Internal("Deleting file failed".to_owned()))?;
+        match removed {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(AppError::Internal(format!(
+                "Could not delete file {}: {}",
+                key, e
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> WebResult<Vec<BlobInfo>> {
+        let root = self.root.clone();
+        let listed = web::block(move || {
+            let mut blobs = Vec::new();
+            match list_files(&root, &root, &mut blobs) {
+                // Nothing has been stored yet
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(blobs),
+                Err(e) => Err(e),
+                Ok(_) => Ok(blobs),
+            }
+        })
+        .await
+        .map_err(|_| AppError::Internal("Listing files failed".to_owned()))?;
+
+        listed.map_err(|e| AppError::Internal(format!("Error listing files: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_local_storage() -> WebResult<()> {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let storage = LocalStorage::new(&root);
+
+        assert_eq!(storage.list().await?, vec![]);
+        assert_eq!(storage.get("blob").await?, None);
+        storage.put("blob", vec![1, 2, 3]).await?;
+        storage.put("nested/blob", vec![4]).await?;
+        assert_eq!(storage.get("blob").await?, Some(vec![1, 2, 3]));
+        assert_eq!(storage.get("nested/blob").await?, Some(vec![4]));
+
+        let mut keys: Vec<String> = storage
+            .list()
+            .await?
+            .into_iter()
+            .map(|blob| blob.key)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["blob", "nested/blob"]);
+
+        storage.delete("blob").await?;
+        storage.delete("blob").await?;
+        assert_eq!(storage.get("blob").await?, None);
+        assert!(storage.get("../blob").await.is_err());
+
+        fs::remove_dir_all(root).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use crate::errors::{AppError, WebResult};
+use crate::utils::{StorageBackend, StorageConfig};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+mod local;
+mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Where uploaded files are kept until they have been imported.
+pub const UPLOAD_FILE_LOCATION: &str = "./tmp";
+pub const AVATAR_FILE_LOCATION: &str = "./static/avatars";
+
+/// A stored blob, as listed by `BlobStorage::list`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobInfo {
+    pub key: String,
+    pub modified: DateTime<Utc>,
+}
+
+/// Stores files (blobs) by key, e.g. on the local disk or in an S3 bucket
+/// that several instances of the server share. Keys are relative paths
+/// without `..` segments, like `{uuid}` or `{avatar_id}.png`.
+#[async_trait(?Send)]
+pub trait BlobStorage: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> WebResult<()>;
+
+    /// The blob with the key, `None` when there is no such blob.
+    async fn get(&self, key: &str) -> WebResult<Option<Vec<u8>>>;
+
+    /// Removes the blob with the key, removing a missing blob is not an error.
+    async fn delete(&self, key: &str) -> WebResult<()>;
+
+    /// Every blob in the storage, in no particular order.
+    async fn list(&self) -> WebResult<Vec<BlobInfo>>;
+}
+
+/// The kinds of blobs that are stored, each kept apart from the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bucket {
+    Avatars,
+    Uploads,
+}
+
+impl Bucket {
+    fn local_path(&self) -> &'static str {
+        match self {
+            Bucket::Avatars => AVATAR_FILE_LOCATION,
+            Bucket::Uploads => UPLOAD_FILE_LOCATION,
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            Bucket::Avatars => "avatars/",
+            Bucket::Uploads => "uploads/",
+        }
+    }
+}
+
+/// Rejects keys that could point outside of the storage.
+pub(crate) fn validate_key(key: &str) -> WebResult<()> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!("Invalid file name '{}'", key)))
+    }
+}
+
+/// The storage of a kind of blob, on the local disk or in the S3 bucket as
+/// configured with `STORAGE__BACKEND`.
+fn blob_storage(config: &StorageConfig, bucket: Bucket) -> WebResult<Box<dyn BlobStorage>> {
+    match config.backend {
+        StorageBackend::Local => Ok(Box::new(LocalStorage::new(bucket.local_path()))),
+        StorageBackend::S3 => match &config.s3 {
+            Some(s3_config) => Ok(Box::new(S3Storage::new(s3_config.clone(), bucket.prefix()))),
+            None => Err(AppError::Internal(
+                "The S3 storage backend is not configured".to_owned(),
+            )),
+        },
+    }
+}
+
+/// The storages of every kind of blob. They are created once and shared by
+/// the handlers, which get them as `web::Data<Storages>`, and the background
+/// jobs.
+#[derive(Clone)]
+pub struct Storages {
+    pub avatars: Arc<dyn BlobStorage>,
+    pub uploads: Arc<dyn BlobStorage>,
+}
+
+impl Storages {
+    pub fn new(config: &StorageConfig) -> WebResult<Self> {
+        Ok(Storages {
+            avatars: Arc::from(blob_storage(config, Bucket::Avatars)?),
+            uploads: Arc::from(blob_storage(config, Bucket::Uploads)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("2c5ea4c0-4067-11e9-8bad-9b1deb4d3b7d").is_ok());
+        assert!(validate_key("0123456789abcdef-64.png").is_ok());
+        assert!(validate_key("avatars/0123456789abcdef.png").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("/etc/passwd").is_err());
+        assert!(validate_key("../secret").is_err());
+        assert!(validate_key("avatars/../../secret").is_err());
+        assert!(validate_key("avatars//secret").is_err());
+    }
+}
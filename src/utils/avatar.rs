@@ -0,0 +1,408 @@
+use crate::errors::{AppError, WebResult};
+use crate::storage::BlobStorage;
+use data_encoding::{BASE64URL_NOPAD, HEXLOWER};
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use std::io::Cursor;
+
+/// Largest avatar upload accepted, in bytes.
+pub const MAX_AVATAR_SIZE: usize = 5 * 1024 * 1024;
+
+/// Widths (and heights) of the square thumbnails generated for every avatar.
+pub const AVATAR_THUMBNAIL_SIZES: [u32; 3] = [64, 128, 256];
+
+/// Uploads with a larger width or height are not decoded at all.
+const MAX_AVATAR_DIMENSION: u32 = 4096;
+
+/// Larger avatars are scaled down to fit within this width and height.
+const AVATAR_DIMENSION: u32 = 512;
+
+/// Image formats an avatar can be uploaded in.
+const AVATAR_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+];
+
+/// An avatar re-encoded as PNG, along with its thumbnails.
+#[derive(Debug)]
+pub struct ProcessedAvatar {
+    /// Changes with the content of the avatar, so it can be cached forever
+    pub version: String,
+    pub image: Vec<u8>,
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+fn encode_png(image: &DynamicImage) -> WebResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .map_err(|e| AppError::Internal(format!("Encoding avatar failed: {}", e)))?;
+    Ok(bytes)
+}
+
+/// Decodes an uploaded image, recognized by its content rather than its name
+/// or content type, and re-encodes it. Only the pixels are kept, so metadata
+/// like EXIF (e.g. where a photo was taken) is not stored.
+pub fn process_avatar(bytes: &[u8]) -> WebResult<ProcessedAvatar> {
+    if bytes.len() > MAX_AVATAR_SIZE {
+        return Err(AppError::PayloadTooLarge(format!(
+            "An avatar can be at most {} bytes",
+            MAX_AVATAR_SIZE
+        )));
+    }
+
+    let format = image::guess_format(bytes)
+        .ok()
+        .filter(|format| AVATAR_FORMATS.contains(format))
+        .ok_or_else(|| {
+            AppError::UnsupportedMediaType(
+                "An avatar has to be a PNG, JPEG, GIF or WebP image".to_owned(),
+            )
+        })?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_AVATAR_DIMENSION);
+    limits.max_image_height = Some(MAX_AVATAR_DIMENSION);
+    let mut reader = Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let decoded = reader
+        .decode()
+        .map_err(|e| AppError::BadRequest(format!("Could not read avatar image: {}", e)))?;
+
+    let decoded = DynamicImage::ImageRgba8(decoded.to_rgba8());
+    let avatar = if decoded.width() > AVATAR_DIMENSION || decoded.height() > AVATAR_DIMENSION {
+        decoded.resize(AVATAR_DIMENSION, AVATAR_DIMENSION, FilterType::Lanczos3)
+    } else {
+        decoded
+    };
+
+    let image = encode_png(&avatar)?;
+    let version = HEXLOWER.encode(digest(&SHA256, &image).as_ref())[..16].to_owned();
+    let thumbnails = AVATAR_THUMBNAIL_SIZES
+        .iter()
+        .map(|&size| {
+            let thumbnail = avatar.resize_to_fill(size, size, FilterType::Lanczos3);
+            encode_png(&thumbnail).map(|bytes| (size, bytes))
+        })
+        .collect::<WebResult<Vec<_>>>()?;
+
+    Ok(ProcessedAvatar {
+        version,
+        image,
+        thumbnails,
+    })
+}
+
+/// The file name of an avatar in the avatar directory, taken from its API path.
+fn avatar_filename(avatar_url: &str) -> Option<&str> {
+    match avatar_url.strip_prefix("/avatars/") {
+        Some(filename) if !filename.contains('/') && !filename.is_empty() => Some(filename),
+        _ => None,
+    }
+}
+
+/// The API path of the avatar that a file in the avatar directory belongs to,
+/// which is the file itself or one of its thumbnails.
+pub fn avatar_url_for_file(filename: &str) -> Option<String> {
+    let name = filename.strip_suffix(".png")?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+
+    let avatar_name = match name.rsplit_once('-') {
+        Some((avatar_name, size))
+            if AVATAR_THUMBNAIL_SIZES
+                .iter()
+                .any(|thumbnail_size| thumbnail_size.to_string() == size) =>
+        {
+            avatar_name
+        }
+        _ => name,
+    };
+    Some(format!("/avatars/{}.png", avatar_name))
+}
+
+/// The API path of a thumbnail of an avatar, `size` being one of
+/// `AVATAR_THUMBNAIL_SIZES`.
+pub fn avatar_thumbnail_url(avatar_url: &str, size: u32) -> Option<String> {
+    avatar_filename(avatar_url)
+        .and_then(|filename| filename.strip_suffix(".png"))
+        .map(|name| format!("/avatars/{}-{}.png", name, size))
+}
+
+/// Stores the re-encoded avatar along with its thumbnails and returns an
+/// API path to the avatar. The file names are derived from the user and the
+/// version of the avatar, so a changed avatar gets a new path without
+/// revealing whose avatar it is.
+pub async fn save_avatar(
+    storage: &dyn BlobStorage,
+    user_id: &str,
+    avatar: ProcessedAvatar,
+) -> WebResult<String> {
+    let avatar_id = digest(
+        &SHA256,
+        format!("{}:{}", user_id, avatar.version).as_bytes(),
+    );
+    let avatar_url = format!(
+        "/avatars/{}.png",
+        &HEXLOWER.encode(avatar_id.as_ref())[..32]
+    );
+
+    // The avatar is stored last, so its path is not handed out before all
+    // of its thumbnails exist
+    for (size, thumbnail) in avatar.thumbnails {
+        if let Some(key) = avatar_thumbnail_url(&avatar_url, size)
+            .as_deref()
+            .and_then(avatar_filename)
+        {
+            storage.put(key, thumbnail).await?;
+        }
+    }
+    if let Some(key) = avatar_filename(&avatar_url) {
+        storage.put(key, avatar.image).await?;
+    }
+
+    Ok(avatar_url)
+}
+
+/// Removes the avatar and its thumbnails, files that are already gone are
+/// skipped.
+pub async fn delete_avatar(storage: &dyn BlobStorage, avatar_url: &str) -> WebResult<()> {
+    let mut urls = vec![avatar_url.to_owned()];
+    urls.extend(
+        AVATAR_THUMBNAIL_SIZES
+            .iter()
+            .filter_map(|&size| avatar_thumbnail_url(avatar_url, size)),
+    );
+
+    for url in urls {
+        if let Some(key) = avatar_filename(&url) {
+            storage.delete(key).await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn avatar_signing_key(secret: &[u8]) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, secret)
+}
+
+/// A path to the avatar that can be used without logging in until `expires`
+/// (a unix timestamp). The signature is valid for the thumbnails as well.
+pub fn signed_avatar_url(avatar_url: &str, expires: i64, secret: &[u8]) -> String {
+    let message = format!("{}:{}", avatar_url, expires);
+    let signature = hmac::sign(&avatar_signing_key(secret), message.as_bytes());
+    format!(
+        "{}?expires={}&signature={}",
+        avatar_url,
+        expires,
+        BASE64URL_NOPAD.encode(signature.as_ref())
+    )
+}
+
+/// Whether the signature of a signed avatar path is valid, without checking
+/// if it has expired.
+pub fn verify_avatar_signature(
+    avatar_url: &str,
+    expires: i64,
+    signature: &str,
+    secret: &[u8],
+) -> bool {
+    let signature = match BASE64URL_NOPAD.decode(signature.as_bytes()) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let message = format!("{}:{}", avatar_url, expires);
+    hmac::verify(&avatar_signing_key(secret), message.as_bytes(), &signature).is_ok()
+}
+
+/// Reads the avatar image, or one of its thumbnails, at an API path. Users
+/// without an avatar have an empty one.
+pub async fn read_avatar(storage: &dyn BlobStorage, avatar_url: &str) -> WebResult<Vec<u8>> {
+    match avatar_filename(avatar_url) {
+        Some(key) => Ok(storage.get(key).await?.unwrap_or_default()),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use image::{Rgba, RgbaImage};
+
+    fn test_image(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255])
+        });
+        let image = match format {
+            ImageOutputFormat::Jpeg(_) => {
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8())
+            }
+            _ => DynamicImage::ImageRgba8(image),
+        };
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_process_avatar() {
+        let processed = process_avatar(&test_image(1024, 768, ImageOutputFormat::Png)).unwrap();
+        assert_eq!(processed.version.len(), 16);
+
+        let avatar = image::load_from_memory(&processed.image).unwrap();
+        assert_eq!((avatar.width(), avatar.height()), (512, 384));
+        assert_eq!(processed.thumbnails.len(), AVATAR_THUMBNAIL_SIZES.len());
+        for (size, thumbnail) in processed.thumbnails {
+            let thumbnail = image::load_from_memory(&thumbnail).unwrap();
+            assert_eq!((thumbnail.width(), thumbnail.height()), (size, size));
+        }
+
+        // The version only changes with the image
+        let again = process_avatar(&test_image(1024, 768, ImageOutputFormat::Png)).unwrap();
+        assert_eq!(again.version, processed.version);
+        let other = process_avatar(&test_image(100, 100, ImageOutputFormat::Png)).unwrap();
+        assert_ne!(other.version, processed.version);
+    }
+
+    #[test]
+    fn test_process_avatar_strips_exif() {
+        let jpeg = test_image(32, 32, ImageOutputFormat::Jpeg(90));
+        // An APP1 segment with EXIF data right after the start of image marker
+        let exif = b"Exif\0\0MM\0*\0\0\0\x08\0\0";
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend_from_slice(&[0xFF, 0xE1, 0, (exif.len() + 2) as u8]);
+        with_exif.extend_from_slice(exif);
+        with_exif.extend_from_slice(&jpeg[2..]);
+
+        let processed = process_avatar(&with_exif).unwrap();
+        assert!(image::guess_format(&processed.image).unwrap() == ImageFormat::Png);
+        assert!(!processed.image.windows(4).any(|bytes| bytes == b"Exif"));
+    }
+
+    #[test]
+    fn test_process_avatar_rejects_invalid_uploads() {
+        let not_an_image = process_avatar(b"SQLite format 3\0");
+        assert!(matches!(
+            not_an_image,
+            Err(AppError::UnsupportedMediaType(_))
+        ));
+
+        let mut corrupt = test_image(32, 32, ImageOutputFormat::Png);
+        corrupt.truncate(40);
+        assert!(matches!(
+            process_avatar(&corrupt),
+            Err(AppError::BadRequest(_))
+        ));
+
+        let too_large = vec![0; MAX_AVATAR_SIZE + 1];
+        assert!(matches!(
+            process_avatar(&too_large),
+            Err(AppError::PayloadTooLarge(_))
+        ));
+
+        let too_wide = test_image(MAX_AVATAR_DIMENSION + 1, 1, ImageOutputFormat::Png);
+        assert!(matches!(
+            process_avatar(&too_wide),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_avatar_url_for_file() {
+        let avatar_url = "/avatars/0123456789abcdef0123456789abcdef.png";
+        assert_eq!(
+            avatar_url_for_file("0123456789abcdef0123456789abcdef.png").as_deref(),
+            Some(avatar_url)
+        );
+        assert_eq!(
+            avatar_url_for_file("0123456789abcdef0123456789abcdef-128.png").as_deref(),
+            Some(avatar_url)
+        );
+        // Only thumbnail sizes are stripped
+        assert_eq!(
+            avatar_url_for_file("0123456789abcdef-100.png").as_deref(),
+            Some("/avatars/0123456789abcdef-100.png")
+        );
+        assert_eq!(avatar_url_for_file("../secret.png"), None);
+        assert_eq!(avatar_url_for_file(".png"), None);
+        assert_eq!(avatar_url_for_file("avatar.jpg"), None);
+    }
+
+    #[test]
+    fn test_signed_avatar_url() {
+        let avatar_url = "/avatars/0123456789abcdef0123456789abcdef.png";
+        let signed = signed_avatar_url(avatar_url, 1700000000, b"secret");
+        let (path, query) = signed.split_once('?').unwrap();
+        assert_eq!(path, avatar_url);
+        let signature = query.strip_prefix("expires=1700000000&signature=").unwrap();
+
+        assert!(verify_avatar_signature(
+            avatar_url, 1700000000, signature, b"secret"
+        ));
+        assert!(!verify_avatar_signature(
+            avatar_url, 1700000001, signature, b"secret"
+        ));
+        assert!(!verify_avatar_signature(
+            "/avatars/other.png",
+            1700000000,
+            signature,
+            b"secret"
+        ));
+        assert!(!verify_avatar_signature(
+            avatar_url,
+            1700000000,
+            signature,
+            b"other secret"
+        ));
+        assert!(!verify_avatar_signature(
+            avatar_url,
+            1700000000,
+            "not base64!",
+            b"secret"
+        ));
+    }
+
+    #[test]
+    fn test_avatar_thumbnail_url() {
+        assert_eq!(
+            avatar_thumbnail_url("/avatars/0123456789abcdef0123456789abcdef.png", 64),
+            Some("/avatars/0123456789abcdef0123456789abcdef-64.png".to_owned())
+        );
+        assert_eq!(avatar_thumbnail_url("", 64), None);
+        assert_eq!(avatar_thumbnail_url("/avatars/../secret.png", 64), None);
+    }
+
+    #[actix_web::test]
+    async fn test_save_avatar() -> WebResult<()> {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let storage = LocalStorage::new(&root);
+        let avatar = process_avatar(&test_image(32, 32, ImageOutputFormat::Png))?;
+
+        let avatar_url = save_avatar(&storage, "user_id", avatar).await?;
+        assert!(!avatar_url.contains("user_id"));
+        assert!(!read_avatar(&storage, &avatar_url).await?.is_empty());
+        let thumbnail_url = avatar_thumbnail_url(&avatar_url, 64).unwrap();
+        assert!(!read_avatar(&storage, &thumbnail_url).await?.is_empty());
+
+        delete_avatar(&storage, &avatar_url).await?;
+        assert!(read_avatar(&storage, &avatar_url).await?.is_empty());
+        assert!(read_avatar(&storage, &thumbnail_url).await?.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+        Ok(())
+    }
+}
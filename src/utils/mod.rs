@@ -1,7 +1,12 @@
 pub mod api_docs;
+pub mod avatar;
 mod configuration;
 pub mod mywod;
 pub mod query_utils;
 pub mod resources;
+pub mod training_log;
+pub mod upload;
 
-pub use configuration::{AppState, Config};
+pub use configuration::{
+    AppState, Config, DatabaseBackend, MongoConfig, S3Config, StorageBackend, StorageConfig,
+};
@@ -1,65 +1,90 @@
 use crate::errors::{AppError, WebResult};
-use crate::models::movement::{CreateMovementScore, MovementMeasurement};
-use crate::models::mywod::{Athlete, CustomWOD, Movement, MovementSession, MyWOD, MyWodData};
-use crate::models::workout::{CreateWorkoutScore, WorkoutMeasurement};
-use actix_multipart::Multipart;
+use crate::models::feed::FeedModel;
+use crate::models::movement::{
+    CreateMovementScore, MovementMeasurement, MovementModel, MovementScoreModel, MovementSet,
+};
+use crate::models::mywod::{
+    Athlete, BackupRow, CustomWOD, Feed, Movement, MovementSession, MyWOD, MyWodData, RowOutcome,
+    RowReport,
+};
+use crate::models::unit::{convert_to_system, parse_distance, Unit, UnitSystem};
+use crate::models::user::{Gender, User};
+use crate::models::workout::{
+    CreateWorkoutScore, WorkoutMeasurement, WorkoutModel, WorkoutScoreModel,
+};
+use crate::storage::BlobStorage;
 use actix_web::web;
 use chrono::{DateTime, NaiveDateTime, Utc};
-use futures::{StreamExt, TryStreamExt};
-use rusqlite::{params, Connection};
+use derive_more::Display;
+use rusqlite::{params, Connection, OpenFlags, Row, Statement};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
 use std::path::Path;
 
-use super::resources::time_to_seconds;
-
-pub const AVATAR_FILE_LOCATION: &str = "./static/avatars";
-
-/// Writes the avatar blob to an image in a static directory and returns
-/// an API path to that image.
-pub fn save_avatar(user_id: &str, avatar: Vec<u8>) -> WebResult<String> {
-    let filename = format!("{}.png", user_id);
-    let filepath = format!("{}/{}", AVATAR_FILE_LOCATION, filename);
+use super::resources::{seconds_to_time, time_to_seconds};
+
+/// Why a field of a myWOD backup row could not be parsed.
+#[derive(Display, Debug, PartialEq)]
+pub enum MyWodParseError {
+    #[display(fmt = "Could not parse {} '{}' as a number", field, value)]
+    InvalidNumber { field: &'static str, value: String },
+    #[display(fmt = "Could not parse {} '{}' as a time", field, value)]
+    InvalidTime { field: &'static str, value: String },
+    #[display(fmt = "Could not parse date '{}'", _0)]
+    InvalidDate(String),
+    #[display(fmt = "A {} movement can not be scored", _0)]
+    Unscored(MovementMeasurement),
+}
 
-    let mut file = fs::File::create(&filepath)
-        .map_err(|_| AppError::Internal(format!("Error creating file: {}", &filepath)))?;
-    file.write_all(&avatar)
-        .map(|_| file)
-        .map_err(|_| AppError::Internal("Saving file failed".to_owned()))?;
+/// Every SQLite database file, and so every myWOD backup, starts with this.
+pub const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// The tables of a myWOD backup that are read when importing it.
+const MYWOD_TABLES: [&str; 5] = [
+    "Athlete",
+    "CustomWODs",
+    "Movement",
+    "MovementSessions",
+    "MyWODs",
+];
+
+/// Function to store the upload from the user, this file gets handled and
+/// all data is attempted to be added for the user. Returns the key of the
+/// upload in the upload storage.
+pub async fn write_payload_to_file(
+    uploads: &dyn BlobStorage,
+    contents: Vec<u8>,
+) -> WebResult<String> {
+    let key = uuid::Uuid::new_v4().to_string();
+    uploads.put(&key, contents).await?;
+
+    Ok(key)
+}
 
-    Ok(format!("/avatars/{}", filename))
+/// Imports created before uploads were kept in a blob storage have the path
+/// of the uploaded file rather than its key.
+pub fn upload_key(key: &str) -> &str {
+    key.strip_prefix("./tmp/").unwrap_or(key)
 }
 
-/// Function to write the multiform upload from the user, this file gets
-/// handled and all data is attempted to be added for the user.
-pub async fn write_payload_to_file(mut payload: Multipart) -> WebResult<String> {
-    let id = uuid::Uuid::new_v4().to_string();
-    let filepath = format!("./tmp/{}", id);
+/// Reads an upload stored by `write_payload_to_file`.
+pub async fn read_payload_file(uploads: &dyn BlobStorage, key: &str) -> WebResult<Vec<u8>> {
+    uploads
+        .get(upload_key(key))
+        .await?
+        .ok_or_else(|| AppError::NotFound("The uploaded file no longer exists".to_owned()))
+}
 
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        // File::create is blocking operation, use thread-pool
-        let fp = filepath.to_owned();
-        let mut f = web::block(move || fs::File::create(fp))
-            .await
-            .unwrap()
-            .unwrap();
-        // Field in turn is stream of *Bytes* object
-        while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            // filesystem operations are blocking, we have to use thread-pool
-            f = web::block(move || f.write_all(&data).map(|_| f))
-                .await
-                .map_err(|_| AppError::Internal("Reading myWOD data failed".to_owned()))?
-                .unwrap();
-        }
-    }
+/// Function that cleans up the uploaded myWOD file after it has been handled
+/// and all data has been added for the user.
+pub async fn delete_payload_file(uploads: &dyn BlobStorage, key: String) -> WebResult<bool> {
+    uploads.delete(upload_key(&key)).await?;
 
-    Ok(filepath)
+    Ok(true)
 }
 
-/// Function that cleans up the myWOD file from the file system after it has been
-/// handled and all data has been added for the user.
-pub async fn delete_payload_file(filename: String) -> WebResult<bool> {
+/// Removes a file from the local file system, if it exists.
+pub fn delete_local_file(filename: String) -> WebResult<bool> {
     if Path::new(&filename).exists() {
         fs::remove_file(filename)
             .map_err(|_| AppError::Internal("Could not delete file".to_owned()))?;
@@ -68,9 +93,116 @@ pub async fn delete_payload_file(filename: String) -> WebResult<bool> {
     Ok(true)
 }
 
+/// Reads an uploaded myWOD backup.
+pub async fn read_payload_contents(uploads: &dyn BlobStorage, key: &str) -> WebResult<MyWodData> {
+    let contents = read_payload_file(uploads, key).await?;
+    read_mywod_contents(contents).await
+}
+
+/// Runs file and SQLite work, which blocks, on the blocking thread pool
+/// instead of the async runtime.
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> WebResult<T> + Send + 'static,
+) -> WebResult<T> {
+    web::block(work)
+        .await
+        .map_err(|_| AppError::Internal("Processing the myWOD backup failed".to_owned()))?
+}
+
+/// Reads a myWOD backup. SQLite can only open files, so the backup is copied
+/// to a local temporary file while it is read.
+pub async fn read_mywod_contents(contents: Vec<u8>) -> WebResult<MyWodData> {
+    run_blocking(move || {
+        let filename = write_temporary_file(&contents)?;
+        let mywod_data = read_contents(&filename);
+        delete_local_file(filename)?;
+
+        mywod_data
+    })
+    .await
+}
+
+/// Writes data to a new myWOD backup and returns its contents.
+pub async fn write_mywod_contents(data: MyWodData) -> WebResult<Vec<u8>> {
+    run_blocking(move || {
+        let filename = std::env::temp_dir()
+            .join(format!("{}.mywod", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        let written = write_contents(&filename, &data);
+        let contents = written.and_then(|_| {
+            fs::read(&filename).map_err(|_| AppError::Internal("Reading backup failed".to_owned()))
+        });
+        delete_local_file(filename)?;
+
+        contents
+    })
+    .await
+}
+
+fn write_temporary_file(contents: &[u8]) -> WebResult<String> {
+    let filename = std::env::temp_dir()
+        .join(format!("{}.mywod", uuid::Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string();
+    fs::write(&filename, contents)
+        .map_err(|e| AppError::Internal(format!("Copying myWOD file failed: {}", e)))?;
+
+    Ok(filename)
+}
+
+/// Checks that an upload is a myWOD backup, a SQLite database with the
+/// tables that are imported, before it is stored and imported. The backup is
+/// handed back when it is valid.
+pub async fn validate_mywod_backup(contents: Vec<u8>) -> WebResult<Vec<u8>> {
+    if !contents.starts_with(SQLITE_HEADER) {
+        return Err(AppError::UnsupportedMediaType(
+            "The file is not a myWOD backup".to_owned(),
+        ));
+    }
+
+    let (contents, missing_tables) = run_blocking(move || {
+        let filename = write_temporary_file(&contents)?;
+        let missing_tables = find_missing_tables(&filename);
+        delete_local_file(filename)?;
+
+        Ok((contents, missing_tables?))
+    })
+    .await?;
+
+    match missing_tables {
+        missing if missing.is_empty() => Ok(contents),
+        missing => Err(AppError::UnprocessableEntity(format!(
+            "The myWOD backup is missing the tables {}",
+            missing.join(", ")
+        ))),
+    }
+}
+
+fn find_missing_tables(filename: &str) -> WebResult<Vec<&'static str>> {
+    let unreadable = |e: rusqlite::Error| {
+        AppError::UnprocessableEntity(format!("The myWOD backup can not be read: {}", e))
+    };
+    let db = Connection::open_with_flags(filename, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(unreadable)?;
+    let tables = db
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table';")
+        .map_err(unreadable)?
+        .query_map(params![], |row| row.get::<_, String>(0))
+        .map_err(unreadable)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(unreadable)?;
+
+    Ok(MYWOD_TABLES
+        .iter()
+        .copied()
+        .filter(|table| !tables.iter().any(|name| name == table))
+        .collect())
+}
+
 /// Function that reads the mywod database file and returns the contents in
 /// a parsed way which is then added to the user profile.
-pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
+pub fn read_contents(filename: &str) -> WebResult<MyWodData> {
     let db = Connection::open(filename)
         .map_err(|_| AppError::Internal("Error opening connection".to_owned()))?;
 
@@ -88,46 +220,52 @@ pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
                 date_of_birth: row.get(10)?,
                 box_name: row.get(11)?,
                 avatar: row.get(13)?,
+                unit_system: map_unit_system(row.get(16)?),
+                gender: map_gender(row.get(9)?),
             })
         })
         .map_err(|_| AppError::Internal("Error reading athlete data".to_owned()))?;
 
-    let mut workouts: Vec<CustomWOD> = Vec::new();
+    let mut unreadable: Vec<RowReport> = Vec::new();
 
-    // READING WORKOUTS
-    db.prepare("SELECT * FROM CustomWODs;")
-        .map_err(|_| AppError::Internal("Error reading athlete information".to_owned()))?
-        .query_map(params![], |row| {
+    // READING WORKOUTS, leaving out the ones the athlete deleted
+    let mut workouts: Vec<CustomWOD> = read_rows(
+        &mut db
+            .prepare("SELECT * FROM CustomWODs WHERE deleted = 0;")
+            .map_err(|_| AppError::Internal("Error reading athlete information".to_owned()))?,
+        "CustomWODs",
+        |row| {
             Ok(CustomWOD {
+                primary_client_id: row.get(0)?,
+                primary_record_id: row.get(1)?,
                 title: row.get(4)?,
                 score_type: row.get(5)?,
                 description: row.get(6)?,
             })
-        })
-        .map_err(|_| AppError::Internal("Error reading workouts".to_owned()))?
-        .for_each(|workout| {
-            if let Ok(workout) = workout {
-                // Do not take the sample workout
-                if !workout
-                    .description
-                    .starts_with("This is a sample custom WOD")
-                {
-                    workouts.push(workout);
-                }
-            }
-        });
+        },
+        &mut unreadable,
+    )
+    .map_err(|_| AppError::Internal("Error reading workouts".to_owned()))?;
+    // Do not take the sample workout
+    workouts.retain(|workout| {
+        !workout
+            .description
+            .starts_with("This is a sample custom WOD")
+    });
 
+    // READING MOVEMENTS
     let mut movements: Vec<Movement> = Vec::new();
 
-    // READING MOVEMENTS
-    db.prepare("SELECT * FROM Movement JOIN MovementSessions ON Movement.primaryclientid = MovementSessions.foreignmovementclientid AND Movement.primaryrecordid = MovementSessions.foreignmovementrecordid;")
-        .map_err(|_| AppError::Internal("Error reading movement information".to_owned()))?
-        .query_map(params![], |row| {
+    read_rows(
+        &mut db.prepare("SELECT * FROM Movement JOIN MovementSessions ON Movement.primaryclientid = MovementSessions.foreignmovementclientid AND Movement.primaryrecordid = MovementSessions.foreignmovementrecordid WHERE Movement.deleted = 0 AND MovementSessions.deleted = 0;")
+            .map_err(|_| AppError::Internal("Error reading movement information".to_owned()))?,
+        "Movement",
+        |row| {
             let mut primary_client_id: String = row.get(0)?;
             let mut name: String = row.get(4)?;
 
             let score_type: i32 = row.get(5)?;
-            if map_movement_measurement(score_type) == MovementMeasurement::Time {
+            if map_movement(score_type, &name) == MovementMeasurement::Time {
                 // measurementAUnitsCode is 6 for km and 5 for m, probably other values
                 let measurement_a_units_code: i32 = row.get(16)?; // m or km indicator
                 let measurement_code = match measurement_a_units_code {
@@ -151,24 +289,33 @@ pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
                 name,
                 score_type: row.get(5)?,
             })
-        })
-        .map_err(|_| AppError::Internal("Error reading movements".to_owned()))?
-        .for_each(|movement| {
-            if let Ok(movement) = movement {
-                movements.push(movement);
-            }
+        },
+        &mut unreadable,
+    )
+    .map_err(|_| AppError::Internal("Error reading movements".to_owned()))?
+    .into_iter()
+    .for_each(|movement| {
+        // The join gives a row for every session, only keep each movement once
+        let seen = movements.iter().any(|m| {
+            m.primary_client_id == movement.primary_client_id
+                && m.primary_record_id == movement.primary_record_id
         });
+        if !seen {
+            movements.push(movement);
+        }
+    });
 
     // READING MOVEMENT SCORES
-    let mut movement_scores: Vec<MovementSession> = Vec::new();
-
-    db.prepare("SELECT * FROM MovementSessions JOIN Movement ON Movement.primaryclientid = MovementSessions.foreignmovementclientid AND Movement.primaryrecordid = MovementSessions.foreignmovementrecordid;")
-        .map_err(|_| AppError::Internal("Error reading movement session information".to_owned()))?
-        .query_map(params![], |row| {
+    let movement_scores: Vec<MovementSession> = read_rows(
+        &mut db.prepare("SELECT * FROM MovementSessions JOIN Movement ON Movement.primaryclientid = MovementSessions.foreignmovementclientid AND Movement.primaryrecordid = MovementSessions.foreignmovementrecordid WHERE Movement.deleted = 0 AND MovementSessions.deleted = 0;")
+            .map_err(|_| AppError::Internal("Error reading movement session information".to_owned()))?,
+        "MovementSessions",
+        |row| {
             let mut foreign_movement_client_id: String = row.get(2)?;
+            let movement_name: String = row.get(17)?;
             let score_type: i32 = row.get(18)?;
 
-            if map_movement_measurement(score_type) == MovementMeasurement::Time {
+            if map_movement(score_type, &movement_name) == MovementMeasurement::Time {
                 // add distance to id value
                 let measurement_a_value: f64 = row.get(7)?; // distance value
                 foreign_movement_client_id = row.get(2)?;
@@ -176,6 +323,8 @@ pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
             }
 
             Ok(MovementSession {
+                primary_client_id: row.get(0)?,
+                primary_record_id: row.get(1)?,
                 foreign_movement_client_id,
                 foreign_movement_record_id: row.get(3)?,
                 date: row.get(6)?,
@@ -185,36 +334,58 @@ pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
                 sets: row.get(10)?,
                 notes: row.get(11)?,
             })
-        })
-        .map_err(|_| AppError::Internal("Error reading movements".to_owned()))?
-        .for_each(|movement_score| {
-            if let Ok(movement_score) = movement_score {
-                movement_scores.push(movement_score);
-            }
-        });
+        },
+        &mut unreadable,
+    )
+    .map_err(|_| AppError::Internal("Error reading movements".to_owned()))?;
 
     // READING WORKOUT SCORES
-    let mut workout_scores: Vec<MyWOD> = Vec::new();
-
-    db.prepare("SELECT * FROM MyWODs;")
-        .map_err(|_| AppError::Internal("Error reading movement session information".to_owned()))?
-        .query_map(params![], |row| {
+    let workout_scores: Vec<MyWOD> = read_rows(
+        &mut db
+            .prepare("SELECT * FROM MyWODs WHERE deleted = 0;")
+            .map_err(|_| {
+                AppError::Internal("Error reading movement session information".to_owned())
+            })?,
+        "MyWODs",
+        |row| {
             Ok(MyWOD {
+                primary_client_id: row.get(0)?,
+                primary_record_id: row.get(1)?,
                 title: row.get(4)?,
                 date: row.get(5)?,
                 score_type: row.get(6)?,
                 score: row.get(7)?,
+                personal_record: row.get(8)?,
                 as_prescribed: row.get(9)?,
                 description: row.get(10)?,
                 notes: row.get(11)?,
             })
-        })
-        .map_err(|_| AppError::Internal("Error reading workout scores".to_owned()))?
-        .for_each(|workout_score| {
-            if let Ok(workout_score) = workout_score {
-                workout_scores.push(workout_score);
-            }
-        });
+        },
+        &mut unreadable,
+    )
+    .map_err(|_| AppError::Internal("Error reading workout scores".to_owned()))?;
+
+    // READING FEEDS, which older backups do not have
+    let feeds: Vec<Feed> = match db.prepare("SELECT * FROM Feeds WHERE deleted = 0;") {
+        Ok(mut statement) => read_rows(
+            &mut statement,
+            "Feeds",
+            |row| {
+                Ok(Feed {
+                    primary_client_id: row.get(0)?,
+                    primary_record_id: row.get(1)?,
+                    title: row.get(4)?,
+                    url: row.get(5)?,
+                })
+            },
+            &mut unreadable,
+        )
+        .map_err(|_| AppError::Internal("Error reading feeds".to_owned()))?,
+        Err(e) => {
+            warn!("Not reading feeds from the backup: {}", e);
+            Vec::new()
+        }
+    };
 
     Ok(MyWodData {
         athlete,
@@ -222,9 +393,366 @@ pub async fn read_contents(filename: &str) -> WebResult<MyWodData> {
         movements,
         movement_scores,
         workout_scores,
+        feeds,
+        unreadable,
     })
 }
 
+/// Reads the rows of a backup query. A row that can not be read is reported
+/// as failed, with the error, instead of being left out of the import.
+fn read_rows<T>(
+    statement: &mut Statement,
+    table: &str,
+    map: impl Fn(&Row) -> rusqlite::Result<T>,
+    unreadable: &mut Vec<RowReport>,
+) -> rusqlite::Result<Vec<T>> {
+    let rows = statement.query_map(params![], |row| Ok((row_source_id(row), map(row))))?;
+
+    let mut read = Vec::new();
+    for row in rows {
+        let (source_id, error) = match row {
+            Ok((_, Ok(value))) => {
+                read.push(value);
+                continue;
+            }
+            Ok((source_id, Err(e))) => (source_id, e),
+            Err(e) => (None, e),
+        };
+        unreadable.push(RowReport {
+            table: table.to_owned(),
+            source_id: source_id.unwrap_or_else(|| "unknown".to_owned()),
+            outcome: RowOutcome::Failed,
+            reason: format!("The row could not be read: {}", error),
+            original: BTreeMap::new(),
+        });
+    }
+
+    Ok(read)
+}
+
+/// The source id of a backup row, every table starts with its primary key.
+fn row_source_id(row: &Row) -> Option<String> {
+    let primary_client_id: String = row.get(0).ok()?;
+    let primary_record_id: i32 = row.get(1).ok()?;
+    Some(source_id(&primary_client_id, primary_record_id))
+}
+
+/// The tables of a myWOD backup, as created by the myWOD app.
+const BACKUP_SCHEMA: &str = "
+CREATE TABLE Athlete(primaryClientID TEXT,primaryRecordID BIGINT,hasChangesForServer INTEGER,randomId CHAR(16) DEFAULT NULL,firstName TEXT,lastName TEXT,email TEXT,height INTEGER,weight INTEGER,gender INTEGER,dateOfBirth TEXT,boxName TEXT,boxID INTEGER,avatar BLOB,avatarMD5Sum TEXT DEFAULT '',avatarAwsUrl TEXT DEFAULT NULL,units INTEGER,everModifiedByAthlete INTEGER,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID));
+CREATE TABLE MyWODs(primaryClientID TEXT,primaryRecordID BIGINT,hasChangesForServer INTEGER,parseId TEXT DEFAULT NULL,title TEXT,date TEXT,scoreType TEXT,score TEXT,personalRecord INTEGER,asPrescribed INTEGER,description TEXT,notes TEXT,heartRate TEXT,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID));
+CREATE TABLE Movement(primaryClientID TEXT,primaryRecordID BIGINT,hasChangesForServer INTEGER,parseId TEXT DEFAULT NULL,name TEXT,type INTEGER,everModifiedByAthlete INTEGER,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID));
+CREATE TABLE MovementSessions(primaryClientID TEXT,primaryRecordID BIGINT,foreignMovementClientID TEXT,foreignMovementRecordID BIGINT,hasChangesForServer INTEGER,parseId TEXT DEFAULT NULL,date TEXT,measurementAValue NUMERIC,measurementAUnitsCode INTEGER,measurementB TEXT,sets TEXT,notes TEXT,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID),FOREIGN KEY(foreignMovementClientID, foreignMovementRecordID)REFERENCES Movement(primaryClientID, primaryRecordID)ON DELETE CASCADE);
+CREATE TABLE CustomWODs(primaryClientID TEXT,primaryRecordID BIGINT,hasChangesForServer INTEGER,parseId TEXT DEFAULT NULL,title TEXT,scoreType TEXT,description TEXT,everModifiedByAthlete INTEGER,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID));
+CREATE TABLE Feeds(primaryClientID TEXT,primaryRecordID BIGINT,hasChangesForServer INTEGER,parseId TEXT DEFAULT NULL,title TEXT,url TEXT,boxID INTEGER,username TEXT,password TEXT,everModifiedByAthlete INTEGER,deleted INTEGER,PRIMARY KEY(primaryClientID, primaryRecordID));
+";
+
+/// Removes the distance `read_contents` puts in front of the client id of a
+/// movement measured in time, e.g. `1000+initial`.
+fn strip_distance_from_client_id(client_id: &str) -> &str {
+    match client_id.split_once('+') {
+        Some((distance, rest)) if distance.parse::<f64>().is_ok() => rest,
+        _ => client_id,
+    }
+}
+
+/// Removes the distance `read_contents` puts in front of the name of a
+/// movement measured in time, e.g. `1000m Rowing`.
+fn strip_distance_from_name(name: &str) -> &str {
+    match parse_distance(name) {
+        Some(_) => name
+            .split_once(char::is_whitespace)
+            .map_or(name, |(_, rest)| rest.trim_start()),
+        None => name,
+    }
+}
+
+/// Writes data to a new myWOD backup, the inverse of `read_contents`.
+/// Movements measured in time are merged back into one movement, with the
+/// distance of every session kept on the session.
+pub fn write_contents(filename: &str, data: &MyWodData) -> WebResult<()> {
+    let db_error = |e: rusqlite::Error| AppError::Internal(format!("Error writing backup: {}", e));
+    let db = Connection::open(filename).map_err(db_error)?;
+    db.execute_batch(BACKUP_SCHEMA).map_err(db_error)?;
+
+    let athlete = &data.athlete;
+    db.execute(
+        "INSERT INTO Athlete VALUES (?1, 1, 0, NULL, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, NULL, ?10, '', NULL, ?11, 1, 0);",
+        params![
+            "wodbook",
+            athlete.first_name,
+            athlete.last_name,
+            athlete.email,
+            athlete.height,
+            athlete.weight,
+            unmap_gender(athlete.gender),
+            athlete.date_of_birth,
+            athlete.box_name,
+            athlete.avatar,
+            unmap_unit_system(athlete.unit_system),
+        ],
+    )
+    .map_err(db_error)?;
+
+    for workout in &data.workouts {
+        db.execute(
+            "INSERT INTO CustomWODs VALUES (?1, ?2, 0, NULL, ?3, ?4, ?5, 1, 0);",
+            params![
+                workout.primary_client_id,
+                workout.primary_record_id,
+                workout.title,
+                workout.score_type,
+                workout.description,
+            ],
+        )
+        .map_err(db_error)?;
+    }
+
+    for score in &data.workout_scores {
+        db.execute(
+            "INSERT INTO MyWODs VALUES (?1, ?2, 0, NULL, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 'NA', 0);",
+            params![
+                score.primary_client_id,
+                score.primary_record_id,
+                score.title,
+                score.date,
+                score.score_type,
+                score.score,
+                score.personal_record,
+                score.as_prescribed,
+                score.description,
+                score.notes,
+            ],
+        )
+        .map_err(db_error)?;
+    }
+
+    for movement in &data.movements {
+        let is_timed =
+            map_movement(movement.score_type, &movement.name) == MovementMeasurement::Time;
+        let (client_id, name) = if is_timed {
+            (
+                strip_distance_from_client_id(&movement.primary_client_id),
+                strip_distance_from_name(&movement.name),
+            )
+        } else {
+            (movement.primary_client_id.as_str(), movement.name.as_str())
+        };
+
+        // Timed movements of different distances share a row
+        db.execute(
+            "INSERT OR IGNORE INTO Movement VALUES (?1, ?2, 0, NULL, ?3, ?4, 1, 0);",
+            params![
+                client_id,
+                movement.primary_record_id,
+                name,
+                movement.score_type
+            ],
+        )
+        .map_err(db_error)?;
+    }
+
+    for session in &data.movement_scores {
+        db.execute(
+            "INSERT INTO MovementSessions VALUES (?1, ?2, ?3, ?4, 0, NULL, ?5, ?6, ?7, ?8, ?9, ?10, 0);",
+            params![
+                session.primary_client_id,
+                session.primary_record_id,
+                strip_distance_from_client_id(&session.foreign_movement_client_id),
+                session.foreign_movement_record_id,
+                session.date,
+                session.measurement_a_value,
+                session.measurement_a_units_code,
+                session.measurement_b,
+                session.sets,
+                session.notes,
+            ],
+        )
+        .map_err(db_error)?;
+    }
+
+    for feed in &data.feeds {
+        db.execute(
+            "INSERT INTO Feeds VALUES (?1, ?2, 0, NULL, ?3, ?4, NULL, NULL, NULL, 1, 0);",
+            params![
+                feed.primary_client_id,
+                feed.primary_record_id,
+                feed.title,
+                feed.url
+            ],
+        )
+        .map_err(db_error)?;
+    }
+
+    Ok(())
+}
+
+/// Gets the myWOD ids of a record, which are kept in the source id of
+/// records imported from myWOD. Other records are identified by their own id.
+pub fn primary_ids(source_id: Option<&str>, id: &str) -> (String, i32) {
+    let ids = source_id
+        .and_then(|source_id| source_id.strip_prefix("mywod:"))
+        .and_then(|ids| ids.rsplit_once(':'))
+        .and_then(|(client_id, record_id)| {
+            record_id
+                .parse::<i32>()
+                .ok()
+                .map(|record_id| (client_id.to_owned(), record_id))
+        });
+
+    ids.unwrap_or_else(|| (id.to_owned(), 1))
+}
+
+/// Gets the date of a timestamp in the 'yyyy-mm-dd' format myWOD uses.
+pub fn to_short_date(timestamp: &str) -> String {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(dt) => dt.format("%Y-%m-%d").to_string(),
+        Err(_) => timestamp.chars().take(10).collect(),
+    }
+}
+
+pub fn to_athlete(user: &User, avatar: Vec<u8>) -> Athlete {
+    Athlete {
+        first_name: user.first_name.to_owned(),
+        last_name: user.last_name.to_owned(),
+        email: user.email.to_owned(),
+        height: user.height,
+        weight: user.weight,
+        date_of_birth: user.date_of_birth.to_owned(),
+        box_name: user.box_name.to_owned(),
+        avatar,
+        unit_system: user.unit_system,
+        gender: user.gender,
+    }
+}
+
+pub fn to_custom_wod(workout: &WorkoutModel) -> CustomWOD {
+    let (primary_client_id, primary_record_id) =
+        primary_ids(workout.source_id.as_deref(), &workout.workout_id);
+
+    CustomWOD {
+        primary_client_id,
+        primary_record_id,
+        title: workout.name.to_owned(),
+        score_type: unmap_workout_measurement(workout.measurement).to_owned(),
+        description: workout.description.to_owned(),
+    }
+}
+
+pub fn to_mywod(workout: &WorkoutModel, score: &WorkoutScoreModel) -> MyWOD {
+    let (primary_client_id, primary_record_id) =
+        primary_ids(score.source_id.as_deref(), &score.workout_score_id);
+    let score_value = match workout.measurement {
+        WorkoutMeasurement::Time => seconds_to_time(score.score),
+        _ => score.score.to_string(),
+    };
+
+    MyWOD {
+        primary_client_id,
+        primary_record_id,
+        title: workout.name.to_owned(),
+        date: to_short_date(&score.created_at),
+        score_type: unmap_workout_measurement(workout.measurement).to_owned(),
+        score: score_value,
+        as_prescribed: score.rx as i32,
+        personal_record: score.personal_record as i32,
+        description: workout.description.to_owned(),
+        notes: score.notes.to_owned(),
+    }
+}
+
+/// Gets the myWOD movement for a movement, if myWOD has that kind of movement.
+pub fn to_movement(movement: &MovementModel) -> Option<Movement> {
+    let (primary_client_id, primary_record_id) =
+        primary_ids(movement.source_id.as_deref(), &movement.movement_id);
+
+    Some(Movement {
+        primary_client_id,
+        primary_record_id,
+        name: movement.name.to_owned(),
+        score_type: unmap_movement_measurement(movement.measurement)?,
+    })
+}
+
+pub fn to_movement_session(movement: &Movement, score: &MovementScoreModel) -> MovementSession {
+    let (primary_client_id, primary_record_id) =
+        primary_ids(score.source_id.as_deref(), &score.movement_score_id);
+    // Only metric units have known codes, other values are converted first
+    let units = |value: f64, unit: Option<Unit>| {
+        let (value, unit) = convert_to_system(value, unit, UnitSystem::Metric);
+        (
+            value,
+            unit.and_then(unmap_units_code).unwrap_or(NO_UNIT_CODE),
+        )
+    };
+
+    let ((measurement_a_value, measurement_a_units_code), measurement_b, sets) =
+        match map_movement(movement.score_type, &movement.name) {
+            MovementMeasurement::Weight => (
+                units(score.score, score.unit),
+                score.reps.to_string(),
+                score.sets.to_string(),
+            ),
+            // The number of jumps is kept where lifts keep their reps
+            MovementMeasurement::Height => (
+                units(score.score, score.unit),
+                score.sets.to_string(),
+                score.sets.to_string(),
+            ),
+            MovementMeasurement::Time => (
+                units(score.distance.unwrap_or(0.0), score.distance_unit),
+                seconds_to_time(score.score),
+                score.sets.to_string(),
+            ),
+            MovementMeasurement::Distance => (
+                units(score.score, score.unit),
+                seconds_to_time(score.duration.unwrap_or(0.0)),
+                score.sets.to_string(),
+            ),
+            MovementMeasurement::Calories => (
+                (score.score, NO_UNIT_CODE),
+                seconds_to_time(score.duration.unwrap_or(0.0)),
+                score.sets.to_string(),
+            ),
+            _ => (
+                (score.score, NO_UNIT_CODE),
+                "0:00".to_owned(),
+                score.sets.to_string(),
+            ),
+        };
+
+    MovementSession {
+        primary_client_id,
+        primary_record_id,
+        foreign_movement_client_id: movement.primary_client_id.to_owned(),
+        foreign_movement_record_id: movement.primary_record_id,
+        date: to_short_date(&score.created_at),
+        measurement_a_value,
+        measurement_a_units_code,
+        measurement_b,
+        sets,
+        notes: score.notes.to_owned(),
+    }
+}
+
+pub fn to_feed(feed: &FeedModel) -> Feed {
+    let (primary_client_id, primary_record_id) =
+        primary_ids(feed.source_id.as_deref(), &feed.feed_id);
+
+    Feed {
+        primary_client_id,
+        primary_record_id,
+        title: feed.title.to_owned(),
+        url: feed.url.to_owned(),
+    }
+}
+
+/// Identifies a myWOD record, so records imported before can be recognized
+/// when a newer backup of the same data is imported.
+pub fn source_id(primary_client_id: &str, primary_record_id: i32) -> String {
+    format!("mywod:{}:{}", primary_client_id, primary_record_id)
+}
+
 // Maps the string value from the myWOD database to a one_word string value.
 pub fn map_workout_measurement(score_type: &str) -> WorkoutMeasurement {
     match score_type {
@@ -241,6 +769,21 @@ pub fn map_workout_measurement(score_type: &str) -> WorkoutMeasurement {
     }
 }
 
+/// Maps a workout measurement to the score type myWOD shows it as.
+pub fn unmap_workout_measurement(measurement: WorkoutMeasurement) -> &'static str {
+    match measurement {
+        WorkoutMeasurement::Time => "For Time:",
+        WorkoutMeasurement::Distance => "For Distance:",
+        WorkoutMeasurement::Load => "For Load:",
+        WorkoutMeasurement::Repetitions => "For Repetitions:",
+        WorkoutMeasurement::Rounds => "For Rounds:",
+        WorkoutMeasurement::TimedRounds => "For Timed Rounds:",
+        WorkoutMeasurement::Tabata => "Tabata Scoring:",
+        WorkoutMeasurement::Total => "Total Score:",
+        WorkoutMeasurement::Unknown | WorkoutMeasurement::None => "No Score:",
+    }
+}
+
 /// Maps the score_type from the myWOD database to a string value
 pub fn map_movement_measurement(score_type: i32) -> MovementMeasurement {
     match score_type {
@@ -252,35 +795,132 @@ pub fn map_movement_measurement(score_type: i32) -> MovementMeasurement {
     }
 }
 
+/// Maps the type and the name of a myWOD movement to how it is measured.
+/// myWOD keeps a distance and a time for every session of a monostructural
+/// movement, the name tells which of them is set, e.g. "20 min Run".
+pub fn map_movement(score_type: i32, name: &str) -> MovementMeasurement {
+    match map_movement_measurement(score_type) {
+        MovementMeasurement::Time => MovementMeasurement::for_monostructural(name),
+        measurement => measurement,
+    }
+}
+
+/// Maps a movement measurement to the myWOD movement type, monostructural
+/// movements all have the same type.
+pub fn unmap_movement_measurement(measurement: MovementMeasurement) -> Option<i32> {
+    match measurement {
+        MovementMeasurement::Weight => Some(0),
+        MovementMeasurement::Time
+        | MovementMeasurement::Distance
+        | MovementMeasurement::Calories => Some(1),
+        MovementMeasurement::Reps => Some(2),
+        MovementMeasurement::Height => Some(3),
+        _ => None,
+    }
+}
+
+/// Maps the `units` setting of the myWOD athlete to a unit system.
+pub fn map_unit_system(units: i32) -> UnitSystem {
+    match units {
+        0 => UnitSystem::Imperial,
+        _ => UnitSystem::Metric,
+    }
+}
+
+pub fn unmap_unit_system(unit_system: UnitSystem) -> i32 {
+    match unit_system {
+        UnitSystem::Imperial => 0,
+        UnitSystem::Metric => 1,
+    }
+}
+
+/// Maps the `gender` of the myWOD athlete. Backups confirm 1 as male, 2 is
+/// taken to be female and anything else as not given.
+pub fn map_gender(gender: i32) -> Option<Gender> {
+    match gender {
+        1 => Some(Gender::Male),
+        2 => Some(Gender::Female),
+        _ => None,
+    }
+}
+
+pub fn unmap_gender(gender: Option<Gender>) -> i32 {
+    match gender {
+        Some(Gender::Male) => 1,
+        Some(Gender::Female) => 2,
+        None => 0,
+    }
+}
+
+/// Maps `measurementAUnitsCode` of a myWOD movement session to a unit. The
+/// codes are not documented; 1 (kg), 3 (cm), 5 (m) and 6 (km) are confirmed
+/// by backups, 8 is used for repetitions and has no unit. Other codes are
+/// left without a unit rather than guessed.
+pub fn map_units_code(code: u32) -> Option<Unit> {
+    match code {
+        1 => Some(Unit::Kg),
+        3 => Some(Unit::Cm),
+        5 => Some(Unit::M),
+        6 => Some(Unit::Km),
+        _ => None,
+    }
+}
+
+/// Maps `measurementAUnitsCode` to a unit for distances, codes of other
+/// units are left without a unit.
+fn length_unit(code: u32) -> Option<Unit> {
+    map_units_code(code).filter(|unit| unit.is_length())
+}
+
+/// The `measurementAUnitsCode` of sessions without a unit, like repetitions.
+const NO_UNIT_CODE: u32 = 8;
+
+/// Maps a unit to its `measurementAUnitsCode`, only for the confirmed codes.
+pub fn unmap_units_code(unit: Unit) -> Option<u32> {
+    match unit {
+        Unit::Kg => Some(1),
+        Unit::Cm => Some(3),
+        Unit::M => Some(5),
+        Unit::Km => Some(6),
+        _ => None,
+    }
+}
+
+/// Parses the score of a myWOD workout score into a number, which is seconds
+/// for timed workouts and otherwise the score without any `+reps`.
+pub fn parse_score_value(score: &MyWOD) -> Result<f64, MyWodParseError> {
+    if map_workout_measurement(&score.score_type) == WorkoutMeasurement::Time {
+        return parse_time(&score.score, "score");
+    }
+
+    // Rounds score could have '+reps' for the additional repetitions.
+    let score_value = match score.score.find('+') {
+        Some(idx) => &score.score[0..idx],
+        None => score.score.as_str(),
+    };
+
+    score_value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| MyWodParseError::InvalidNumber {
+            field: "score",
+            value: score.score.to_owned(),
+        })
+}
+
 /// Deals with all sorts of scoring inconsistencies between my models
 /// and the myWOD models, as well as how the scoring
 pub fn parse_workout_score(score: &MyWOD) -> CreateWorkoutScore {
     let mut note = "".to_string();
-    let workout_measurement = map_workout_measurement(&score.score_type);
-    let s = if workout_measurement == WorkoutMeasurement::Time {
-        time_to_seconds(&score.score)
-    } else {
-        // Rounds score could have '+reps' for the additional repetitions.
-        let score_value = match score.score.find('+') {
-            Some(idx) => score.score[0..idx].to_string(),
-            None => score.score.to_owned(),
-        };
-
-        match score_value.parse::<f64>() {
-            Ok(val) => val,
-            Err(e) => {
-                error!(
-                    "Tried to parse score from: {} (score: {}). Could not parse: {}",
-                    score.title,
-                    score.score,
-                    e.to_string()
-                );
-                note = format!(
-                    "Score could not be processed. Original value: {}",
-                    score.score
-                );
-                0.0
-            }
+    let s = match parse_score_value(score) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Tried to parse score from: {}. {}", score.title, e);
+            note = format!(
+                "Score could not be processed. Original value: {}",
+                score.score
+            );
+            0.0
         }
     };
 
@@ -291,76 +931,230 @@ pub fn parse_workout_score(score: &MyWOD) -> CreateWorkoutScore {
 
     CreateWorkoutScore {
         score: s,
+        // myWOD does not record which unit workout scores are in
+        unit: None,
         rx: score.as_prescribed != 0,
         notes: note.trim().to_string(),
-        created_at: parse_short_date(&score.date),
+        personal_record: score.personal_record != 0,
+        created_at: parse_short_date(&score.date).ok(),
+        source_id: Some(source_id(&score.primary_client_id, score.primary_record_id)),
+        import_id: None,
+    }
+}
+
+/// Reports a row whose date can not be parsed, as it is imported with the
+/// date of the import instead.
+pub fn date_report(row: &impl BackupRow, date: &str) -> Option<RowReport> {
+    match parse_short_date(date) {
+        Ok(_) => None,
+        Err(e) => Some(RowReport::new(
+            row,
+            RowOutcome::Altered,
+            &format!("{}, the date of the import is used instead", e),
+        )),
     }
 }
 
+/// Gets the scores for a movement from all sessions, along with reports
+/// of the sessions that could not be imported as they are.
 pub fn get_scores_for_movement(
     movement: &Movement,
     movement_scores: &[MovementSession],
-) -> Vec<CreateMovementScore> {
+) -> (Vec<CreateMovementScore>, Vec<RowReport>) {
     let movement_client_id = movement.primary_client_id.to_owned();
     let movement_id = movement.primary_record_id;
-    let movement_type = map_movement_measurement(movement.score_type);
+    let movement_type = map_movement(movement.score_type, &movement.name);
 
     let mut scores: Vec<CreateMovementScore> = Vec::new();
+    let mut report: Vec<RowReport> = Vec::new();
 
     for score in movement_scores {
         if score.foreign_movement_client_id == movement_client_id
             && score.foreign_movement_record_id == movement_id
         {
-            let new_score = adjust_movement_score_to_measurement(&movement_type, score);
-            if let Some(new_score) = new_score {
-                scores.push(new_score);
+            match adjust_movement_score_to_measurement(&movement_type, score) {
+                Ok(new_score) => {
+                    report.extend(date_report(score, &score.date));
+                    scores.push(new_score);
+                }
+                Err(e) => report.push(RowReport::new(score, RowOutcome::Failed, &e.to_string())),
             }
         }
     }
 
-    scores
+    (scores, report)
+}
+
+/// Parses a whole number field of a movement session, like the sets. myWOD
+/// leaves the field empty when it was not filled in, which counts as one.
+fn parse_count(value: &str, field: &'static str) -> Result<u32, MyWodParseError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(1);
+    }
+
+    let invalid = || MyWodParseError::InvalidNumber {
+        field,
+        value: value.to_owned(),
+    };
+    // Older backups store some counts as decimals, like "5.0"
+    match value.parse::<u32>() {
+        Ok(count) => Ok(count),
+        Err(_) => match value.parse::<f64>() {
+            Ok(count) if count >= 0.0 && count.fract() == 0.0 => Ok(count as u32),
+            _ => Err(invalid()),
+        },
+    }
+}
+
+/// Parses a time like `1:34:40`, `3:14.1` or `30` to seconds.
+pub fn parse_time(value: &str, field: &'static str) -> Result<f64, MyWodParseError> {
+    let value = value.trim();
+    let parts: Vec<&str> = value.split(':').collect();
+    let valid = parts.len() <= 3
+        && parts
+            .iter()
+            .all(|part| part.parse::<f64>().is_ok_and(|n| n >= 0.0));
+    if !valid {
+        return Err(MyWodParseError::InvalidTime {
+            field,
+            value: value.to_owned(),
+        });
+    }
+
+    Ok(time_to_seconds(value))
+}
+
+/// More sets than this in a session are taken as a typo, e.g. the reps in
+/// the sets field, and are not broken down into a list.
+const MAX_SESSION_SETS: u32 = 100;
+
+/// myWOD only logs the number of sets in a session, all with the same
+/// reps and load, so the set list is that set repeated.
+fn session_sets(sets: u32, reps: u32, load: f64) -> Vec<MovementSet> {
+    if sets > MAX_SESSION_SETS {
+        return vec![];
+    }
+
+    let set = MovementSet {
+        reps,
+        load,
+        rpe: None,
+        completed: true,
+    };
+    vec![set; sets as usize]
 }
 
 pub fn adjust_movement_score_to_measurement(
     score_type: &MovementMeasurement,
     score: &MovementSession,
-) -> Option<CreateMovementScore> {
-    let created_at = parse_short_date(score.date.as_ref());
+) -> Result<CreateMovementScore, MyWodParseError> {
+    let created_at = parse_short_date(score.date.as_ref()).ok();
+    let source_id = Some(source_id(&score.primary_client_id, score.primary_record_id));
 
     match score_type {
         // Lifting
-        MovementMeasurement::Weight => Some(CreateMovementScore {
-            score: score.measurement_a_value,
-            sets: score.sets.parse::<u32>().unwrap(),
-            reps: score.measurement_b.parse::<u32>().unwrap(),
-            notes: score.notes.trim().to_owned(),
-            created_at,
-        }),
+        MovementMeasurement::Weight => {
+            let sets = parse_count(&score.sets, "sets")?;
+            let reps = parse_count(&score.measurement_b, "reps")?;
+            Ok(CreateMovementScore {
+                score: Some(score.measurement_a_value),
+                sets,
+                reps,
+                set_details: session_sets(sets, reps, score.measurement_a_value),
+                unit: map_units_code(score.measurement_a_units_code),
+                distance: None,
+                distance_unit: None,
+                duration: None,
+                notes: score.notes.trim().to_owned(),
+                created_at,
+                source_id,
+                import_id: None,
+            })
+        }
         // Box jumps
-        MovementMeasurement::Height => Some(CreateMovementScore {
-            score: score.measurement_a_value,
-            sets: score.measurement_b.parse::<u32>().unwrap(),
+        MovementMeasurement::Height => {
+            let sets = parse_count(&score.measurement_b, "sets")?;
+            Ok(CreateMovementScore {
+                score: Some(score.measurement_a_value),
+                sets,
+                reps: 1,
+                set_details: session_sets(sets, 1, score.measurement_a_value),
+                unit: map_units_code(score.measurement_a_units_code),
+                distance: None,
+                distance_unit: None,
+                duration: None,
+                notes: score.notes.trim().to_owned(),
+                created_at,
+                source_id,
+                import_id: None,
+            })
+        }
+        // Rowing, running, something for a set distance
+        MovementMeasurement::Time => Ok(CreateMovementScore {
+            score: Some(parse_time(&score.measurement_b, "time")?),
+            sets: parse_count(&score.sets, "sets")?,
             reps: 1,
+            set_details: vec![],
+            unit: None,
+            distance: Some(score.measurement_a_value),
+            distance_unit: length_unit(score.measurement_a_units_code),
+            duration: None,
             notes: score.notes.trim().to_owned(),
             created_at,
+            source_id,
+            import_id: None,
         }),
-        // Rowing, running, something for a set distance
-        MovementMeasurement::Time => Some(CreateMovementScore {
-            score: time_to_seconds(&score.measurement_b),
-            sets: score.sets.parse::<u32>().unwrap(),
+        // Running, rowing, something for a set time
+        MovementMeasurement::Distance => Ok(CreateMovementScore {
+            score: Some(score.measurement_a_value),
+            sets: parse_count(&score.sets, "sets")?,
             reps: 1,
+            set_details: vec![],
+            unit: length_unit(score.measurement_a_units_code),
+            distance: None,
+            distance_unit: None,
+            duration: Some(parse_time(&score.measurement_b, "time")?),
             notes: score.notes.trim().to_owned(),
             created_at,
+            source_id,
+            import_id: None,
         }),
-        // Double unders
-        MovementMeasurement::Reps => Some(CreateMovementScore {
-            score: score.measurement_a_value,
-            sets: score.sets.parse::<u32>().unwrap(),
-            reps: score.measurement_a_value as u32,
+        // Assault bike, ski erg, calories in a set time
+        MovementMeasurement::Calories => Ok(CreateMovementScore {
+            score: Some(score.measurement_a_value),
+            sets: parse_count(&score.sets, "sets")?,
+            reps: 1,
+            set_details: vec![],
+            unit: None,
+            distance: None,
+            distance_unit: None,
+            duration: Some(parse_time(&score.measurement_b, "time")?),
             notes: score.notes.trim().to_owned(),
             created_at,
+            source_id,
+            import_id: None,
         }),
-        _ => None,
+        // Double unders
+        MovementMeasurement::Reps => {
+            let sets = parse_count(&score.sets, "sets")?;
+            let reps = score.measurement_a_value as u32;
+            Ok(CreateMovementScore {
+                score: Some(score.measurement_a_value),
+                sets,
+                reps,
+                set_details: session_sets(sets, reps, 0.0),
+                unit: None,
+                distance: None,
+                distance_unit: None,
+                duration: None,
+                notes: score.notes.trim().to_owned(),
+                created_at,
+                source_id,
+                import_id: None,
+            })
+        }
+        _ => Err(MyWodParseError::Unscored(*score_type)),
     }
 }
 
@@ -371,20 +1165,20 @@ pub fn adjust_movement_score_to_measurement(
 /// ```
 /// parse_short_date("1991-12-06"); // "1991-12-06T00:00:00+00:00"
 /// ```
-pub fn parse_short_date(short_date: &str) -> Option<String> {
+pub fn parse_short_date(short_date: &str) -> Result<String, MyWodParseError> {
     let date_parsed = NaiveDateTime::parse_from_str(
         format!("{} 00:00:00", short_date).as_ref(),
         "%Y-%m-%d %H:%M:%S",
     );
     match date_parsed {
-        Ok(dt) => Some(DateTime::<Utc>::from_utc(dt, Utc).to_rfc3339()),
+        Ok(dt) => Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc).to_rfc3339()),
         Err(e) => {
             warn!(
                 "Could not parse date from mywod entry: {}. Error: {}",
                 short_date,
                 e.to_string()
             );
-            None
+            Err(MyWodParseError::InvalidDate(short_date.to_owned()))
         }
     }
 }
@@ -394,21 +1188,39 @@ mod tests {
     use super::*;
     use futures_await_test::async_test;
 
-    #[test]
-    #[ignore = "fs calls are not working properly in ci"]
-    fn test_save_avatar() {
-        let res = save_avatar("user_id", vec![0, 1, 2, 3]);
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), "/avatars/user_id.png");
-    }
-
     #[async_test]
     async fn test_read_contents() -> WebResult<()> {
-        let res = read_contents("data.mywod").await;
+        let res = read_contents("data.mywod");
         assert!(res.is_ok());
         Ok(())
     }
 
+    #[actix_web::test]
+    async fn test_validate_mywod_backup() -> WebResult<()> {
+        let backup = fs::read("data.mywod").unwrap();
+        validate_mywod_backup(backup.clone()).await?;
+
+        let not_sqlite = validate_mywod_backup(b"Date,Workout,Score".to_vec()).await;
+        assert!(matches!(not_sqlite, Err(AppError::UnsupportedMediaType(_))));
+
+        let mut corrupt = SQLITE_HEADER.to_vec();
+        corrupt.extend_from_slice(&[0xff; 200]);
+        let corrupt = validate_mywod_backup(corrupt).await;
+        assert!(matches!(corrupt, Err(AppError::UnprocessableEntity(_))));
+
+        let filename = write_temporary_file(&backup)?;
+        Connection::open(&filename)
+            .and_then(|db| db.execute("DROP TABLE MyWODs;", params![]))
+            .unwrap();
+        let without_scores = fs::read(&filename).unwrap();
+        delete_local_file(filename)?;
+        match validate_mywod_backup(without_scores).await {
+            Err(AppError::UnprocessableEntity(message)) => assert!(message.ends_with("MyWODs")),
+            _ => panic!("A backup without scores should not be valid"),
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_map_workout_measurement() {
         assert_eq!(
@@ -465,6 +1277,8 @@ mod tests {
     #[test]
     fn test_adjust_movement_score_to_measurement_weight() {
         let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 1,
             foreign_movement_client_id: "initial".to_string(),
             foreign_movement_record_id: 12,
             measurement_a_value: 70.0,
@@ -476,16 +1290,47 @@ mod tests {
         };
         let res =
             adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &score).unwrap();
-        assert_eq!(res.score, 70.0);
+        assert_eq!(res.score, Some(70.0));
+        assert_eq!(res.unit, Some(Unit::Kg));
         assert_eq!(res.sets, 1);
         assert_eq!(res.reps, 1);
         assert_eq!(res.notes, "back squat");
         assert_eq!(res.created_at.unwrap(), "2012-10-10T00:00:00+00:00");
     }
 
+    #[test]
+    fn test_adjust_movement_score_to_measurement_weight_sets() {
+        let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 2,
+            foreign_movement_client_id: "initial".to_string(),
+            foreign_movement_record_id: 12,
+            measurement_a_value: 100.0,
+            measurement_a_units_code: 1,
+            measurement_b: 5.to_string(),
+            sets: 5.to_string(),
+            notes: "".to_string(),
+            date: "2012-10-10".to_owned(),
+        };
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &score).unwrap();
+        assert_eq!(res.sets, 5);
+        assert_eq!(res.reps, 5);
+        assert_eq!(res.set_details.len(), 5);
+        assert!(res
+            .set_details
+            .iter()
+            .all(|set| set.reps == 5 && set.load == 100.0 && set.completed));
+
+        assert_eq!(session_sets(MAX_SESSION_SETS, 1, 100.0).len(), 100);
+        assert!(session_sets(4_000_000_000, 1, 100.0).is_empty());
+    }
+
     #[test]
     fn test_adjust_movement_score_to_measurement_height() {
         let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 3,
             foreign_movement_client_id: "initial".to_string(),
             foreign_movement_record_id: 13,
             measurement_a_value: 126.0,
@@ -497,7 +1342,7 @@ mod tests {
         };
         let res =
             adjust_movement_score_to_measurement(&MovementMeasurement::Height, &score).unwrap();
-        assert_eq!(res.score, 126.0);
+        assert_eq!(res.score, Some(126.0));
         assert_eq!(res.sets, 1);
         assert_eq!(res.reps, 1);
         assert_eq!(res.notes, "box jumps");
@@ -505,28 +1350,99 @@ mod tests {
     }
 
     #[test]
-    fn test_adjust_movement_score_to_measurement_distance() {
+    fn test_adjust_movement_score_to_measurement_time() {
         let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 4,
             foreign_movement_client_id: "initial".to_string(),
             foreign_movement_record_id: 14,
             measurement_a_value: 1000.0,
-            measurement_a_units_code: 1,
+            measurement_a_units_code: 5,
             measurement_b: "2:50".to_string(),
             sets: 1.to_string(),
             notes: "1000m rowing".to_string(),
             date: "2012-10-12".to_owned(),
         };
         let res = adjust_movement_score_to_measurement(&MovementMeasurement::Time, &score).unwrap();
-        assert_eq!(res.score, time_to_seconds("2:50"));
+        assert_eq!(res.score, Some(time_to_seconds("2:50")));
+        assert_eq!(res.distance, Some(1000.0));
+        assert_eq!(res.distance_unit, Some(Unit::M));
         assert_eq!(res.sets, 1);
         assert_eq!(res.reps, 1);
         assert_eq!(res.notes, "1000m rowing");
         assert_eq!(res.created_at.unwrap(), "2012-10-12T00:00:00+00:00");
     }
 
+    #[test]
+    fn test_adjust_movement_score_to_measurement_distance() {
+        let mut score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 4,
+            foreign_movement_client_id: "initial".to_string(),
+            foreign_movement_record_id: 14,
+            measurement_a_value: 4.2,
+            measurement_a_units_code: 6,
+            measurement_b: "20:00".to_string(),
+            sets: 1.to_string(),
+            notes: "".to_string(),
+            date: "2012-10-12".to_owned(),
+        };
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Distance, &score).unwrap();
+        assert_eq!(res.score, Some(4.2));
+        assert_eq!(res.unit, Some(Unit::Km));
+        assert_eq!(res.duration, Some(1200.0));
+        assert_eq!(res.distance, None);
+
+        // Only lengths are taken as the unit of a distance
+        score.measurement_a_units_code = 1;
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Distance, &score).unwrap();
+        assert_eq!(res.unit, None);
+        let res = adjust_movement_score_to_measurement(&MovementMeasurement::Time, &score).unwrap();
+        assert_eq!(res.distance_unit, None);
+    }
+
+    #[test]
+    fn test_adjust_movement_score_to_measurement_calories() {
+        let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 4,
+            foreign_movement_client_id: "initial".to_string(),
+            foreign_movement_record_id: 15,
+            measurement_a_value: 21.0,
+            measurement_a_units_code: 8,
+            measurement_b: "1:00".to_string(),
+            sets: 1.to_string(),
+            notes: "".to_string(),
+            date: "2012-10-12".to_owned(),
+        };
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Calories, &score).unwrap();
+        assert_eq!(res.score, Some(21.0));
+        assert_eq!(res.unit, None);
+        assert_eq!(res.duration, Some(60.0));
+    }
+
+    #[test]
+    fn test_map_movement() {
+        assert_eq!(map_movement(1, "1000m Rowing"), MovementMeasurement::Time);
+        assert_eq!(map_movement(1, "20 min Run"), MovementMeasurement::Distance);
+        assert_eq!(
+            map_movement(1, "1 min Bike Calories"),
+            MovementMeasurement::Calories
+        );
+        assert_eq!(
+            map_movement(0, "20 min Squats"),
+            MovementMeasurement::Weight
+        );
+    }
+
     #[test]
     fn test_adjust_movement_score_to_measurement_reps() {
         let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 5,
             foreign_movement_client_id: "initial".to_string(),
             foreign_movement_record_id: 15,
             measurement_a_value: 7.0,
@@ -537,7 +1453,7 @@ mod tests {
             date: "2012-10-13".to_owned(),
         };
         let res = adjust_movement_score_to_measurement(&MovementMeasurement::Reps, &score).unwrap();
-        assert_eq!(res.score, 7.0);
+        assert_eq!(res.score, Some(7.0));
         assert_eq!(res.sets, 1);
         assert_eq!(res.reps, 7);
         assert_eq!(res.notes, "hspu");
@@ -547,6 +1463,8 @@ mod tests {
     #[test]
     fn test_adjust_movement_score_to_measurement_invalid_score_type() {
         let score = MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 6,
             foreign_movement_client_id: "".to_string(),
             foreign_movement_record_id: 0,
             measurement_a_value: 0.0,
@@ -557,24 +1475,166 @@ mod tests {
             date: "2012-10-13".to_owned(),
         };
         let res = adjust_movement_score_to_measurement(&MovementMeasurement::None, &score);
-        assert!(res.is_none());
+        assert_eq!(
+            res.unwrap_err(),
+            MyWodParseError::Unscored(MovementMeasurement::None)
+        );
+    }
+
+    fn session(measurement_b: &str, sets: &str) -> MovementSession {
+        MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 1,
+            foreign_movement_client_id: "initial".to_owned(),
+            foreign_movement_record_id: 1,
+            measurement_a_value: 100.0,
+            measurement_a_units_code: 1,
+            measurement_b: measurement_b.to_owned(),
+            sets: sets.to_owned(),
+            notes: "".to_owned(),
+            date: "2017-11-19".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_adjust_movement_score_empty_counts() {
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &session("", ""))
+                .unwrap();
+        assert_eq!(res.sets, 1);
+        assert_eq!(res.reps, 1);
+        assert_eq!(res.set_details.len(), 1);
+
+        let res = adjust_movement_score_to_measurement(
+            &MovementMeasurement::Weight,
+            &session("5.0", " 3"),
+        )
+        .unwrap();
+        assert_eq!(res.sets, 3);
+        assert_eq!(res.reps, 5);
+    }
+
+    #[test]
+    fn test_adjust_movement_score_invalid_fields() {
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &session("5", "x"));
+        assert_eq!(
+            res.unwrap_err(),
+            MyWodParseError::InvalidNumber {
+                field: "sets",
+                value: "x".to_owned()
+            }
+        );
+
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &session("-1", "1"));
+        assert_eq!(
+            res.unwrap_err(),
+            MyWodParseError::InvalidNumber {
+                field: "reps",
+                value: "-1".to_owned()
+            }
+        );
+
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Time, &session("", "1"));
+        assert_eq!(
+            res.unwrap_err(),
+            MyWodParseError::InvalidTime {
+                field: "time",
+                value: "".to_owned()
+            }
+        );
+
+        let res = adjust_movement_score_to_measurement(
+            &MovementMeasurement::Time,
+            &session("1:2:3:4", "1"),
+        );
+        assert!(matches!(
+            res.unwrap_err(),
+            MyWodParseError::InvalidTime { .. }
+        ));
+    }
+
+    #[test]
+    fn test_adjust_movement_score_invalid_date() {
+        let mut score = session("1", "1");
+        score.date = "".to_owned();
+        let res =
+            adjust_movement_score_to_measurement(&MovementMeasurement::Weight, &score).unwrap();
+        assert!(res.created_at.is_none());
+    }
+
+    #[async_test]
+    async fn test_read_contents_sessions_parse() -> WebResult<()> {
+        let data = read_contents("data.mywod")?;
+
+        // Every session in the backup is importable, including the quirks:
+        // reps sessions with '0:00' as measurementB and rows over an hour
+        for movement in &data.movements {
+            let (scores, report) = get_scores_for_movement(movement, &data.movement_scores);
+            assert!(report.is_empty(), "{:?}", report);
+            assert!(!scores.is_empty());
+        }
+
+        let deadlifts = data
+            .movements
+            .iter()
+            .find(|m| m.name == "Dead Lift")
+            .unwrap();
+        let (scores, _) = get_scores_for_movement(deadlifts, &data.movement_scores);
+        let eleven_reps = scores.iter().find(|s| s.reps == 11).unwrap();
+        assert_eq!(eleven_reps.score, Some(162.5));
+        assert_eq!(eleven_reps.set_details.len(), 1);
+
+        let half_marathon = data
+            .movements
+            .iter()
+            .find(|m| m.name == "21.1km Rowing")
+            .unwrap();
+        let (scores, _) = get_scores_for_movement(half_marathon, &data.movement_scores);
+        assert_eq!(scores[0].score, Some(5680.0));
+        assert_eq!(scores[0].distance_unit, Some(Unit::Km));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_read_contents_workout_scores_parse() -> WebResult<()> {
+        let data = read_contents("data.mywod")?;
+
+        for score in &data.workout_scores {
+            assert!(parse_score_value(score).is_ok(), "{}", score.score);
+            assert!(parse_short_date(&score.date).is_ok(), "{}", score.date);
+        }
+
+        // Rounds plus reps only count the rounds
+        let rounds = data
+            .workout_scores
+            .iter()
+            .find(|s| s.score == "10+15")
+            .unwrap();
+        assert_eq!(parse_score_value(rounds), Ok(10.0));
+        Ok(())
     }
 
     #[test]
     fn test_parse_workout() {
         let score = MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 7,
             title: "181017".to_owned(),
             date: "2017-11-18".to_owned(),
             score_type: "For Time:".to_owned(),
             score: "14:20".to_owned(),
             as_prescribed: 1,
+            personal_record: 0,
             description: "5 rounds:\n15 ft rope climb, 3 ascents,\n10 toes-to-bar,\n21 walking lunges with 20.4/13.6kg plate overhead,\n400 meter run".to_owned(),
             notes: "".to_owned(),
         };
 
         let res = parse_workout_score(&score);
         assert_eq!(res.score, time_to_seconds("14:20"));
-        assert_eq!(res.rx, true);
+        assert!(res.rx);
         assert_eq!(res.notes, "");
         assert_eq!(res.created_at.unwrap(), "2017-11-18T00:00:00+00:00");
     }
@@ -582,18 +1642,21 @@ mod tests {
     #[test]
     fn test_parse_workout_rounds() {
         let score = MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 8,
             title: "Cindy".to_owned(),
             date: "2010-12-27".to_owned(),
             score_type: "For Rounds:".to_owned(),
             score: "20".to_owned(),
             as_prescribed: 1,
+            personal_record: 0,
             description: "20 min AMRAP:\n5 Pull-ups,\n10 Push-ups,\n15 Squats.\n".to_owned(),
             notes: "".to_owned(),
         };
 
         let res = parse_workout_score(&score);
         assert_eq!(res.score, 20.0);
-        assert_eq!(res.rx, true);
+        assert!(res.rx);
         assert_eq!(res.notes, "");
         assert_eq!(res.created_at.unwrap(), "2010-12-27T00:00:00+00:00");
     }
@@ -601,22 +1664,85 @@ mod tests {
     #[test]
     fn test_parse_workout_rounds_plus_reps() {
         let score = MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 9,
             title: "Death By Clean & Jerk".to_owned(),
             date: "2017-11-18".to_owned(),
             score_type: "For Rounds:".to_owned(),
             score: "10+6".to_owned(),
             as_prescribed: 1,
+            personal_record: 0,
             description: "1 Rep minute 1,\n2 reps minute 2,\n3 reps minute 3,\n….. etc. for as long as you are able to complete the reps in the minute allotted.\n\nWeight (135/95#)\n".to_owned(),
             notes: "".to_owned(),
         };
 
         let res = parse_workout_score(&score);
         assert_eq!(res.score, 10.0);
-        assert_eq!(res.rx, true);
+        assert!(res.rx);
         assert_eq!(res.notes, "");
         assert_eq!(res.created_at.unwrap(), "2017-11-18T00:00:00+00:00");
     }
 
+    #[test]
+    fn test_parse_workout_invalid_score() {
+        let score = MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 10,
+            title: "Karen".to_owned(),
+            date: "2017-11-19".to_owned(),
+            score_type: "For Repetitions:".to_owned(),
+            score: "all of them".to_owned(),
+            as_prescribed: 0,
+            personal_record: 0,
+            description: "150 Wall balls".to_owned(),
+            notes: "Ouch".to_owned(),
+        };
+
+        assert_eq!(
+            parse_score_value(&score),
+            Err(MyWodParseError::InvalidNumber {
+                field: "score",
+                value: "all of them".to_owned()
+            })
+        );
+        let res = parse_workout_score(&score);
+        assert_eq!(res.score, 0.0);
+        assert_eq!(
+            res.notes,
+            "Score could not be processed. Original value: all of them\n\nOuch"
+        );
+    }
+
+    #[test]
+    fn test_get_scores_for_movement_invalid_session() {
+        let movement = Movement {
+            primary_client_id: "initial".to_owned(),
+            primary_record_id: 12,
+            name: "Back Squat".to_owned(),
+            score_type: 0,
+        };
+        let movement_scores = vec![MovementSession {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 11,
+            foreign_movement_client_id: "initial".to_owned(),
+            foreign_movement_record_id: 12,
+            measurement_a_value: 100.0,
+            measurement_a_units_code: 1,
+            measurement_b: "five".to_owned(),
+            sets: "1".to_owned(),
+            notes: "".to_owned(),
+            date: "2017-11-19".to_owned(),
+        }];
+
+        let (scores, report) = get_scores_for_movement(&movement, &movement_scores);
+        assert!(scores.is_empty());
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].table, "MovementSessions");
+        assert_eq!(report[0].source_id, "mywod:client-id:11");
+        assert_eq!(report[0].outcome, RowOutcome::Failed);
+        assert_eq!(report[0].original["measurementB"], "five");
+    }
+
     #[test]
     fn test_get_scores_for_movement() {
         let movement = Movement {
@@ -629,6 +1755,8 @@ mod tests {
 
         let movement_scores: Vec<MovementSession> = vec![
             MovementSession {
+                primary_client_id: "client-id".to_owned(),
+                primary_record_id: 10,
                 foreign_movement_client_id:
                     "i-1fa65b03fbd343ef86270ad1bad1c369-2017-01-02 17:32:34 +0000".to_owned(),
                 foreign_movement_record_id: 5,
@@ -640,6 +1768,8 @@ mod tests {
                 notes: "HSPU score".to_owned(),
             },
             MovementSession {
+                primary_client_id: "client-id".to_owned(),
+                primary_record_id: 11,
                 foreign_movement_client_id: "initial".to_owned(),
                 foreign_movement_record_id: 3,
                 date: "2017-04-11".to_owned(),
@@ -651,9 +1781,10 @@ mod tests {
             },
         ];
 
-        let res = get_scores_for_movement(&movement, &movement_scores);
+        let (res, report) = get_scores_for_movement(&movement, &movement_scores);
         assert_eq!(res.len(), 1);
-        let my_score: &CreateMovementScore = res.get(0).unwrap();
+        assert!(report.is_empty());
+        let my_score: &CreateMovementScore = res.first().unwrap();
         assert_eq!(&my_score.notes, "HSPU score");
     }
 
@@ -677,6 +1808,8 @@ mod tests {
 
         let movement_scores: Vec<MovementSession> = vec![
             MovementSession {
+                primary_client_id: "client-id".to_owned(),
+                primary_record_id: 12,
                 foreign_movement_client_id:
                     "1000+i-1fa65b03fbd343ef86270ad1bad1c369-2017-01-02 17:32:34 +0000".to_string(),
                 foreign_movement_record_id: 6,
@@ -688,6 +1821,8 @@ mod tests {
                 notes: "".to_string(),
             },
             MovementSession {
+                primary_client_id: "client-id".to_owned(),
+                primary_record_id: 13,
                 foreign_movement_client_id:
                     "21.1+i-1fa65b03fbd343ef86270ad1bad1c369-2017-01-02 17:32:34 +0000".to_string(),
                 foreign_movement_record_id: 6,
@@ -700,20 +1835,351 @@ mod tests {
             },
         ];
 
-        let res1 = get_scores_for_movement(&movement1, &movement_scores);
+        let (res1, _) = get_scores_for_movement(&movement1, &movement_scores);
         assert_eq!(res1.len(), 1);
-        let my_score: &CreateMovementScore = res1.get(0).unwrap();
-        assert_eq!(my_score.score, time_to_seconds("3:14.1"));
+        let my_score: &CreateMovementScore = res1.first().unwrap();
+        assert_eq!(my_score.score, Some(time_to_seconds("3:14.1")));
 
-        let res2 = get_scores_for_movement(&movement2, &movement_scores);
+        let (res2, _) = get_scores_for_movement(&movement2, &movement_scores);
         assert_eq!(res2.len(), 1);
-        let my_score: &CreateMovementScore = res2.get(0).unwrap();
-        assert_eq!(my_score.score, time_to_seconds("1:34:40"));
+        let my_score: &CreateMovementScore = res2.first().unwrap();
+        assert_eq!(my_score.score, Some(time_to_seconds("1:34:40")));
+    }
+
+    #[async_test]
+    async fn test_read_contents_reports_unreadable_rows() -> WebResult<()> {
+        let original = read_contents("data.mywod")?;
+        let filename = std::env::temp_dir()
+            .join(format!("{}.mywod", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        fs::copy("data.mywod", &filename).unwrap();
+        Connection::open(&filename)
+            .and_then(|db| {
+                db.execute(
+                    "INSERT INTO MyWODs (primaryClientID, primaryRecordID, title, date, deleted) VALUES ('broken', 7, NULL, '2022-01-01', 0);",
+                    params![],
+                )
+            })
+            .unwrap();
+
+        let copy = read_contents(&filename);
+        fs::remove_file(&filename).unwrap();
+        let copy = copy?;
+
+        assert_eq!(copy.workout_scores.len(), original.workout_scores.len());
+        assert_eq!(copy.unreadable.len(), 1);
+        let row = &copy.unreadable[0];
+        assert_eq!(row.table, "MyWODs");
+        assert_eq!(row.source_id, source_id("broken", 7));
+        assert_eq!(row.outcome, RowOutcome::Failed);
+        assert!(row.reason.starts_with("The row could not be read: "));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_read_contents_movements_once() -> WebResult<()> {
+        let res = read_contents("data.mywod")?;
+        let mut ids: Vec<String> = res
+            .movements
+            .iter()
+            .map(|m| source_id(&m.primary_client_id, m.primary_record_id))
+            .collect();
+        let movement_count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), movement_count);
+        assert_eq!(movement_count, 18);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_read_contents_skips_deleted() -> WebResult<()> {
+        let res = read_contents("data.mywod")?;
+
+        // The sample workout and the only feed were deleted by the athlete
+        assert!(res
+            .workouts
+            .iter()
+            .all(|w| w.primary_client_id != "initial" || w.primary_record_id != 1));
+        assert!(res.feeds.is_empty());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_read_contents_athlete_and_records() -> WebResult<()> {
+        let res = read_contents("data.mywod")?;
+        assert_eq!(res.athlete.gender, Some(Gender::Male));
+
+        let records = res
+            .workout_scores
+            .iter()
+            .filter(|score| score.personal_record != 0)
+            .count();
+        assert_eq!(records, 94);
+        let record = res
+            .workout_scores
+            .iter()
+            .find(|score| score.personal_record != 0)
+            .unwrap();
+        assert!(parse_workout_score(record).personal_record);
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_id() {
+        assert_eq!(source_id("initial", 12), "mywod:initial:12");
+    }
+
+    #[test]
+    fn test_map_units_code() {
+        assert_eq!(map_units_code(1), Some(Unit::Kg));
+        assert_eq!(map_units_code(3), Some(Unit::Cm));
+        assert_eq!(map_units_code(5), Some(Unit::M));
+        assert_eq!(map_units_code(6), Some(Unit::Km));
+        assert_eq!(map_units_code(8), None);
+        assert_eq!(map_units_code(0), None);
+        assert_eq!(map_units_code(7), None);
+    }
+
+    #[test]
+    fn test_unmap_units_code() {
+        assert_eq!(unmap_units_code(Unit::Kg), Some(1));
+        assert_eq!(unmap_units_code(Unit::Km), Some(6));
+        assert_eq!(unmap_units_code(Unit::Lb), None);
+    }
+
+    #[test]
+    fn test_map_unit_system() {
+        assert_eq!(map_unit_system(0), UnitSystem::Imperial);
+        assert_eq!(map_unit_system(1), UnitSystem::Metric);
+    }
+
+    fn sort_movements(data: &mut MyWodData) {
+        data.movements.sort_by(|a, b| {
+            (&a.primary_client_id, a.primary_record_id)
+                .cmp(&(&b.primary_client_id, b.primary_record_id))
+        });
+    }
+
+    #[async_test]
+    async fn test_write_contents_round_trip() -> WebResult<()> {
+        let mut original = read_contents("data.mywod")?;
+        let filename = std::env::temp_dir()
+            .join(format!("{}.mywod", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+
+        write_contents(&filename, &original)?;
+        let copy = read_contents(&filename);
+        delete_local_file(filename)?;
+        let mut copy = copy?;
+
+        sort_movements(&mut original);
+        sort_movements(&mut copy);
+        assert_eq!(copy.athlete, original.athlete);
+        assert_eq!(copy.workouts, original.workouts);
+        assert_eq!(copy.workout_scores, original.workout_scores);
+        assert_eq!(copy.movements, original.movements);
+        assert_eq!(copy.movement_scores, original.movement_scores);
+        assert_eq!(copy.feeds, original.feeds);
+        Ok(())
+    }
+
+    fn stored_workout(workout: &CustomWOD) -> WorkoutModel {
+        WorkoutModel {
+            workout_id: "workout-id".to_owned(),
+            user_id: "user-id".to_owned(),
+            forked_from: None,
+            source_id: Some(source_id(
+                &workout.primary_client_id,
+                workout.primary_record_id,
+            )),
+            import_id: None,
+            name: workout.title.to_owned(),
+            measurement: map_workout_measurement(&workout.score_type),
+            description: workout.description.to_owned(),
+            is_public: false,
+            created_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    #[async_test]
+    async fn test_export_workouts_round_trip() -> WebResult<()> {
+        let data = read_contents("data.mywod")?;
+
+        for workout in &data.workouts {
+            let stored = stored_workout(workout);
+            assert_eq!(&to_custom_wod(&stored), workout);
+        }
+
+        for score in &data.workout_scores {
+            let workout = stored_workout(&CustomWOD {
+                primary_client_id: "client-id".to_owned(),
+                primary_record_id: 1,
+                title: score.title.to_owned(),
+                score_type: score.score_type.to_owned(),
+                description: score.description.to_owned(),
+            });
+            let imported = parse_workout_score(score);
+            let stored = WorkoutScoreModel {
+                workout_score_id: "workout-score-id".to_owned(),
+                workout_id: "workout-id".to_owned(),
+                user_id: "user-id".to_owned(),
+                score: imported.score,
+                unit: imported.unit,
+                rx: imported.rx,
+                notes: imported.notes,
+                personal_record: imported.personal_record,
+                source_id: imported.source_id,
+                import_id: None,
+                created_at: imported.created_at.unwrap(),
+                updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            };
+
+            let exported = to_mywod(&workout, &stored);
+            let reimported = parse_workout_score(&exported);
+            assert_eq!(reimported.score, stored.score, "{}", score.score);
+            assert_eq!(reimported.rx, stored.rx);
+            assert_eq!(reimported.personal_record, stored.personal_record);
+            assert_eq!(reimported.notes, stored.notes);
+            assert_eq!(reimported.created_at, Some(stored.created_at));
+            assert_eq!(reimported.source_id, stored.source_id);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_export_movements_round_trip() -> WebResult<()> {
+        let data = read_contents("data.mywod")?;
+
+        for movement in &data.movements {
+            let stored = MovementModel {
+                movement_id: "movement-id".to_owned(),
+                user_id: "user-id".to_owned(),
+                forked_from: None,
+                source_id: Some(source_id(
+                    &movement.primary_client_id,
+                    movement.primary_record_id,
+                )),
+                import_id: None,
+                name: movement.name.to_owned(),
+                measurement: map_movement(movement.score_type, &movement.name),
+                is_public: false,
+                created_at: "2020-01-01T00:00:00+00:00".to_owned(),
+                updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            };
+            let exported = to_movement(&stored).unwrap();
+            assert_eq!(&exported, movement);
+
+            let (scores, _) = get_scores_for_movement(movement, &data.movement_scores);
+            for imported in scores {
+                let stored = MovementScoreModel {
+                    movement_score_id: "movement-score-id".to_owned(),
+                    movement_id: "movement-id".to_owned(),
+                    user_id: "user-id".to_owned(),
+                    score: imported.score.unwrap(),
+                    sets: imported.sets,
+                    reps: imported.reps,
+                    set_details: imported.set_details,
+                    unit: imported.unit,
+                    distance: imported.distance,
+                    distance_unit: imported.distance_unit,
+                    duration: imported.duration,
+                    notes: imported.notes,
+                    source_id: imported.source_id,
+                    import_id: None,
+                    created_at: imported.created_at.unwrap(),
+                    updated_at: "2020-01-01T00:00:00+00:00".to_owned(),
+                };
+
+                let session = to_movement_session(&exported, &stored);
+                let reimported = adjust_movement_score_to_measurement(
+                    &map_movement(exported.score_type, &exported.name),
+                    &session,
+                )
+                .unwrap();
+                assert_eq!(reimported.score, Some(stored.score));
+                assert_eq!(reimported.sets, stored.sets);
+                assert_eq!(reimported.reps, stored.reps);
+                assert_eq!(reimported.set_details, stored.set_details);
+                assert_eq!(reimported.unit, stored.unit);
+                assert_eq!(reimported.distance, stored.distance);
+                assert_eq!(reimported.distance_unit, stored.distance_unit);
+                assert_eq!(reimported.created_at, Some(stored.created_at));
+                assert_eq!(reimported.source_id, stored.source_id);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_ids() {
+        assert_eq!(
+            primary_ids(
+                Some("mywod:i-1fa65b03-2017-01-02 17:32:34 +0000:5"),
+                "movement-id"
+            ),
+            ("i-1fa65b03-2017-01-02 17:32:34 +0000".to_owned(), 5)
+        );
+        assert_eq!(
+            primary_ids(None, "movement-id"),
+            ("movement-id".to_owned(), 1)
+        );
+        assert_eq!(
+            primary_ids(Some("sugarwod:123"), "movement-id"),
+            ("movement-id".to_owned(), 1)
+        );
+    }
+
+    #[test]
+    fn test_strip_distance() {
+        assert_eq!(strip_distance_from_client_id("21.1+initial"), "initial");
+        assert_eq!(strip_distance_from_client_id("initial"), "initial");
+        assert_eq!(strip_distance_from_name("21.1km Rowing"), "Rowing");
+        assert_eq!(strip_distance_from_name("Rowing"), "Rowing");
+    }
+
+    #[test]
+    fn test_map_gender() {
+        assert_eq!(map_gender(1), Some(Gender::Male));
+        assert_eq!(map_gender(2), Some(Gender::Female));
+        assert_eq!(map_gender(0), None);
     }
 
     #[test]
     fn test_parse_short_date() {
         let res = parse_short_date("1991-12-06");
         assert_eq!(res.unwrap(), "1991-12-06T00:00:00+00:00");
+        assert_eq!(
+            parse_short_date("06/12/1991"),
+            Err(MyWodParseError::InvalidDate("06/12/1991".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_date_report() {
+        let mut score = MyWOD {
+            primary_client_id: "client-id".to_owned(),
+            primary_record_id: 10,
+            title: "Karen".to_owned(),
+            date: "2017-11-19".to_owned(),
+            score_type: "For Repetitions:".to_owned(),
+            score: "150".to_owned(),
+            as_prescribed: 1,
+            personal_record: 0,
+            description: "150 Wall balls".to_owned(),
+            notes: "".to_owned(),
+        };
+        assert!(date_report(&score, &score.date).is_none());
+
+        score.date = "19/11/2017".to_owned();
+        let report = date_report(&score, &score.date).unwrap();
+        assert_eq!(report.table, "MyWODs");
+        assert_eq!(report.source_id, "mywod:client-id:10");
+        assert_eq!(report.outcome, RowOutcome::Altered);
+        assert_eq!(report.original["date"], "19/11/2017");
+        assert_eq!(report.original["score"], "150");
     }
 }
@@ -7,6 +7,35 @@ pub fn create_hash(s: &str) -> String {
     HEXLOWER.encode(actual.as_ref())
 }
 
+/// Rounds to two decimals, which is the precision scores are shown in.
+pub fn round_to_hundredths(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Formats seconds as a time like `1:34:40` or `3:14.1`, the inverse of
+/// `time_to_seconds`.
+pub fn seconds_to_time(seconds: f64) -> String {
+    let hours = (seconds / 3600.0).floor();
+    let minutes = ((seconds - hours * 3600.0) / 60.0).floor();
+    let rest = round_to_tenths(seconds - hours * 3600.0 - minutes * 60.0);
+
+    let rest = if rest.fract() == 0.0 {
+        format!("{:02}", rest as u32)
+    } else {
+        format!("{:04.1}", rest)
+    };
+
+    if hours > 0.0 {
+        format!("{}:{:02}:{}", hours, minutes, rest)
+    } else {
+        format!("{}:{}", minutes, rest)
+    }
+}
+
+fn round_to_tenths(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
 fn parse_num(s: &str) -> f64 {
     s.parse::<f64>().unwrap_or(0.0)
 }
@@ -50,6 +79,41 @@ pub fn time_to_seconds(time: &str) -> f64 {
     seconds
 }
 
+/// Parses a set time like `20 min`, `30sec` or `1:00` from the start of a
+/// string, which is how times are put into movement names, e.g. "20 min Run".
+pub fn parse_set_time(s: &str) -> Option<f64> {
+    let mut tokens = s.split_whitespace();
+    let token = tokens.next()?.to_lowercase();
+    if token.contains(':') {
+        let valid = token
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ':' || c == '.');
+        let seconds = time_to_seconds(&token);
+        return if valid && seconds > 0.0 {
+            Some(seconds)
+        } else {
+            None
+        };
+    }
+
+    let split_at = token
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(token.len());
+    let (value, unit) = token.split_at(split_at);
+    let value = value.parse::<f64>().ok()?;
+    let unit = if unit.is_empty() {
+        tokens.next()?.to_lowercase()
+    } else {
+        unit.to_owned()
+    };
+
+    match unit.as_str() {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(value),
+        "min" | "mins" | "minute" | "minutes" => Some(value * 60.0),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,4 +142,24 @@ mod tests {
         assert_eq!(30.0, time_to_seconds("00:00:30"));
         assert_eq!(4523.0, time_to_seconds("01:15:23"));
     }
+
+    #[test]
+    fn test_seconds_to_time() {
+        assert_eq!(seconds_to_time(91.1), "1:31.1");
+        assert_eq!(seconds_to_time(119.0), "1:59");
+        assert_eq!(seconds_to_time(30.0), "0:30");
+        assert_eq!(seconds_to_time(5680.0), "1:34:40");
+        assert_eq!(seconds_to_time(3605.5), "1:00:05.5");
+        assert_eq!(time_to_seconds(&seconds_to_time(194.1)), 194.1);
+    }
+
+    #[test]
+    fn test_parse_set_time() {
+        assert_eq!(parse_set_time("20 min Run"), Some(1200.0));
+        assert_eq!(parse_set_time("30sec Assault Bike"), Some(30.0));
+        assert_eq!(parse_set_time("1:00 Row"), Some(60.0));
+        assert_eq!(parse_set_time("1000m Rowing"), None);
+        assert_eq!(parse_set_time("Rowing"), None);
+        assert_eq!(parse_set_time("5 Rounds"), None);
+    }
 }
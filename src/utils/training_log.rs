@@ -0,0 +1,737 @@
+use crate::errors::{AppError, WebResult};
+use crate::models::movement::{top_set, CreateMovementScore, MovementMeasurement, MovementSet};
+use crate::models::mywod::{RowOutcome, RowReport};
+use crate::models::training_log::{
+    LogMovementScore, LogRow, LogSource, LogWorkoutScore, TrainingLog,
+};
+use crate::models::unit::{parse_distance, Unit, UnitSystem};
+use crate::models::workout::{CreateWorkoutScore, WorkoutMeasurement};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use super::mywod::{parse_time, MyWodParseError};
+
+type CsvRow = BTreeMap<String, String>;
+
+/// The columns an export needs to have to be imported.
+fn required_columns(source: LogSource) -> &'static [&'static str] {
+    match source {
+        LogSource::Sugarwod => &[
+            "date",
+            "title",
+            "best_result_raw",
+            "best_result_display",
+            "score_type",
+            "barbell_lift",
+        ],
+        LogSource::Btwb => &["Date", "Workout", "Result"],
+        LogSource::Strong => &[
+            "Date",
+            "Exercise Name",
+            "Weight",
+            "Reps",
+            "Distance",
+            "Seconds",
+        ],
+    }
+}
+
+/// Maps the CSV export of a training log app to workout and movement scores.
+/// Loads without a unit in the export are taken to be in the `unit_system`
+/// of the athlete, as the apps export them in the units they are shown in.
+pub fn parse_training_log(
+    reader: impl io::Read,
+    source: LogSource,
+    unit_system: UnitSystem,
+) -> WebResult<TrainingLog> {
+    let invalid = |e: csv::Error| {
+        AppError::BadRequest(format!("Could not read the {} export: {}", source, e))
+    };
+
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    // Strong starts its export with a byte order mark
+    let headers: Vec<String> = csv_reader
+        .headers()
+        .map_err(invalid)?
+        .iter()
+        .map(|header| header.trim_start_matches('\u{feff}').to_owned())
+        .collect();
+
+    if let Some(column) = required_columns(source)
+        .iter()
+        .find(|column| !headers.iter().any(|header| header == *column))
+    {
+        return Err(AppError::BadRequest(format!(
+            "The {} export has no '{}' column",
+            source, column
+        )));
+    }
+
+    let mut rows: Vec<CsvRow> = Vec::new();
+    for record in csv_reader.records() {
+        let record = record.map_err(invalid)?;
+        rows.push(
+            headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| (header.to_owned(), value.to_owned()))
+                .collect(),
+        );
+    }
+
+    let mut log = match source {
+        LogSource::Sugarwod => map_sugarwod(&rows, unit_system),
+        LogSource::Btwb => map_btwb(&rows),
+        LogSource::Strong => map_strong(&rows, unit_system),
+    };
+    log.rows = rows.len() as u32;
+
+    Ok(log)
+}
+
+fn value<'a>(row: &'a CsvRow, column: &str) -> &'a str {
+    row.get(column).map(|value| value.as_str()).unwrap_or("")
+}
+
+/// Hands out source ids for the records of an export. The exports have no
+/// ids, so a record is identified by what was logged on which day. Records
+/// logged more than once on the same day are numbered in the order of the
+/// export, which keeps importing the same export again idempotent.
+struct SourceIds {
+    source: LogSource,
+    seen: HashMap<String, u32>,
+}
+
+impl SourceIds {
+    fn new(source: LogSource) -> Self {
+        SourceIds {
+            source,
+            seen: HashMap::new(),
+        }
+    }
+
+    fn next(&mut self, date: &str, name: &str) -> String {
+        let id = format!("{}:{}:{}", self.source, date, name.trim().to_lowercase());
+        let count = self.seen.entry(id.to_owned()).or_insert(0);
+        *count += 1;
+
+        match *count {
+            1 => id,
+            count => format!("{}:{}", id, count),
+        }
+    }
+}
+
+/// Parses the date of a row with the given format, which may or may not
+/// have a time. Rows with a date that can not be parsed are reported, as they
+/// are imported with the date of the import instead.
+fn parse_log_date(
+    row: &LogRow,
+    date: &str,
+    format: &str,
+    report: &mut Vec<RowReport>,
+) -> Option<String> {
+    let parsed = NaiveDateTime::parse_from_str(date, format).or_else(|_| {
+        NaiveDate::parse_from_str(date, format)
+            .map(|day| day.and_hms_opt(0, 0, 0).unwrap_or_default())
+    });
+
+    match parsed {
+        Ok(dt) => Some(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc).to_rfc3339()),
+        Err(_) => {
+            report.push(RowReport::new(
+                row,
+                RowOutcome::Altered,
+                &format!(
+                    "{}, the date of the import is used instead",
+                    MyWodParseError::InvalidDate(date.to_owned())
+                ),
+            ));
+            None
+        }
+    }
+}
+
+fn parse_number(value: &str, field: &'static str) -> Result<f64, MyWodParseError> {
+    value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| MyWodParseError::InvalidNumber {
+            field,
+            value: value.to_owned(),
+        })
+}
+
+/// Parses an optional number, empty cells count as zero.
+fn parse_optional_number(value: &str, field: &'static str) -> Result<f64, MyWodParseError> {
+    if value.trim().is_empty() {
+        return Ok(0.0);
+    }
+    parse_number(value, field)
+}
+
+/// Parses the number a result starts with, like the rounds of `5+12` or
+/// `10 rounds + 5 reps`. The reps after the rounds are dropped, as they are
+/// for myWOD scores.
+fn parse_leading_number(value: &str, field: &'static str) -> Result<f64, MyWodParseError> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    parse_number(&value[..end], field).map_err(|_| MyWodParseError::InvalidNumber {
+        field,
+        value: value.to_owned(),
+    })
+}
+
+/// Scores that can not be parsed are imported as 0 and reported, with the
+/// original value in the notes, the same as scores of a myWOD backup.
+fn score_or_zero(
+    row: &LogRow,
+    parsed: Result<f64, MyWodParseError>,
+    original: &str,
+    notes: &str,
+    report: &mut Vec<RowReport>,
+) -> (f64, String) {
+    match parsed {
+        Ok(score) => (score, notes.trim().to_owned()),
+        Err(e) => {
+            report.push(RowReport::new(
+                row,
+                RowOutcome::Altered,
+                &format!("{}, the score is imported as 0", e),
+            ));
+            let note = format!("Score could not be processed. Original value: {}", original);
+            (0.0, format!("{}\n\n{}", note, notes).trim().to_owned())
+        }
+    }
+}
+
+/// Maps the `score_type` of a SugarWOD result to a workout measurement.
+fn map_sugarwod_score_type(score_type: &str) -> WorkoutMeasurement {
+    match score_type.to_lowercase().as_str() {
+        "time" => WorkoutMeasurement::Time,
+        "rounds + reps" => WorkoutMeasurement::Rounds,
+        "reps" => WorkoutMeasurement::Repetitions,
+        "load" => WorkoutMeasurement::Load,
+        "distance" => WorkoutMeasurement::Distance,
+        "calories" | "points" => WorkoutMeasurement::Total,
+        _ => WorkoutMeasurement::None,
+    }
+}
+
+/// Maps a SugarWOD export, which has a row per result. Results for a barbell
+/// lift become scores of that lift, all others workout scores.
+fn map_sugarwod(rows: &[CsvRow], unit_system: UnitSystem) -> TrainingLog {
+    let mut log = TrainingLog::default();
+    let mut ids = SourceIds::new(LogSource::Sugarwod);
+
+    for values in rows {
+        let date = value(values, "date");
+        let lift = value(values, "barbell_lift");
+        let title = value(values, "title");
+        let name = if lift.is_empty() { title } else { lift };
+        let row = LogRow {
+            source: LogSource::Sugarwod,
+            source_id: ids.next(date, name),
+            values: values.clone(),
+        };
+        let created_at = parse_log_date(&row, date, "%m/%d/%Y", &mut log.report);
+        let notes = value(values, "notes");
+        let raw = value(values, "best_result_raw");
+
+        if !lift.is_empty() {
+            let load = match parse_number(raw, "best_result_raw") {
+                Ok(load) => load,
+                Err(e) => {
+                    log.report
+                        .push(RowReport::new(&row, RowOutcome::Failed, &e.to_string()));
+                    continue;
+                }
+            };
+            let source_id = Some(row.source_id.to_owned());
+            log.movement_scores.push(LogMovementScore {
+                row,
+                name: lift.to_owned(),
+                measurement: MovementMeasurement::Weight,
+                score: CreateMovementScore {
+                    score: Some(load),
+                    sets: 1,
+                    reps: 1,
+                    set_details: vec![MovementSet {
+                        reps: 1,
+                        load,
+                        rpe: None,
+                        completed: true,
+                    }],
+                    unit: MovementMeasurement::Weight.default_unit(unit_system),
+                    distance: None,
+                    distance_unit: None,
+                    duration: None,
+                    notes: notes.trim().to_owned(),
+                    created_at,
+                    source_id,
+                    import_id: None,
+                },
+            });
+            continue;
+        }
+
+        let measurement = map_sugarwod_score_type(value(values, "score_type"));
+        let display = value(values, "best_result_display");
+        let parsed = match measurement {
+            WorkoutMeasurement::Time => parse_time(raw, "best_result_raw"),
+            // The raw value of rounds is not documented, the display is `5+12`
+            WorkoutMeasurement::Rounds => parse_leading_number(display, "best_result_display"),
+            _ => parse_number(raw, "best_result_raw"),
+        };
+        let (score, notes) = score_or_zero(&row, parsed, display, notes, &mut log.report);
+        let unit = match measurement {
+            WorkoutMeasurement::Load => MovementMeasurement::Weight.default_unit(unit_system),
+            _ => None,
+        };
+        let source_id = Some(row.source_id.to_owned());
+
+        log.workout_scores.push(LogWorkoutScore {
+            row,
+            name: title.to_owned(),
+            description: value(values, "description").trim().to_owned(),
+            measurement,
+            score: CreateWorkoutScore {
+                score,
+                unit,
+                rx: value(values, "rx_or_scaled").eq_ignore_ascii_case("rx"),
+                notes,
+                personal_record: value(values, "pr").eq_ignore_ascii_case("pr"),
+                created_at,
+                source_id,
+                import_id: None,
+            },
+        });
+    }
+
+    log
+}
+
+/// Works out how a Beyond the Whiteboard result is scored from how it reads,
+/// like `4:32`, `225 lbs`, `5000 m`, `10 rounds + 5 reps` or `150 reps`.
+fn parse_btwb_result(
+    result: &str,
+) -> (
+    WorkoutMeasurement,
+    Option<Unit>,
+    Result<f64, MyWodParseError>,
+) {
+    let lower = result.trim().to_lowercase();
+    let compact = lower.replace(' ', "");
+
+    if lower.contains(':') {
+        return (WorkoutMeasurement::Time, None, parse_time(&lower, "Result"));
+    }
+    if let Some(load) = compact
+        .strip_suffix("lbs")
+        .or_else(|| compact.strip_suffix("lb"))
+    {
+        return (
+            WorkoutMeasurement::Load,
+            Some(Unit::Lb),
+            parse_number(load, "Result"),
+        );
+    }
+    if let Some(load) = compact.strip_suffix("kg") {
+        return (
+            WorkoutMeasurement::Load,
+            Some(Unit::Kg),
+            parse_number(load, "Result"),
+        );
+    }
+    if let Some((distance, unit)) = parse_distance(&compact) {
+        return (WorkoutMeasurement::Distance, Some(unit), Ok(distance));
+    }
+    if lower.contains("round") {
+        return (
+            WorkoutMeasurement::Rounds,
+            None,
+            parse_leading_number(&lower, "Result"),
+        );
+    }
+
+    (
+        WorkoutMeasurement::Repetitions,
+        None,
+        parse_leading_number(&lower, "Result"),
+    )
+}
+
+/// Maps a Beyond the Whiteboard export, which has a row per workout result.
+fn map_btwb(rows: &[CsvRow]) -> TrainingLog {
+    let mut log = TrainingLog::default();
+    let mut ids = SourceIds::new(LogSource::Btwb);
+
+    for values in rows {
+        let date = value(values, "Date");
+        let name = value(values, "Workout");
+        let row = LogRow {
+            source: LogSource::Btwb,
+            source_id: ids.next(date, name),
+            values: values.clone(),
+        };
+        let created_at = parse_log_date(&row, date, "%Y-%m-%d", &mut log.report);
+
+        let result = value(values, "Result");
+        let (measurement, unit, parsed) = parse_btwb_result(result);
+        let (score, notes) = score_or_zero(
+            &row,
+            parsed,
+            result,
+            value(values, "Notes"),
+            &mut log.report,
+        );
+        let rx = matches!(
+            value(values, "Prescribed").to_lowercase().as_str(),
+            "yes" | "true" | "rx" | "1"
+        );
+        let source_id = Some(row.source_id.to_owned());
+
+        log.workout_scores.push(LogWorkoutScore {
+            row,
+            name: name.to_owned(),
+            description: value(values, "Description").trim().to_owned(),
+            measurement,
+            score: CreateWorkoutScore {
+                score,
+                unit,
+                rx,
+                notes,
+                personal_record: false,
+                created_at,
+                source_id,
+                import_id: None,
+            },
+        });
+    }
+
+    log
+}
+
+/// A set as Strong logs it, the columns that do not apply are empty or 0.
+struct StrongSet {
+    weight: f64,
+    reps: u32,
+    distance: f64,
+    seconds: f64,
+    rpe: Option<f64>,
+}
+
+fn parse_strong_set(values: &CsvRow) -> Result<StrongSet, MyWodParseError> {
+    let rpe = value(values, "RPE");
+    Ok(StrongSet {
+        weight: parse_optional_number(value(values, "Weight"), "Weight")?,
+        reps: parse_optional_number(value(values, "Reps"), "Reps")? as u32,
+        distance: parse_optional_number(value(values, "Distance"), "Distance")?,
+        seconds: parse_optional_number(value(values, "Seconds"), "Seconds")?,
+        rpe: if rpe.is_empty() {
+            None
+        } else {
+            Some(parse_number(rpe, "RPE")?)
+        },
+    })
+}
+
+/// Maps a Strong export, which has a row per set. The sets of an exercise
+/// in the same workout become one movement score.
+fn map_strong(rows: &[CsvRow], unit_system: UnitSystem) -> TrainingLog {
+    let mut log = TrainingLog::default();
+    let mut ids = SourceIds::new(LogSource::Strong);
+
+    let mut sessions: Vec<Vec<&CsvRow>> = Vec::new();
+    let mut session_index: HashMap<(&str, &str), usize> = HashMap::new();
+    for values in rows {
+        // Newer exports have rows for the rest timer in between the sets
+        if value(values, "Set Order").eq_ignore_ascii_case("rest timer") {
+            continue;
+        }
+        let key = (value(values, "Date"), value(values, "Exercise Name"));
+        let index = *session_index.entry(key).or_insert_with(|| {
+            sessions.push(Vec::new());
+            sessions.len() - 1
+        });
+        sessions[index].push(values);
+    }
+
+    for session in sessions {
+        let first = session[0];
+        let date = value(first, "Date");
+        let name = value(first, "Exercise Name");
+        let row = LogRow {
+            source: LogSource::Strong,
+            source_id: ids.next(date, name),
+            values: first.clone(),
+        };
+
+        let sets = match session
+            .iter()
+            .map(|values| parse_strong_set(values))
+            .collect::<Result<Vec<StrongSet>, MyWodParseError>>()
+        {
+            Ok(sets) => sets,
+            Err(e) => {
+                log.report
+                    .push(RowReport::new(&row, RowOutcome::Failed, &e.to_string()));
+                continue;
+            }
+        };
+
+        let measurement = if sets.iter().any(|set| set.weight > 0.0) {
+            MovementMeasurement::Weight
+        } else if sets.iter().any(|set| set.distance > 0.0) {
+            MovementMeasurement::Time
+        } else if sets.iter().any(|set| set.reps > 0) {
+            MovementMeasurement::Reps
+        } else {
+            log.report.push(RowReport::new(
+                &row,
+                RowOutcome::Failed,
+                &MyWodParseError::Unscored(MovementMeasurement::None).to_string(),
+            ));
+            continue;
+        };
+
+        let created_at = parse_log_date(&row, date, "%Y-%m-%d %H:%M:%S", &mut log.report);
+        let mut notes: Vec<&str> = Vec::new();
+        for values in &session {
+            let note = value(values, "Notes");
+            if !note.is_empty() && !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+
+        let mut score = CreateMovementScore {
+            score: None,
+            sets: sets.len() as u32,
+            reps: 1,
+            set_details: vec![],
+            unit: measurement.default_unit(unit_system),
+            distance: None,
+            distance_unit: None,
+            duration: None,
+            notes: notes.join("\n"),
+            created_at,
+            source_id: Some(row.source_id.to_owned()),
+            import_id: None,
+        };
+
+        match measurement {
+            // Rowing, running, something for a set distance
+            MovementMeasurement::Time => {
+                if let Some(set) = sets.iter().find(|set| set.distance > 0.0) {
+                    score.score = Some(set.seconds);
+                    score.distance = Some(set.distance);
+                    score.distance_unit = Some(Unit::Km.in_system(unit_system));
+                }
+            }
+            _ => {
+                score.set_details = sets
+                    .iter()
+                    .map(|set| MovementSet {
+                        reps: set.reps,
+                        load: set.weight,
+                        rpe: set.rpe,
+                        completed: true,
+                    })
+                    .collect();
+                if let Some(top) = top_set(measurement, &score.set_details) {
+                    score.score = Some(top.score(measurement));
+                    score.reps = top.reps;
+                }
+            }
+        }
+
+        log.movement_scores.push(LogMovementScore {
+            row,
+            name: name.to_owned(),
+            measurement,
+            score,
+        });
+    }
+
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(csv: &str, source: LogSource) -> TrainingLog {
+        parse_training_log(csv.as_bytes(), source, UnitSystem::Metric).unwrap()
+    }
+
+    #[test]
+    fn test_parse_training_log_requires_columns() {
+        let res = parse_training_log(
+            "Date,Workout\n2021-01-01,Fran".as_bytes(),
+            LogSource::Btwb,
+            UnitSystem::Metric,
+        );
+        assert!(matches!(res, Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_map_sugarwod() {
+        let log = parse(
+            "date,title,description,best_result_raw,best_result_display,score_type,barbell_lift,set_details,notes,rx_or_scaled,pr
+03/14/2022,Fran,21-15-9,245,4:05,Time,,,,RX,PR
+03/15/2022,Cindy,AMRAP 20,,18+7,Rounds + Reps,,,felt good,SCALED,
+03/16/2022,Back Squat 1RM,,140,140,Load,Back Squat,,,RX,
+03/16/2022,Grace,,,DNF,Time,,,,RX,",
+            LogSource::Sugarwod,
+        );
+
+        assert_eq!(log.rows, 4);
+        assert_eq!(log.workout_scores.len(), 3);
+        assert_eq!(log.movement_scores.len(), 1);
+
+        let fran = &log.workout_scores[0];
+        assert_eq!(fran.name, "Fran");
+        assert_eq!(fran.measurement, WorkoutMeasurement::Time);
+        assert_eq!(fran.score.score, 245.0);
+        assert!(fran.score.rx);
+        assert!(fran.score.personal_record);
+        assert_eq!(
+            fran.score.created_at,
+            Some("2022-03-14T00:00:00+00:00".to_owned())
+        );
+        assert_eq!(
+            fran.score.source_id,
+            Some("sugarwod:03/14/2022:fran".to_owned())
+        );
+
+        let cindy = &log.workout_scores[1];
+        assert_eq!(cindy.measurement, WorkoutMeasurement::Rounds);
+        assert_eq!(cindy.score.score, 18.0);
+        assert!(!cindy.score.rx);
+        assert_eq!(cindy.score.notes, "felt good");
+
+        let squat = &log.movement_scores[0];
+        assert_eq!(squat.name, "Back Squat");
+        assert_eq!(squat.measurement, MovementMeasurement::Weight);
+        assert_eq!(squat.score.score, Some(140.0));
+        assert_eq!(squat.score.unit, Some(Unit::Kg));
+
+        // Grace has no time, so it is imported as 0 and reported
+        let grace = &log.workout_scores[2];
+        assert_eq!(grace.score.score, 0.0);
+        assert!(grace.score.notes.contains("Original value: DNF"));
+        assert_eq!(log.report.len(), 1);
+        assert_eq!(log.report[0].outcome, RowOutcome::Altered);
+        assert_eq!(log.report[0].table, "sugarwod");
+    }
+
+    #[test]
+    fn test_parse_btwb_result() {
+        let (measurement, unit, score) = parse_btwb_result("4:32");
+        assert_eq!(measurement, WorkoutMeasurement::Time);
+        assert_eq!(unit, None);
+        assert_eq!(score, Ok(272.0));
+
+        let (measurement, unit, score) = parse_btwb_result("225 lbs");
+        assert_eq!(measurement, WorkoutMeasurement::Load);
+        assert_eq!(unit, Some(Unit::Lb));
+        assert_eq!(score, Ok(225.0));
+
+        let (measurement, unit, score) = parse_btwb_result("100 kg");
+        assert_eq!(measurement, WorkoutMeasurement::Load);
+        assert_eq!(unit, Some(Unit::Kg));
+        assert_eq!(score, Ok(100.0));
+
+        let (measurement, unit, score) = parse_btwb_result("5000 m");
+        assert_eq!(measurement, WorkoutMeasurement::Distance);
+        assert_eq!(unit, Some(Unit::M));
+        assert_eq!(score, Ok(5000.0));
+
+        let (measurement, _, score) = parse_btwb_result("10 rounds + 5 reps");
+        assert_eq!(measurement, WorkoutMeasurement::Rounds);
+        assert_eq!(score, Ok(10.0));
+
+        let (measurement, _, score) = parse_btwb_result("150 reps");
+        assert_eq!(measurement, WorkoutMeasurement::Repetitions);
+        assert_eq!(score, Ok(150.0));
+
+        let (_, _, score) = parse_btwb_result("DNF");
+        assert!(score.is_err());
+    }
+
+    #[test]
+    fn test_map_btwb() {
+        let log = parse(
+            "Date,Workout,Result,Prescribed,Notes
+2021-06-01,Fran,3:58,Yes,
+2021-06-01,Fran,4:30,No,second try
+2021-06-02,Deadlift 1-1-1,180 kg,Yes,",
+            LogSource::Btwb,
+        );
+
+        assert_eq!(log.workout_scores.len(), 3);
+        assert!(log.report.is_empty());
+        assert!(log.workout_scores[0].score.rx);
+        assert!(!log.workout_scores[1].score.rx);
+        // Logged twice on the same day, so the second one is numbered
+        assert_eq!(
+            log.workout_scores[0].score.source_id,
+            Some("btwb:2021-06-01:fran".to_owned())
+        );
+        assert_eq!(
+            log.workout_scores[1].score.source_id,
+            Some("btwb:2021-06-01:fran:2".to_owned())
+        );
+        assert_eq!(log.workout_scores[2].score.unit, Some(Unit::Kg));
+    }
+
+    #[test]
+    fn test_map_strong() {
+        let log = parse(
+            "\u{feff}Date,Workout Name,Duration,Exercise Name,Set Order,Weight,Reps,Distance,Seconds,Notes,Workout Notes,RPE
+2022-01-10 18:00:00,Evening,1h,Bench Press (Barbell),1,60,10,0,0,,,
+2022-01-10 18:00:00,Evening,1h,Bench Press (Barbell),2,80,5,0,0,paused,,8
+2022-01-10 18:00:00,Evening,1h,Bench Press (Barbell),Rest Timer,0,0,0,90,,,
+2022-01-10 18:00:00,Evening,1h,Pull Up,1,0,12,0,0,,,
+2022-01-10 18:00:00,Evening,1h,Rowing (Machine),1,0,0,2,480,,,
+2022-01-10 18:00:00,Evening,1h,Plank,1,0,0,0,60,,,",
+            LogSource::Strong,
+        );
+
+        assert_eq!(log.rows, 6);
+        assert_eq!(log.movement_scores.len(), 3);
+
+        let bench = &log.movement_scores[0];
+        assert_eq!(bench.measurement, MovementMeasurement::Weight);
+        assert_eq!(bench.score.sets, 2);
+        assert_eq!(bench.score.score, Some(80.0));
+        assert_eq!(bench.score.reps, 5);
+        assert_eq!(bench.score.set_details[1].rpe, Some(8.0));
+        assert_eq!(bench.score.notes, "paused");
+        assert_eq!(
+            bench.score.created_at,
+            Some("2022-01-10T18:00:00+00:00".to_owned())
+        );
+
+        let pull_ups = &log.movement_scores[1];
+        assert_eq!(pull_ups.measurement, MovementMeasurement::Reps);
+        assert_eq!(pull_ups.score.score, Some(12.0));
+        assert_eq!(pull_ups.score.unit, None);
+
+        let row = &log.movement_scores[2];
+        assert_eq!(row.measurement, MovementMeasurement::Time);
+        assert_eq!(row.score.score, Some(480.0));
+        assert_eq!(row.score.distance, Some(2.0));
+        assert_eq!(row.score.distance_unit, Some(Unit::Km));
+
+        // A plank only has seconds, which no movement is scored by
+        assert_eq!(log.report.len(), 1);
+        assert_eq!(log.report[0].outcome, RowOutcome::Failed);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::errors::{AppError, WebResult};
+use actix_multipart::Multipart;
+use futures::{StreamExt, TryStreamExt};
+
+/// The multipart field uploaded files are expected in.
+pub const UPLOAD_FIELD: &str = "file";
+
+/// Reads the file in the `file` field of a multipart upload. Reading stops as
+/// soon as the file is larger than `max_size` bytes, or does not start with
+/// the `header` that files of the expected type start with.
+pub async fn read_payload(
+    mut payload: Multipart,
+    max_size: usize,
+    header: &[u8],
+    expected_type: &str,
+) -> WebResult<Vec<u8>> {
+    let mut contents: Option<Vec<u8>> = None;
+
+    while let Some(mut field) = payload
+        .try_next()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Reading upload failed: {}", e)))?
+    {
+        if field.name() != Some(UPLOAD_FIELD) {
+            return Err(AppError::BadRequest(format!(
+                "Unexpected field '{}', the file is expected in the '{}' field",
+                field.name().unwrap_or_default(),
+                UPLOAD_FIELD
+            )));
+        }
+        if contents.is_some() {
+            return Err(AppError::BadRequest(
+                "Only one file can be uploaded at once".to_owned(),
+            ));
+        }
+
+        let mut file = Vec::new();
+        // Field in turn is stream of *Bytes* object
+        while let Some(chunk) = field.next().await {
+            let data =
+                chunk.map_err(|e| AppError::BadRequest(format!("Reading upload failed: {}", e)))?;
+            if file.len() + data.len() > max_size {
+                return Err(AppError::PayloadTooLarge(format!(
+                    "The file can be at most {} bytes",
+                    max_size
+                )));
+            }
+            let checked = file.len() >= header.len();
+            file.extend_from_slice(&data);
+            if !checked && file.len() >= header.len() && !file.starts_with(header) {
+                return Err(AppError::UnsupportedMediaType(format!(
+                    "The file is not {}",
+                    expected_type
+                )));
+            }
+        }
+        if !file.starts_with(header) {
+            return Err(AppError::UnsupportedMediaType(format!(
+                "The file is not {}",
+                expected_type
+            )));
+        }
+        contents = Some(file);
+    }
+
+    contents.ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "The file is missing from the '{}' field",
+            UPLOAD_FIELD
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::web::Bytes;
+    use futures::stream;
+
+    fn multipart(fields: &[(&str, &[u8])]) -> Multipart {
+        let mut body = Vec::new();
+        for (name, contents) in fields {
+            body.extend_from_slice(b"--boundary\r\n");
+            body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"upload\"\r\n\
+                     Content-Type: application/octet-stream\r\n\r\n",
+                    name
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--boundary--\r\n");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=boundary"),
+        );
+        let body = stream::iter(vec![Ok(Bytes::from(body))]);
+        Multipart::new(&headers, body)
+    }
+
+    #[actix_web::test]
+    async fn test_read_payload() {
+        let contents = read_payload(multipart(&[("file", b"SQLite data")]), 100, b"SQLite", "")
+            .await
+            .unwrap();
+        assert_eq!(contents, b"SQLite data");
+
+        let too_large = read_payload(multipart(&[("file", b"SQLite data")]), 5, b"", "").await;
+        assert!(matches!(too_large, Err(AppError::PayloadTooLarge(_))));
+
+        let wrong_type =
+            read_payload(multipart(&[("file", b"PNG data")]), 100, b"SQLite", "").await;
+        assert!(matches!(wrong_type, Err(AppError::UnsupportedMediaType(_))));
+
+        let too_short = read_payload(multipart(&[("file", b"SQL")]), 100, b"SQLite", "").await;
+        assert!(matches!(too_short, Err(AppError::UnsupportedMediaType(_))));
+
+        let wrong_field = read_payload(multipart(&[("backup", b"data")]), 100, b"", "").await;
+        assert!(matches!(wrong_field, Err(AppError::BadRequest(_))));
+
+        let two_files =
+            read_payload(multipart(&[("file", b"a"), ("file", b"b")]), 100, b"", "").await;
+        assert!(matches!(two_files, Err(AppError::BadRequest(_))));
+
+        let missing = read_payload(multipart(&[]), 100, b"", "").await;
+        assert!(matches!(missing, Err(AppError::BadRequest(_))));
+    }
+}